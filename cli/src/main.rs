@@ -1,6 +1,9 @@
 use bytes::Bytes;
 use clap::{Parser, ValueHint};
 use shiva::core::{Document, DocumentType};
+use shiva::docx::{DocxOptions, DocxParseOptions, RevisionMode};
+use shiva::pdf::{FontOptions, PdfOptions, PdfParseOptions};
+use shiva::svg::ImageOptions;
 use std::path::Path;
 
 #[derive(Parser, Debug)]
@@ -31,6 +34,61 @@ struct Args {
         value_hint = ValueHint::FilePath
     )]
     output_file: String,
+
+    #[arg(
+        long = "font-path",
+        value_name = "PATH",
+        help = "Font file or directory to use for PDF, PNG and SVG output, can be repeated",
+        value_hint = ValueHint::AnyPath
+    )]
+    font_paths: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Use the fonts installed on the system for PDF, PNG and SVG output"
+    )]
+    system_fonts: bool,
+
+    #[arg(long, help = "Add a table of contents page to PDF output")]
+    table_of_contents: bool,
+
+    #[arg(long, help = "Write PDF output conforming to PDF/A-2b for archiving")]
+    pdf_a: bool,
+
+    #[arg(
+        long,
+        help = "Tag the structure of PDF output for accessibility (PDF/UA)"
+    )]
+    tagged: bool,
+
+    #[arg(
+        long,
+        help = "Render only this page for PNG and SVG output, counting from 1"
+    )]
+    page: Option<usize>,
+
+    #[arg(long, help = "Password of an encrypted PDF input")]
+    password: Option<String>,
+
+    #[arg(long, help = "Keep the review comments of DOCX input")]
+    comments: bool,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = ["accept", "reject", "annotate"],
+        default_value = "accept",
+        help = "Tracked changes of DOCX input: accept all, reject all or keep them annotated"
+    )]
+    revisions: String,
+
+    #[arg(
+        long = "reference-doc",
+        value_name = "FILE",
+        help = "DOCX whose styles, numbering, theme and page headers and footers are used for DOCX output",
+        value_hint = ValueHint::FilePath
+    )]
+    reference_doc: Option<String>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -90,9 +148,60 @@ fn main() -> anyhow::Result<()> {
     let input_vec = std::fs::read(&args.input_file)?;
     let input_bytes = Bytes::from(input_vec);
 
-    let document = Document::parse(&input_bytes, input_doc_type)?;
+    let document = match (input_doc_type, &args.password) {
+        (DocumentType::PDF, Some(password)) => {
+            let options = PdfParseOptions::new().with_password(password);
+            shiva::pdf::Transformer::parse_with_options(&input_bytes, &options)?
+        }
+        (DocumentType::DOCX, _) => {
+            let revisions = match args.revisions.as_str() {
+                "reject" => RevisionMode::RejectAll,
+                "annotate" => RevisionMode::Annotate,
+                _ => RevisionMode::AcceptAll,
+            };
+            let options = DocxParseOptions::new()
+                .with_comments(args.comments)
+                .with_revisions(revisions);
+            shiva::docx::Transformer::parse_with_options(&input_bytes, &options)?
+        }
+        _ => Document::parse(&input_bytes, input_doc_type)?,
+    };
 
-    let output = document.generate(output_doc_type)?;
+    let fonts = args
+        .font_paths
+        .iter()
+        .fold(FontOptions::new(), |fonts, path| fonts.with_path(path))
+        .with_system_fonts(args.system_fonts);
+    let output = match output_doc_type {
+        DocumentType::PDF => {
+            let options = PdfOptions::new()
+                .with_fonts(fonts)
+                .with_table_of_contents(args.table_of_contents)
+                .with_pdf_a(args.pdf_a)
+                .with_tagged(args.tagged);
+            shiva::pdf::Transformer::generate_with_options(&document, &options)?
+        }
+        DocumentType::DOCX => {
+            let mut options = DocxOptions::new();
+            if let Some(reference) = &args.reference_doc {
+                options = options.with_reference(Bytes::from(std::fs::read(reference)?));
+            }
+            shiva::docx::Transformer::generate_with_options(&document, &options)?
+        }
+        DocumentType::PNG | DocumentType::SVG => {
+            let mut options = ImageOptions::new().with_fonts(fonts);
+            if let Some(page) = args.page {
+                options = options.with_page(page);
+            }
+            match output_doc_type {
+                DocumentType::PNG => {
+                    shiva::png::Transformer::generate_with_options(&document, &options)?
+                }
+                _ => shiva::svg::Transformer::generate_with_options(&document, &options)?,
+            }
+        }
+        _ => document.generate(output_doc_type)?,
+    };
 
     std::fs::write(&args.output_file, output)?;
 
@@ -0,0 +1,57 @@
+//! Compares PDF generation with and without the process-wide font cache.
+//!
+//! Run with `cargo bench -p shiva --bench pdf_generation`.
+
+use shiva::core::{Document, Element, TextStyle};
+use shiva::pdf::{clear_font_cache, evict_layout_cache, FontOptions, Transformer};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 10;
+
+fn document(iteration: u32) -> Document {
+    // every document differs, so typst can't reuse the previous layout
+    let elements = (0..20)
+        .map(|paragraph| Element::Text {
+            text: format!("Run {iteration}, paragraph {paragraph}: the quick brown fox"),
+            size: 12,
+            style: TextStyle::default(),
+        })
+        .collect();
+    Document::new(elements)
+}
+
+fn measure(fonts: &FontOptions, clear_cache: bool) -> anyhow::Result<Duration> {
+    let mut total = Duration::ZERO;
+    for iteration in 0..ITERATIONS {
+        if clear_cache {
+            clear_font_cache();
+        }
+        let document = document(iteration);
+        let start = Instant::now();
+        Transformer::generate_with_fonts(&document, fonts)?;
+        total += start.elapsed();
+        evict_layout_cache(10);
+    }
+    Ok(total / ITERATIONS)
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = [
+        ("embedded fonts", FontOptions::new()),
+        (
+            "font directory",
+            FontOptions::new()
+                .with_embedded_fonts(false)
+                .with_path(concat!(env!("CARGO_MANIFEST_DIR"), "/fonts")),
+        ),
+    ];
+    for (name, fonts) in options {
+        let uncached = measure(&fonts, true)?;
+        let cached = measure(&fonts, false)?;
+        println!(
+            "{name}: {uncached:?} per document without cache, {cached:?} with cache ({:.1}x)",
+            uncached.as_secs_f64() / cached.as_secs_f64()
+        );
+    }
+    Ok(())
+}
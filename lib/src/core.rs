@@ -22,10 +22,16 @@ use crate::markdown;
 use crate::ods;
 #[cfg(feature = "pdf")]
 use crate::pdf;
+#[cfg(feature = "png")]
+use crate::png;
 #[cfg(feature = "rtf")]
 use crate::rtf;
+#[cfg(feature = "svg")]
+use crate::svg;
 #[cfg(feature = "text")]
 use crate::text;
+#[cfg(feature = "typst")]
+use crate::typst;
 #[cfg(feature = "xls")]
 use crate::xls;
 #[cfg(feature = "xlsx")]
@@ -96,6 +102,45 @@ impl PageFormat {
             PageFormat::Custom(dimensions) => dimensions.clone(),
         }
     }
+
+    /// Named format with the given portrait dimensions, `Custom` when none matches.
+    pub fn from_dimensions(dimensions: PageDimensions) -> PageFormat {
+        [
+            PageFormat::A4,
+            PageFormat::Letter,
+            PageFormat::Legal,
+            PageFormat::Tabloid,
+        ]
+        .into_iter()
+        .find(|format| format.dimensions().approx_eq(&dimensions))
+        .unwrap_or(PageFormat::Custom(dimensions))
+    }
+}
+
+/// Converts mm to twips (twentieths of a point), the page unit of DOCX and RTF.
+pub fn mm_to_twips(mm: f32) -> i32 {
+    (mm / 25.4 * 1440.0).round() as i32
+}
+
+/// Converts twips to mm, rounded to 0.1 mm.
+pub fn twips_to_mm(twips: i32) -> f32 {
+    (twips as f32 * 25.4 / 1440.0 * 10.0).round() / 10.0
+}
+
+impl PageDimensions {
+    // Formats store lengths in twips, points or inches, so round trips may be off by a fraction of a mm
+    fn approx_eq(&self, other: &PageDimensions) -> bool {
+        [
+            (self.page_width, other.page_width),
+            (self.page_height, other.page_height),
+            (self.page_margin_top, other.page_margin_top),
+            (self.page_margin_bottom, other.page_margin_bottom),
+            (self.page_margin_left, other.page_margin_left),
+            (self.page_margin_right, other.page_margin_right),
+        ]
+        .iter()
+        .all(|(a, b)| (a - b).abs() < 0.5)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -106,6 +151,29 @@ pub enum PageOrientation {
     Landscape,
 }
 
+/// Descriptive information about a document (title, author, keywords...).
+///
+/// Dates are kept as ISO-8601 strings, as they are stored by most formats.
+#[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub language: Option<String>,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+}
+
+impl DocumentMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == DocumentMetadata::default()
+    }
+}
+
 /// Band is a section of a document(Title, PageHeader, ColumnHeader, Detail, ColumnFooter, PageFooter, Summary).
 ///
 /// Each band contains a list of elements (Text, Table, List, Image, Hyperlink...).
@@ -166,6 +234,12 @@ impl Band {
     }
 }
 
+/// Placeholder for the current page number in page header and footer text
+pub const PAGE_NUMBER: &str = "{page}";
+
+/// Placeholder for the total number of pages in page header and footer text
+pub const PAGE_COUNT: &str = "{pages}";
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Document {
@@ -178,6 +252,10 @@ pub struct Document {
 
     /// Page orientation (Portrait, Landscape) Default is Portrait.
     pub orientation: PageOrientation,
+
+    /// Document metadata (title, author, keywords...)
+    #[cfg_attr(feature = "json", serde(default))]
+    pub metadata: DocumentMetadata,
 }
 
 impl Document {
@@ -186,6 +264,7 @@ impl Document {
             bands: vec![Band::Detail(elements)],
             page_format: PageFormat::default(),
             orientation: PageOrientation::default(),
+            metadata: DocumentMetadata::default(),
         }
     }
 
@@ -203,6 +282,7 @@ impl Document {
             ],
             page_format,
             orientation: PageOrientation::default(),
+            metadata: DocumentMetadata::default(),
         }
     }
 
@@ -258,6 +338,18 @@ impl Document {
             DocumentType::ODS => ods::Transformer::parse(input_bytes)?,
             #[cfg(not(feature = "ods"))]
             DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
+            #[cfg(feature = "typst")]
+            DocumentType::Typst => typst::Transformer::parse(input_bytes)?,
+            #[cfg(not(feature = "typst"))]
+            DocumentType::Typst => return Err(anyhow::anyhow!("Typst feature is not enabled")),
+            #[cfg(feature = "png")]
+            DocumentType::PNG => png::Transformer::parse(input_bytes)?,
+            #[cfg(not(feature = "png"))]
+            DocumentType::PNG => return Err(anyhow::anyhow!("PNG feature is not enabled")),
+            #[cfg(feature = "svg")]
+            DocumentType::SVG => svg::Transformer::parse(input_bytes)?,
+            #[cfg(not(feature = "svg"))]
+            DocumentType::SVG => return Err(anyhow::anyhow!("SVG feature is not enabled")),
         };
         Ok(document)
     }
@@ -314,6 +406,18 @@ impl Document {
             DocumentType::ODS => ods::Transformer::generate(self)?,
             #[cfg(not(feature = "ods"))]
             DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
+            #[cfg(feature = "typst")]
+            DocumentType::Typst => typst::Transformer::generate(self)?,
+            #[cfg(not(feature = "typst"))]
+            DocumentType::Typst => return Err(anyhow::anyhow!("Typst feature is not enabled")),
+            #[cfg(feature = "png")]
+            DocumentType::PNG => png::Transformer::generate(self)?,
+            #[cfg(not(feature = "png"))]
+            DocumentType::PNG => return Err(anyhow::anyhow!("PNG feature is not enabled")),
+            #[cfg(feature = "svg")]
+            DocumentType::SVG => svg::Transformer::generate(self)?,
+            #[cfg(not(feature = "svg"))]
+            DocumentType::SVG => return Err(anyhow::anyhow!("SVG feature is not enabled")),
         };
         Ok(output)
     }
@@ -331,7 +435,12 @@ impl Document {
     pub fn get_elements_by_band(&self, band: &Band) -> Vec<&Element> {
         let mut elements = Vec::new();
         for b in &self.bands {
-            if b == band {
+            // bands are matched by kind and custom bands by name, not by content
+            let same_band = match (b, band) {
+                (Band::Custom(name, _), Band::Custom(other, _)) => name == other,
+                _ => std::mem::discriminant(b) == std::mem::discriminant(band),
+            };
+            if same_band {
                 elements.extend(b.elements());
             }
         }
@@ -391,6 +500,32 @@ impl Document {
         self.orientation = orientation;
     }
 
+    /// Page size and margins in mm, width and height are swapped for landscape pages.
+    pub fn page_dimensions(&self) -> PageDimensions {
+        let mut dimensions = self.page_format.dimensions();
+        if self.orientation == PageOrientation::Landscape
+            && dimensions.page_width < dimensions.page_height
+        {
+            std::mem::swap(&mut dimensions.page_width, &mut dimensions.page_height);
+        }
+        dimensions
+    }
+
+    /// Sets the page format and orientation from the size of the page as it is printed.
+    pub fn set_page_dimensions(&mut self, mut dimensions: PageDimensions) {
+        if dimensions.page_width > dimensions.page_height {
+            std::mem::swap(&mut dimensions.page_width, &mut dimensions.page_height);
+            self.orientation = PageOrientation::Landscape;
+        } else {
+            self.orientation = PageOrientation::Portrait;
+        }
+        self.page_format = PageFormat::from_dimensions(dimensions);
+    }
+
+    pub fn set_metadata(&mut self, metadata: DocumentMetadata) {
+        self.metadata = metadata;
+    }
+
     pub fn set_title(&mut self, elements: Vec<Element>) {
         self.bands.push(Band::Title(elements));
     }
@@ -516,6 +651,10 @@ pub trait TransformerWithImageLoaderSaverTrait {
 pub enum ParserError {
     #[error("Parser error")]
     Common,
+    #[error("Document is encrypted and needs a password")]
+    PasswordRequired,
+    #[error("Password of the encrypted document is wrong")]
+    IncorrectPassword,
 }
 #[derive(Error, Debug)]
 pub enum GeneratorError {
@@ -528,6 +667,7 @@ pub enum Element {
     Text {
         text: String,
         size: u8,
+        style: TextStyle,
     },
     Header {
         level: u8,
@@ -537,6 +677,12 @@ pub enum Element {
         elements: Vec<Element>,
     },
     Table {
+        /// Name of the table, the sheet name in spreadsheets
+        #[cfg_attr(
+            feature = "json",
+            serde(default, skip_serializing_if = "Option::is_none")
+        )]
+        name: Option<String>,
         headers: Vec<TableHeader>,
         rows: Vec<TableRow>,
     },
@@ -551,29 +697,500 @@ pub enum Element {
         alt: String,
         size: u8,
     },
+    /// Preformatted code, whitespace is kept as is
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    /// Quotation, may contain any other elements
+    Blockquote {
+        elements: Vec<Element>,
+    },
+    /// Horizontal rule separating sections
+    ThematicBreak,
+    /// Hard break, the following content starts on a new page
+    PageBreak,
+    /// Review comment, `anchor` is the commented text of the document
+    Comment {
+        author: Option<String>,
+        date: Option<String>,
+        text: String,
+        anchor: String,
+    },
+    /// Tracked change of a reviewed document, holds the inserted or deleted inline elements
+    Revision {
+        kind: RevisionKind,
+        author: Option<String>,
+        date: Option<String>,
+        elements: Vec<Element>,
+    },
+    /// Footnote placed at its reference in the text, `elements` are the blocks of the note
+    Footnote {
+        label: String,
+        elements: Vec<Element>,
+    },
+}
+
+/// Kind of a tracked change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum RevisionKind {
+    Insertion,
+    Deletion,
+}
+
+/// Inline formatting of a text run.
+///
+/// Paragraphs keep mixed formatting as a sequence of `Element::Text` runs, each with its own style.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Inline code, usually rendered with a monospace font
+    pub code: bool,
+}
+
+impl TextStyle {
+    pub fn bold() -> TextStyle {
+        TextStyle {
+            bold: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn italic() -> TextStyle {
+        TextStyle {
+            italic: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn code() -> TextStyle {
+        TextStyle {
+            code: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if no formatting is applied
+    pub fn is_plain(&self) -> bool {
+        self == &TextStyle::default()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ListItem {
     pub element: Element,
+    /// State of the checkbox of a task list item, None for plain items
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub checked: Option<bool>,
+}
+
+impl ListItem {
+    pub fn new(element: Element) -> ListItem {
+        ListItem {
+            element,
+            checked: None,
+        }
+    }
+
+    /// Task list item with a checkbox
+    pub fn task(element: Element, checked: bool) -> ListItem {
+        ListItem {
+            element,
+            checked: Some(checked),
+        }
+    }
 }
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct TableHeader {
-    pub element: Element,
+    /// Block content of the header cell
+    pub elements: Vec<Element>,
     pub width: f32,
+    #[cfg_attr(feature = "json", serde(default = "default_span"))]
+    pub colspan: usize,
+    #[cfg_attr(feature = "json", serde(default = "default_span"))]
+    pub rowspan: usize,
+    /// Horizontal alignment of the cells of the column
+    #[cfg_attr(feature = "json", serde(default))]
+    pub alignment: ColumnAlignment,
+}
+
+/// Horizontal alignment of a table column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum ColumnAlignment {
+    /// Left to the format or the renderer
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+impl TableHeader {
+    pub fn new(element: Element, width: f32) -> TableHeader {
+        TableHeader {
+            elements: vec![element],
+            width,
+            colspan: 1,
+            rowspan: 1,
+            alignment: ColumnAlignment::None,
+        }
+    }
+
+    pub fn align(mut self, alignment: ColumnAlignment) -> TableHeader {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn span(mut self, colspan: usize, rowspan: usize) -> TableHeader {
+        self.colspan = colspan.max(1);
+        self.rowspan = rowspan.max(1);
+        self
+    }
+
+    /// Plain text of all children, for formats without rich cells
+    pub fn text(&self) -> String {
+        plain_text(&self.elements)
+    }
 }
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct TableRow {
     pub cells: Vec<TableCell>,
 }
 
+/// Cell of a table row.
+///
+/// A cell spanning several columns or rows is stored once, at its top left position;
+/// the positions it covers have no cell of their own.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct TableCell {
-    pub element: Element,
+    /// Block content of the cell
+    pub elements: Vec<Element>,
+    #[cfg_attr(feature = "json", serde(default = "default_span"))]
+    pub colspan: usize,
+    #[cfg_attr(feature = "json", serde(default = "default_span"))]
+    pub rowspan: usize,
+    /// Typed value of a spreadsheet cell, the elements hold its displayed text
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub value: Option<CellValue>,
+}
+
+impl TableCell {
+    pub fn new(element: Element) -> TableCell {
+        TableCell::with_elements(vec![element])
+    }
+
+    pub fn with_elements(elements: Vec<Element>) -> TableCell {
+        TableCell {
+            elements,
+            colspan: 1,
+            rowspan: 1,
+            value: None,
+        }
+    }
+
+    /// Cell showing a typed value as text
+    pub fn with_value(value: CellValue, size: u8) -> TableCell {
+        TableCell {
+            value: Some(value.clone()),
+            ..TableCell::new(Element::Text {
+                text: value.to_string(),
+                size,
+                style: TextStyle::default(),
+            })
+        }
+    }
+
+    pub fn span(mut self, colspan: usize, rowspan: usize) -> TableCell {
+        self.colspan = colspan.max(1);
+        self.rowspan = rowspan.max(1);
+        self
+    }
+
+    /// Plain text of all children, for formats without rich cells
+    pub fn text(&self) -> String {
+        plain_text(&self.elements)
+    }
+}
+
+#[cfg(feature = "json")]
+fn default_span() -> usize {
+    1
+}
+
+/// Value of a spreadsheet cell
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum CellValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// ISO 8601 date (`2024-05-01`) or date and time (`2024-05-01T10:30:00`)
+    DateTime(String),
+    /// Duration in seconds
+    Duration(f64),
+    /// Error value such as `#DIV/0!`
+    Error(String),
+    /// Formula without the leading `=`, in the syntax of the source spreadsheet,
+    /// with the value cached at the last calculation
+    Formula {
+        formula: String,
+        cached: Option<Box<CellValue>>,
+    },
+}
+
+impl std::fmt::Display for CellValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellValue::String(value) | CellValue::DateTime(value) | CellValue::Error(value) => {
+                write!(f, "{value}")
+            }
+            CellValue::Int(value) => write!(f, "{value}"),
+            CellValue::Float(value) => write!(f, "{value}"),
+            CellValue::Bool(value) => write!(f, "{}", if *value { "TRUE" } else { "FALSE" }),
+            CellValue::Duration(seconds) => {
+                let seconds = seconds.round() as i64;
+                let sign = if seconds < 0 { "-" } else { "" };
+                let seconds = seconds.abs();
+                write!(
+                    f,
+                    "{sign}{}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            }
+            CellValue::Formula { formula, cached } => match cached {
+                Some(cached) => write!(f, "{cached}"),
+                None => write!(f, "={formula}"),
+            },
+        }
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "xls", feature = "ods"))]
+impl CellValue {
+    /// Typed value of a cell read by calamine, `None` for an empty cell
+    pub(crate) fn from_calamine(
+        data: &calamine::Data,
+        formula: Option<&String>,
+    ) -> Option<CellValue> {
+        use calamine::Data;
+        let value = match data {
+            Data::Empty => None,
+            Data::String(value) => Some(CellValue::String(value.clone())),
+            Data::Int(value) => Some(CellValue::Int(*value)),
+            Data::Float(value) => Some(CellValue::Float(*value)),
+            Data::Bool(value) => Some(CellValue::Bool(*value)),
+            Data::DateTime(value) if value.is_duration() => {
+                // serial days lose precision, durations are kept to the millisecond
+                Some(CellValue::Duration(
+                    (value.as_f64() * 86400000.0).round() / 1000.0,
+                ))
+            }
+            Data::DateTime(value) => Some(match value.as_datetime() {
+                Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => {
+                    CellValue::DateTime(datetime.format("%Y-%m-%d").to_string())
+                }
+                Some(datetime) => {
+                    CellValue::DateTime(datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+                }
+                None => CellValue::Float(value.as_f64()),
+            }),
+            Data::DateTimeIso(value) => Some(CellValue::DateTime(
+                value.trim_end_matches("T00:00:00").to_string(),
+            )),
+            Data::DurationIso(value) => Some(
+                parse_iso_duration(value)
+                    .map(CellValue::Duration)
+                    .unwrap_or_else(|| CellValue::String(value.clone())),
+            ),
+            Data::Error(error) => Some(CellValue::Error(error.to_string())),
+        };
+        match formula.map(|formula| formula.trim_start_matches("of:").trim_start_matches('=')) {
+            Some(formula) if !formula.is_empty() => Some(CellValue::Formula {
+                formula: formula.to_string(),
+                cached: value.map(Box::new),
+            }),
+            _ => value,
+        }
+    }
+}
+
+/// Seconds of an ISO 8601 duration such as `PT12H30M15S`
+#[cfg(any(feature = "xlsx", feature = "xls", feature = "ods"))]
+fn parse_iso_duration(duration: &str) -> Option<f64> {
+    fn parse_units(mut part: &str, units: &[(char, f64)]) -> Option<f64> {
+        let mut seconds = 0.0;
+        for (unit, factor) in units {
+            if let Some((number, rest)) = part.split_once(*unit) {
+                seconds += number.parse::<f64>().ok()? * factor;
+                part = rest;
+            }
+        }
+        part.is_empty().then_some(seconds)
+    }
+
+    let (sign, duration) = match duration.strip_prefix('-') {
+        Some(duration) => (-1.0, duration),
+        None => (1.0, duration),
+    };
+    let (days, time) = duration
+        .strip_prefix('P')?
+        .split_once('T')
+        .unwrap_or((duration.strip_prefix('P')?, ""));
+    let days = parse_units(days, &[('D', 86400.0)])?;
+    let time = parse_units(time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)])?;
+    Some(sign * (days + time))
+}
+
+/// Grid column of every header and cell of a table.
+///
+/// Positions covered by a cell spanning several columns or rows are skipped,
+/// the header row counts as the first row of the grid.
+pub fn table_columns(headers: &[TableHeader], rows: &[TableRow]) -> (Vec<usize>, Vec<Vec<usize>>) {
+    // number of following rows still covered, per column
+    let mut covered: Vec<usize> = vec![];
+    let mut place_row = |spans: Vec<(usize, usize)>| {
+        let mut columns = vec![];
+        let mut column = 0;
+        for (colspan, rowspan) in spans {
+            while covered.get(column).is_some_and(|rows| *rows > 0) {
+                column += 1;
+            }
+            columns.push(column);
+            if covered.len() < column + colspan {
+                covered.resize(column + colspan, 0);
+            }
+            covered[column..column + colspan].fill(rowspan);
+            column += colspan;
+        }
+        covered
+            .iter_mut()
+            .for_each(|rows| *rows = rows.saturating_sub(1));
+        columns
+    };
+    let header_columns = place_row(headers.iter().map(|h| (h.colspan, h.rowspan)).collect());
+    let row_columns = rows
+        .iter()
+        .map(|row| place_row(row.cells.iter().map(|c| (c.colspan, c.rowspan)).collect()))
+        .collect();
+    (header_columns, row_columns)
+}
+
+/// Sheet name of every table, as accepted by spreadsheet applications.
+///
+/// Invalid characters are replaced, long names shortened and repeated names numbered,
+/// tables without a name are called `Sheet1`, `Sheet2`, ...
+#[cfg(any(feature = "xlsx", feature = "xls", feature = "ods"))]
+pub(crate) fn sheet_names<'a>(elements: impl IntoIterator<Item = &'a Element>) -> Vec<String> {
+    const MAX_LENGTH: usize = 31;
+    let mut names: Vec<String> = vec![];
+    let tables = elements.into_iter().filter_map(|element| match element {
+        Element::Table { name, .. } => Some(name.as_deref()),
+        _ => None,
+    });
+    for (index, name) in (1..).zip(tables) {
+        let name: String = name
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+            .collect();
+        let name = name.trim().trim_matches('\'');
+        let base = if name.is_empty() {
+            format!("Sheet{index}")
+        } else {
+            name.chars().take(MAX_LENGTH).collect()
+        };
+        let mut unique = base.clone();
+        let mut counter = 1;
+        while names.iter().any(|name| name.eq_ignore_ascii_case(&unique)) {
+            counter += 1;
+            let suffix = format!(" ({counter})");
+            let base: String = base.chars().take(MAX_LENGTH - suffix.len()).collect();
+            unique = base + &suffix;
+        }
+        names.push(unique);
+    }
+    names
+}
+
+/// Text of block elements, one line per block
+pub fn plain_text(elements: &[Element]) -> String {
+    let mut blocks: Vec<String> = vec![];
+    for element in elements {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => blocks.push(text.clone()),
+            Element::Hyperlink { title, .. } => blocks.push(title.clone()),
+            Element::CodeBlock { code, .. } => blocks.push(code.trim_end().to_string()),
+            // runs of a paragraph share a line
+            Element::Paragraph { elements } => blocks.push(
+                elements
+                    .iter()
+                    .map(|run| plain_text(std::slice::from_ref(run)))
+                    .collect(),
+            ),
+            Element::Blockquote { elements } => blocks.push(plain_text(elements)),
+            // the text reads as if all changes were accepted
+            Element::Revision {
+                kind: RevisionKind::Insertion,
+                elements,
+                ..
+            } => blocks.push(
+                elements
+                    .iter()
+                    .map(|run| plain_text(std::slice::from_ref(run)))
+                    .collect(),
+            ),
+            Element::List { elements, .. } => {
+                for item in elements {
+                    blocks.push(plain_text(std::slice::from_ref(&item.element)));
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks.join("\n")
+}
+
+/// One line describing a review comment, for formats that keep comments as hidden text
+pub fn comment_summary(
+    author: &Option<String>,
+    date: &Option<String>,
+    text: &str,
+    anchor: &str,
+) -> String {
+    let mut comment = "Comment".to_string();
+    if let Some(author) = author {
+        comment.push_str(&format!(" by {author}"));
+    }
+    if let Some(date) = date {
+        comment.push_str(&format!(" on {date}"));
+    }
+    if !anchor.is_empty() {
+        comment.push_str(&format!(" about \"{anchor}\""));
+    }
+    comment.push_str(&format!(": {text}"));
+    comment
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -616,7 +1233,7 @@ impl ImageData {
     pub fn set_image_type(&mut self, image_type_str: &str) {
         let image_type_str = image_type_str
             .split('.')
-            .last()
+            .next_back()
             .unwrap_or("")
             .trim()
             .to_lowercase();
@@ -768,6 +1385,9 @@ pub enum DocumentType {
     XLS = 9,
     XLSX = 10,
     ODS = 11,
+    Typst = 12,
+    PNG = 13,
+    SVG = 14,
 }
 
 impl DocumentType {
@@ -790,6 +1410,9 @@ impl DocumentType {
         map.insert("xls", DocumentType::XLS);
         map.insert("xlsx", DocumentType::XLSX);
         map.insert("ods", DocumentType::ODS);
+        map.insert("typ", DocumentType::Typst);
+        map.insert("png", DocumentType::PNG);
+        map.insert("svg", DocumentType::SVG);
         map
     }
 
@@ -802,6 +1425,8 @@ impl DocumentType {
     }
 }
 
+pub use bytes;
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -819,6 +1444,15 @@ pub mod tests {
         });
     }
 
+    /// Text run without formatting
+    pub fn text(text: &str, size: u8) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            size,
+            style: TextStyle::default(),
+        }
+    }
+
     const VARIANTS: &[DocumentType] = &[
         DocumentType::HTML,
         DocumentType::Markdown,
@@ -832,8 +1466,39 @@ pub mod tests {
         DocumentType::XLS,
         DocumentType::XLSX,
         DocumentType::ODS,
+        DocumentType::Typst,
+        DocumentType::PNG,
+        DocumentType::SVG,
     ];
 
+    #[test]
+    fn test_page_dimensions() {
+        let mut document = Document::new(vec![]);
+        document.set_page_format(PageFormat::Letter);
+        document.set_orientation(PageOrientation::Landscape);
+        let dimensions = document.page_dimensions();
+        assert_eq!(
+            (dimensions.page_width, dimensions.page_height),
+            (279.0, 216.0)
+        );
+
+        let mut parsed = Document::new(vec![]);
+        parsed.set_page_dimensions(PageDimensions {
+            page_width: 279.4,
+            page_height: 215.9,
+            ..dimensions
+        });
+        assert_eq!(parsed.page_format, PageFormat::Letter);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+
+        parsed.set_page_dimensions(PageDimensions {
+            page_margin_top: 25.0,
+            ..PageFormat::A4.dimensions()
+        });
+        assert_eq!(parsed.orientation, PageOrientation::Portrait);
+        assert!(matches!(parsed.page_format, PageFormat::Custom(_)));
+    }
+
     #[test]
     fn test_document_type_count() {
         assert_eq!(VARIANTS.len(), DocumentType::COUNT);
@@ -878,6 +1543,30 @@ pub mod tests {
         assert!(variants.contains(&"md"));
     }
 
+    #[test]
+    fn test_elements_by_band() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 10,
+            style: TextStyle::default(),
+        };
+        let mut document = Document::new_with_dimensions(
+            vec![text("header")],
+            vec![text("first"), text("second")],
+            vec![text("footer")],
+            PageFormat::default(),
+        );
+        document.set_detail(vec![text("third")]);
+
+        assert_eq!(document.get_page_header(), vec![&text("header")]);
+        assert_eq!(
+            document.get_detail(),
+            vec![&text("first"), &text("second"), &text("third")]
+        );
+        assert_eq!(document.get_page_footer(), vec![&text("footer")]);
+        assert!(document.get_summary().is_empty());
+    }
+
     #[test]
     fn test_as_repr() {
         assert_eq!(DocumentType::HTML as u8, 0);
@@ -943,6 +1632,100 @@ pub mod tests {
             ImageAlignment::from_str("right").unwrap()
         );
     }
-}
 
-pub use bytes;
+    #[test]
+    fn test_table_columns() {
+        let headers = vec![
+            TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+            TableHeader::new(text("Total", 8), 10.0),
+        ];
+        let rows = vec![
+            TableRow {
+                cells: vec![
+                    TableCell::new(text("Group", 8)).span(1, 2),
+                    TableCell::new(text("First", 8)),
+                    TableCell::new(text("1", 8)),
+                ],
+            },
+            TableRow {
+                cells: vec![
+                    TableCell::new(text("Second", 8)),
+                    TableCell::new(text("2", 8)),
+                ],
+            },
+            TableRow {
+                cells: vec![
+                    TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)]).span(3, 1),
+                ],
+            },
+        ];
+        let (header_columns, row_columns) = table_columns(&headers, &rows);
+        assert_eq!(header_columns, vec![0, 2]);
+        assert_eq!(row_columns, vec![vec![0, 1, 2], vec![1, 2], vec![0]]);
+        assert_eq!(plain_text(&rows[2].cells[0].elements), "Sum\nof all");
+    }
+
+    #[test]
+    fn test_custom_bands_by_name() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 10,
+            style: TextStyle::default(),
+        };
+        let mut document = Document::new(vec![text("detail")]);
+        document.set_custom_band("notes".to_string(), vec![text("note")]);
+        document.set_custom_band("appendix".to_string(), vec![text("appendix")]);
+        document.set_custom_band("notes".to_string(), vec![text("second note")]);
+
+        assert_eq!(
+            document.get_custom_band("notes"),
+            vec![&text("note"), &text("second note")]
+        );
+        assert_eq!(
+            document.get_custom_band("appendix"),
+            vec![&text("appendix")]
+        );
+        assert!(document.get_custom_band("missing").is_empty());
+        assert_eq!(document.get_detail(), vec![&text("detail")]);
+    }
+
+    #[test]
+    fn test_sheet_names() {
+        let table = |name: Option<&str>| Element::Table {
+            name: name.map(str::to_string),
+            headers: vec![],
+            rows: vec![],
+        };
+        let elements = vec![
+            table(Some("Q1/Q2 [draft]")),
+            table(None),
+            table(Some("Sales")),
+            table(Some("sales")),
+            table(Some(&"x".repeat(40))),
+        ];
+        assert_eq!(
+            sheet_names(&elements),
+            vec![
+                "Q1_Q2 _draft_".to_string(),
+                "Sheet2".to_string(),
+                "Sales".to_string(),
+                "sales (2)".to_string(),
+                "x".repeat(31),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cell_value() {
+        assert_eq!(CellValue::Duration(45015.0).to_string(), "12:30:15");
+        assert_eq!(CellValue::Bool(false).to_string(), "FALSE");
+        let formula = CellValue::Formula {
+            formula: "SUM(A1:A2)".to_string(),
+            cached: None,
+        };
+        assert_eq!(formula.to_string(), "=SUM(A1:A2)");
+        assert_eq!(parse_iso_duration("PT12H30M15S"), Some(45015.0));
+        assert_eq!(parse_iso_duration("-P1DT1S"), Some(-86401.0));
+        assert_eq!(parse_iso_duration("12:30"), None);
+    }
+}
@@ -1,14 +1,12 @@
 use crate::core::{
     Document,
     Element::{Table, Text},
-    TableCell, TableHeader, TableRow, TransformerTrait,
+    TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
 };
 use bytes::Bytes;
-use log::info;
 pub struct Transformer;
 
 #[allow(unused)]
-
 impl TransformerTrait for Transformer {
     /// Parses CSV data from a `Bytes` object and converts it into a structured `Document`.
     /// This function is used to interpret CSV data, typically transforming it into a format
@@ -37,13 +35,14 @@ impl TransformerTrait for Transformer {
 
         // Process each header name to create `TableHeader` elements.
         for name in headings {
-            headers.push(TableHeader {
-                element: Text {
+            headers.push(TableHeader::new(
+                Text {
                     text: name.clone(),
                     size: 8, // Default font size
+                    style: TextStyle::default(),
                 },
-                width: 10.0, // Default width, can be adjusted as needed
-            });
+                10.0, // Default width, can be adjusted as needed
+            ));
         }
 
         // Prepare to collect the table rows.
@@ -55,12 +54,11 @@ impl TransformerTrait for Transformer {
 
             // Create a `TableCell` for each cell in the row.
             for cell in lines {
-                curr_row.push(TableCell {
-                    element: Text {
-                        text: cell.clone(),
-                        size: 8, // Default font size
-                    },
-                });
+                curr_row.push(TableCell::new(Text {
+                    text: cell.clone(),
+                    size: 8, // Default font size
+                    style: TextStyle::default(),
+                }));
             }
 
             // Add the completed row to the rows collection.
@@ -68,7 +66,11 @@ impl TransformerTrait for Transformer {
         }
 
         // Construct the `Document` with the table created from the CSV data.
-        Ok(Document::new(vec![Table { headers, rows }]))
+        Ok(Document::new(vec![Table {
+            name: None,
+            headers,
+            rows,
+        }]))
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
@@ -77,13 +79,11 @@ impl TransformerTrait for Transformer {
         let mut data: Vec<Vec<String>> = Vec::new();
 
         for element in elements {
-            if let Table { headers, rows } = element {
+            if let Table { headers, rows, .. } = element {
                 // Create a new vector for the header row
                 let mut header_line = Vec::new();
                 for header in headers {
-                    if let Text { text, size: _ } = &header.element {
-                        header_line.push(text.clone())
-                    }
+                    header_line.push(header.text());
                 }
                 // Push header row to data
                 data.push(header_line);
@@ -92,9 +92,7 @@ impl TransformerTrait for Transformer {
                 for row in rows {
                     let mut curr_line = Vec::new(); // This must be inside the loop
                     for cell in &row.cells {
-                        if let Text { text, size: _ } = &cell.element {
-                            curr_line.push(text.clone())
-                        }
+                        curr_line.push(cell.text());
                     }
                     // Push each row to data
                     data.push(curr_line);
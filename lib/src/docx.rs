@@ -1,18 +1,467 @@
 use crate::core::{
-    Document, Element, ImageDimension, ListItem, TableCell, TableRow, TransformerTrait,
+    mm_to_twips, plain_text, table_columns, twips_to_mm, Band, Document, DocumentMetadata, Element,
+    ImageData, ImageDimension, ImageType, ListItem, PageDimensions, PageOrientation, RevisionKind,
+    TableCell, TableRow, TextStyle, TransformerTrait, PAGE_COUNT, PAGE_NUMBER,
 };
 
 use bytes::Bytes;
 use docx_rs::{
-    read_docx, AbstractNumbering, Docx, Hyperlink, HyperlinkType, IndentLevel, Level, LevelJc,
-    LevelText, NumberFormat, Numbering, NumberingId, Paragraph, ParagraphStyle, Pic, Run, RunChild,
-    SpecialIndentType, Start, TableRowChild,
+    read_docx, AbstractNumbering, Bold, BorderType, Break, BreakType, BuildXML, Comment,
+    CommentChild, CommentRangeEnd, CommentRangeStart, Delete, DeleteChild, DeleteText,
+    DocumentChild, Docx, DrawingData, FieldCharType, Footer, FooterChild, Footnote, Header,
+    HeaderChild, Hyperlink, HyperlinkData, HyperlinkType, IndentLevel, Insert, InsertChild, Italic,
+    Level, LevelJc, LevelText, Name, NumPages, NumberFormat, Numbering, NumberingId, PageMargin,
+    PageNum, PageOrientationType, Paragraph, ParagraphBorder, ParagraphBorderPosition,
+    ParagraphBorders, ParagraphChild, ParagraphProperty, Pic, Run, RunChild, RunFonts,
+    SpecialIndentType, Start, Style, StyleType, TableRowChild, Text, Underline, VMergeType,
 };
-use log::{error, info, warn};
-use std::io::Cursor;
+use log::{error, warn};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use zip::{ZipArchive, ZipWriter};
 
 pub struct Transformer;
 
+// font used for inline code runs
+const MONOSPACE_FONT: &str = "Courier New";
+
+// paragraph style used for code blocks
+const CODE_STYLE: &str = "SourceCode";
+// paragraph styles used for quotations
+const QUOTE_STYLE: &str = "Quote";
+const INTENSE_QUOTE_STYLE: &str = "IntenseQuote";
+// paragraph style of the document title, read as a level 1 heading
+const TITLE_STYLE: &str = "Title";
+
+// DrawingML sizes are in EMU, image sizes in pixels at 96 dpi
+const EMU_PER_PIXEL: u32 = 9525;
+
+fn monospace_fonts() -> RunFonts {
+    RunFonts::new()
+        .ascii(MONOSPACE_FONT)
+        .hi_ansi(MONOSPACE_FONT)
+        .cs(MONOSPACE_FONT)
+}
+
+//create a run with the text formatting applied
+fn styled_run(text: &str, size: u8, style: &TextStyle) -> Run {
+    format_run(Run::new().add_text(text), size, style)
+}
+
+fn format_run(run: Run, size: u8, style: &TextStyle) -> Run {
+    let mut run = run.size(size as usize * 2);
+    if style.bold {
+        run = run.bold();
+    }
+    if style.italic {
+        run = run.italic();
+    }
+    if style.underline {
+        run = run.underline("single");
+    }
+    if style.strikethrough {
+        run = run.strike();
+    }
+    if style.code {
+        run = run.fonts(monospace_fonts());
+    }
+    run
+}
+
+//read the text formatting of a run
+fn run_style(run: &Run) -> TextStyle {
+    let property = &run.run_property;
+    TextStyle {
+        bold: property.bold == Some(Bold::new()),
+        italic: property.italic == Some(Italic::new()),
+        underline: property
+            .underline
+            .as_ref()
+            .is_some_and(|underline| *underline != Underline::new("none")),
+        strikethrough: property.strike.as_ref().is_some_and(|strike| strike.val),
+        code: property.fonts == Some(monospace_fonts()),
+    }
+}
+
+//add paragraph children (text runs and hyperlinks) to a single docx paragraph
+fn add_paragraph_children(mut paragraph: Paragraph, elements: &[Element]) -> Paragraph {
+    for element in elements {
+        match element {
+            Element::Text { text, size, style } => {
+                paragraph = paragraph.add_run(styled_run(text, *size, style));
+            }
+            Element::Hyperlink {
+                title, url, size, ..
+            } => {
+                let hyperlink = Hyperlink::new(url, HyperlinkType::External)
+                    .add_run(Run::new().add_text(title).size(*size as usize * 2));
+                paragraph = paragraph.add_hyperlink(hyperlink);
+            }
+            Element::Revision {
+                kind,
+                author,
+                date,
+                elements,
+            } => {
+                let runs = elements.iter().filter_map(|element| match element {
+                    Element::Text { text, size, style } => Some((text, *size, style)),
+                    _ => None,
+                });
+                paragraph = match kind {
+                    RevisionKind::Insertion => {
+                        let mut insert = Insert::new_with_empty();
+                        for (text, size, style) in runs {
+                            insert = insert.add_run(styled_run(text, size, style));
+                        }
+                        // w:author is required, an empty one reads back as no author
+                        insert = insert.author(author.as_deref().unwrap_or_default());
+                        if let Some(date) = date {
+                            insert = insert.date(date);
+                        }
+                        paragraph.add_insert(insert)
+                    }
+                    RevisionKind::Deletion => {
+                        let mut delete = Delete::new();
+                        for (text, size, style) in runs {
+                            let run = Run::new().add_delete_text(text);
+                            delete = delete.add_run(format_run(run, size, style));
+                        }
+                        // w:author is required, an empty one reads back as no author
+                        delete = delete.author(author.as_deref().unwrap_or_default());
+                        if let Some(date) = date {
+                            delete = delete.date(date);
+                        }
+                        paragraph.add_delete(delete)
+                    }
+                };
+            }
+            // the note paragraphs are written to the footnotes part by docx-rs
+            Element::Footnote { elements, .. } => {
+                let mut footnote = Footnote::new();
+                for block in elements {
+                    let note = match block {
+                        Element::Paragraph { elements } => {
+                            add_paragraph_children(Paragraph::new(), elements)
+                        }
+                        block => Paragraph::new()
+                            .add_run(Run::new().add_text(plain_text(std::slice::from_ref(block)))),
+                    };
+                    footnote = footnote.add_content(note);
+                }
+                paragraph = paragraph.add_run(Run::new().add_footnote_reference(footnote));
+            }
+            _ => {
+                error!("Unknown paragraph element");
+            }
+        }
+    }
+    paragraph
+}
+
+//the comment covers the paragraph before it, or a paragraph of its anchored text
+fn add_comment(
+    mut doc: Docx,
+    id: usize,
+    author: &Option<String>,
+    date: &Option<String>,
+    text: &str,
+    anchor: &str,
+) -> Docx {
+    let mut comment =
+        Comment::new(id).add_paragraph(Paragraph::new().add_run(Run::new().add_text(text)));
+    comment = comment.author(author.as_deref().unwrap_or_default());
+    if let Some(date) = date {
+        comment = comment.date(date);
+    }
+    match doc.document.children.last_mut() {
+        Some(DocumentChild::Paragraph(paragraph)) => {
+            let start = ParagraphChild::CommentStart(Box::new(CommentRangeStart::new(comment)));
+            paragraph.children.insert(0, start);
+            paragraph
+                .children
+                .push(ParagraphChild::CommentEnd(CommentRangeEnd::new(id)));
+            doc
+        }
+        _ => doc.add_paragraph(
+            Paragraph::new()
+                .add_comment_start(comment)
+                .add_run(Run::new().add_text(anchor))
+                .add_comment_end(id),
+        ),
+    }
+}
+
+//create a single paragraph for a code block, lines are separated by line breaks
+fn code_block_paragraph(code: &str) -> Paragraph {
+    let mut run = Run::new().fonts(monospace_fonts()).size(20);
+    for (i, line) in code.trim_end_matches('\n').split('\n').enumerate() {
+        if i > 0 {
+            run = run.add_break(BreakType::TextWrapping);
+        }
+        run = run.add_text(line);
+    }
+    Paragraph::new().style(CODE_STYLE).add_run(run)
+}
+
+//read a code block paragraph back, line breaks become new lines
+fn extract_code_block(doc_element: &Paragraph) -> Element {
+    let mut code = String::new();
+    for c in &doc_element.children {
+        if let docx_rs::ParagraphChild::Run(run) = c {
+            for child in &run.children {
+                match child {
+                    RunChild::Text(t) => code.push_str(&t.text),
+                    RunChild::Break(_) => code.push('\n'),
+                    RunChild::Tab(_) => code.push('\t'),
+                    _ => {}
+                }
+            }
+        }
+    }
+    code.push('\n');
+    Element::CodeBlock {
+        language: None,
+        code,
+    }
+}
+
+//create the paragraphs of a blockquote, nested quotes are flattened
+fn quote_paragraphs(elements: &[Element]) -> Vec<Paragraph> {
+    let mut paragraphs = vec![];
+    for element in elements {
+        match element {
+            Element::Text { text, size, style } => paragraphs.push(
+                Paragraph::new()
+                    .style(QUOTE_STYLE)
+                    .add_run(styled_run(text, *size, style)),
+            ),
+            Element::Paragraph { elements } => paragraphs.push(add_paragraph_children(
+                Paragraph::new().style(QUOTE_STYLE),
+                elements,
+            )),
+            Element::Header { text, .. } => paragraphs.push(
+                Paragraph::new()
+                    .style(QUOTE_STYLE)
+                    .add_run(Run::new().add_text(text).bold()),
+            ),
+            Element::Blockquote { elements } => paragraphs.extend(quote_paragraphs(elements)),
+            Element::CodeBlock { code, .. } => paragraphs.push(code_block_paragraph(code)),
+            _ => warn!("Unsupported element in blockquote"),
+        }
+    }
+    paragraphs
+}
+
+//text run with the page number placeholders written as PAGE and NUMPAGES fields
+fn add_field_text(mut paragraph: Paragraph, text: &str, size: u8, style: &TextStyle) -> Paragraph {
+    let mut rest = text;
+    while let Some((index, placeholder)) = [PAGE_NUMBER, PAGE_COUNT]
+        .into_iter()
+        .filter_map(|placeholder| Some((rest.find(placeholder)?, placeholder)))
+        .min()
+    {
+        if index > 0 {
+            paragraph = paragraph.add_run(styled_run(&rest[..index], size, style));
+        }
+        paragraph = if placeholder == PAGE_NUMBER {
+            paragraph.add_page_num(PageNum::new())
+        } else {
+            paragraph.add_num_pages(NumPages::new())
+        };
+        rest = &rest[index + placeholder.len()..];
+    }
+    if !rest.is_empty() {
+        paragraph = paragraph.add_run(styled_run(rest, size, style));
+    }
+    paragraph
+}
+
+//paragraphs of a page header or footer band
+fn page_band_paragraphs(elements: &[&Element]) -> Vec<Paragraph> {
+    let mut paragraphs = vec![];
+    for element in elements {
+        let runs = match element {
+            Element::Paragraph { elements } => elements.as_slice(),
+            element => std::slice::from_ref(*element),
+        };
+        let mut paragraph = Paragraph::new();
+        for run in runs {
+            paragraph = match run {
+                Element::Text { text, size, style } => {
+                    add_field_text(paragraph, text, *size, style)
+                }
+                Element::Hyperlink { .. } => {
+                    add_paragraph_children(paragraph, std::slice::from_ref(run))
+                }
+                _ => paragraph.add_run(Run::new().add_text(plain_text(std::slice::from_ref(run)))),
+            };
+        }
+        paragraphs.push(paragraph);
+    }
+    paragraphs
+}
+
+// grid column, colspan, rowspan and content of a table cell
+type GridCell<'a> = (usize, usize, usize, &'a [Element]);
+
+//create the paragraphs of a table cell, every text run gets its own paragraph
+fn cell_paragraphs(elements: &[Element]) -> Vec<Paragraph> {
+    let mut paragraphs = vec![];
+    for element in elements {
+        match element {
+            Element::Text { .. } | Element::Hyperlink { .. } => paragraphs.push(
+                add_paragraph_children(Paragraph::new(), std::slice::from_ref(element)),
+            ),
+            Element::Paragraph { elements } => {
+                paragraphs.push(add_paragraph_children(Paragraph::new(), elements))
+            }
+            Element::CodeBlock { code, .. } => paragraphs.push(code_block_paragraph(code)),
+            Element::Blockquote { elements } => paragraphs.extend(quote_paragraphs(elements)),
+            _ => paragraphs.push(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(plain_text(std::slice::from_ref(element)))),
+            ),
+        }
+    }
+    // a table cell must contain at least one paragraph
+    if paragraphs.is_empty() {
+        paragraphs.push(Paragraph::new());
+    }
+    paragraphs
+}
+
+//placeholder for the part of a vertically merged cell in the following rows
+fn merged_table_cell(colspan: usize) -> docx_rs::TableCell {
+    let mut cell = docx_rs::TableCell::new()
+        .add_paragraph(Paragraph::new())
+        .vertical_merge(VMergeType::Continue);
+    if colspan > 1 {
+        cell = cell.grid_span(colspan);
+    }
+    cell
+}
+
+//docx-rs keeps the cell properties private, the spans are read back from their XML
+fn cell_merge(cell: &docx_rs::TableCell) -> anyhow::Result<(usize, Option<VMergeType>)> {
+    let xml = cell.property.build();
+    let mut reader = Reader::from_reader(xml.as_slice());
+    let mut colspan = 1;
+    let mut vertical_merge = None;
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(tag) | Event::Empty(tag) => {
+                let value = tag
+                    .try_get_attribute("w:val")?
+                    .map(|attribute| String::from_utf8_lossy(&attribute.value).to_string());
+                match tag.local_name().as_ref() {
+                    b"gridSpan" => colspan = value.and_then(|v| v.parse().ok()).unwrap_or(1).max(1),
+                    b"vMerge" => {
+                        vertical_merge = Some(match value.as_deref() {
+                            Some("restart") => VMergeType::Restart,
+                            _ => VMergeType::Continue,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok((colspan, vertical_merge))
+}
+
+//docx-rs keeps the page size private, it is read back from its XML like the cell spans
+fn page_dimensions(section: &docx_rs::SectionProperty) -> anyhow::Result<PageDimensions> {
+    let xml = section.page_size.build();
+    let mut reader = Reader::from_reader(xml.as_slice());
+    let mut size = (0, 0);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(tag) | Event::Empty(tag) if tag.local_name().as_ref() == b"pgSz" => {
+                let twips = |name| -> anyhow::Result<i32> {
+                    Ok(tag
+                        .try_get_attribute(name)?
+                        .and_then(|attribute| {
+                            String::from_utf8_lossy(&attribute.value).parse().ok()
+                        })
+                        .unwrap_or_default())
+                };
+                size = (twips("w:w")?, twips("w:h")?);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    let margin = &section.page_margin;
+    Ok(PageDimensions {
+        page_width: twips_to_mm(size.0),
+        page_height: twips_to_mm(size.1),
+        page_margin_top: twips_to_mm(margin.top),
+        page_margin_bottom: twips_to_mm(margin.bottom),
+        page_margin_left: twips_to_mm(margin.left),
+        page_margin_right: twips_to_mm(margin.right),
+    })
+}
+
+//consecutive quote paragraphs belong to the same blockquote
+fn push_quote(result: &mut Vec<Element>, element: Element) {
+    match result.last_mut() {
+        Some(Element::Blockquote { elements }) => elements.push(element),
+        _ => result.push(Element::Blockquote {
+            elements: vec![element],
+        }),
+    }
+}
+
+//a horizontal rule is an empty paragraph with a bottom border
+fn thematic_break_paragraph() -> Paragraph {
+    let mut paragraph = Paragraph::new();
+    paragraph.property = paragraph.property.set_borders(
+        ParagraphBorders::with_empty().set(
+            ParagraphBorder::new(ParagraphBorderPosition::Bottom)
+                .val(BorderType::Single)
+                .size(6)
+                .space(1),
+        ),
+    );
+    paragraph
+}
+
+fn is_thematic_break(doc_element: &Paragraph) -> bool {
+    doc_element.property.borders.is_some() && !has_text(doc_element)
+}
+
+fn has_page_break(doc_element: &Paragraph) -> bool {
+    doc_element.property.page_break_before == Some(true)
+        || doc_element.children.iter().any(|c| match c {
+            docx_rs::ParagraphChild::Run(run) => run.children.iter().any(
+                |child| matches!(child, RunChild::Break(br) if *br == Break::new(BreakType::Page)),
+            ),
+            _ => false,
+        })
+}
+
+fn has_text(doc_element: &Paragraph) -> bool {
+    doc_element.children.iter().any(|c| match c {
+        docx_rs::ParagraphChild::Run(run) => run
+            .children
+            .iter()
+            .any(|child| matches!(child, RunChild::Text(t) if !t.text.is_empty())),
+        docx_rs::ParagraphChild::Hyperlink(_) => true,
+        _ => false,
+    })
+}
+
 //function re_size input picture (if size very big)
 fn re_size_picture(pic: Pic) -> Pic {
     let mut pic = pic;
@@ -46,9 +495,8 @@ fn re_size_picture(pic: Pic) -> Pic {
 //recursive function for processing nested elements in Element::List
 fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, depth: usize) {
     match element {
-        Element::Text { text, size } => {
-            let mut paragraph =
-                Paragraph::new().add_run(Run::new().add_text(text).size(*size as usize * 2));
+        Element::Text { text, size, style } => {
+            let mut paragraph = Paragraph::new().add_run(styled_run(text, *size, style));
 
             if numbered {
                 paragraph = paragraph.numbering(NumberingId::new(2), IndentLevel::new(depth));
@@ -56,8 +504,7 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
                 // Add the "-" character at the beginning of the text, taking into account the nesting level
                 let indent = " ".repeat(depth * 4); // 4 spaces for each nesting level
                 let modified_text = format!("{}- {}", indent, text);
-                paragraph = Paragraph::new()
-                    .add_run(Run::new().add_text(modified_text).size(*size as usize * 2));
+                paragraph = Paragraph::new().add_run(styled_run(&modified_text, *size, style));
             }
             *doc = doc.clone().add_paragraph(paragraph);
         }
@@ -116,231 +563,984 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
     }
 }
 
-impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        fn extract_text(doc_element: &docx_rs::Paragraph) -> String {
-            let mut result = "".to_string();
-            for c in &doc_element.children {
-                match c {
-                    docx_rs::ParagraphChild::Run(run) => {
-                        if run.children.is_empty() {
-                            result.push_str("");
-                            return result;
+//runs of a paragraph, including the runs of its hyperlinks and tracked insertions
+fn paragraph_runs(children: &[docx_rs::ParagraphChild]) -> Vec<&Run> {
+    let mut runs = vec![];
+    for c in children {
+        match c {
+            docx_rs::ParagraphChild::Run(run) => runs.push(run.as_ref()),
+            docx_rs::ParagraphChild::Hyperlink(hyperlink) => {
+                runs.extend(paragraph_runs(&hyperlink.children))
+            }
+            docx_rs::ParagraphChild::Insert(insert) => runs.extend(insert_runs(insert)),
+            _ => {}
+        }
+    }
+    runs
+}
+
+fn insert_runs(insert: &Insert) -> impl Iterator<Item = &Run> {
+    insert.children.iter().filter_map(|child| match child {
+        InsertChild::Run(run) => Some(run.as_ref()),
+        _ => None,
+    })
+}
+
+// complex field (w:fldChar) whose runs are being read
+#[derive(Default)]
+struct Field {
+    instruction: String,
+    separated: bool,
+}
+
+impl Field {
+    //page number fields are read back as placeholders
+    fn placeholder(&self) -> Option<&'static str> {
+        let name = self.instruction.split_whitespace().next()?;
+        if name.eq_ignore_ascii_case("PAGE") {
+            Some(PAGE_NUMBER)
+        } else if name.eq_ignore_ascii_case("NUMPAGES") {
+            Some(PAGE_COUNT)
+        } else {
+            None
+        }
+    }
+}
+
+//text of a run, the field state is carried over between the runs of a paragraph
+fn run_text(run: &Run, field: &mut Option<Field>) -> String {
+    let mut text = String::new();
+    for child in &run.children {
+        match child {
+            // the cached result of a page number field is replaced by its placeholder
+            RunChild::Text(t)
+                if !field
+                    .as_ref()
+                    .is_some_and(|field| field.separated && field.placeholder().is_some()) =>
+            {
+                text.push_str(&t.text)
+            }
+            RunChild::DeleteText(t) => text.push_str(&deleted_text(t)),
+            RunChild::Tab(_) => text.push('\t'),
+            RunChild::FieldChar(field_char) => match field_char.field_char_type {
+                FieldCharType::Begin => *field = Some(Field::default()),
+                FieldCharType::Separate => {
+                    if let Some(field) = field {
+                        field.separated = true;
+                        text.push_str(field.placeholder().unwrap_or_default());
+                    }
+                }
+                FieldCharType::End => {
+                    if let Some(field) = field.take() {
+                        if !field.separated {
+                            text.push_str(field.placeholder().unwrap_or_default());
                         }
-                        if let RunChild::Text(t) = &run.children[0] {
-                            result.push_str(&t.text);
+                    }
+                }
+                FieldCharType::Unsupported => {}
+            },
+            RunChild::InstrTextString(instruction) => {
+                if let Some(field) = field {
+                    field.instruction.push_str(instruction);
+                }
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+fn runs_text(runs: Vec<&Run>) -> String {
+    let mut field = None;
+    runs.into_iter()
+        .map(|run| run_text(run, &mut field))
+        .collect()
+}
+
+//heading level from the paragraph style (Heading1-9, Title or a style named "heading N") or the outline level
+fn heading_level(docx: &Docx, property: &ParagraphProperty) -> Option<usize> {
+    let style_id = property.style.as_ref().map(|style| style.val.as_str());
+    let style =
+        style_id.and_then(|id| docx.styles.styles.iter().find(|style| style.style_id == id));
+    let level = style_id
+        .and_then(|id| {
+            if id.eq_ignore_ascii_case(TITLE_STYLE) {
+                return Some(1);
+            }
+            id.to_lowercase().strip_prefix("heading")?.parse().ok()
+        })
+        .or_else(|| {
+            let style = style?;
+            if style.name == Name::new(TITLE_STYLE) {
+                return Some(1);
+            }
+            style.name.get_heading_number()
+        })
+        .or_else(|| {
+            let outline = property
+                .outline_lvl
+                .as_ref()
+                .or(style.and_then(|style| style.paragraph_property.outline_lvl.as_ref()))?;
+            // outline level 9 is body text
+            Some(outline.v + 1)
+        })?;
+    (1..=9).contains(&level).then_some(level)
+}
+
+//list type from the numbering definition: numbered, bulleted or no list at all (format "none")
+fn list_numbered(docx: &Docx, num_id: usize, level: usize) -> Option<bool> {
+    let numberings = &docx.numberings;
+    let numbering = numberings.numberings.iter().find(|n| n.id == num_id)?;
+    let definition = numbering
+        .level_overrides
+        .iter()
+        .find(|level_override| level_override.level == level)
+        .and_then(|level_override| level_override.override_level.as_ref())
+        .or_else(|| {
+            numberings
+                .abstract_nums
+                .iter()
+                .find(|abstract_num| abstract_num.id == numbering.abstract_num_id)?
+                .levels
+                .iter()
+                .find(|l| l.level == level)
+        })?;
+    match definition.format.val.as_str() {
+        "none" => None,
+        "bullet" => Some(false),
+        _ => Some(true),
+    }
+}
+
+//target of a hyperlink, external links are resolved through the document relationships
+fn hyperlink_url(docx: &Docx, hyperlink: &Hyperlink) -> String {
+    match &hyperlink.link {
+        HyperlinkData::External { rid, path } if path.is_empty() => docx
+            .hyperlinks
+            .iter()
+            .find(|(id, _, _)| id == rid)
+            .map(|(_, url, _)| url.clone())
+            .unwrap_or_default(),
+        HyperlinkData::External { path, .. } => path.clone(),
+        HyperlinkData::Anchor { anchor } => format!("#{anchor}"),
+    }
+}
+
+//consecutive runs with the same formatting are merged into one text element
+fn push_run(elements: &mut Vec<Element>, text: String, style: TextStyle, size: u8) {
+    if text.is_empty() {
+        return;
+    }
+    match elements.last_mut() {
+        Some(Element::Text {
+            text: last_text,
+            style: last_style,
+            ..
+        }) if *last_style == style => last_text.push_str(&text),
+        _ => elements.push(Element::Text { text, size, style }),
+    }
+}
+
+// A paragraph with uniform formatting becomes a single text element (or hyperlink),
+// mixed formatting is kept as a paragraph of text runs and hyperlinks
+fn paragraph_element(docx: &Docx, doc_element: &Paragraph, size: u8) -> Element {
+    let mut elements = vec![];
+    let mut field = None;
+    for c in &doc_element.children {
+        match c {
+            docx_rs::ParagraphChild::Run(run) => push_run(
+                &mut elements,
+                run_text(run, &mut field),
+                run_style(run),
+                size,
+            ),
+            docx_rs::ParagraphChild::Hyperlink(hyperlink) => {
+                let title = runs_text(paragraph_runs(&hyperlink.children));
+                if !title.is_empty() {
+                    elements.push(Element::Hyperlink {
+                        title,
+                        url: hyperlink_url(docx, hyperlink),
+                        alt: String::new(),
+                        size,
+                    });
+                }
+            }
+            // left in the paragraph only when revisions are annotated
+            docx_rs::ParagraphChild::Insert(insert) => elements.extend(revision_element(
+                RevisionKind::Insertion,
+                &insert.author,
+                &insert.date,
+                insert_runs(insert).collect(),
+                size,
+            )),
+            docx_rs::ParagraphChild::Delete(delete) => {
+                let runs = delete.children.iter().filter_map(|child| match child {
+                    DeleteChild::Run(run) => Some(run.as_ref()),
+                    _ => None,
+                });
+                elements.extend(revision_element(
+                    RevisionKind::Deletion,
+                    &delete.author,
+                    &delete.date,
+                    runs.collect(),
+                    size,
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    match elements.len() {
+        0 => Element::Text {
+            text: String::new(),
+            size,
+            style: TextStyle::default(),
+        },
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+//inline pictures of a paragraph, the image data comes from the media part
+fn paragraph_images(docx: &Docx, doc_element: &Paragraph) -> Vec<Element> {
+    let mut images = vec![];
+    for run in paragraph_runs(&doc_element.children) {
+        for child in &run.children {
+            if let RunChild::Drawing(drawing) = child {
+                if let Some(DrawingData::Pic(pic)) = &drawing.data {
+                    images.extend(drawing_image(docx, pic));
+                }
+            }
+        }
+    }
+    images
+}
+
+fn drawing_image(docx: &Docx, pic: &Pic) -> Option<Element> {
+    let Some((_, path, image, preview)) = docx.images.iter().find(|(id, ..)| *id == pic.id) else {
+        warn!("Image {} not found in the media part", pic.id);
+        return None;
+    };
+    let path = Path::new(path);
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let (bytes, image_type) = match extension.as_str() {
+        "jpg" => (&image.0, "jpeg".to_string()),
+        extension if ImageType::from_str(extension).is_ok() => (&image.0, extension.to_string()),
+        // other formats (bmp, tiff, ...) use the PNG preview decoded by docx-rs
+        _ if !preview.0.is_empty() => (&preview.0, "png".to_string()),
+        _ => {
+            warn!("Unsupported image format: {}", path.display());
+            return None;
+        }
+    };
+    let pixels = |emu: u32| Some((emu / EMU_PER_PIXEL).to_string());
+    Some(Element::Image(ImageData::new(
+        Bytes::from(bytes.clone()),
+        title,
+        String::new(),
+        image_type,
+        String::new(),
+        ImageDimension {
+            width: pixels(pic.size.0),
+            height: pixels(pic.size.1),
+        },
+    )))
+}
+
+//text of a paragraph followed by its images, an empty paragraph with images keeps only the images
+fn paragraph_elements(docx: &Docx, doc_element: &Paragraph, size: u8) -> Vec<Element> {
+    let element = paragraph_element(docx, doc_element, size);
+    let images = paragraph_images(docx, doc_element);
+    let mut elements = vec![];
+    if images.is_empty() || !matches!(&element, Element::Text { text, .. } if text.is_empty()) {
+        elements.push(element);
+    }
+    elements.extend(images);
+    elements
+}
+
+//rows of a table, vertically merged cells become row spans
+fn table_element(docx: &Docx, table: &docx_rs::Table) -> anyhow::Result<Element> {
+    let mut rows: Vec<TableRow> = vec![];
+    // vertically merged cells still open, by grid column: (row, cell) index
+    let mut merged_cells: HashMap<usize, (usize, usize)> = HashMap::new();
+    for row in &table.rows {
+        let docx_rs::TableChild::TableRow(tr) = row;
+        let mut cells = TableRow { cells: vec![] };
+        let mut column = 0;
+
+        for table_cell in &tr.cells {
+            let TableRowChild::TableCell(tc) = table_cell;
+            let (colspan, vertical_merge) = cell_merge(tc)?;
+            if vertical_merge == Some(VMergeType::Continue) {
+                if let Some(&(row_index, cell_index)) = merged_cells.get(&column) {
+                    rows[row_index].cells[cell_index].rowspan += 1;
+                }
+                column += colspan;
+                continue;
+            }
+            if vertical_merge == Some(VMergeType::Restart) {
+                merged_cells.insert(column, (rows.len(), cells.cells.len()));
+            } else {
+                merged_cells.remove(&column);
+            }
+
+            let mut elements = vec![];
+            for ch in &tc.children {
+                if let docx_rs::TableCellContent::Paragraph(par) = ch {
+                    elements.extend(paragraph_elements(docx, par, 16));
+                }
+            }
+            cells
+                .cells
+                .push(TableCell::with_elements(elements).span(colspan, 1));
+            column += colspan;
+        }
+        rows.push(cells);
+    }
+
+    Ok(Element::Table {
+        name: None,
+        headers: vec![],
+        rows,
+    })
+}
+
+//elements of a header or footer paragraph, empty paragraphs are left out
+fn band_paragraph_elements(docx: &Docx, doc_element: &Paragraph) -> Vec<Element> {
+    paragraph_elements(docx, doc_element, 16)
+        .into_iter()
+        .filter(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()))
+        .collect()
+}
+
+fn header_elements(docx: &Docx, header: &Header) -> anyhow::Result<Vec<Element>> {
+    let mut elements = vec![];
+    for child in &header.children {
+        match child {
+            HeaderChild::Paragraph(par) => elements.extend(band_paragraph_elements(docx, par)),
+            HeaderChild::Table(table) => elements.push(table_element(docx, table)?),
+            HeaderChild::StructuredDataTag(_) => {}
+        }
+    }
+    Ok(elements)
+}
+
+fn footer_elements(docx: &Docx, footer: &Footer) -> anyhow::Result<Vec<Element>> {
+    let mut elements = vec![];
+    for child in &footer.children {
+        match child {
+            FooterChild::Paragraph(par) => elements.extend(band_paragraph_elements(docx, par)),
+            FooterChild::Table(table) => elements.push(table_element(docx, table)?),
+            FooterChild::StructuredDataTag(_) => {}
+        }
+    }
+    Ok(elements)
+}
+
+//docx-rs keeps deleted text private, it is taken from the w:delText element it builds
+fn deleted_text(text: &DeleteText) -> String {
+    let xml = String::from_utf8_lossy(&text.build()).to_string();
+    xml.split_once('>')
+        .and_then(|(_, rest)| rest.rsplit_once("</"))
+        .map(|(text, _)| text.to_string())
+        .unwrap_or_default()
+}
+
+//a deleted run restored as a regular run of text
+fn restored_run(mut run: Run) -> Run {
+    for child in &mut run.children {
+        if let RunChild::DeleteText(text) = child {
+            *child = RunChild::Text(Text {
+                text: deleted_text(text),
+                preserve_space: true,
+            });
+        }
+    }
+    run
+}
+
+// Accepts or rejects the tracked changes of a paragraph, the comment marks inside them are kept.
+// Returns false for a paragraph that only held removed changes.
+fn resolve_paragraph_revisions(paragraph: &mut Paragraph, accept: bool) -> bool {
+    let mut removed = false;
+    let mut kept = false;
+    let mut children = vec![];
+    for child in std::mem::take(&mut paragraph.children) {
+        match child {
+            ParagraphChild::Insert(insert) => {
+                for child in insert.children {
+                    match child {
+                        InsertChild::Run(run) if accept => {
+                            kept = true;
+                            children.push(ParagraphChild::Run(run));
+                        }
+                        InsertChild::CommentStart(start) => {
+                            children.push(ParagraphChild::CommentStart(start))
+                        }
+                        InsertChild::CommentEnd(end) => {
+                            children.push(ParagraphChild::CommentEnd(end))
                         }
+                        _ => removed = true,
                     }
-                    _ => {}
                 }
             }
+            ParagraphChild::Delete(delete) => {
+                for child in delete.children {
+                    match child {
+                        DeleteChild::Run(run) if !accept => {
+                            kept = true;
+                            children.push(ParagraphChild::Run(Box::new(restored_run(*run))));
+                        }
+                        DeleteChild::CommentStart(start) => {
+                            children.push(ParagraphChild::CommentStart(start))
+                        }
+                        DeleteChild::CommentEnd(end) => {
+                            children.push(ParagraphChild::CommentEnd(end))
+                        }
+                        DeleteChild::Run(_) => removed = true,
+                    }
+                }
+            }
+            ParagraphChild::CommentStart(_) | ParagraphChild::CommentEnd(_) => children.push(child),
+            child => {
+                kept = true;
+                children.push(child);
+            }
+        }
+    }
+    paragraph.children = children;
+    !removed || kept
+}
 
-            return result;
+fn resolve_table_revisions(table: &mut docx_rs::Table, accept: bool) {
+    for docx_rs::TableChild::TableRow(row) in &mut table.rows {
+        for TableRowChild::TableCell(cell) in &mut row.cells {
+            cell.children.retain_mut(|child| match child {
+                docx_rs::TableCellContent::Paragraph(paragraph) => {
+                    resolve_paragraph_revisions(paragraph, accept)
+                }
+                docx_rs::TableCellContent::Table(table) => {
+                    resolve_table_revisions(table, accept);
+                    true
+                }
+                _ => true,
+            });
         }
+    }
+}
 
-        let docx = read_docx(document)?;
-        const HEADING1: &str = "Heading1";
-        const HEADING2: &str = "Heading2";
-        const NORMAL: &str = "Normal";
-        const BODY_TEXT: &str = "BodyText";
-        let mut result: Vec<Element> = vec![];
+//the document as it reads with all tracked changes accepted or rejected
+fn resolve_revisions(docx: &mut Docx, accept: bool) {
+    docx.document.children.retain_mut(|child| match child {
+        DocumentChild::Paragraph(paragraph) => resolve_paragraph_revisions(paragraph, accept),
+        DocumentChild::Table(table) => {
+            resolve_table_revisions(table, accept);
+            true
+        }
+        _ => true,
+    });
+    let section = &mut docx.document.section_property;
+    let headers = section
+        .header
+        .iter_mut()
+        .chain(section.first_header.iter_mut());
+    for (_, header) in headers {
+        header.children.retain_mut(|child| match child {
+            HeaderChild::Paragraph(paragraph) => resolve_paragraph_revisions(paragraph, accept),
+            HeaderChild::Table(table) => {
+                resolve_table_revisions(table, accept);
+                true
+            }
+            HeaderChild::StructuredDataTag(_) => true,
+        });
+    }
+    let footers = section
+        .footer
+        .iter_mut()
+        .chain(section.first_footer.iter_mut());
+    for (_, footer) in footers {
+        footer.children.retain_mut(|child| match child {
+            FooterChild::Paragraph(paragraph) => resolve_paragraph_revisions(paragraph, accept),
+            FooterChild::Table(table) => {
+                resolve_table_revisions(table, accept);
+                true
+            }
+            FooterChild::StructuredDataTag(_) => true,
+        });
+    }
+}
 
-        let mut is_list_numbered = false;
+// docx-rs reads a missing author or date of a tracked change or comment as these
+const DEFAULT_AUTHOR: &str = "unnamed";
+const DEFAULT_DATE: &str = "1970-01-01T00:00:00Z";
 
-        let mut current_list: Option<(usize, Vec<ListItem>)> = None;
+//author or date of a tracked change or comment, None when left empty or out
+fn review_attribute(value: &str, default: &str) -> Option<String> {
+    (!value.is_empty() && value != default).then(|| value.to_string())
+}
 
-        for ch in docx.document.children {
-            if let docx_rs::DocumentChild::Paragraph(par) = ch {
-                if let Some(numbering_property) = &par.property.numbering_property {
-                    let num_id = numbering_property
-                        .id
-                        .as_ref()
-                        .expect("No number id in list item")
-                        .id;
-                    if num_id == 3 || num_id == 2 {
-                        let list_text = extract_text(&par);
-
-                        let list_item = ListItem {
-                            element: Element::Text {
-                                text: list_text,
-                                size: 12,
-                            },
-                        };
-
-                        let numbered = numbering_property
-                            .id
-                            .as_ref()
-                            .expect("No number id in list item")
-                            .id
-                            == 3;
-                        let level = numbering_property
-                            .level
-                            .as_ref()
-                            .expect("Expect indent level to be Some")
-                            .val;
-                        if let Some((last_level, ref mut list_items)) = current_list {
-                            if level > last_level {
-                                let nested_list = Element::List {
-                                    elements: vec![list_item],
-                                    numbered,
-                                };
-                                list_items.push(ListItem {
-                                    element: nested_list,
-                                });
-                            } else if level < last_level {
-                                // Finish the current list and start a new one
-                                result.push(Element::List {
-                                    elements: list_items.clone(),
-                                    numbered,
-                                });
-                                current_list = Some((level, vec![list_item]));
-                            } else {
-                                list_items.push(list_item);
-                            }
-                        } else {
-                            current_list = Some((level, vec![list_item]));
-                            is_list_numbered = numbered;
+//drops the dates docx-rs writes for tracked changes and comments without one, w:date is optional
+fn without_default_dates(xml: &[u8]) -> Vec<u8> {
+    String::from_utf8_lossy(xml)
+        .replace(&format!(" w:date=\"{DEFAULT_DATE}\""), "")
+        .into_bytes()
+}
+
+//a tracked change kept as an annotated element, None when it holds no text
+fn revision_element(
+    kind: RevisionKind,
+    author: &str,
+    date: &str,
+    runs: Vec<&Run>,
+    size: u8,
+) -> Option<Element> {
+    let mut elements = vec![];
+    let mut field = None;
+    for run in runs {
+        push_run(
+            &mut elements,
+            run_text(run, &mut field),
+            run_style(run),
+            size,
+        );
+    }
+    (!elements.is_empty()).then(|| Element::Revision {
+        kind,
+        author: review_attribute(author, DEFAULT_AUTHOR),
+        date: review_attribute(date, DEFAULT_DATE),
+        elements,
+    })
+}
+
+// comment whose anchored text is still being read
+struct OpenComment {
+    id: usize,
+    anchor: String,
+}
+
+//comment marks and runs of a paragraph in reading order
+enum CommentMark<'a> {
+    Start(usize),
+    End(usize),
+    Run(&'a Run),
+}
+
+fn comment_end_id(end: &CommentRangeEnd) -> Option<usize> {
+    let xml = String::from_utf8_lossy(&end.build()).to_string();
+    xml_elements(&xml, "w:commentRangeEnd", "w:id")
+        .ok()?
+        .first()
+        .and_then(|(_, id)| id.parse().ok())
+}
+
+fn comment_marks(children: &[ParagraphChild]) -> Vec<CommentMark<'_>> {
+    let mut marks = vec![];
+    for child in children {
+        match child {
+            ParagraphChild::Run(run) => marks.push(CommentMark::Run(run)),
+            ParagraphChild::Hyperlink(hyperlink) => {
+                marks.extend(comment_marks(&hyperlink.children))
+            }
+            ParagraphChild::CommentStart(start) => marks.push(CommentMark::Start(start.id)),
+            ParagraphChild::CommentEnd(end) => {
+                marks.extend(comment_end_id(end).map(CommentMark::End))
+            }
+            ParagraphChild::Insert(insert) => {
+                for child in &insert.children {
+                    match child {
+                        InsertChild::Run(run) => marks.push(CommentMark::Run(run)),
+                        InsertChild::CommentStart(start) => {
+                            marks.push(CommentMark::Start(start.id))
                         }
-                    } else {
-                        if let Some((_, list_items)) = current_list.take() {
-                            result.push(Element::List {
-                                elements: list_items,
-                                numbered: is_list_numbered,
-                            });
+                        InsertChild::CommentEnd(end) => {
+                            marks.extend(comment_end_id(end).map(CommentMark::End))
+                        }
+                        InsertChild::Delete(_) => {}
+                    }
+                }
+            }
+            ParagraphChild::Delete(delete) => {
+                for child in &delete.children {
+                    match child {
+                        DeleteChild::Run(run) => marks.push(CommentMark::Run(run)),
+                        DeleteChild::CommentStart(start) => {
+                            marks.push(CommentMark::Start(start.id))
+                        }
+                        DeleteChild::CommentEnd(end) => {
+                            marks.extend(comment_end_id(end).map(CommentMark::End))
                         }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    marks
+}
 
-                        match &par.property.style {
-                            Some(ParagraphStyle { val }) => match val.as_str() {
-                                HEADING1 => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Header { level: 1, text };
+//a comment of the comments part with the text it is anchored to
+fn comment_element(docx: &Docx, id: usize, anchor: &str) -> Option<Element> {
+    let comment = docx
+        .comments
+        .inner()
+        .iter()
+        .find(|comment| comment.id == id)?;
+    let text = comment
+        .children
+        .iter()
+        .filter_map(|child| match child {
+            CommentChild::Paragraph(paragraph) => {
+                Some(runs_text(paragraph_runs(&paragraph.children)))
+            }
+            CommentChild::Table(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(Element::Comment {
+        author: review_attribute(&comment.author, DEFAULT_AUTHOR),
+        date: review_attribute(&comment.date, DEFAULT_DATE),
+        text,
+        anchor: anchor.trim().to_string(),
+    })
+}
 
-                                    result.push(element);
-                                }
-                                HEADING2 => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Header { level: 2, text };
+//comments whose range ends in the paragraph, comments spanning paragraphs stay open
+fn paragraph_comments(
+    docx: &Docx,
+    paragraph: &Paragraph,
+    open: &mut Vec<OpenComment>,
+) -> Vec<Element> {
+    let mut comments = vec![];
+    let mut field = None;
+    for mark in comment_marks(&paragraph.children) {
+        match mark {
+            CommentMark::Start(id) => open.push(OpenComment {
+                id,
+                anchor: String::new(),
+            }),
+            CommentMark::End(id) => {
+                if let Some(index) = open.iter().position(|comment| comment.id == id) {
+                    let comment = open.remove(index);
+                    comments.extend(comment_element(docx, id, &comment.anchor));
+                }
+            }
+            CommentMark::Run(run) => {
+                let text = run_text(run, &mut field);
+                for comment in open.iter_mut() {
+                    comment.anchor.push_str(&text);
+                }
+            }
+        }
+    }
+    for comment in open.iter_mut() {
+        comment.anchor.push('\n');
+    }
+    comments
+}
 
-                                    result.push(element);
-                                }
+fn table_comments(
+    docx: &Docx,
+    table: &docx_rs::Table,
+    open: &mut Vec<OpenComment>,
+) -> Vec<Element> {
+    let mut comments = vec![];
+    for docx_rs::TableChild::TableRow(row) in &table.rows {
+        for TableRowChild::TableCell(cell) in &row.cells {
+            for child in &cell.children {
+                match child {
+                    docx_rs::TableCellContent::Paragraph(paragraph) => {
+                        comments.extend(paragraph_comments(docx, paragraph, open))
+                    }
+                    docx_rs::TableCellContent::Table(table) => {
+                        comments.extend(table_comments(docx, table, open))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    comments
+}
 
-                                BODY_TEXT => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+//pushes the finished list, followed by the comments anchored in it
+fn push_list(
+    result: &mut Vec<Element>,
+    list: Option<(usize, Vec<ListItem>)>,
+    numbered: bool,
+    comments: &mut Vec<Element>,
+) {
+    if let Some((_, list_items)) = list {
+        result.push(Element::List {
+            elements: list_items,
+            numbered,
+        });
+    }
+    result.append(comments);
+}
+
+/// How the tracked changes (insertions and deletions) of a reviewed document are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RevisionMode {
+    /// The document as it reads with all changes accepted
+    #[default]
+    AcceptAll,
+    /// The document as it read before the changes
+    RejectAll,
+    /// Changes are kept as `Element::Revision` elements inside their paragraphs
+    Annotate,
+}
 
-                                    result.push(element);
-                                }
+/// Options of DOCX parsing.
+///
+/// By default tracked changes are accepted and comments are left out.
+#[derive(Debug, Clone, Default)]
+pub struct DocxParseOptions {
+    comments: bool,
+    revisions: RevisionMode,
+}
 
-                                NORMAL => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+impl DocxParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-                                    result.push(element);
-                                }
+    /// Adds the review comments as `Element::Comment` after the paragraph, table or list they end in
+    pub fn with_comments(mut self, comments: bool) -> Self {
+        self.comments = comments;
+        self
+    }
 
-                                _ => {}
-                            },
-                            _ => {
-                                // TODO: Implement other styles
-                            }
+    pub fn with_revisions(mut self, revisions: RevisionMode) -> Self {
+        self.revisions = revisions;
+        self
+    }
+}
+
+/// Options of DOCX generation.
+#[derive(Debug, Clone, Default)]
+pub struct DocxOptions {
+    reference: Option<Bytes>,
+}
+
+impl DocxOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reuses the styles, numbering, theme, fonts and page headers and footers of a reference DOCX,
+    /// the body is generated from the document. Text sizes are left to the reference styles and
+    /// page headers and footers of the document take precedence over those of the reference.
+    pub fn with_reference(mut self, reference: Bytes) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+}
+
+impl Transformer {
+    /// Parses the DOCX, tracked changes and comments are handled as set in the options
+    pub fn parse_with_options(
+        document: &Bytes,
+        options: &DocxParseOptions,
+    ) -> anyhow::Result<Document> {
+        fn extract_text(doc_element: &Paragraph) -> String {
+            runs_text(paragraph_runs(&doc_element.children))
+        }
+
+        let mut docx = read_docx(document)?;
+        match options.revisions {
+            RevisionMode::AcceptAll => resolve_revisions(&mut docx, true),
+            RevisionMode::RejectAll => resolve_revisions(&mut docx, false),
+            RevisionMode::Annotate => {}
+        }
+        let mut result: Vec<Element> = vec![];
+        // comments still open, and the ones that follow the list being read
+        let mut open_comments = vec![];
+        let mut list_comments = vec![];
+
+        let mut is_list_numbered = false;
+
+        let mut current_list: Option<(usize, Vec<ListItem>)> = None;
+
+        for ch in &docx.document.children {
+            let comments = match ch {
+                DocumentChild::Paragraph(par) if options.comments => {
+                    paragraph_comments(&docx, par, &mut open_comments)
+                }
+                DocumentChild::Table(table) if options.comments => {
+                    table_comments(&docx, table, &mut open_comments)
+                }
+                _ => vec![],
+            };
+            if let docx_rs::DocumentChild::Paragraph(par) = ch {
+                let page_break = has_page_break(par);
+                if page_break || is_thematic_break(par) {
+                    push_list(
+                        &mut result,
+                        current_list.take(),
+                        is_list_numbered,
+                        &mut list_comments,
+                    );
+                    if !page_break {
+                        result.push(Element::ThematicBreak);
+                        result.extend(comments);
+                        continue;
+                    }
+                    result.push(Element::PageBreak);
+                    if !has_text(par) {
+                        result.extend(comments);
+                        continue;
+                    }
+                }
+                let heading = heading_level(&docx, &par.property);
+                // headings may be numbered, they are never list items
+                let list = match (&par.property.numbering_property, heading) {
+                    (Some(numbering_property), None) => {
+                        let level = numbering_property
+                            .level
+                            .as_ref()
+                            .map_or(0, |level| level.val);
+                        numbering_property
+                            .id
+                            .as_ref()
+                            .and_then(|id| list_numbered(&docx, id.id, level))
+                            .map(|numbered| (level, numbered))
+                    }
+                    _ => None,
+                };
+                if let Some((level, numbered)) = list {
+                    let list_item = ListItem::new(paragraph_element(&docx, par, 12));
+
+                    // a list of the other type at the same level starts a new list
+                    if current_list.as_ref().is_some_and(|(last_level, _)| {
+                        *last_level == level && numbered != is_list_numbered
+                    }) {
+                        push_list(
+                            &mut result,
+                            current_list.take(),
+                            is_list_numbered,
+                            &mut list_comments,
+                        );
+                    }
+                    if let Some((last_level, ref mut list_items)) = current_list {
+                        if level > last_level {
+                            let nested_list = Element::List {
+                                elements: vec![list_item],
+                                numbered,
+                            };
+                            list_items.push(ListItem::new(nested_list));
+                        } else if level < last_level {
+                            // Finish the current list and start a new one
+                            result.push(Element::List {
+                                elements: list_items.clone(),
+                                numbered,
+                            });
+                            current_list = Some((level, vec![list_item]));
+                        } else {
+                            list_items.push(list_item);
                         }
+                    } else {
+                        current_list = Some((level, vec![list_item]));
+                        is_list_numbered = numbered;
                     }
-                } else {
-                    if let Some((_, list_items)) = current_list.take() {
-                        result.push(Element::List {
-                            elements: list_items,
-                            numbered: is_list_numbered,
-                        });
+                    if let Some((_, list_items)) = &mut current_list {
+                        list_items
+                            .extend(paragraph_images(&docx, par).into_iter().map(ListItem::new));
                     }
-                    match &par.property.style {
-                        Some(ParagraphStyle { val }) => match val.as_str() {
-                            HEADING1 => {
-                                let text = extract_text(&par);
-                                let element = Element::Header { level: 1, text };
-
-                                result.push(element);
-                            }
-                            HEADING2 => {
-                                let text = extract_text(&par);
-                                let element = Element::Header { level: 2, text };
-
-                                result.push(element);
-                            }
-
-                            BODY_TEXT => {
-                                let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
-
-                                result.push(element);
-                            }
-
-                            NORMAL => {
-                                let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
+                    list_comments.extend(comments);
+                } else {
+                    push_list(
+                        &mut result,
+                        current_list.take(),
+                        is_list_numbered,
+                        &mut list_comments,
+                    );
+                    let style = par.property.style.as_ref().map(|style| style.val.as_str());
+                    match (heading, style) {
+                        (Some(level), _) => result.push(Element::Header {
+                            level: level as u8,
+                            text: extract_text(par),
+                        }),
 
-                                result.push(element);
-                            }
+                        (None, Some(CODE_STYLE)) => result.push(extract_code_block(par)),
 
-                            _ => {}
-                        },
-                        _ => {
-                            // TODO: Implement other styles
+                        (None, Some(QUOTE_STYLE | INTENSE_QUOTE_STYLE)) => {
+                            push_quote(&mut result, paragraph_element(&docx, par, 16))
                         }
+
+                        // Normal, BodyText, other styles and paragraphs without a style
+                        _ => result.extend(paragraph_elements(&docx, par, 16)),
                     }
+                    result.extend(comments);
                 }
             } else {
-                if let Some((_, list_items)) = current_list.take() {
-                    result.push(Element::List {
-                        elements: list_items,
-                        numbered: is_list_numbered,
-                    });
-                }
-                match ch {
-                    docx_rs::DocumentChild::Table(table) => {
-                        let mut rows = vec![];
-                        for row in &table.rows {
-                            let docx_rs::TableChild::TableRow(tr) = row;
-                            let mut cells = TableRow { cells: vec![] };
-
-                            for table_cell in &tr.cells {
-                                let TableRowChild::TableCell(tc) = table_cell;
-                                for ch in &tc.children {
-                                    match ch {
-                                        docx_rs::TableCellContent::Paragraph(par) => {
-                                            let text = extract_text(par);
-                                            cells.cells.push(TableCell {
-                                                element: Element::Text { text, size: 16 },
-                                            });
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            rows.push(cells);
-                        }
-
-                        result.push(Element::Table {
-                            headers: vec![],
-                            rows,
-                        });
-                    }
-                    _ => {}
+                push_list(
+                    &mut result,
+                    current_list.take(),
+                    is_list_numbered,
+                    &mut list_comments,
+                );
+                if let docx_rs::DocumentChild::Table(table) = ch {
+                    result.push(table_element(&docx, table)?);
                 }
+                result.extend(comments);
             }
         }
 
-        if let Some((_, list_items)) = current_list.take() {
-            result.push(Element::List {
-                elements: list_items,
-                numbered: is_list_numbered,
-            });
+        push_list(
+            &mut result,
+            current_list.take(),
+            is_list_numbered,
+            &mut list_comments,
+        );
+
+        let mut document_result = Document::new(result);
+        let section = &docx.document.section_property;
+        if let Some((_, header)) = section.header.as_ref().or(section.first_header.as_ref()) {
+            let elements = header_elements(&docx, header)?;
+            if !elements.is_empty() {
+                document_result.set_page_header(elements);
+            }
+        }
+        if let Some((_, footer)) = section.footer.as_ref().or(section.first_footer.as_ref()) {
+            let elements = footer_elements(&docx, footer)?;
+            if !elements.is_empty() {
+                document_result.set_page_footer(elements);
+            }
         }
 
-        Ok(Document::new(result))
+        document_result.set_page_dimensions(page_dimensions(&docx.document.section_property)?);
+        document_result.set_metadata(read_core_properties(document)?);
+        Ok(document_result)
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let mut doc = Docx::new();
+    pub fn generate_with_options(
+        document: &Document,
+        options: &DocxOptions,
+    ) -> anyhow::Result<Bytes> {
+        let page = document.page_dimensions();
+        let mut doc = Docx::new()
+            .page_size(
+                mm_to_twips(page.page_width) as u32,
+                mm_to_twips(page.page_height) as u32,
+            )
+            .page_margin(
+                PageMargin::new()
+                    .top(mm_to_twips(page.page_margin_top))
+                    .bottom(mm_to_twips(page.page_margin_bottom))
+                    .left(mm_to_twips(page.page_margin_left))
+                    .right(mm_to_twips(page.page_margin_right)),
+            );
+        if document.orientation == PageOrientation::Landscape {
+            doc = doc.page_orient(PageOrientationType::Landscape);
+        }
 
         // region:    ---abstract_numbering
         let mut abstract_numbering = AbstractNumbering::new(2);
@@ -381,45 +1581,112 @@ impl TransformerTrait for Transformer {
 
         doc = doc
             .add_abstract_numbering(abstract_numbering)
-            .add_numbering(Numbering::new(2, 2));
+            .add_numbering(Numbering::new(2, 2))
+            .add_style(
+                Style::new(CODE_STYLE, StyleType::Paragraph)
+                    .name("Source Code")
+                    .fonts(monospace_fonts()),
+            )
+            .add_style(
+                Style::new(QUOTE_STYLE, StyleType::Paragraph)
+                    .name("Quote")
+                    .italic()
+                    .indent(Some(720), None, Some(720), None),
+            );
+        // headers use the built-in heading styles, so that a reference document can restyle them
+        for level in 1..=9 {
+            let size = match level {
+                1 => 18,
+                2 => 16,
+                _ => 14,
+            };
+            doc = doc.add_style(
+                Style::new(format!("Heading{level}"), StyleType::Paragraph)
+                    .name(format!("heading {level}"))
+                    .size(size * 2)
+                    .outline_lvl(level - 1),
+            );
+        }
+
+        let page_header = document.get_page_header();
+        if !page_header.is_empty() {
+            let header = page_band_paragraphs(&page_header)
+                .into_iter()
+                .fold(Header::new(), Header::add_paragraph);
+            doc = doc.header(header);
+        }
+        let page_footer = document.get_page_footer();
+        if !page_footer.is_empty() {
+            let footer = page_band_paragraphs(&page_footer)
+                .into_iter()
+                .fold(Footer::new(), Footer::add_paragraph);
+            doc = doc.footer(footer);
+        }
 
-        // TODO: Consider to refactor this code to use the new #Band Enum (header, footer, etc)
-        for element in &document.get_all_elements() {
+        // page header and footer bands are written to their own parts
+        let body_elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(Band::elements);
+        let mut comments = 0;
+        for element in body_elements {
             match element {
                 Element::Header { level, text } => {
-                    let size = match level {
-                        1 => 18,
-                        2 => 16,
-                        _ => 14,
-                    };
+                    let level = (*level).clamp(1, 9);
                     doc = doc.add_paragraph(
-                        Paragraph::new().add_run(Run::new().add_text(text).size(size * 2)),
+                        Paragraph::new()
+                            .style(&format!("Heading{level}"))
+                            .add_run(Run::new().add_text(text)),
                     );
                 }
 
-                Element::Text { text, size } => {
-                    doc = doc.add_paragraph(
-                        Paragraph::new()
-                            .add_run(Run::new().add_text(text).size(*size as usize * 2)),
-                    )
+                Element::Text { text, size, style } => {
+                    doc =
+                        doc.add_paragraph(Paragraph::new().add_run(styled_run(text, *size, style)))
                 }
 
                 Element::Paragraph { elements } => {
-                    for paragraph_element in elements {
-                        match paragraph_element {
-                            Element::Text { text, size } => {
-                                doc =
-                                    doc.add_paragraph(Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ));
-                            }
-                            _ => {
-                                error!("Unknown paragraph element");
-                            }
-                        }
+                    doc = doc.add_paragraph(add_paragraph_children(Paragraph::new(), elements));
+                }
+
+                Element::CodeBlock { code, .. } => {
+                    doc = doc.add_paragraph(code_block_paragraph(code));
+                }
+
+                Element::Blockquote { elements } => {
+                    for paragraph in quote_paragraphs(elements) {
+                        doc = doc.add_paragraph(paragraph);
                     }
                 }
 
+                Element::ThematicBreak => {
+                    doc = doc.add_paragraph(thematic_break_paragraph());
+                }
+
+                Element::PageBreak => {
+                    doc = doc.add_paragraph(
+                        Paragraph::new().add_run(Run::new().add_break(BreakType::Page)),
+                    );
+                }
+
+                Element::Revision { .. } | Element::Footnote { .. } => {
+                    doc = doc.add_paragraph(add_paragraph_children(
+                        Paragraph::new(),
+                        std::slice::from_ref(element),
+                    ));
+                }
+
+                Element::Comment {
+                    author,
+                    date,
+                    text,
+                    anchor,
+                } => {
+                    comments += 1;
+                    doc = add_comment(doc, comments, author, date, text, anchor);
+                }
+
                 Element::List { elements, numbered } => {
                     for list_item in elements {
                         detect_element_in_list(&mut doc, &list_item.element, *numbered, 0);
@@ -442,20 +1709,21 @@ impl TransformerTrait for Transformer {
                 }
 
                 Element::Image(image) => {
-                    let mut pic = Pic::new(&image.bytes());
-
-                    match &image.size() {
-                        &ImageDimension {
-                            width: Some(width),
-                            height: Some(height),
-                        } => {
-                            let width = width.parse().unwrap_or(0);
-                            let height = height.parse().unwrap_or(0);
-                            if width > 0 && height > 0 {
-                                pic = pic.size(width, height);
-                            }
+                    let mut pic = Pic::new(image.bytes());
+
+                    if let &ImageDimension {
+                        width: Some(width),
+                        height: Some(height),
+                    } = &image.size()
+                    {
+                        let width: u32 = width.parse().unwrap_or(0);
+                        let height: u32 = height.parse().unwrap_or(0);
+                        if width > 0 && height > 0 {
+                            pic = pic.size(
+                                width.saturating_mul(EMU_PER_PIXEL),
+                                height.saturating_mul(EMU_PER_PIXEL),
+                            );
                         }
-                        _ => {}
                     }
 
                     pic = re_size_picture(pic);
@@ -465,40 +1733,58 @@ impl TransformerTrait for Transformer {
                     doc = doc.add_paragraph(paragraph);
                 }
 
-                Element::Table { headers, rows } => {
-                    let mut table_rows = Vec::new();
-
+                Element::Table { headers, rows, .. } => {
+                    let (header_columns, row_columns) = table_columns(headers, rows);
+                    let mut grid_rows: Vec<Vec<GridCell>> = vec![];
                     if !headers.is_empty() {
-                        let mut header_cell: Vec<docx_rs::TableCell> = Vec::new();
-                        for header in headers {
-                            if let Element::Text { text, size } = &header.element {
-                                let cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                header_cell.push(cell);
-                            }
-                        }
-                        let header_row = docx_rs::TableRow::new(header_cell);
-                        table_rows.push(header_row)
+                        grid_rows.push(
+                            headers
+                                .iter()
+                                .zip(header_columns)
+                                .map(|(h, column)| (column, h.colspan, h.rowspan, &h.elements[..]))
+                                .collect(),
+                        );
+                    }
+                    for (row, columns) in rows.iter().zip(row_columns) {
+                        grid_rows.push(
+                            row.cells
+                                .iter()
+                                .zip(columns)
+                                .map(|(c, column)| (column, c.colspan, c.rowspan, &c.elements[..]))
+                                .collect(),
+                        );
                     }
 
-                    for row in rows {
-                        let mut rows_cell = Vec::new();
-
-                        for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
-                                let table_cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                rows_cell.push(table_cell);
+                    let mut table_rows = Vec::new();
+                    // cells merged from the rows above: (column, colspan, rows left)
+                    let mut merged_cells: Vec<(usize, usize, usize)> = vec![];
+                    for grid_row in grid_rows {
+                        let mut row_cells: Vec<(usize, docx_rs::TableCell)> = merged_cells
+                            .iter()
+                            .map(|&(column, colspan, _)| (column, merged_table_cell(colspan)))
+                            .collect();
+                        merged_cells.retain_mut(|(_, _, rows_left)| {
+                            *rows_left -= 1;
+                            *rows_left > 0
+                        });
+                        for (column, colspan, rowspan, elements) in grid_row {
+                            let mut table_cell = docx_rs::TableCell::new();
+                            for paragraph in cell_paragraphs(elements) {
+                                table_cell = table_cell.add_paragraph(paragraph);
                             }
+                            if colspan > 1 {
+                                table_cell = table_cell.grid_span(colspan);
+                            }
+                            if rowspan > 1 {
+                                table_cell = table_cell.vertical_merge(VMergeType::Restart);
+                                merged_cells.push((column, colspan, rowspan - 1));
+                            }
+                            row_cells.push((column, table_cell));
                         }
-                        let table_row = docx_rs::TableRow::new(rows_cell);
-                        table_rows.push(table_row);
+                        row_cells.sort_by_key(|(column, _)| *column);
+                        table_rows.push(docx_rs::TableRow::new(
+                            row_cells.into_iter().map(|(_, cell)| cell).collect(),
+                        ));
                     }
                     let table = docx_rs::Table::new(table_rows);
                     doc = doc.add_table(table);
@@ -509,18 +1795,627 @@ impl TransformerTrait for Transformer {
         let buffer = Vec::new();
         let mut cursor = Cursor::new(buffer);
 
-        doc.build().pack(&mut cursor)?;
-        let buffer = cursor.into_inner();
+        let mut xml = doc.build();
+        xml.document = without_default_dates(&xml.document);
+        xml.comments = without_default_dates(&xml.comments);
+        xml.pack(&mut cursor)?;
+        // replaces the placeholder core properties written by docx-rs
+        let mut buffer = write_core_properties(&cursor.into_inner(), &document.metadata)?;
+        if let Some(reference) = &options.reference {
+            buffer = apply_reference(&buffer, reference)?;
+        }
 
         Ok(bytes::Bytes::from(buffer))
     }
 }
 
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_options(document, &DocxParseOptions::new())
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &DocxOptions::new())
+    }
+}
+
+const CORE_PROPERTIES: &str = "docProps/core.xml";
+
+// docx-rs neither reads nor exposes the core properties, so docProps/core.xml is handled here
+fn read_core_properties(document: &[u8]) -> anyhow::Result<DocumentMetadata> {
+    let mut metadata = DocumentMetadata::default();
+    let mut archive = ZipArchive::new(Cursor::new(document))?;
+    let mut xml = String::new();
+    match archive.by_name(CORE_PROPERTIES) {
+        Ok(mut file) => file.read_to_string(&mut xml)?,
+        Err(_) => return Ok(metadata),
+    };
+
+    let mut reader = Reader::from_str(&xml);
+    reader.trim_text(true);
+    let mut current = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) => {
+                current = String::from_utf8_lossy(tag.local_name().as_ref()).to_string();
+            }
+            Event::Text(text) => {
+                let value = text.unescape()?.to_string();
+                match current.as_str() {
+                    "title" => metadata.title = Some(value),
+                    "creator" => metadata.author = Some(value),
+                    "subject" => metadata.subject = Some(value),
+                    "description" => metadata.description = Some(value),
+                    "language" => metadata.language = Some(value),
+                    "created" => metadata.created = Some(value),
+                    "modified" => metadata.modified = Some(value),
+                    "keywords" => {
+                        metadata.keywords = value
+                            .split([',', ';'])
+                            .map(str::trim)
+                            .filter(|keyword| !keyword.is_empty())
+                            .map(str::to_string)
+                            .collect();
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(_) => current.clear(),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+fn write_core_properties(document: &[u8], metadata: &DocumentMetadata) -> anyhow::Result<Vec<u8>> {
+    use quick_xml::escape::escape;
+
+    let keywords = metadata.keywords.join(", ");
+    let fields = [
+        ("dc:title", metadata.title.as_deref()),
+        ("dc:subject", metadata.subject.as_deref()),
+        ("dc:creator", metadata.author.as_deref()),
+        (
+            "cp:keywords",
+            Some(keywords.as_str()).filter(|k| !k.is_empty()),
+        ),
+        ("dc:description", metadata.description.as_deref()),
+        ("dc:language", metadata.language.as_deref()),
+    ];
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <cp:coreProperties \
+         xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:dcmitype=\"http://purl.org/dc/dcmitype/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">",
+    );
+    for (tag, value) in fields {
+        if let Some(value) = value {
+            xml.push_str(&format!("<{tag}>{}</{tag}>", escape(value)));
+        }
+    }
+    let dates = [
+        ("dcterms:created", metadata.created.as_deref()),
+        ("dcterms:modified", metadata.modified.as_deref()),
+    ];
+    for (tag, value) in dates {
+        if let Some(value) = value {
+            xml.push_str(&format!(
+                "<{tag} xsi:type=\"dcterms:W3CDTF\">{}</{tag}>",
+                escape(value)
+            ));
+        }
+    }
+    xml.push_str("</cp:coreProperties>");
+
+    let mut archive = ZipArchive::new(Cursor::new(document))?;
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.name() == CORE_PROPERTIES {
+            writer.start_file(CORE_PROPERTIES, zip::write::FileOptions::default())?;
+            writer.write_all(xml.as_bytes())?;
+        } else {
+            writer.raw_copy_file(file)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+const CONTENT_TYPES: &str = "[Content_Types].xml";
+const DOCUMENT_PART: &str = "word/document.xml";
+const DOCUMENT_RELATIONSHIPS: &str = "word/_rels/document.xml.rels";
+const RELATIONSHIP_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/";
+const CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.";
+// prefix of the parts copied from a reference document, to keep them apart from the generated ones
+const REFERENCE_PREFIX: &str = "reference_";
+
+struct Relationship {
+    id: String,
+    // last segment of the relationship type, e.g. "styles"
+    kind: String,
+    target: String,
+    external: bool,
+}
+
+fn attribute_value(tag: &BytesStart, name: &str) -> anyhow::Result<Option<String>> {
+    Ok(tag
+        .try_get_attribute(name)?
+        .map(|attribute| {
+            quick_xml::escape::unescape(&String::from_utf8_lossy(&attribute.value))
+                .map(|value| value.to_string())
+        })
+        .transpose()?)
+}
+
+fn relationships(xml: &str) -> anyhow::Result<Vec<Relationship>> {
+    let mut reader = Reader::from_str(xml);
+    let mut relationships = vec![];
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) | Event::Empty(tag)
+                if tag.local_name().as_ref() == b"Relationship" =>
+            {
+                let kind = attribute_value(&tag, "Type")?.unwrap_or_default();
+                relationships.push(Relationship {
+                    id: attribute_value(&tag, "Id")?.unwrap_or_default(),
+                    kind: kind.rsplit('/').next().unwrap_or_default().to_string(),
+                    target: attribute_value(&tag, "Target")?.unwrap_or_default(),
+                    external: attribute_value(&tag, "TargetMode")?.as_deref() == Some("External"),
+                });
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(relationships)
+}
+
+//byte ranges of the elements with the given name, with the value of one of their attributes
+fn xml_elements(
+    xml: &str,
+    name: &str,
+    attribute: &str,
+) -> anyhow::Result<Vec<(Range<usize>, String)>> {
+    let mut reader = Reader::from_str(xml);
+    let mut elements = vec![];
+    // start and attribute value of the element being read, and its nesting depth
+    let mut current: Option<(usize, String)> = None;
+    let mut depth = 0;
+    loop {
+        let position = reader.buffer_position();
+        match reader.read_event()? {
+            Event::Start(tag) => {
+                if current.is_none() && tag.name().as_ref() == name.as_bytes() {
+                    current = Some((
+                        position,
+                        attribute_value(&tag, attribute)?.unwrap_or_default(),
+                    ));
+                    depth = 0;
+                }
+                depth += 1;
+            }
+            Event::Empty(tag) if current.is_none() && tag.name().as_ref() == name.as_bytes() => {
+                let value = attribute_value(&tag, attribute)?.unwrap_or_default();
+                elements.push((position..reader.buffer_position(), value));
+            }
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some((start, value)) = current.take() {
+                        elements.push((start..reader.buffer_position(), value));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(elements)
+}
+
+//inserts text before the closing tag of the root element
+fn insert_before_end(xml: &str, root: &str, text: &str) -> anyhow::Result<String> {
+    let end = xml
+        .rfind(&format!("</{root}>"))
+        .ok_or_else(|| anyhow::anyhow!("Missing </{root}> in reference document"))?;
+    Ok(format!("{}{}{}", &xml[..end], text, &xml[end..]))
+}
+
+//reference styles, with the generated styles the reference does not define
+fn merge_styles(reference: &str, generated: &str) -> anyhow::Result<String> {
+    let defined: HashSet<String> = xml_elements(reference, "w:style", "w:styleId")?
+        .into_iter()
+        .map(|(_, id)| id)
+        .collect();
+    let missing: String = xml_elements(generated, "w:style", "w:styleId")?
+        .into_iter()
+        .filter(|(_, id)| !defined.contains(id))
+        .map(|(range, _)| &generated[range])
+        .collect();
+    insert_before_end(reference, "w:styles", &missing)
+}
+
+//reference numbering, with the generated list definitions renumbered after those of the reference
+fn merge_numbering(
+    reference: &str,
+    generated: &str,
+    used: &HashSet<String>,
+) -> anyhow::Result<(String, HashMap<String, String>)> {
+    let max_id = |elements: Vec<(Range<usize>, String)>| {
+        elements
+            .into_iter()
+            .filter_map(|(_, id)| id.parse::<usize>().ok())
+            .max()
+            .unwrap_or(0)
+    };
+    let reference_abstract_nums = xml_elements(reference, "w:abstractNum", "w:abstractNumId")?;
+    let reference_nums = xml_elements(reference, "w:num", "w:numId")?;
+    // abstract numberings go before all numberings
+    let abstract_position = reference_nums.first().map(|(range, _)| range.start);
+    let mut next_abstract_id = max_id(reference_abstract_nums) + 1;
+    let mut next_num_id = max_id(reference_nums) + 1;
+
+    let generated_abstract_nums = xml_elements(generated, "w:abstractNum", "w:abstractNumId")?;
+    let mut abstract_ids: HashMap<String, String> = HashMap::new();
+    let mut num_ids = HashMap::new();
+    let mut abstract_nums = String::new();
+    let mut nums = String::new();
+    for (range, id) in xml_elements(generated, "w:num", "w:numId")? {
+        if !used.contains(&id) {
+            continue;
+        }
+        let num = &generated[range];
+        let Some((_, abstract_id)) = xml_elements(num, "w:abstractNumId", "w:val")?.pop() else {
+            continue;
+        };
+        if !abstract_ids.contains_key(&abstract_id) {
+            let new_id = next_abstract_id.to_string();
+            next_abstract_id += 1;
+            if let Some((range, _)) = generated_abstract_nums
+                .iter()
+                .find(|(_, id)| *id == abstract_id)
+            {
+                abstract_nums.push_str(&generated[range.clone()].replace(
+                    &format!("w:abstractNumId=\"{abstract_id}\""),
+                    &format!("w:abstractNumId=\"{new_id}\""),
+                ));
+            }
+            abstract_ids.insert(abstract_id.clone(), new_id);
+        }
+        let new_id = next_num_id.to_string();
+        next_num_id += 1;
+        nums.push_str(
+            &num.replace(
+                &format!("w:numId=\"{id}\""),
+                &format!("w:numId=\"{new_id}\""),
+            )
+            .replace(
+                &format!("<w:abstractNumId w:val=\"{abstract_id}\""),
+                &format!("<w:abstractNumId w:val=\"{}\"", abstract_ids[&abstract_id]),
+            ),
+        );
+        num_ids.insert(id, new_id);
+    }
+
+    let merged = match abstract_position {
+        Some(position) => format!(
+            "{}{}{}",
+            &reference[..position],
+            abstract_nums,
+            &reference[position..]
+        ),
+        None => insert_before_end(reference, "w:numbering", &abstract_nums)?,
+    };
+    Ok((insert_before_end(&merged, "w:numbering", &nums)?, num_ids))
+}
+
+// header or footer reference of the section: element name, type and relationship id
+type SectionReference = (String, String, String);
+
+//header and footer references of the last section, and whether it has a different first page
+fn section_references(xml: &str) -> anyhow::Result<(Vec<SectionReference>, bool)> {
+    let mut reader = Reader::from_str(xml);
+    let mut references = vec![];
+    let mut title_page = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) | Event::Empty(tag) => match tag.name().as_ref() {
+                b"w:sectPr" => {
+                    references.clear();
+                    title_page = false;
+                }
+                b"w:headerReference" | b"w:footerReference" => references.push((
+                    String::from_utf8_lossy(tag.name().as_ref()).to_string(),
+                    attribute_value(&tag, "w:type")?.unwrap_or_else(|| "default".to_string()),
+                    attribute_value(&tag, "r:id")?.unwrap_or_default(),
+                )),
+                b"w:titlePg" => {
+                    title_page = attribute_value(&tag, "w:val")?
+                        .is_none_or(|value| value != "false" && value != "0")
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((references, title_page))
+}
+
+//document body with the reference sections, numbering ids and without direct text sizes
+fn rewrite_document(
+    xml: &str,
+    references: &[SectionReference],
+    title_page: bool,
+    num_ids: &HashMap<String, String>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = quick_xml::Writer::new(Vec::new());
+    let mut title_page_pending = false;
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            // text sizes come from the reference styles
+            Event::Empty(tag) if matches!(tag.name().as_ref(), b"w:sz" | b"w:szCs") => continue,
+            Event::Empty(tag) if tag.name().as_ref() == b"w:numId" => {
+                let id = attribute_value(tag, "w:val")?.unwrap_or_default();
+                if let Some(new_id) = num_ids.get(&id) {
+                    writer.write_event(Event::Empty(
+                        BytesStart::new("w:numId").with_attributes([("w:val", new_id.as_str())]),
+                    ))?;
+                    continue;
+                }
+            }
+            Event::Start(tag) if tag.name().as_ref() == b"w:sectPr" => {
+                writer.write_event(event.clone())?;
+                for (name, kind, id) in references {
+                    writer.write_event(Event::Empty(
+                        BytesStart::new(name.as_str())
+                            .with_attributes([("w:type", kind.as_str()), ("r:id", id.as_str())]),
+                    ))?;
+                }
+                title_page_pending = title_page;
+                continue;
+            }
+            Event::Start(tag) | Event::Empty(tag) if title_page_pending => {
+                match tag.name().as_ref() {
+                    b"w:titlePg" => title_page_pending = false,
+                    // elements following titlePg in a section
+                    b"w:textDirection" | b"w:bidi" | b"w:rtlGutter" | b"w:docGrid"
+                    | b"w:printerSettings" => {
+                        writer.write_event(Event::Empty(BytesStart::new("w:titlePg")))?;
+                        title_page_pending = false;
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(tag) if title_page_pending && tag.name().as_ref() == b"w:sectPr" => {
+                writer.write_event(Event::Empty(BytesStart::new("w:titlePg")))?;
+                title_page_pending = false;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        writer.write_event(event)?;
+    }
+    Ok(writer.into_inner())
+}
+
+//name of a part targeted from the word directory
+fn word_part(target: &str) -> String {
+    match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("word/{target}"),
+    }
+}
+
+fn read_parts(package: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = ZipArchive::new(Cursor::new(package))?;
+    let mut parts = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        parts.push((file.name().to_string(), data));
+    }
+    Ok(parts)
+}
+
+// Reuses styles, numbering, theme, fonts and headers and footers of a reference document,
+// the generated package is rewritten part by part like the core properties
+fn apply_reference(generated: &[u8], reference: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut parts = read_parts(generated)?;
+    let reference_parts = read_parts(reference)?;
+    let reference_part = |name: &str| -> Option<String> {
+        reference_parts
+            .iter()
+            .find(|(part, _)| part == name)
+            .map(|(_, data)| String::from_utf8_lossy(data).to_string())
+    };
+    let generated_part = |parts: &[(String, Vec<u8>)], name: &str| -> anyhow::Result<String> {
+        parts
+            .iter()
+            .find(|(part, _)| part == name)
+            .map(|(_, data)| String::from_utf8_lossy(data).to_string())
+            .ok_or_else(|| anyhow::anyhow!("Missing {name} in generated document"))
+    };
+    let reference_document = reference_part(DOCUMENT_PART)
+        .ok_or_else(|| anyhow::anyhow!("The reference document has no {DOCUMENT_PART}"))?;
+    let reference_relationships =
+        relationships(&reference_part(DOCUMENT_RELATIONSHIPS).unwrap_or_default())?;
+    let reference_target = |kind: &str| {
+        reference_relationships
+            .iter()
+            .find(|relationship| relationship.kind == kind && !relationship.external)
+            .and_then(|relationship| reference_part(&word_part(&relationship.target)))
+    };
+
+    let mut document = generated_part(&parts, DOCUMENT_PART)?;
+    let mut relationships_xml = generated_part(&parts, DOCUMENT_RELATIONSHIPS)?;
+    let mut content_types = generated_part(&parts, CONTENT_TYPES)?;
+    let mut replaced: Vec<(String, String)> = vec![];
+    let mut added: Vec<(String, Vec<u8>)> = vec![];
+
+    if let Some(styles) = reference_target("styles") {
+        let merged = merge_styles(&styles, &generated_part(&parts, "word/styles.xml")?)?;
+        replaced.push(("word/styles.xml".to_string(), merged));
+    }
+    if let Some(fonts) = reference_target("fontTable") {
+        replaced.push(("word/fontTable.xml".to_string(), fonts));
+    }
+
+    let mut num_ids = HashMap::new();
+    if let Some(numbering) = reference_target("numbering") {
+        let used = xml_elements(&document, "w:numId", "w:val")?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        let (merged, ids) = merge_numbering(
+            &numbering,
+            &generated_part(&parts, "word/numbering.xml")?,
+            &used,
+        )?;
+        replaced.push(("word/numbering.xml".to_string(), merged));
+        num_ids = ids;
+    }
+
+    let mut new_relationships = String::new();
+    let mut new_content_types = String::new();
+    if let Some(theme) = reference_target("theme") {
+        let name = "word/theme/theme1.xml";
+        added.push((name.to_string(), theme.into_bytes()));
+        new_relationships.push_str(&format!(
+            "<Relationship Id=\"rIdReferenceTheme\" Type=\"{RELATIONSHIP_TYPE}theme\" Target=\"theme/theme1.xml\"/>"
+        ));
+        new_content_types.push_str(&format!(
+            "<Override PartName=\"/{name}\" ContentType=\"{CONTENT_TYPE}theme+xml\"/>"
+        ));
+    }
+
+    // headers and footers of the document take precedence over those of the reference
+    let (generated_references, _) = section_references(&document)?;
+    let (reference_references, title_page) = section_references(&reference_document)?;
+    let reference_defaults = reference_part(CONTENT_TYPES).unwrap_or_default();
+    let mut references = vec![];
+    for (index, (name, kind, id)) in reference_references.into_iter().enumerate() {
+        if generated_references
+            .iter()
+            .any(|(generated_name, generated_kind, _)| {
+                *generated_name == name && *generated_kind == kind
+            })
+        {
+            continue;
+        }
+        let Some(relationship) = reference_relationships
+            .iter()
+            .find(|relationship| relationship.id == id)
+        else {
+            continue;
+        };
+        let part_name = word_part(&relationship.target);
+        let Some((_, data)) = reference_parts.iter().find(|(part, _)| *part == part_name) else {
+            continue;
+        };
+        let file_name = part_name.rsplit('/').next().unwrap_or_default();
+        let new_name = format!("{REFERENCE_PREFIX}{file_name}");
+        added.push((format!("word/{new_name}"), data.clone()));
+
+        // images and other parts of the header or footer are copied along
+        let part_relationships = format!("word/_rels/{file_name}.rels");
+        if let Some(mut xml) = reference_part(&part_relationships) {
+            for relationship in relationships(&xml)? {
+                if relationship.external {
+                    continue;
+                }
+                let Some((_, data)) = reference_parts
+                    .iter()
+                    .find(|(part, _)| *part == word_part(&relationship.target))
+                else {
+                    continue;
+                };
+                let (directory, file) = match relationship.target.rsplit_once('/') {
+                    Some((directory, file)) => (format!("{directory}/"), file),
+                    None => (String::new(), relationship.target.as_str()),
+                };
+                let target = format!("{directory}{REFERENCE_PREFIX}{file}");
+                added.push((word_part(&target), data.clone()));
+                xml = xml.replace(
+                    &format!("Target=\"{}\"", relationship.target),
+                    &format!("Target=\"{target}\""),
+                );
+                // image types the generated package does not declare yet
+                let extension = file.rsplit('.').next().unwrap_or_default();
+                let declaration = format!("Extension=\"{extension}\"");
+                if !content_types.contains(&declaration)
+                    && !new_content_types.contains(&declaration)
+                {
+                    if let Some((range, _)) =
+                        xml_elements(&reference_defaults, "Default", "Extension")?
+                            .into_iter()
+                            .find(|(_, default)| default == extension)
+                    {
+                        new_content_types.push_str(&reference_defaults[range]);
+                    }
+                }
+            }
+            added.push((format!("word/_rels/{new_name}.rels"), xml.into_bytes()));
+        }
+
+        let kind_name = if name == "w:headerReference" {
+            "header"
+        } else {
+            "footer"
+        };
+        let new_id = format!("rIdReference{}", index + 1);
+        new_relationships.push_str(&format!(
+            "<Relationship Id=\"{new_id}\" Type=\"{RELATIONSHIP_TYPE}{kind_name}\" Target=\"{new_name}\"/>"
+        ));
+        new_content_types.push_str(&format!(
+            "<Override PartName=\"/word/{new_name}\" ContentType=\"{CONTENT_TYPE}wordprocessingml.{kind_name}+xml\"/>"
+        ));
+        references.push((name, kind, new_id));
+    }
+
+    document = String::from_utf8(rewrite_document(
+        &document,
+        &references,
+        title_page,
+        &num_ids,
+    )?)?;
+    relationships_xml = insert_before_end(&relationships_xml, "Relationships", &new_relationships)?;
+    content_types = insert_before_end(&content_types, "Types", &new_content_types)?;
+    replaced.push((DOCUMENT_PART.to_string(), document));
+    replaced.push((DOCUMENT_RELATIONSHIPS.to_string(), relationships_xml));
+    replaced.push((CONTENT_TYPES.to_string(), content_types));
+
+    for (name, xml) in replaced {
+        match parts.iter_mut().find(|(part, _)| *part == name) {
+            Some((_, data)) => *data = xml.into_bytes(),
+            None => parts.push((name, xml.into_bytes())),
+        }
+    }
+    parts.extend(added);
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in parts {
+        writer.start_file(name, zip::write::FileOptions::default())?;
+        writer.write_all(&data)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::tests::init_logger;
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::tests::{init_logger, text};
+    use crate::core::{
+        disk_image_loader, PageFormat, TableHeader, TransformerWithImageLoaderSaverTrait,
+    };
     use crate::{docx, markdown};
     use bytes::Bytes;
     use log::info;
@@ -554,9 +2449,24 @@ mod tests {
 
         info!("Parsed - {:#?}", parsed);
         let elements = vec![
-            Element::Text {
-                text: "Warszawa, dnia {{DATA}} r. ".to_string(),
-                size: 16,
+            Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "Warszawa, dnia ".to_string(),
+                        size: 16,
+                        style: TextStyle::default(),
+                    },
+                    Element::Text {
+                        text: "{{DATA}}".to_string(),
+                        size: 16,
+                        style: TextStyle::bold(),
+                    },
+                    Element::Text {
+                        text: " r. ".to_string(),
+                        size: 16,
+                        style: TextStyle::default(),
+                    },
+                ],
             },
             Element::Header {
                 level: 1,
@@ -565,10 +2475,573 @@ mod tests {
             Element::Text {
                 text: "".to_string(),
                 size: 16,
+                style: TextStyle::default(),
             },
         ];
-        let expected_result = Document::new(elements);
+        let mut expected_result = Document::new(elements);
+        // A4 with 2 cm margins
+        expected_result.set_page_format(PageFormat::Custom(PageDimensions {
+            page_margin_top: 20.0,
+            page_margin_bottom: 20.0,
+            page_margin_left: 20.0,
+            page_margin_right: 20.0,
+            ..PageFormat::A4.dimensions()
+        }));
+        expected_result.set_metadata(DocumentMetadata {
+            language: Some("en-US".to_string()),
+            created: Some("2024-04-29T12:11:32Z".to_string()),
+            modified: Some("2024-07-08T16:24:59Z".to_string()),
+            ..Default::default()
+        });
         assert_eq!(expected_result, parsed);
         Ok(())
     }
+
+    #[test]
+    fn test_text_style() -> anyhow::Result<()> {
+        let text = |text: &str, style: TextStyle| Element::Text {
+            text: text.to_string(),
+            size: 16,
+            style,
+        };
+        let paragraph = Element::Paragraph {
+            elements: vec![
+                text("Plain ", TextStyle::default()),
+                text("bold", TextStyle::bold()),
+                text(" ", TextStyle::default()),
+                text(
+                    "italic underline",
+                    TextStyle {
+                        italic: true,
+                        underline: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(
+                    " strike",
+                    TextStyle {
+                        strikethrough: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(" code", TextStyle::code()),
+            ],
+        };
+        let document = Document::new(vec![paragraph]);
+
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Text".to_string(),
+            size: 8,
+            style: TextStyle::default(),
+        }]);
+        document.set_metadata(DocumentMetadata {
+            title: Some("Report & summary".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en-US".to_string()),
+            created: Some("2024-05-01T10:30:00Z".to_string()),
+            ..Default::default()
+        });
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata, document.metadata);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_setup() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![]);
+        document.set_page_format(PageFormat::Legal);
+        document.set_orientation(PageOrientation::Landscape);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        assert_eq!(parsed.page_format, PageFormat::Legal);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+
+        let margins = PageFormat::Custom(PageDimensions {
+            page_margin_left: 25.4,
+            page_margin_right: 25.4,
+            ..PageFormat::A4.dimensions()
+        });
+        document.set_page_format(margins.clone());
+        document.set_orientation(PageOrientation::Portrait);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        assert_eq!(parsed.page_format, margins);
+        assert_eq!(parsed.orientation, PageOrientation::Portrait);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::CodeBlock {
+            language: None,
+            code: "fn main() {\n    println!(\"Hello\");\n}\n".to_string(),
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote_and_breaks() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 16,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            text("Before"),
+            Element::Blockquote {
+                elements: vec![text("First quoted"), text("Second quoted")],
+            },
+            Element::ThematicBreak,
+            text("First page"),
+            Element::PageBreak,
+            text("Second page"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let table = Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 16), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 16), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 16)).span(1, 2),
+                        TableCell::new(text("First", 16)),
+                        TableCell::new(text("1", 16)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 16)),
+                        TableCell::new(text("2", 16)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![TableCell::with_elements(vec![
+                        text("Sum", 16),
+                        text("of all", 16),
+                    ])
+                    .span(3, 1)],
+                },
+            ],
+        };
+        let document = Document::new(vec![table.clone()]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let Element::Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        // the header row is parsed as a regular row
+        assert!(headers.is_empty());
+        let Element::Table {
+            headers: expected_headers,
+            rows: expected_rows,
+            ..
+        } = table
+        else {
+            unreachable!()
+        };
+        let header_row: Vec<TableCell> = expected_headers
+            .into_iter()
+            .map(|h| TableCell::with_elements(h.elements).span(h.colspan, h.rowspan))
+            .collect();
+        assert_eq!(rows[0].cells, header_row);
+        assert_eq!(rows[1..], expected_rows[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rich_content() -> anyhow::Result<()> {
+        let picture = std::fs::read("test/data/picture.png")?;
+        let list_level = |format: &str| {
+            Level::new(
+                0,
+                Start::new(1),
+                NumberFormat::new(format),
+                LevelText::new("%1."),
+                LevelJc::new("left"),
+            )
+        };
+        let list_item = |text: &str, id: usize| {
+            Paragraph::new()
+                .add_run(Run::new().add_text(text))
+                .numbering(NumberingId::new(id), IndentLevel::new(0))
+        };
+        let docx = Docx::new()
+            .add_style(Style::new("Berschrift2", StyleType::Paragraph).name("heading 2"))
+            .add_abstract_numbering(AbstractNumbering::new(5).add_level(list_level("bullet")))
+            .add_abstract_numbering(AbstractNumbering::new(6).add_level(list_level("decimal")))
+            .add_numbering(Numbering::new(7, 5))
+            .add_numbering(Numbering::new(8, 6))
+            .add_paragraph(
+                Paragraph::new()
+                    .style(TITLE_STYLE)
+                    .add_run(Run::new().add_text("Shiva "))
+                    .add_run(Run::new().add_text("manual")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .style("Heading3")
+                    .add_run(Run::new().add_text("Install").add_tab().add_text("guide")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .style("Berschrift2")
+                    .add_run(Run::new().add_text("Usage")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text("Read ").add_text("the "))
+                    .add_hyperlink(
+                        Hyperlink::new("https://example.com/docs", HyperlinkType::External)
+                            .add_run(Run::new().add_text("docs")),
+                    )
+                    .add_run(Run::new().add_text(".")),
+            )
+            .add_paragraph(Paragraph::new().add_run(Run::new().add_image(Pic::new(&picture))))
+            .add_paragraph(list_item("apple", 7))
+            .add_paragraph(list_item("pear", 7))
+            .add_paragraph(list_item("first", 8));
+        let mut cursor = Cursor::new(Vec::new());
+        docx.build().pack(&mut cursor)?;
+
+        let parsed = Transformer::parse(&Bytes::from(cursor.into_inner()))?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(elements.len(), 7);
+        assert_eq!(
+            elements[..3],
+            [
+                &Element::Header {
+                    level: 1,
+                    text: "Shiva manual".to_string(),
+                },
+                &Element::Header {
+                    level: 3,
+                    text: "Install\tguide".to_string(),
+                },
+                &Element::Header {
+                    level: 2,
+                    text: "Usage".to_string(),
+                },
+            ]
+        );
+        let text = |text: &str, size| Element::Text {
+            text: text.to_string(),
+            size,
+            style: TextStyle::default(),
+        };
+        assert_eq!(
+            elements[3],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Read the ", 16),
+                    Element::Hyperlink {
+                        title: "docs".to_string(),
+                        url: "https://example.com/docs".to_string(),
+                        alt: String::new(),
+                        size: 16,
+                    },
+                    text(".", 16),
+                ],
+            }
+        );
+        let Element::Image(image) = elements[4] else {
+            panic!("Expected image, got {:?}", elements[4]);
+        };
+        assert_eq!(image.bytes().as_ref(), picture.as_slice());
+        assert_eq!(*image.image_type(), ImageType::Png);
+        assert_eq!(
+            *image.size(),
+            ImageDimension {
+                width: Some("256".to_string()),
+                height: Some("256".to_string()),
+            }
+        );
+        let list = |numbered, items: &[&str]| Element::List {
+            elements: items
+                .iter()
+                .map(|item| ListItem::new(text(item, 12)))
+                .collect(),
+            numbered,
+        };
+        assert_eq!(elements[5], &list(false, &["apple", "pear"]));
+        assert_eq!(elements[6], &list(true, &["first"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_header_footer() -> anyhow::Result<()> {
+        let text = |text: &str, size| Element::Text {
+            text: text.to_string(),
+            size,
+            style: TextStyle::default(),
+        };
+        let mut document = Document::new_with_dimensions(
+            vec![text("Quarterly report", 10)],
+            vec![text("Body", 10)],
+            vec![text(&format!("Page {PAGE_NUMBER} of {PAGE_COUNT}"), 10)],
+            PageFormat::Letter,
+        );
+        document.set_orientation(PageOrientation::Landscape);
+        let generated = Transformer::generate(&document)?;
+
+        // the page number is a field of the footer part, not text of the body
+        let docx = read_docx(&generated)?;
+        let (_, footer) = docx.document.section_property.footer.as_ref().unwrap();
+        let FooterChild::Paragraph(paragraph) = &footer.children[0] else {
+            panic!("Expected footer paragraph");
+        };
+        let page_field = RunChild::InstrTextString("PAGE".to_string());
+        assert!(paragraph_runs(&paragraph.children)
+            .iter()
+            .any(|run| run.children.contains(&page_field)));
+
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_detail(), vec![&text("Body", 16)]);
+        assert_eq!(
+            parsed.get_page_header(),
+            vec![&text("Quarterly report", 16)]
+        );
+        assert_eq!(
+            parsed.get_page_footer(),
+            vec![&text("Page {page} of {pages}", 16)]
+        );
+        assert_eq!(parsed.page_format, PageFormat::Letter);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+        Ok(())
+    }
+
+    #[test]
+    fn test_review() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 16,
+            style: TextStyle::default(),
+        };
+        let comment = Comment::new(1)
+            .author("John Doe")
+            .date("2024-05-02T09:00:00Z")
+            .add_paragraph(Paragraph::new().add_run(Run::new().add_text("Is this agreed?")));
+        let paragraph = Paragraph::new()
+            .add_run(Run::new().add_text("The term is "))
+            .add_delete(
+                Delete::new()
+                    .author("Jane Roe")
+                    .date("2024-05-01T10:30:00Z")
+                    .add_run(Run::new().add_delete_text("30")),
+            )
+            .add_comment_start(comment)
+            .add_insert(
+                Insert::new(Run::new().add_text("60"))
+                    .author("Jane Roe")
+                    .date("2024-05-01T10:30:00Z"),
+            )
+            .add_run(Run::new().add_text(" days."))
+            .add_comment_end(1);
+        let removed = Paragraph::new()
+            .add_delete(Delete::new().add_run(Run::new().add_delete_text("Removed clause.")));
+        let mut reviewed = vec![];
+        Docx::new()
+            .add_paragraph(paragraph)
+            .add_paragraph(removed)
+            .build()
+            .pack(Cursor::new(&mut reviewed))?;
+        let reviewed = Bytes::from(reviewed);
+
+        // tracked changes are accepted and comments left out by default
+        let parsed = Transformer::parse(&reviewed)?;
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![&text("The term is 60 days.")]
+        );
+
+        let options = DocxParseOptions::new().with_revisions(RevisionMode::RejectAll);
+        let parsed = Transformer::parse_with_options(&reviewed, &options)?;
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![&text("The term is 30 days."), &text("Removed clause.")]
+        );
+
+        let options = DocxParseOptions::new()
+            .with_revisions(RevisionMode::Annotate)
+            .with_comments(true);
+        let parsed = Transformer::parse_with_options(&reviewed, &options)?;
+        let revision =
+            |kind, author: Option<&str>, date: Option<&str>, text: Element| Element::Revision {
+                kind,
+                author: author.map(str::to_string),
+                date: date.map(str::to_string),
+                elements: vec![text],
+            };
+        let author = Some("Jane Roe");
+        let date = Some("2024-05-01T10:30:00Z");
+        let comment = |anchor: &str| Element::Comment {
+            author: Some("John Doe".to_string()),
+            date: Some("2024-05-02T09:00:00Z".to_string()),
+            text: "Is this agreed?".to_string(),
+            anchor: anchor.to_string(),
+        };
+        let reviewed_paragraph = Element::Paragraph {
+            elements: vec![
+                text("The term is "),
+                revision(RevisionKind::Deletion, author, date, text("30")),
+                revision(RevisionKind::Insertion, author, date, text("60")),
+                text(" days."),
+            ],
+        };
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![
+                &reviewed_paragraph,
+                &comment("60 days."),
+                &revision(RevisionKind::Deletion, None, None, text("Removed clause.")),
+            ]
+        );
+
+        // annotated changes are written back as tracked changes, comments cover the paragraph before them
+        let generated = Transformer::generate(&parsed)?;
+        let parsed = Transformer::parse_with_options(&generated, &options)?;
+        assert_eq!(
+            parsed.get_all_elements()[..2],
+            [&reviewed_paragraph, &comment("The term is 3060 days.")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_review_without_author() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 16,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("The term is "),
+                    Element::Revision {
+                        kind: RevisionKind::Insertion,
+                        author: None,
+                        date: None,
+                        elements: vec![text("60")],
+                    },
+                    text(" days."),
+                ],
+            },
+            Element::Comment {
+                author: None,
+                date: None,
+                text: "Is this agreed?".to_string(),
+                anchor: "The term is 60 days.".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        // no placeholder author or date is written
+        for part in ["word/document.xml", "word/comments.xml"] {
+            let xml = package_part(&generated, part)?;
+            assert!(!xml.contains("w:date=") && !xml.contains("unnamed"));
+        }
+
+        let options = DocxParseOptions::new()
+            .with_revisions(RevisionMode::Annotate)
+            .with_comments(true);
+        let parsed = Transformer::parse_with_options(&generated, &options)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    fn package_part(package: &[u8], name: &str) -> anyhow::Result<String> {
+        let mut archive = ZipArchive::new(Cursor::new(package))?;
+        let mut xml = String::new();
+        archive.by_name(name)?.read_to_string(&mut xml)?;
+        Ok(xml)
+    }
+
+    #[test]
+    fn test_reference_document() -> anyhow::Result<()> {
+        let reference = std::fs::read("test/data/document.docx")?;
+        let item = |text: &str| {
+            ListItem::new(Element::Text {
+                text: text.to_string(),
+                size: 12,
+                style: TextStyle::default(),
+            })
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Report".to_string(),
+            },
+            Element::List {
+                elements: vec![item("first"), item("second")],
+                numbered: true,
+            },
+        ]);
+        let options = DocxOptions::new().with_reference(Bytes::from(reference.clone()));
+        let generated = Transformer::generate_with_options(&document, &options)?;
+
+        // theme and heading style come from the reference, generated styles are kept
+        assert_eq!(
+            package_part(&generated, "word/theme/theme1.xml")?,
+            package_part(&reference, "word/theme/theme1.xml")?
+        );
+        let styles = package_part(&generated, "word/styles.xml")?;
+        assert!(styles.contains("<w:name w:val=\"Heading 1\"/>"));
+        assert!(styles.contains(&format!("w:styleId=\"{CODE_STYLE}\"")));
+        // sizes are left to the reference styles
+        assert!(!package_part(&generated, DOCUMENT_PART)?.contains("<w:sz "));
+
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_header_footer() -> anyhow::Result<()> {
+        let band = |text: &str| Paragraph::new().add_run(Run::new().add_text(text));
+        let mut reference = Cursor::new(Vec::new());
+        Docx::new()
+            .header(Header::new().add_paragraph(band("ACME Corp")))
+            .footer(Footer::new().add_paragraph(band("Confidential")))
+            .build()
+            .pack(&mut reference)?;
+        let options = DocxOptions::new().with_reference(Bytes::from(reference.into_inner()));
+
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 16,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![text("Body")]);
+        let parsed = Transformer::parse(&Transformer::generate_with_options(&document, &options)?)?;
+        assert_eq!(parsed.get_page_header(), vec![&text("ACME Corp")]);
+        assert_eq!(parsed.get_page_footer(), vec![&text("Confidential")]);
+
+        // the page header of the document replaces the one of the reference
+        let mut document = Document::new(vec![text("Body")]);
+        document.set_page_header(vec![text("Own header")]);
+        let parsed = Transformer::parse(&Transformer::generate_with_options(&document, &options)?)?;
+        assert_eq!(parsed.get_page_header(), vec![&text("Own header")]);
+        assert_eq!(parsed.get_page_footer(), vec![&text("Confidential")]);
+        Ok(())
+    }
 }
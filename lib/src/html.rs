@@ -2,11 +2,16 @@ use crate::core::*;
 use bytes::Bytes;
 use ego_tree::{iter::Children, NodeRef};
 
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
-use scraper::{Html, Node};
+use crate::core::Element::{
+    Blockquote, CodeBlock, Comment, Footnote, Header, Hyperlink, Image, List, PageBreak, Paragraph,
+    Revision, Table, Text, ThematicBreak,
+};
+use scraper::{ElementRef, Html, Node, Selector};
 
 pub struct Transformer;
 
+const PAGE_BREAK_HTML: &str = "<div style=\"page-break-after: always\"></div>";
+
 impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
         Transformer::parse_with_loader(document, disk_image_loader("."))
@@ -23,17 +28,17 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
     {
         let html = String::from_utf8(document.to_vec())?;
         let document = Html::parse_document(&html);
-        let mut elements: Vec<Element> = Vec::new();
-
         let image_loader = ImageLoader {
             function: image_loader,
         };
-        parse_html(
+        let elements = parse_block(
             document.root_element().children(),
-            &mut elements,
             &image_loader,
+            &TextStyle::default(),
         )?;
-        Ok(Document::new(elements))
+        let mut result = Document::new(elements);
+        result.set_metadata(parse_metadata(&document));
+        Ok(result)
     }
 
     fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
@@ -46,34 +51,29 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             function: image_saver,
         };
 
-        //TODO: Is this needed? Commented out for now! header_text and footer_text are not read anywhere
-        let mut header_text = String::new();
-        document.get_page_header().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
-                header_text.push_str(text);
-            }
-            _ => {}
-        });
-        let mut footer_text = String::new();
+        if document.metadata.is_empty() {
+            html.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
+        } else {
+            html.push_str(&generate_head(&document.metadata));
+            html.push_str("<body>\n");
+        }
 
-        document.get_page_footer().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
-                footer_text.push_str(text);
+        // Page header goes first and page footer last, whatever order the bands were added in
+        let mut all_elements: Vec<&Element> = document.get_page_header();
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                all_elements.extend(band.elements());
             }
-            _ => {}
-        });
-
-        html.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
-
-        let all_elements: Vec<&Element> = document.get_all_elements();
+        }
+        all_elements.extend(document.get_page_footer());
 
         for element in &all_elements {
             match element {
                 Element::Header { level, text } => {
                     html.push_str(&format!("<h{}>{}</h{}>\n", level, text, level));
                 }
-                Element::Text { text, size: _ } => {
-                    html.push_str(&format!("<p>{}</p>\n", text));
+                Element::Text { text, style, .. } => {
+                    html.push_str(&format!("<p>{}</p>\n", styled_text(text, style)));
                 }
                 Paragraph { elements } => {
                     html.push_str("<p>");
@@ -96,20 +96,31 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
 
                     html.push_str(&list);
                 }
-                Table { headers, rows } => {
+                Table {
+                    name,
+                    headers,
+                    rows,
+                } => {
                     let mut table_html = String::from("<table  border=\"1\">\n");
+                    if let Some(name) = name {
+                        table_html.push_str(&format!("<caption>{}</caption>\n", escape_html(name)));
+                    }
 
                     if !headers.is_empty() {
                         table_html.push_str("<tr>\n");
 
                         for header in headers {
-                            let header_html = generate_html_for_element(
-                                &header.element,
+                            let header_html = generate_html_for_elements(
+                                &header.elements,
                                 &mut image_num,
                                 &image_saver,
                             )?;
 
-                            table_html.push_str(&format!("<th>{}</th>\n", header_html));
+                            table_html.push_str(&format!(
+                                "<th{}>{}</th>\n",
+                                span_attributes(header.colspan, header.rowspan),
+                                header_html
+                            ));
                         }
 
                         table_html.push_str("</tr>\n");
@@ -118,13 +129,17 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         table_html.push_str("<tr>\n");
 
                         for cell in &row.cells {
-                            let cell_html = generate_html_for_element(
-                                &cell.element,
+                            let cell_html = generate_html_for_elements(
+                                &cell.elements,
                                 &mut image_num,
                                 &image_saver,
                             )?;
 
-                            table_html.push_str(&format!("<td>{}</td>\n", cell_html));
+                            table_html.push_str(&format!(
+                                "<td{}>{}</td>\n",
+                                span_attributes(cell.colspan, cell.rowspan),
+                                cell_html
+                            ));
                         }
 
                         table_html.push_str("</tr>\n");
@@ -133,10 +148,45 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                     table_html.push_str("</table>\n");
                     html.push_str(&table_html)
                 }
+                CodeBlock { language, code } => {
+                    html.push_str(&code_block_html(language, code));
+                    html.push('\n');
+                }
+                Revision { .. } | Footnote { .. } => {
+                    html.push_str(&format!(
+                        "<p>{}</p>\n",
+                        generate_html_for_element(element, &mut image_num, &image_saver)?
+                    ));
+                }
+                Blockquote { .. } | ThematicBreak | PageBreak | Comment { .. } => {
+                    html.push_str(&generate_html_for_element(
+                        element,
+                        &mut image_num,
+                        &image_saver,
+                    )?);
+                    html.push('\n');
+                }
                 _ => {}
             }
         }
 
+        // Notes are listed after the text, each links back to where it is referenced
+        let mut footnotes: Vec<(&String, &Vec<Element>)> = Vec::new();
+        for element in all_elements {
+            collect_footnotes(element, &mut footnotes);
+        }
+        if !footnotes.is_empty() {
+            html.push_str("<section class=\"footnotes\">\n<ol>\n");
+            for (label, elements) in footnotes {
+                html.push_str(&format!(
+                    "<li id=\"fn-{label}\">{}<a href=\"#fnref-{label}\">&#8617;</a></li>\n",
+                    generate_html_for_elements(elements, &mut image_num, &image_saver)?,
+                    label = escape_html(label)
+                ));
+            }
+            html.push_str("</ol>\n</section>\n");
+        }
+
         html.push_str("</body>\n</html>");
 
         Ok(Bytes::from(html))
@@ -157,10 +207,92 @@ where
     pub function: F,
 }
 
+fn parse_metadata(document: &Html) -> DocumentMetadata {
+    let mut metadata = DocumentMetadata::default();
+    let root = document.root_element();
+    metadata.language = root.value().attr("lang").map(str::to_string);
+
+    if let Ok(selector) = Selector::parse("head title") {
+        metadata.title = document
+            .select(&selector)
+            .next()
+            .map(|title| title.text().collect::<String>().trim().to_string());
+    }
+
+    if let Ok(selector) = Selector::parse("meta[name][content]") {
+        for meta in document.select(&selector) {
+            let name = meta.value().attr("name").unwrap_or_default();
+            let content = meta.value().attr("content").unwrap_or_default().to_string();
+            match name.to_lowercase().as_str() {
+                "author" => metadata.author = Some(content),
+                "subject" => metadata.subject = Some(content),
+                "description" => metadata.description = Some(content),
+                "created" => metadata.created = Some(content),
+                "modified" => metadata.modified = Some(content),
+                "keywords" => {
+                    metadata.keywords = content
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|keyword| !keyword.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+    }
+    metadata
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn generate_head(metadata: &DocumentMetadata) -> String {
+    let mut head = match &metadata.language {
+        Some(language) => format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n",
+            escape_html(language)
+        ),
+        None => "<!DOCTYPE html>\n<html>\n<head>\n".to_string(),
+    };
+    head.push_str("<meta charset=\"utf-8\">\n");
+    if let Some(title) = &metadata.title {
+        head.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+    }
+    let keywords = metadata.keywords.join(", ");
+    let meta = [
+        ("author", metadata.author.as_deref()),
+        ("subject", metadata.subject.as_deref()),
+        ("description", metadata.description.as_deref()),
+        (
+            "keywords",
+            Some(keywords.as_str()).filter(|k| !k.is_empty()),
+        ),
+        ("created", metadata.created.as_deref()),
+        ("modified", metadata.modified.as_deref()),
+    ];
+    for (name, content) in meta {
+        if let Some(content) = content {
+            head.push_str(&format!(
+                "<meta name=\"{}\" content=\"{}\">\n",
+                name,
+                escape_html(content)
+            ));
+        }
+    }
+    head.push_str("</head>\n");
+    head
+}
+
 fn parse_html<F>(
     children: Children<Node>,
     elements: &mut Vec<Element>,
     image_loader: &ImageLoader<F>,
+    style: &TextStyle,
 ) -> anyhow::Result<()>
 where
     F: Fn(&str) -> anyhow::Result<Bytes>,
@@ -168,10 +300,21 @@ where
     for child in children {
         match child.value() {
             Node::Element(ref element) => match element.name() {
+                // an empty element only carrying a page break, e.g. <br style="page-break-before: always">
+                _ if child.children().next().is_none() && is_page_break(element) => {
+                    elements.push(PageBreak);
+                }
                 "table" => {
                     let mut headers: Vec<TableHeader> = Vec::new();
                     let mut rows: Vec<TableRow> = Vec::new();
+                    let mut name = None;
                     for table_child in child.children() {
+                        if let Some(caption) =
+                            ElementRef::wrap(table_child).filter(|e| e.value().name() == "caption")
+                        {
+                            name = Some(caption.text().collect::<String>().trim().to_string());
+                            continue;
+                        }
                         for child in table_child.children() {
                             match child.value() {
                                 Node::Element(ref table_element) => match table_element.name() {
@@ -184,34 +327,34 @@ where
                                                     match tr_element.name() {
                                                         "th" => {
                                                             is_header = true;
-                                                            let mut header_elements: Vec<Element> =
-                                                                Vec::new();
-                                                            parse_html(
+                                                            let header_elements = parse_block(
                                                                 tr_child.children(),
-                                                                &mut header_elements,
                                                                 image_loader,
+                                                                style,
                                                             )?;
-                                                            headers.extend(
-                                                                header_elements.into_iter().map(
-                                                                    |element| TableHeader {
-                                                                        element,
-                                                                        width: 10.0,
-                                                                    },
-                                                                ),
-                                                            );
+                                                            let (colspan, rowspan) =
+                                                                cell_spans(tr_element);
+                                                            headers.push(TableHeader {
+                                                                elements: header_elements,
+                                                                width: 10.0,
+                                                                colspan,
+                                                                rowspan,
+                                                                alignment: ColumnAlignment::None,
+                                                            });
                                                         }
                                                         "td" => {
-                                                            let mut cell_elements: Vec<Element> =
-                                                                Vec::new();
-                                                            parse_html(
+                                                            let cell_elements = parse_block(
                                                                 tr_child.children(),
-                                                                &mut cell_elements,
                                                                 image_loader,
+                                                                style,
                                                             )?;
-                                                            cells.extend(
-                                                                cell_elements.into_iter().map(
-                                                                    |element| TableCell { element },
-                                                                ),
+                                                            let (colspan, rowspan) =
+                                                                cell_spans(tr_element);
+                                                            cells.push(
+                                                                TableCell::with_elements(
+                                                                    cell_elements,
+                                                                )
+                                                                .span(colspan, rowspan),
                                                             );
                                                         }
                                                         _ => { /*  */ }
@@ -231,12 +374,17 @@ where
                         }
                     }
                     if !headers.is_empty() || !rows.is_empty() {
-                        elements.push(Table { headers, rows });
+                        elements.push(Table {
+                            name,
+                            headers,
+                            rows,
+                        });
                     }
                 }
-                "p" | "title" => {
-                    let mut paragraph_elements: Vec<Element> = Vec::new();
-                    parse_html(child.children(), &mut paragraph_elements, image_loader)?;
+                // the document title is kept in the metadata, see parse_metadata
+                "title" => {}
+                "p" => {
+                    let paragraph_elements = parse_block(child.children(), image_loader, style)?;
                     elements.push(Paragraph {
                         elements: paragraph_elements,
                     });
@@ -284,17 +432,9 @@ where
                     for list_child in child.children() {
                         if let Node::Element(ref li_element) = list_child.value() {
                             if li_element.name() == "li" {
-                                let mut item_elements: Vec<Element> = Vec::new();
-                                parse_html(
-                                    list_child.children(),
-                                    &mut item_elements,
-                                    image_loader,
-                                )?;
-                                list_items.extend(
-                                    item_elements
-                                        .into_iter()
-                                        .map(|element| ListItem { element }),
-                                );
+                                let item_elements =
+                                    parse_block(list_child.children(), image_loader, style)?;
+                                list_items.extend(item_elements.into_iter().map(ListItem::new));
                             }
                         }
                     }
@@ -303,6 +443,30 @@ where
                         numbered,
                     });
                 }
+                "blockquote" => {
+                    let quote_elements = parse_block(child.children(), image_loader, style)?;
+                    elements.push(Blockquote {
+                        elements: quote_elements,
+                    });
+                }
+                "hr" => elements.push(ThematicBreak),
+                "pre" => {
+                    // whitespace is significant, so the text is taken as is
+                    let code = ElementRef::wrap(child)
+                        .map(|pre| pre.text().collect::<String>())
+                        .unwrap_or_default();
+                    let language = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_element())
+                        .flat_map(|element| element.classes())
+                        .find_map(|class| {
+                            class
+                                .strip_prefix("language-")
+                                .or_else(|| class.strip_prefix("lang-"))
+                        })
+                        .map(str::to_string);
+                    elements.push(CodeBlock { language, code });
+                }
                 "a" => {
                     let href = element.attr("href").unwrap_or_default().to_string();
                     let text = child
@@ -323,16 +487,36 @@ where
                         size: 8,
                     });
                 }
+                "b" | "strong" | "i" | "em" | "u" | "ins" | "s" | "strike" | "del" | "code" => {
+                    let mut style = style.clone();
+                    match element.name() {
+                        "b" | "strong" => style.bold = true,
+                        "i" | "em" => style.italic = true,
+                        "u" | "ins" => style.underline = true,
+                        "s" | "strike" | "del" => style.strikethrough = true,
+                        _ => style.code = true,
+                    }
+                    parse_html(child.children(), elements, image_loader, &style)?;
+                }
                 _ => {
-                    parse_html(child.children(), elements, image_loader)?;
+                    parse_html(child.children(), elements, image_loader, style)?;
                 }
             },
             Node::Text(ref text) => {
-                let txt_strings = text.lines().map(str::trim).filter(|p| !p.is_empty());
-                for text_str in txt_strings {
+                // whitespace collapses to a single space like a browser renders it, the
+                // spaces around block elements are trimmed afterwards by trim_runs
+                let mut text_str = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if text.starts_with(char::is_whitespace) {
+                    text_str.insert(0, ' ');
+                }
+                if text.ends_with(char::is_whitespace) && !text_str.trim().is_empty() {
+                    text_str.push(' ');
+                }
+                if !text_str.is_empty() {
                     elements.push(Text {
-                        text: text_str.to_owned(),
+                        text: text_str,
                         size: 8,
+                        style: style.clone(),
                     });
                 }
             }
@@ -342,13 +526,173 @@ where
     Ok(())
 }
 
+/// Parses the children of a block element such as a paragraph, list item or table cell
+fn parse_block<F>(
+    children: Children<Node>,
+    image_loader: &ImageLoader<F>,
+    style: &TextStyle,
+) -> anyhow::Result<Vec<Element>>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    let mut elements = Vec::new();
+    parse_html(children, &mut elements, image_loader, style)?;
+    trim_runs(&mut elements);
+    Ok(elements)
+}
+
+/// Drops the whitespace a browser would not render: at the start and end of a block,
+/// next to block elements and a space following another space
+fn trim_runs(elements: &mut Vec<Element>) {
+    let is_inline =
+        |element: Option<&Element>| matches!(element, Some(Text { .. } | Hyperlink { .. }));
+    for i in 0..elements.len() {
+        let after_space = match i.checked_sub(1).map(|i| &elements[i]) {
+            Some(Text { text, .. }) => text.is_empty() || text.ends_with(' '),
+            previous => !is_inline(previous),
+        };
+        let before_block = !is_inline(elements.get(i + 1));
+        if let Text { text, .. } = &mut elements[i] {
+            if after_space {
+                text.drain(..text.len() - text.trim_start().len());
+            }
+            if before_block {
+                text.truncate(text.trim_end().len());
+            }
+        }
+    }
+    elements.retain(|element| !matches!(element, Text { text, .. } if text.is_empty()));
+}
+
+fn styled_text(text: &str, style: &TextStyle) -> String {
+    let mut html = escape_html(text);
+    let tags = [
+        (style.code, "code"),
+        (style.strikethrough, "s"),
+        (style.underline, "u"),
+        (style.italic, "em"),
+        (style.bold, "strong"),
+    ];
+    for (enabled, tag) in tags {
+        if enabled {
+            html = format!("<{tag}>{html}</{tag}>");
+        }
+    }
+    html
+}
+
+fn cell_spans(element: &scraper::node::Element) -> (usize, usize) {
+    let span = |name: &str| {
+        element
+            .attr(name)
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(1)
+    };
+    (span("colspan"), span("rowspan"))
+}
+
+fn span_attributes(colspan: usize, rowspan: usize) -> String {
+    let mut attributes = String::new();
+    if colspan > 1 {
+        attributes.push_str(&format!(" colspan=\"{}\"", colspan));
+    }
+    if rowspan > 1 {
+        attributes.push_str(&format!(" rowspan=\"{}\"", rowspan));
+    }
+    attributes
+}
+
+fn is_page_break(element: &scraper::node::Element) -> bool {
+    let style: String = element
+        .attr("style")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    style.contains("page-break-before:always") || style.contains("page-break-after:always")
+}
+
+fn code_block_html(language: &Option<String>, code: &str) -> String {
+    let class = match language {
+        Some(language) => format!(" class=\"language-{}\"", escape_html(language)),
+        None => String::new(),
+    };
+    format!("<pre><code{}>{}</code></pre>", class, escape_html(code))
+}
+
+fn collect_footnotes<'e>(
+    element: &'e Element,
+    footnotes: &mut Vec<(&'e String, &'e Vec<Element>)>,
+) {
+    let children: Vec<&Element> = match element {
+        Footnote { label, elements } => {
+            if !footnotes.iter().any(|(known, _)| *known == label) {
+                footnotes.push((label, elements));
+            }
+            return;
+        }
+        Paragraph { elements } | Blockquote { elements } | Revision { elements, .. } => {
+            elements.iter().collect()
+        }
+        List { elements, .. } => elements.iter().map(|item| &item.element).collect(),
+        Table { headers, rows, .. } => headers
+            .iter()
+            .flat_map(|header| &header.elements)
+            .chain(
+                rows.iter()
+                    .flat_map(|row| &row.cells)
+                    .flat_map(|cell| &cell.elements),
+            )
+            .collect(),
+        _ => return,
+    };
+    for child in children {
+        collect_footnotes(child, footnotes);
+    }
+}
+
+fn generate_html_for_elements(
+    elements: &[Element],
+    image_num: &mut i32,
+    image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
+) -> anyhow::Result<String> {
+    let mut html = String::new();
+    for element in elements {
+        let element_html = generate_html_for_element(element, image_num, image_saver)?;
+        // several text blocks would run together, each gets its own paragraph
+        match element {
+            Text { .. } if elements.len() > 1 => html.push_str(&format!("<p>{}</p>", element_html)),
+            _ => html.push_str(&element_html),
+        }
+    }
+    Ok(html)
+}
+
 fn generate_html_for_element(
     element: &Element,
     image_num: &mut i32,
     image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
 ) -> anyhow::Result<String> {
     match element {
-        Text { text, size: _ } => Ok(text.to_string()),
+        Text { text, style, .. } => Ok(styled_text(text, style)),
+        CodeBlock { language, code } => Ok(code_block_html(language, code)),
+        Blockquote { elements } => {
+            let mut quote_html = String::from("<blockquote>\n");
+            for child in elements {
+                let child_html = generate_html_for_element(child, image_num, image_saver)?;
+                if let Text { .. } = child {
+                    quote_html.push_str(&format!("<p>{}</p>\n", child_html));
+                } else {
+                    quote_html.push_str(&child_html);
+                    quote_html.push('\n');
+                }
+            }
+            quote_html.push_str("</blockquote>");
+            Ok(quote_html)
+        }
+        ThematicBreak => Ok("<hr>".to_string()),
+        PageBreak => Ok(PAGE_BREAK_HTML.to_string()),
         Paragraph { elements } => {
             let mut paragraph_html = String::from("<p>");
             for child in elements {
@@ -361,7 +705,7 @@ fn generate_html_for_element(
         Header { level, text } => Ok(format!(
             "<h{level}>{text}</h{level}>",
             level = level,
-            text = text
+            text = escape_html(text)
         )),
         List { elements, numbered } => {
             let tag = if *numbered { "ol" } else { "ul" };
@@ -372,7 +716,12 @@ fn generate_html_for_element(
                 if let List { .. } = item.element {
                     list_html.push_str(&item_html.to_string());
                 } else {
-                    list_html.push_str(&format!("<li>{}</li>", item_html));
+                    let checkbox = match item.checked {
+                        Some(true) => "<input type=\"checkbox\" disabled checked> ",
+                        Some(false) => "<input type=\"checkbox\" disabled> ",
+                        None => "",
+                    };
+                    list_html.push_str(&format!("<li>{}{}</li>", checkbox, item_html));
                     list_html.push('\n');
                 }
             }
@@ -403,8 +752,8 @@ fn generate_html_for_element(
             Ok(format!(
                 "<img src=\"{}\" alt=\"{}\" title=\"{}\"{}{}{} />",
                 image_path,
-                image.alt(),
-                image.title(),
+                escape_html(image.alt()),
+                escape_html(image.title()),
                 align_str,
                 width_str,
                 height_str
@@ -414,7 +763,47 @@ fn generate_html_for_element(
             title, url, alt, ..
         } => Ok(format!(
             "<a href=\"{}\" title=\"{}\">{}</a>",
-            url, alt, title
+            escape_html(url),
+            escape_html(alt),
+            escape_html(title)
+        )),
+        Revision {
+            kind,
+            author,
+            date,
+            elements,
+        } => {
+            let tag = match kind {
+                RevisionKind::Insertion => "ins",
+                RevisionKind::Deletion => "del",
+            };
+            let mut attributes = String::new();
+            if let Some(author) = author {
+                attributes.push_str(&format!(" title=\"{}\"", escape_html(author)));
+            }
+            if let Some(date) = date {
+                attributes.push_str(&format!(" datetime=\"{}\"", escape_html(date)));
+            }
+            let mut revision_html = format!("<{tag}{attributes}>");
+            for child in elements {
+                revision_html.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+            }
+            revision_html.push_str(&format!("</{tag}>"));
+            Ok(revision_html)
+        }
+        Footnote { label, .. } => Ok(format!(
+            "<sup id=\"fnref-{label}\"><a href=\"#fn-{label}\">{label}</a></sup>",
+            label = escape_html(label)
+        )),
+        // comments are kept but not shown
+        Comment {
+            author,
+            date,
+            text,
+            anchor,
+        } => Ok(format!(
+            "<!-- {} -->",
+            comment_summary(author, date, text, anchor).replace("--", "- -")
         )),
         _ => Ok("".to_string()),
     }
@@ -444,7 +833,7 @@ fn retrieve_deep_text(node: NodeRef<Node>, tag_name: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, text};
     use crate::core::*;
     use crate::html::*;
     use crate::markdown;
@@ -517,4 +906,307 @@ mod tests {
         info!("{}", String::from_utf8(markdown.to_vec())?);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_text_style() -> anyhow::Result<()> {
+        let document_html =
+            "<p>\n  Plain <b>bold <i>both</i></b> <u>under</u>\n  <code>a &lt; b &amp;&amp; c</code>\n</p>";
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let text = |text: &str, style: TextStyle| Text {
+            text: text.to_string(),
+            size: 8,
+            style,
+        };
+        let expected = Document::new(vec![Paragraph {
+            elements: vec![
+                text("Plain ", TextStyle::default()),
+                text("bold ", TextStyle::bold()),
+                text(
+                    "both",
+                    TextStyle {
+                        bold: true,
+                        italic: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(" ", TextStyle::default()),
+                text(
+                    "under",
+                    TextStyle {
+                        underline: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(" ", TextStyle::default()),
+                text("a < b && c", TextStyle::code()),
+            ],
+        }]);
+        assert_eq!(document, expected);
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<strong><em>both</em></strong>"));
+        assert!(generated.contains("<code>a &lt; b &amp;&amp; c</code>"));
+        assert_eq!(Transformer::parse(&Bytes::from(generated))?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_band_order() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 14,
+            style: TextStyle::default(),
+        };
+        let mut document = Document::new(vec![text("Detail")]);
+        document.set_page_footer(vec![text("Footer")]);
+        document.set_page_header(vec![text("Header")]);
+
+        let generated = Transformer::generate(&document)?;
+        let html = std::str::from_utf8(&generated)?;
+        let position = |text: &str| html.find(text).unwrap();
+        assert!(position("Header") < position("Detail"));
+        assert!(position("Detail") < position("Footer"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let document_html = r#"<html lang="en">
+            <head>
+                <title>Report</title>
+                <meta name="author" content="John Doe">
+                <meta name="keywords" content="rust, shiva">
+            </head>
+            <body><p>Text</p></body>
+        </html>"#;
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let expected = DocumentMetadata {
+            title: Some("Report".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(document.metadata, expected);
+        assert_eq!(document.get_all_elements().len(), 1);
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<title>Report</title>"));
+        assert_eq!(Transformer::parse(&Bytes::from(generated))?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document_html = "<p>Text</p><pre><code class=\"language-rust\">fn main() {\n    if a &lt; b {}\n}\n</code></pre>";
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let code_block = CodeBlock {
+            language: Some("rust".to_string()),
+            code: "fn main() {\n    if a < b {}\n}\n".to_string(),
+        };
+        assert_eq!(document.get_all_elements()[1], &code_block);
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated
+            .contains("<pre><code class=\"language-rust\">fn main() {\n    if a &lt; b {}"));
+        assert_eq!(Transformer::parse(&Bytes::from(generated))?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote_and_breaks() -> anyhow::Result<()> {
+        let document_html = "<p>Before</p><blockquote><p>Quoted</p><blockquote><p>Nested</p></blockquote></blockquote><hr><p>Next</p><br style=\"page-break-before: always\"><p>Last</p>";
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let paragraph = |text: &str| Paragraph {
+            elements: vec![Text {
+                text: text.to_string(),
+                size: 8,
+                style: TextStyle::default(),
+            }],
+        };
+        let expected = Document::new(vec![
+            paragraph("Before"),
+            Blockquote {
+                elements: vec![
+                    paragraph("Quoted"),
+                    Blockquote {
+                        elements: vec![paragraph("Nested")],
+                    },
+                ],
+            },
+            ThematicBreak,
+            paragraph("Next"),
+            PageBreak,
+            paragraph("Last"),
+        ]);
+        assert_eq!(document, expected);
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<blockquote>\n<p>Quoted</p>\n<blockquote>"));
+        assert!(generated.contains("<hr>"));
+        assert_eq!(Transformer::parse(&Bytes::from(generated))?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<th colspan=\"2\">Name</th>"));
+        assert!(generated.contains("<td rowspan=\"2\">Group</td>"));
+        assert!(generated.contains("<td colspan=\"3\"><p>Sum</p><p>of all</p></td>"));
+
+        let parsed = Transformer::parse(&Bytes::from(generated))?;
+        let Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        assert_eq!((headers[0].colspan, headers[0].rowspan), (2, 1));
+        let spans: Vec<Vec<(usize, usize)>> = rows
+            .iter()
+            .map(|row| row.cells.iter().map(|c| (c.colspan, c.rowspan)).collect())
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                vec![(1, 2), (1, 1), (1, 1)],
+                vec![(1, 1), (1, 1)],
+                vec![(3, 1)]
+            ]
+        );
+        assert_eq!(rows[2].cells[0].elements.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_name() -> anyhow::Result<()> {
+        let document = Document::new(
+            ["Q1 Sales", "Q2 Sales"]
+                .into_iter()
+                .map(|name| Element::Table {
+                    name: Some(name.to_string()),
+                    headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text(&name[..2], 8))],
+                    }],
+                })
+                .collect(),
+        );
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<caption>Q1 Sales</caption>"));
+
+        let parsed = Transformer::parse(&Bytes::from(generated))?;
+        let Table { name, .. } = parsed.get_all_elements()[1] else {
+            panic!("Expected table");
+        };
+        assert_eq!(name.as_deref(), Some("Q2 Sales"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_review() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Payment within", 8),
+                    Element::Revision {
+                        kind: RevisionKind::Deletion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("30", 8)],
+                    },
+                    Element::Revision {
+                        kind: RevisionKind::Insertion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("60", 8)],
+                    },
+                    text("days", 8),
+                ],
+            },
+            Element::Comment {
+                author: Some("John Doe".to_string()),
+                date: None,
+                text: "Is this agreed?".to_string(),
+                anchor: "60 days".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        let changed = "title=\"Jane Roe\" datetime=\"2024-05-01T10:30:00Z\"";
+        assert!(generated.contains(&format!(
+            "<p>Payment within<del {changed}>30</del><ins {changed}>60</ins>days</p>"
+        )));
+        assert!(
+            generated.contains("<!-- Comment by John Doe about \"60 days\": Is this agreed? -->")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnotes_and_task_list() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Read first", 8),
+                    Element::Footnote {
+                        label: "note".to_string(),
+                        elements: vec![Element::Paragraph {
+                            elements: vec![text("Checked twice.", 8)],
+                        }],
+                    },
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem::task(text("Write", 8), true),
+                    ListItem::task(text("Review", 8), false),
+                ],
+                numbered: false,
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(
+            "<p>Read first<sup id=\"fnref-note\"><a href=\"#fn-note\">note</a></sup></p>"
+        ));
+        assert!(generated.contains(
+            "<li id=\"fn-note\"><p>Checked twice.</p><a href=\"#fnref-note\">&#8617;</a></li>"
+        ));
+        assert!(generated.contains("<li><input type=\"checkbox\" disabled checked> Write</li>"));
+        assert!(generated.contains("<li><input type=\"checkbox\" disabled> Review</li>"));
+        Ok(())
+    }
 }
@@ -1,7 +1,9 @@
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    ColumnAlignment, Document, DocumentMetadata, Element, ImageAlignment, ImageData,
+    ImageDimension, ImageType, ListItem, PageDimensions, PageFormat, TableCell, TableHeader,
+    TableRow, TextStyle, TransformerTrait,
 };
+use base64::Engine;
 use bytes::Bytes;
 use serde_json::Value;
 use std::str::FromStr;
@@ -55,10 +57,8 @@ impl TransformerTrait for Transformer {
                 .clone(),
         )?;
         // Извлекаем заголовки и нижние колонтитулы страницы
-        let page_header =
-            parse_elements(&root.get("page_header").unwrap_or(&Value::Array(vec![])))?;
-        let page_footer =
-            parse_elements(&root.get("page_footer").unwrap_or(&Value::Array(vec![])))?;
+        let page_header = parse_elements(root.get("page_header").unwrap_or(&Value::Array(vec![])))?;
+        let page_footer = parse_elements(root.get("page_footer").unwrap_or(&Value::Array(vec![])))?;
 
         let page_custom_format = PageFormat::Custom(PageDimensions {
             page_width,
@@ -69,21 +69,149 @@ impl TransformerTrait for Transformer {
             page_margin_right,
         });
 
-        let document =
+        let mut document =
             Document::new_with_dimensions(page_header, elements, page_footer, page_custom_format);
+
+        // metadata is optional, documents without it get the defaults
+        if let Some(metadata) = root.get("metadata") {
+            let metadata: DocumentMetadata = serde_json::from_value(metadata.clone())
+                .map_err(|e| anyhow::anyhow!("Invalid 'metadata' field: {e}"))?;
+            document.set_metadata(metadata);
+        }
         Ok(document)
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
         use serde_json::{Map, Value};
         // Helper function to serialize an Element into serde_json::Value
+        // a plain cell is written as its element, a spanning or typed cell or one with several children as an object
+        fn serialize_table_cell(cell: &TableCell) -> Value {
+            if let ([element], 1, 1, None) = (
+                cell.elements.as_slice(),
+                cell.colspan,
+                cell.rowspan,
+                &cell.value,
+            ) {
+                return serialize_element(element);
+            }
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("TableCell".to_string()));
+            map.insert(
+                "elements".to_string(),
+                Value::Array(cell.elements.iter().map(serialize_element).collect()),
+            );
+            insert_spans(&mut map, cell.colspan, cell.rowspan);
+            if let Some(value) = &cell.value {
+                map.insert(
+                    "value".to_string(),
+                    serde_json::to_value(value).unwrap_or_default(),
+                );
+            }
+            Value::Object(map)
+        }
+
+        fn insert_spans(map: &mut Map<String, Value>, colspan: usize, rowspan: usize) {
+            if colspan > 1 {
+                map.insert("colspan".to_string(), Value::from(colspan));
+            }
+            if rowspan > 1 {
+                map.insert("rowspan".to_string(), Value::from(rowspan));
+            }
+        }
+
+        fn insert_review(
+            map: &mut Map<String, Value>,
+            author: &Option<String>,
+            date: &Option<String>,
+        ) {
+            if let Some(author) = author {
+                map.insert("author".to_string(), Value::String(author.clone()));
+            }
+            if let Some(date) = date {
+                map.insert("date".to_string(), Value::String(date.clone()));
+            }
+        }
+
         fn serialize_element(element: &Element) -> Value {
             match element {
-                Element::Text { text, size } => {
+                Element::Text { text, size, style } => {
                     let mut map = Map::new();
                     map.insert("type".to_string(), Value::String("Text".to_string()));
                     map.insert("text".to_string(), Value::String(text.clone()));
                     map.insert("size".to_string(), Value::Number((*size).into()));
+                    if !style.is_plain() {
+                        map.insert(
+                            "style".to_string(),
+                            serde_json::to_value(style).unwrap_or_default(),
+                        );
+                    }
+                    Value::Object(map)
+                }
+                Element::Blockquote { elements } => {
+                    let elements_json = elements.iter().map(serialize_element).collect();
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Blockquote".to_string()));
+                    map.insert("elements".to_string(), Value::Array(elements_json));
+                    Value::Object(map)
+                }
+                Element::ThematicBreak => {
+                    let mut map = Map::new();
+                    map.insert(
+                        "type".to_string(),
+                        Value::String("ThematicBreak".to_string()),
+                    );
+                    Value::Object(map)
+                }
+                Element::PageBreak => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("PageBreak".to_string()));
+                    Value::Object(map)
+                }
+                Element::Comment {
+                    author,
+                    date,
+                    text,
+                    anchor,
+                } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Comment".to_string()));
+                    insert_review(&mut map, author, date);
+                    map.insert("text".to_string(), Value::String(text.clone()));
+                    map.insert("anchor".to_string(), Value::String(anchor.clone()));
+                    Value::Object(map)
+                }
+                Element::Revision {
+                    kind,
+                    author,
+                    date,
+                    elements,
+                } => {
+                    let elements_json = elements.iter().map(serialize_element).collect();
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Revision".to_string()));
+                    map.insert(
+                        "kind".to_string(),
+                        serde_json::to_value(kind).unwrap_or_default(),
+                    );
+                    insert_review(&mut map, author, date);
+                    map.insert("elements".to_string(), Value::Array(elements_json));
+                    Value::Object(map)
+                }
+                Element::Footnote { label, elements } => {
+                    let elements_json = elements.iter().map(serialize_element).collect();
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Footnote".to_string()));
+                    map.insert("label".to_string(), Value::String(label.clone()));
+                    map.insert("elements".to_string(), Value::Array(elements_json));
+                    Value::Object(map)
+                }
+                Element::CodeBlock { language, code } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("CodeBlock".to_string()));
+                    if let Some(language) = language {
+                        map.insert("language".to_string(), Value::String(language.clone()));
+                    }
+                    map.insert("code".to_string(), Value::String(code.clone()));
                     Value::Object(map)
                 }
                 Element::Header { level, text } => {
@@ -100,12 +228,35 @@ impl TransformerTrait for Transformer {
                     map.insert("elements".to_string(), Value::Array(elements_json));
                     Value::Object(map)
                 }
-                Element::Table { headers, rows } => {
+                Element::Table {
+                    name,
+                    headers,
+                    rows,
+                } => {
                     let headers_json: Vec<Value> = headers
                         .iter()
                         .map(|h| {
                             let mut h_map = Map::new();
-                            h_map.insert("element".to_string(), serialize_element(&h.element));
+                            match h.elements.as_slice() {
+                                [element] => {
+                                    h_map.insert("element".to_string(), serialize_element(element));
+                                }
+                                elements => {
+                                    h_map.insert(
+                                        "elements".to_string(),
+                                        Value::Array(
+                                            elements.iter().map(serialize_element).collect(),
+                                        ),
+                                    );
+                                }
+                            }
+                            insert_spans(&mut h_map, h.colspan, h.rowspan);
+                            if h.alignment != ColumnAlignment::None {
+                                h_map.insert(
+                                    "alignment".to_string(),
+                                    serde_json::to_value(h.alignment).unwrap_or_default(),
+                                );
+                            }
                             h_map.insert(
                                 "width".to_string(),
                                 Value::Number(
@@ -119,11 +270,8 @@ impl TransformerTrait for Transformer {
                     let rows_json: Vec<Value> = rows
                         .iter()
                         .map(|r| {
-                            let cells_json: Vec<Value> = r
-                                .cells
-                                .iter()
-                                .map(|c| serialize_element(&c.element))
-                                .collect();
+                            let cells_json: Vec<Value> =
+                                r.cells.iter().map(serialize_table_cell).collect();
                             let mut row_map = Map::new();
                             row_map.insert("cells".to_string(), Value::Array(cells_json));
                             Value::Object(row_map)
@@ -132,6 +280,9 @@ impl TransformerTrait for Transformer {
 
                     let mut map = Map::new();
                     map.insert("type".to_string(), Value::String("Table".to_string()));
+                    if let Some(name) = name {
+                        map.insert("name".to_string(), Value::String(name.clone()));
+                    }
                     map.insert("headers".to_string(), Value::Array(headers_json));
                     map.insert("rows".to_string(), Value::Array(rows_json));
                     Value::Object(map)
@@ -143,6 +294,9 @@ impl TransformerTrait for Transformer {
                             let mut item_map = Map::new();
                             item_map
                                 .insert("element".to_string(), serialize_element(&item.element));
+                            if let Some(checked) = item.checked {
+                                item_map.insert("checked".to_string(), Value::Bool(checked));
+                            }
                             Value::Object(item_map)
                         })
                         .collect();
@@ -159,7 +313,9 @@ impl TransformerTrait for Transformer {
                     // Encode image bytes to base64 for JSON representation
                     map.insert(
                         "bytes".to_string(),
-                        Value::String(base64::encode(&image_data.bytes())),
+                        Value::String(
+                            base64::engine::general_purpose::STANDARD.encode(image_data.bytes()),
+                        ),
                     );
                     map.insert(
                         "title".to_string(),
@@ -285,6 +441,14 @@ impl TransformerTrait for Transformer {
             .collect();
         doc_map.insert("page_footer".to_string(), Value::Array(page_footer_json));
 
+        // Serialize metadata
+        if !document.metadata.is_empty() {
+            doc_map.insert(
+                "metadata".to_string(),
+                serde_json::to_value(&document.metadata)?,
+            );
+        }
+
         // Create the final JSON value
         let doc_value = Value::Object(doc_map);
 
@@ -311,6 +475,10 @@ fn parse_elements(value: &Value) -> anyhow::Result<Vec<Element>> {
     Ok(elements)
 }
 
+fn optional_string(value: Option<&Value>) -> Option<String> {
+    value.and_then(|v| v.as_str()).map(str::to_string)
+}
+
 // Функция для разбора отдельного элемента
 fn parse_element(value: &Value) -> anyhow::Result<Element> {
     let obj = value
@@ -332,7 +500,13 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 obj.get("size").and_then(|v| v.as_u64()).ok_or_else(|| {
                     anyhow::anyhow!("Text element missing or invalid 'size' field")
                 })? as u8;
-            Ok(Element::Text { text, size })
+            // style is optional, plain text is written without it
+            let style = match obj.get("style") {
+                Some(style) => serde_json::from_value(style.clone())
+                    .map_err(|e| anyhow::anyhow!("Text element has invalid 'style' field: {e}"))?,
+                None => TextStyle::default(),
+            };
+            Ok(Element::Text { text, size, style })
         }
         "Header" => {
             let level =
@@ -346,6 +520,75 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .to_string();
             Ok(Element::Header { level, text })
         }
+        "Blockquote" => {
+            let elements = parse_elements(
+                obj.get("elements")
+                    .ok_or_else(|| anyhow::anyhow!("Blockquote missing 'elements' field"))?,
+            )?;
+            Ok(Element::Blockquote { elements })
+        }
+        "ThematicBreak" => Ok(Element::ThematicBreak),
+        "PageBreak" => Ok(Element::PageBreak),
+        "Comment" => {
+            let text = obj
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Comment element missing 'text' field"))?
+                .to_string();
+            let anchor = obj
+                .get("anchor")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            Ok(Element::Comment {
+                author: optional_string(obj.get("author")),
+                date: optional_string(obj.get("date")),
+                text,
+                anchor,
+            })
+        }
+        "Revision" => {
+            let kind = serde_json::from_value(
+                obj.get("kind")
+                    .ok_or_else(|| anyhow::anyhow!("Revision missing 'kind' field"))?
+                    .clone(),
+            )
+            .map_err(|e| anyhow::anyhow!("Revision has invalid 'kind' field: {e}"))?;
+            let elements = parse_elements(
+                obj.get("elements")
+                    .ok_or_else(|| anyhow::anyhow!("Revision missing 'elements' field"))?,
+            )?;
+            Ok(Element::Revision {
+                kind,
+                author: optional_string(obj.get("author")),
+                date: optional_string(obj.get("date")),
+                elements,
+            })
+        }
+        "Footnote" => {
+            let label = obj
+                .get("label")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let elements = parse_elements(
+                obj.get("elements")
+                    .ok_or_else(|| anyhow::anyhow!("Footnote missing 'elements' field"))?,
+            )?;
+            Ok(Element::Footnote { label, elements })
+        }
+        "CodeBlock" => {
+            let language = obj
+                .get("language")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let code = obj
+                .get("code")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("CodeBlock element missing 'code' field"))?
+                .to_string();
+            Ok(Element::CodeBlock { language, code })
+        }
         "Paragraph" => {
             let elements = parse_elements(
                 &obj.get("elements")
@@ -365,7 +608,15 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                     .ok_or_else(|| anyhow::anyhow!("Table missing 'rows' field"))?
                     .clone(),
             )?;
-            Ok(Element::Table { headers, rows })
+            let name = obj
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(str::to_string);
+            Ok(Element::Table {
+                name,
+                headers,
+                rows,
+            })
         }
         "List" => {
             let numbered = obj
@@ -395,7 +646,7 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .get("bytes")
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| anyhow::anyhow!("Image element missing 'bytes' field"))?;
-            let bytes = Bytes::from(base64::decode(bytes_str)?);
+            let bytes = Bytes::from(base64::engine::general_purpose::STANDARD.decode(bytes_str)?);
             let title = obj
                 .get("title")
                 .and_then(|v| v.as_str())
@@ -416,7 +667,7 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .get("align")
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| anyhow::anyhow!("Image element missing 'align' field"))?;
-            let align = ImageAlignment::from_str(align_str)
+            let _align = ImageAlignment::from_str(align_str)
                 .map_err(|_| anyhow::anyhow!("Invalid align: {}", align_str))?;
             let size_obj = obj
                 .get("size")
@@ -469,6 +720,10 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
     }
 }
 
+fn parse_span(map: &serde_json::Map<String, Value>, key: &str) -> usize {
+    map.get(key).and_then(|v| v.as_u64()).unwrap_or(1) as usize
+}
+
 // Функция для разбора заголовков таблицы
 fn parse_table_headers(value: &Value) -> anyhow::Result<Vec<TableHeader>> {
     let headers_array = value
@@ -480,18 +735,26 @@ fn parse_table_headers(value: &Value) -> anyhow::Result<Vec<TableHeader>> {
         let header_obj = header
             .as_object()
             .ok_or_else(|| anyhow::anyhow!("Header is not an object"))?;
-        let element = parse_element(
-            &header_obj
-                .get("element")
-                .ok_or_else(|| anyhow::anyhow!("Header missing 'element' field"))?
-                .clone(),
-        )?;
+        let elements = match (header_obj.get("element"), header_obj.get("elements")) {
+            (Some(element), _) => vec![parse_element(element)?],
+            (None, Some(elements)) => parse_elements(elements)?,
+            (None, None) => return Err(anyhow::anyhow!("Header missing 'element' field")),
+        };
         let width = header_obj
             .get("width")
             .and_then(|v| v.as_f64())
             .ok_or_else(|| anyhow::anyhow!("Header missing or invalid 'width' field"))?
             as f32;
-        headers.push(TableHeader { element, width });
+        headers.push(TableHeader {
+            elements,
+            width,
+            colspan: parse_span(header_obj, "colspan"),
+            rowspan: parse_span(header_obj, "rowspan"),
+            alignment: header_obj
+                .get("alignment")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or_default(),
+        });
     }
 
     Ok(headers)
@@ -516,10 +779,23 @@ fn parse_table_rows(value: &Value) -> anyhow::Result<Vec<TableRow>> {
             .ok_or_else(|| anyhow::anyhow!("Row 'cells' is not an array"))?;
         let mut table_cells = Vec::new();
         for cell in cells_array {
-            let cell_element = parse_element(cell)?;
-            table_cells.push(TableCell {
-                element: cell_element,
-            });
+            let table_cell = match cell.as_object() {
+                Some(cell_obj) if cell_obj.get("type") == Some(&Value::from("TableCell")) => {
+                    TableCell {
+                        elements: parse_elements(cell_obj.get("elements").ok_or_else(|| {
+                            anyhow::anyhow!("TableCell missing 'elements' field")
+                        })?)?,
+                        colspan: parse_span(cell_obj, "colspan"),
+                        rowspan: parse_span(cell_obj, "rowspan"),
+                        value: cell_obj
+                            .get("value")
+                            .map(|value| serde_json::from_value(value.clone()))
+                            .transpose()?,
+                    }
+                }
+                _ => TableCell::new(parse_element(cell)?),
+            };
+            table_cells.push(table_cell);
         }
         rows.push(TableRow { cells: table_cells });
     }
@@ -537,15 +813,21 @@ fn parse_list_item(value: &Value) -> anyhow::Result<ListItem> {
             .ok_or_else(|| anyhow::anyhow!("ListItem missing 'element' field"))?
             .clone(),
     )?;
-    Ok(ListItem { element })
+    Ok(ListItem {
+        element,
+        checked: obj.get("checked").and_then(|v| v.as_bool()),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use log::{debug, info};
 
-    use crate::core::tests::init_logger;
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::tests::{init_logger, text};
+    use crate::core::{
+        disk_image_loader, CellValue, Element, TableCell, TableHeader, TableRow,
+        TransformerWithImageLoaderSaverTrait,
+    };
     use crate::json::TransformerTrait;
 
     #[test]
@@ -615,4 +897,253 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         info!("{}", generated_text2);
         Ok(())
     }
+
+    #[test]
+    fn test_text_style() -> anyhow::Result<()> {
+        let document = "Plain **bold** *italic* `code`";
+        let parsed = crate::markdown::Transformer::parse(&document.as_bytes().into())?;
+        let generated = crate::json::Transformer::generate(&parsed)?;
+        let generated_text = std::str::from_utf8(&generated)?;
+        assert!(generated_text.contains("\"bold\":true"));
+        let parsed2 = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), parsed2.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_text_style() -> anyhow::Result<()> {
+        let text = crate::core::Element::Text {
+            text: "bold".to_string(),
+            size: 10,
+            style: crate::core::TextStyle::bold(),
+        };
+        let generated =
+            crate::json::Transformer::generate(&crate::core::Document::new(vec![text.clone()]))?;
+        // flags left out of the style object are off
+        let mut json: serde_json::Value = serde_json::from_slice(&generated)?;
+        json["elements"][0]["style"] = serde_json::json!({ "bold": true });
+        let parsed = crate::json::Transformer::parse(&serde_json::to_vec(&json)?.into())?;
+        assert_eq!(parsed.get_all_elements(), vec![&text]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = crate::core::Document::new(vec![]);
+        document.set_metadata(crate::core::DocumentMetadata {
+            title: Some("Report".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            ..Default::default()
+        });
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.metadata, parsed.metadata);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document = crate::core::Document::new(vec![crate::core::Element::CodeBlock {
+            language: Some("rust".to_string()),
+            code: "fn main() {\n    println!(\"Hello\");\n}\n".to_string(),
+        }]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote_and_breaks() -> anyhow::Result<()> {
+        use crate::core::Element;
+        let document = crate::core::Document::new(vec![
+            Element::Blockquote {
+                elements: vec![Element::Header {
+                    level: 2,
+                    text: "Quoted".to_string(),
+                }],
+            },
+            Element::ThematicBreak,
+            Element::PageBreak,
+        ]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_review() -> anyhow::Result<()> {
+        let document = crate::core::Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Payment within", 8),
+                    Element::Revision {
+                        kind: crate::core::RevisionKind::Deletion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("30", 8)],
+                    },
+                    Element::Revision {
+                        kind: crate::core::RevisionKind::Insertion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("60", 8)],
+                    },
+                    text("days", 8),
+                ],
+            },
+            Element::Comment {
+                author: Some("John Doe".to_string()),
+                date: None,
+                text: "Is this agreed?".to_string(),
+                anchor: "60 days".to_string(),
+            },
+        ]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gfm_elements() -> anyhow::Result<()> {
+        let document = crate::core::Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Read ", 8),
+                    Element::Hyperlink {
+                        title: "https://example.com".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: "".to_string(),
+                        size: 8,
+                    },
+                    text(" first", 8),
+                    Element::Footnote {
+                        label: "note".to_string(),
+                        elements: vec![Element::Paragraph {
+                            elements: vec![text("Checked twice.", 8)],
+                        }],
+                    },
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    crate::core::ListItem::task(text("Write", 8), true),
+                    crate::core::ListItem::task(text("Review", 8), false),
+                ],
+                numbered: false,
+            },
+            Element::Table {
+                name: None,
+                headers: vec![
+                    TableHeader::new(text("Item", 8), 30.0)
+                        .align(crate::core::ColumnAlignment::Left),
+                    TableHeader::new(text("Count", 8), 30.0)
+                        .align(crate::core::ColumnAlignment::Right),
+                ],
+                rows: vec![TableRow {
+                    cells: vec![
+                        TableCell::new(text("Apples", 8)),
+                        TableCell::new(text("3", 8)),
+                    ],
+                }],
+            },
+        ]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let document = crate::core::Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        }]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_name() -> anyhow::Result<()> {
+        let document = crate::core::Document::new(
+            ["Q1 Sales", "Q2 Sales"]
+                .into_iter()
+                .map(|name| Element::Table {
+                    name: Some(name.to_string()),
+                    headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text(&name[..2], 8))],
+                    }],
+                })
+                .collect(),
+        );
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_cells() -> anyhow::Result<()> {
+        let values = vec![
+            CellValue::String("Apples".to_string()),
+            CellValue::Int(42),
+            CellValue::Float(2.5),
+            CellValue::Bool(true),
+            CellValue::DateTime("2024-05-01".to_string()),
+            CellValue::DateTime("2024-05-01T10:30:00".to_string()),
+            CellValue::Duration(45015.0),
+            CellValue::Formula {
+                formula: "B2*2".to_string(),
+                cached: Some(Box::new(CellValue::Float(84.0))),
+            },
+        ];
+        // the formula doubles the number in the second column
+        let headers = (1..=values.len())
+            .map(|column| TableHeader::new(text(&format!("Column {column}"), 8), 10.0))
+            .collect();
+        let cells = values
+            .into_iter()
+            .map(|value| TableCell::with_value(value, 8))
+            .collect();
+        let document = crate::core::Document::new(vec![Element::Table {
+            name: None,
+            headers,
+            rows: vec![TableRow { cells }],
+        }]);
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
 }
@@ -1,6 +1,4 @@
-//!
 //#![doc = include_str!("../README.md")]
-//!
 
 pub mod core;
 
@@ -42,3 +40,9 @@ pub mod ods;
 
 #[cfg(feature = "xls")]
 pub mod xls;
+
+#[cfg(feature = "svg")]
+pub mod svg;
+
+#[cfg(feature = "png")]
+pub mod png;
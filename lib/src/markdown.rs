@@ -3,11 +3,19 @@ use crate::core::*;
 use bytes::Bytes;
 use comrak::arena_tree::Node;
 use comrak::Arena;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeStream};
+use pulldown_cmark::{
+    Alignment, CodeBlockKind, Event, HeadingLevel, LinkType, Options, Parser, Tag, TagEnd,
+    TextMergeStream,
+};
+use regex::Regex;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 pub struct Transformer;
 
+const PAGE_BREAK_HTML: &str = "<div style=\"page-break-after: always\"></div>";
+
 impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
         Transformer::parse_with_loader(document, disk_image_loader("."))
@@ -43,8 +51,8 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             list_depth: &mut i32,
         ) {
             match current_element.as_mut() {
-                Some(element) => match element {
-                    Element::List { elements, numbered } => {
+                Some(element) => {
+                    if let Element::List { elements, numbered } = element {
                         let mut list_elements = elements;
 
                         for _ in 1..*list_depth {
@@ -61,10 +69,12 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         }
                         match &new_el {
                             Element::Hyperlink { .. } | Element::Header { .. } => {
-                                if let Some(ListItem { element }) = list_elements.last() {
-                                    if let Text { .. } = element {
-                                        list_elements.pop();
-                                    }
+                                if let Some(ListItem {
+                                    element: Text { .. },
+                                    ..
+                                }) = list_elements.last()
+                                {
+                                    list_elements.pop();
                                 }
                             }
 
@@ -72,9 +82,8 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         }
 
                         if matches!(new_el, Element::List { .. }) {
-                            let list_item_children = ListItem {
-                                element: create_element_list(None, *numbered),
-                            };
+                            let list_item_children =
+                                ListItem::new(create_element_list(None, *numbered));
 
                             if let Element::List {
                                 ref mut elements, ..
@@ -89,11 +98,10 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             }
                         }
 
-                        let li = ListItem { element: new_el };
+                        let li = ListItem::new(new_el);
                         list_elements.push(li);
                     }
-                    _ => {}
-                },
+                }
                 None => {
                     *current_element = Some(new_el);
                 }
@@ -106,9 +114,12 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         let mut options = Options::empty();
         options.insert(Options::ENABLE_TABLES);
         options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_FOOTNOTES);
         options.insert(Options::ENABLE_SMART_PUNCTUATION);
         options.insert(Options::ENABLE_MATH);
         options.insert(Options::ENABLE_GFM);
+        options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
 
         let parser = Parser::new_ext(document_str, options);
         let md_iterator = TextMergeStream::new(parser);
@@ -116,18 +127,50 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         let mut current_element: Option<Element> = None;
         let mut list_depth = 0;
         let mut table_element: Option<(bool, Element)> = None;
+        let mut text_style = TextStyle::default();
+        // elements outside the blockquotes being parsed, None for quotes inside lists
+        let mut quote_stack: Vec<Option<Vec<Element>>> = Vec::new();
+        let mut front_matter = String::new();
+        let mut in_front_matter = false;
+        let mut column_alignments: Vec<ColumnAlignment> = vec![];
+        // a link inside a paragraph is one of its runs, its text is the link title
+        let mut in_link = false;
+        // label and outer elements of the footnote definition being parsed
+        let mut definition: Option<(String, Vec<Element>)> = None;
+        let mut footnotes: HashMap<String, Vec<Element>> = HashMap::new();
         for event in md_iterator {
+            // YAML front matter is collected and turned into the document metadata
+            match &event {
+                Event::Start(Tag::MetadataBlock(_)) => {
+                    in_front_matter = true;
+                    continue;
+                }
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    in_front_matter = false;
+                    continue;
+                }
+                Event::Text(text) if in_front_matter => {
+                    front_matter.push_str(text);
+                    continue;
+                }
+                _ => {}
+            }
+            // Inline code is handled as a text run with code style
+            let (event, inline_code) = match event {
+                Event::Code(code) => (Event::Text(code), true),
+                event => (event, false),
+            };
             match event {
                 Event::Start(tag) => {
                     match tag {
-                        Tag::Paragraph => {
-                            if !matches!(current_element, Some(Element::List { .. })) {
-                                process_element_creation(
-                                    &mut current_element,
-                                    Element::Paragraph { elements: vec![] },
-                                    &mut list_depth,
-                                );
-                            }
+                        Tag::Paragraph
+                            if !matches!(current_element, Some(Element::List { .. })) =>
+                        {
+                            process_element_creation(
+                                &mut current_element,
+                                Element::Paragraph { elements: vec![] },
+                                &mut list_depth,
+                            );
                         }
                         Tag::Heading { level, .. } => {
                             let level = match level {
@@ -167,6 +210,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             let list_li = Text {
                                 text: "".to_string(),
                                 size: 14,
+                                style: TextStyle::default(),
                             };
 
                             process_element_creation(
@@ -175,8 +219,18 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 &mut list_depth,
                             );
                         }
-                        Tag::Table(_) => {
+                        Tag::Table(alignments) => {
+                            column_alignments = alignments
+                                .into_iter()
+                                .map(|alignment| match alignment {
+                                    Alignment::None => ColumnAlignment::None,
+                                    Alignment::Left => ColumnAlignment::Left,
+                                    Alignment::Center => ColumnAlignment::Center,
+                                    Alignment::Right => ColumnAlignment::Right,
+                                })
+                                .collect();
                             let table_el = Table {
+                                name: None,
                                 headers: vec![],
                                 rows: vec![],
                             };
@@ -188,6 +242,29 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 table.0 = true;
                             }
                         }
+                        Tag::TableRow => {
+                            if let Some((_, Table { rows, .. })) = table_element.as_mut() {
+                                rows.push(TableRow { cells: vec![] });
+                            }
+                        }
+                        Tag::TableCell => match table_element.as_mut() {
+                            Some((true, Table { headers, .. })) => headers.push(TableHeader {
+                                elements: vec![],
+                                width: 30.,
+                                colspan: 1,
+                                rowspan: 1,
+                                alignment: column_alignments
+                                    .get(headers.len())
+                                    .copied()
+                                    .unwrap_or_default(),
+                            }),
+                            Some((false, Table { rows, .. })) => {
+                                if let Some(row) = rows.last_mut() {
+                                    row.cells.push(TableCell::with_elements(vec![]));
+                                }
+                            }
+                            _ => {}
+                        },
                         Tag::Image {
                             dest_url, title, ..
                         } => {
@@ -205,6 +282,27 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             current_element = None;
                             process_element_creation(&mut current_element, img_el, &mut list_depth);
                         }
+                        Tag::Link {
+                            link_type,
+                            dest_url,
+                            title,
+                            ..
+                        } if matches!(current_element, Some(Element::Paragraph { .. })) => {
+                            let url = match link_type {
+                                LinkType::Email => format!("mailto:{dest_url}"),
+                                _ => dest_url.to_string(),
+                            };
+                            if let Some(Element::Paragraph { elements }) = current_element.as_mut()
+                            {
+                                elements.push(Hyperlink {
+                                    title: "".to_string(),
+                                    url,
+                                    alt: title.to_string(),
+                                    size: 14,
+                                });
+                            }
+                            in_link = true;
+                        }
                         Tag::Link {
                             dest_url, title, ..
                         } => {
@@ -221,37 +319,70 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             );
                         }
 
+                        Tag::BlockQuote(_) => {
+                            if matches!(current_element, Some(Element::List { .. })) {
+                                quote_stack.push(None);
+                            } else {
+                                quote_stack.push(Some(std::mem::take(&mut doc_elements)));
+                            }
+                        }
+                        Tag::FootnoteDefinition(label) => {
+                            definition =
+                                Some((label.to_string(), std::mem::take(&mut doc_elements)));
+                        }
+                        Tag::CodeBlock(kind) => {
+                            // only the first word of the info string is the language
+                            let language = match kind {
+                                CodeBlockKind::Fenced(info) => info
+                                    .split_whitespace()
+                                    .next()
+                                    .map(|language| language.to_string()),
+                                CodeBlockKind::Indented => None,
+                            };
+                            process_element_creation(
+                                &mut current_element,
+                                Element::CodeBlock {
+                                    language,
+                                    code: "".to_string(),
+                                },
+                                &mut list_depth,
+                            );
+                        }
+                        Tag::Emphasis => text_style.italic = true,
+                        Tag::Strong => text_style.bold = true,
+                        Tag::Strikethrough => text_style.strikethrough = true,
                         _rest => {
                             // warn!("The tag parsing is not implemented {:#?}", rest);
                         }
                     }
                 }
                 Event::Text(text) => {
+                    let style = TextStyle {
+                        code: inline_code,
+                        ..text_style.clone()
+                    };
                     if let Some(curr_el) = current_element.as_mut() {
                         match curr_el {
-                            Element::Paragraph { ref mut elements } => {
-                                elements.push(Element::Text {
+                            Element::Paragraph { ref mut elements } if in_link => {
+                                if let Some(Hyperlink { title, .. }) = elements.last_mut() {
+                                    title.push_str(&text);
+                                }
+                            }
+                            Element::Paragraph { ref mut elements } if inline_code => elements
+                                .push(Element::Text {
                                     text: text.to_string(),
                                     size: 14,
-                                })
+                                    style: style.clone(),
+                                }),
+                            Element::Paragraph { ref mut elements } => {
+                                elements.extend(split_autolinks(&text, &style))
                             }
                             Element::Header { text: el_text, .. } => {
                                 el_text.push_str(&text);
                             }
+                            Element::CodeBlock { code, .. } => code.push_str(&text),
                             Element::List { elements, .. } => {
-                                let mut li_vec_to_insert = elements;
-                                for _ in 1..list_depth {
-                                    let last_index = li_vec_to_insert.len() - 1;
-                                    if let Element::List {
-                                        elements: ref mut inner_els,
-                                        ..
-                                    } = li_vec_to_insert[last_index].element
-                                    {
-                                        li_vec_to_insert = inner_els;
-                                    } else {
-                                        panic!("Expected a nested list structure at the specified depth");
-                                    }
-                                }
+                                let li_vec_to_insert = nested_list_items(elements, list_depth);
 
                                 let li = li_vec_to_insert.last_mut().unwrap();
 
@@ -269,6 +400,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                     } => {
                                         *header_text = text.to_string();
                                     }
+                                    Element::CodeBlock { code, .. } => code.push_str(&text),
                                     _ => {}
                                 }
                             }
@@ -279,67 +411,40 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             _ => {}
                         }
                     }
-                    match table_element {
-                        Some(ref mut t_el) => {
-                            if let (is_header, Element::Table { headers, rows }) = t_el {
-                                if *is_header {
-                                    headers.push(TableHeader {
-                                        element: Text {
-                                            text: text.to_string(),
-                                            size: 14,
-                                        },
-                                        width: 30.,
-                                    })
-                                } else {
-                                    let last_row = rows.last_mut();
-
-                                    match last_row {
-                                        Some(tr) => {
-                                            if tr.cells.len() == headers.len() {
-                                                rows.push(TableRow {
-                                                    cells: vec![TableCell {
-                                                        element: Text {
-                                                            text: text.to_string(),
-                                                            size: 14,
-                                                        },
-                                                    }],
-                                                });
-                                            } else {
-                                                tr.cells.push(TableCell {
-                                                    element: Text {
-                                                        text: text.to_string(),
-                                                        size: 14,
-                                                    },
-                                                });
-                                            }
-                                        }
-                                        None => {
-                                            rows.push(TableRow {
-                                                cells: vec![TableCell {
-                                                    element: Text {
-                                                        text: text.to_string(),
-                                                        size: 14,
-                                                    },
-                                                }],
-                                            });
-                                        }
-                                    }
-                                }
-                            }
+                    if let Some((is_header, Element::Table { headers, rows, .. })) =
+                        &mut table_element
+                    {
+                        let cell_elements = if *is_header {
+                            headers.last_mut().map(|header| &mut header.elements)
+                        } else {
+                            rows.last_mut()
+                                .and_then(|row| row.cells.last_mut())
+                                .map(|cell| &mut cell.elements)
+                        };
+                        if let Some(cell_elements) = cell_elements {
+                            cell_elements.push(Text {
+                                text: text.to_string(),
+                                size: 14,
+                                style: style.clone(),
+                            });
                         }
-                        None => {}
                     }
                 }
                 Event::End(tag) => match tag {
-                    TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Link | TagEnd::Image => {
-                        if !matches!(current_element, Some(Element::List { .. })) {
-                            let curr_el = current_element.take();
-                            if let Some(curr_el) = curr_el {
-                                match curr_el {
-                                    List { .. } => current_element = Some(curr_el),
-                                    _ => {
-                                        doc_elements.push(curr_el);
-                                    }
+                    TagEnd::Link if in_link => in_link = false,
+                    TagEnd::Paragraph
+                    | TagEnd::Heading(_)
+                    | TagEnd::Link
+                    | TagEnd::Image
+                    | TagEnd::CodeBlock
+                        if !matches!(current_element, Some(Element::List { .. })) =>
+                    {
+                        let curr_el = current_element.take();
+                        if let Some(curr_el) = curr_el {
+                            match curr_el {
+                                List { .. } => current_element = Some(curr_el),
+                                _ => {
+                                    doc_elements.push(curr_el);
                                 }
                             }
                         }
@@ -354,6 +459,25 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             }
                         }
                     }
+                    // the runs of a cell form a single paragraph
+                    TagEnd::TableCell => {
+                        let cell_elements = match table_element.as_mut() {
+                            Some((true, Table { headers, .. })) => {
+                                headers.last_mut().map(|header| &mut header.elements)
+                            }
+                            Some((false, Table { rows, .. })) => rows
+                                .last_mut()
+                                .and_then(|row| row.cells.last_mut())
+                                .map(|cell| &mut cell.elements),
+                            _ => None,
+                        };
+                        if let Some(cell_elements) = cell_elements {
+                            if cell_elements.len() > 1 {
+                                let elements = std::mem::take(cell_elements);
+                                cell_elements.push(Element::Paragraph { elements });
+                            }
+                        }
+                    }
                     TagEnd::TableHead => {
                         if let Some((is_header, _t_el)) = &mut table_element {
                             *is_header = false;
@@ -364,6 +488,52 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             doc_elements.push(t_el);
                         }
                     }
+                    TagEnd::FootnoteDefinition => {
+                        if let Some((label, outer)) = definition.take() {
+                            let elements = std::mem::replace(&mut doc_elements, outer);
+                            footnotes.insert(label, elements);
+                        }
+                    }
+                    TagEnd::BlockQuote => {
+                        if let Some(Some(outer)) = quote_stack.pop() {
+                            let elements = std::mem::replace(&mut doc_elements, outer);
+                            doc_elements.push(Element::Blockquote { elements });
+                        }
+                    }
+                    TagEnd::Emphasis => text_style.italic = false,
+                    TagEnd::Strong => text_style.bold = false,
+                    TagEnd::Strikethrough => text_style.strikethrough = false,
+                    _ => {}
+                },
+                // the note is filled in from its definition once the whole document is read
+                Event::FootnoteReference(label) => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::Footnote {
+                            label: label.to_string(),
+                            elements: vec![],
+                        });
+                    }
+                }
+                Event::TaskListMarker(checked) => {
+                    if let Some(Element::List { elements, .. }) = current_element.as_mut() {
+                        if let Some(item) = nested_list_items(elements, list_depth).last_mut() {
+                            item.checked = Some(checked);
+                        }
+                    }
+                }
+                Event::Rule if current_element.is_none() => {
+                    doc_elements.push(Element::ThematicBreak);
+                }
+                Event::Html(html)
+                    if current_element.is_none()
+                        && (html.contains("page-break-after: always")
+                            || html.contains("page-break-before: always")) =>
+                {
+                    doc_elements.push(Element::PageBreak);
+                }
+                Event::InlineHtml(html) => match html.trim() {
+                    "<u>" => text_style.underline = true,
+                    "</u>" => text_style.underline = false,
                     _ => {}
                 },
 
@@ -371,7 +541,10 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             }
         }
 
-        Ok(Document::new(doc_elements))
+        fill_footnotes(&mut doc_elements, &footnotes);
+        let mut document = Document::new(doc_elements);
+        document.set_metadata(parse_front_matter(&front_matter));
+        Ok(document)
     }
 
     fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
@@ -395,25 +568,276 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             function: &image_saver,
         };
 
+        if !document.metadata.is_empty() {
+            root.append(arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FrontMatter(generate_front_matter(&document.metadata)),
+                LineColumn { line: 0, column: 0 },
+            )))));
+        }
+
         let all_elements: Vec<&Element> = document.get_all_elements();
 
-        for element in all_elements {
-            let node = element_to_ast_node(&arena, element, &image_num, &image_saver)?;
-            root.append(node);
+        for element in all_elements.iter() {
+            append_block_element(&arena, root, element, &image_num, &image_saver)?;
+        }
+
+        // the notes are written once, after the text, in the order they are first referenced
+        let mut footnotes: Vec<(&str, &[Element])> = vec![];
+        for element in all_elements.iter() {
+            collect_footnotes(std::slice::from_ref(*element), &mut footnotes);
+        }
+        for (label, elements) in footnotes {
+            let definition = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FootnoteDefinition(NodeFootnoteDefinition {
+                    name: label.to_string(),
+                    total_references: 0,
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for element in elements {
+                append_block_element(&arena, definition, element, &image_num, &image_saver)?;
+            }
+            root.append(definition);
         }
 
         let mut md = vec![];
 
-        format_commonmark(root, &Options::default(), &mut md)?;
+        let mut options = Options::default();
+        options.extension.strikethrough = true;
+        options.extension.table = true;
+        options.extension.tasklist = true;
+        options.extension.autolink = true;
+        options.extension.footnotes = true;
+        // code blocks without a language would otherwise be written indented
+        options.render.prefer_fenced = true;
+        format_commonmark(root, &options, &mut md)?;
 
         Ok(Bytes::from(md))
     }
 }
 
 use comrak::nodes::{
-    Ast, AstNode, LineColumn, NodeHeading, NodeLink, NodeList, NodeTable, NodeValue, TableAlignment,
+    Ast, AstNode, LineColumn, NodeCode, NodeCodeBlock, NodeFootnoteDefinition,
+    NodeFootnoteReference, NodeHeading, NodeHtmlBlock, NodeLink, NodeList, NodeTable, NodeValue,
+    TableAlignment,
 };
 
+// Only flat `key: value` pairs are supported, keywords may be a flow or a block sequence
+fn parse_front_matter(yaml: &str) -> DocumentMetadata {
+    fn unquote(value: &str) -> String {
+        let value = value.trim();
+        let quoted = value.len() >= 2
+            && ((value.starts_with('"') && value.ends_with('"'))
+                || (value.starts_with('\'') && value.ends_with('\'')));
+        if quoted {
+            value[1..value.len() - 1].replace("\\\"", "\"")
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut metadata = DocumentMetadata::default();
+    let mut current_key = String::new();
+    for line in yaml.lines() {
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if current_key == "keywords" {
+                metadata.keywords.push(unquote(item));
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        current_key = key.trim().to_lowercase();
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match current_key.as_str() {
+            "title" => metadata.title = Some(unquote(value)),
+            "author" => metadata.author = Some(unquote(value)),
+            "subject" => metadata.subject = Some(unquote(value)),
+            "description" => metadata.description = Some(unquote(value)),
+            "lang" | "language" => metadata.language = Some(unquote(value)),
+            "date" | "created" => metadata.created = Some(unquote(value)),
+            "modified" => metadata.modified = Some(unquote(value)),
+            "keywords" => {
+                metadata.keywords = value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(unquote)
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+fn generate_front_matter(metadata: &DocumentMetadata) -> String {
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    }
+
+    let fields = [
+        ("title", &metadata.title),
+        ("author", &metadata.author),
+        ("subject", &metadata.subject),
+        ("description", &metadata.description),
+        ("language", &metadata.language),
+        ("created", &metadata.created),
+        ("modified", &metadata.modified),
+    ];
+    let mut yaml = String::from("---\n");
+    for (key, value) in fields {
+        if let Some(value) = value {
+            yaml.push_str(&format!("{}: {}\n", key, quote(value)));
+        }
+    }
+    if !metadata.keywords.is_empty() {
+        yaml.push_str("keywords:\n");
+        for keyword in metadata.keywords.iter() {
+            yaml.push_str(&format!("  - {}\n", quote(keyword)));
+        }
+    }
+    yaml.push_str("---\n\n");
+    yaml
+}
+
+// Items of the innermost list being parsed, nested lists are the last item of their parent
+fn nested_list_items(elements: &mut Vec<ListItem>, list_depth: i32) -> &mut Vec<ListItem> {
+    let mut list_items = elements;
+    for _ in 1..list_depth {
+        let last_index = list_items.len() - 1;
+        if let Element::List {
+            elements: ref mut inner_els,
+            ..
+        } = list_items[last_index].element
+        {
+            list_items = inner_els;
+        } else {
+            panic!("Expected a nested list structure at the specified depth");
+        }
+    }
+    list_items
+}
+
+// Bare urls and www. addresses in text become links, as GFM autolinks them
+fn split_autolinks(text: &str, style: &TextStyle) -> Vec<Element> {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    let url_regex = URL_REGEX.get_or_init(|| Regex::new(r"(?:https?://|www\.)[^\s<]+").unwrap());
+    let text_run = |text: &str| Text {
+        text: text.to_string(),
+        size: 14,
+        style: style.clone(),
+    };
+    let mut elements = vec![];
+    let mut start = 0;
+    for url_match in url_regex.find_iter(text) {
+        // trailing punctuation and unbalanced closing parentheses are not part of the url
+        let mut url = url_match.as_str();
+        loop {
+            let trimmed =
+                url.trim_end_matches(['.', ',', ':', ';', '!', '?', '*', '_', '~', '\'', '"']);
+            let unbalanced = trimmed.matches(')').count() > trimmed.matches('(').count();
+            let next = match trimmed.strip_suffix(')') {
+                Some(inner) if unbalanced => inner,
+                _ => trimmed,
+            };
+            if next == url {
+                break;
+            }
+            url = next;
+        }
+        if url.len() <= "www.".len() {
+            continue;
+        }
+        if url_match.start() > start {
+            elements.push(text_run(&text[start..url_match.start()]));
+        }
+        elements.push(Hyperlink {
+            title: url.to_string(),
+            url: if url.starts_with("www.") {
+                format!("http://{url}")
+            } else {
+                url.to_string()
+            },
+            alt: "".to_string(),
+            size: 14,
+        });
+        start = url_match.start() + url.len();
+    }
+    if start < text.len() {
+        elements.push(text_run(&text[start..]));
+    }
+    elements
+}
+
+// Footnote references get the blocks of their definition, unknown labels keep no content
+fn fill_footnotes(elements: &mut [Element], footnotes: &HashMap<String, Vec<Element>>) {
+    for element in elements {
+        match element {
+            Element::Footnote { label, elements } => {
+                if let Some(definition) = footnotes.get(label) {
+                    *elements = definition.clone();
+                }
+            }
+            Element::Paragraph { elements } | Element::Blockquote { elements } => {
+                fill_footnotes(elements, footnotes)
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    fill_footnotes(std::slice::from_mut(&mut item.element), footnotes);
+                }
+            }
+            Element::Table { headers, rows, .. } => {
+                for header in headers {
+                    fill_footnotes(&mut header.elements, footnotes);
+                }
+                for row in rows {
+                    for cell in &mut row.cells {
+                        fill_footnotes(&mut cell.elements, footnotes);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_footnotes<'e>(elements: &'e [Element], footnotes: &mut Vec<(&'e str, &'e [Element])>) {
+    for element in elements {
+        match element {
+            Element::Footnote { label, elements }
+                if !footnotes.iter().any(|(known, _)| known == label) =>
+            {
+                footnotes.push((label, elements))
+            }
+            Element::Paragraph { elements } | Element::Blockquote { elements } => {
+                collect_footnotes(elements, footnotes)
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    collect_footnotes(std::slice::from_ref(&item.element), footnotes);
+                }
+            }
+            Element::Table { headers, rows, .. } => {
+                for header in headers {
+                    collect_footnotes(&header.elements, footnotes);
+                }
+                for row in rows {
+                    for cell in &row.cells {
+                        collect_footnotes(&cell.elements, footnotes);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 fn is_parent_list(list_item: &ListItem) -> bool {
     if let Element::List { elements, .. } = &list_item.element {
         let first = elements.first();
@@ -430,9 +854,14 @@ fn is_parent_list(list_item: &ListItem) -> bool {
     false
 }
 
-fn create_item_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a AstNode<'a> {
-    let item_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-        NodeValue::Item(NodeList {
+fn create_item_node<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    numbered: bool,
+    checked: Option<bool>,
+) -> &'a AstNode<'a> {
+    let value = match checked {
+        Some(checked) => NodeValue::TaskItem(checked.then_some('x')),
+        None => NodeValue::Item(NodeList {
             list_type: if numbered {
                 comrak::nodes::ListType::Ordered
             } else {
@@ -442,6 +871,9 @@ fn create_item_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
             tight: true,
             ..Default::default()
         }),
+    };
+    let item_node = arena.alloc(Node::new(RefCell::new(Ast::new(
+        value,
         LineColumn { line: 0, column: 0 },
     ))));
 
@@ -470,18 +902,185 @@ fn create_list_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
 }
 
 fn text_to_paragraph(element: Element) -> Element {
-    if let Element::Text { text, .. } = element {
+    if let Element::Text { .. } = element {
         Element::Paragraph {
-            elements: vec![Element::Text {
-                text: text.to_string(),
-                size: 14,
-            }],
+            elements: vec![element],
         }
     } else {
         element
     }
 }
 
+fn append_styled_text<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    parent: &'a AstNode<'a>,
+    text: &str,
+    style: &TextStyle,
+) {
+    let new_node = |value: NodeValue| {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            value,
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+
+    let mut node: &'a AstNode<'a> = if style.code {
+        new_node(NodeValue::Code(NodeCode {
+            num_backticks: 1,
+            literal: text.to_string(),
+        }))
+    } else {
+        new_node(NodeValue::Text(text.to_string()))
+    };
+
+    let wrappers = [
+        (style.strikethrough, NodeValue::Strikethrough),
+        (style.italic, NodeValue::Emph),
+        (style.bold, NodeValue::Strong),
+    ];
+    for (enabled, value) in wrappers {
+        if enabled {
+            let wrapper = new_node(value);
+            wrapper.append(node);
+            node = wrapper;
+        }
+    }
+
+    // Markdown has no underline syntax, so it is kept as inline html
+    if style.underline {
+        parent.append(new_node(NodeValue::HtmlInline("<u>".to_string())));
+        parent.append(node);
+        parent.append(new_node(NodeValue::HtmlInline("</u>".to_string())));
+    } else {
+        parent.append(node);
+    }
+}
+
+fn append_inline_element<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    parent: &'a AstNode<'a>,
+    element: &Element,
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    match element {
+        Element::Text { text, style, .. } => append_styled_text(arena, parent, text, style),
+        // Markdown has no syntax for tracked changes, they are kept as inline html like underlines
+        Element::Revision { kind, elements, .. } => {
+            let tag = match kind {
+                RevisionKind::Insertion => "ins",
+                RevisionKind::Deletion => "del",
+            };
+            let html = |html: String| {
+                arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::HtmlInline(html),
+                    LineColumn { line: 0, column: 0 },
+                ))))
+            };
+            parent.append(html(format!("<{tag}>")));
+            for child in elements {
+                append_inline_element(arena, parent, child, image_num, image_saver)?;
+            }
+            parent.append(html(format!("</{tag}>")));
+        }
+        Element::Footnote { label, .. } => {
+            parent.append(arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FootnoteReference(NodeFootnoteReference {
+                    name: label.clone(),
+                    ..Default::default()
+                }),
+                LineColumn { line: 0, column: 0 },
+            )))));
+        }
+        _ => parent.append(element_to_ast_node(arena, element, image_num, image_saver)?),
+    }
+    Ok(())
+}
+
+fn place_in_grid<'e>(
+    grid: &mut Vec<&'e [Element]>,
+    column: usize,
+    colspan: usize,
+    elements: &'e [Element],
+) {
+    if grid.len() < column + colspan {
+        grid.resize(column + colspan, &[]);
+    }
+    grid[column] = elements;
+}
+
+// Table cells only hold inline content, blocks are flattened and separated by <br>
+fn append_cell_elements<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    cell: &'a AstNode<'a>,
+    elements: &[Element],
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    let is_inline = |element: &Element| {
+        matches!(
+            element,
+            Element::Text { .. } | Element::Hyperlink { .. } | Element::Image(_)
+        )
+    };
+    for (index, element) in elements.iter().enumerate() {
+        if index > 0 {
+            cell.append(arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlInline("<br>".to_string()),
+                LineColumn { line: 0, column: 0 },
+            )))));
+        }
+        match element {
+            Element::Paragraph { elements } => {
+                for child in elements {
+                    append_inline_element(arena, cell, child, image_num, image_saver)?;
+                }
+            }
+            element if is_inline(element) => {
+                append_inline_element(arena, cell, element, image_num, image_saver)?
+            }
+            element => append_styled_text(
+                arena,
+                cell,
+                &plain_text(std::slice::from_ref(element)).replace('\n', " "),
+                &TextStyle::default(),
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Inline nodes are not allowed directly in block containers, they are wrapped in a paragraph
+fn append_block_element<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    parent: &'a AstNode<'a>,
+    element: &Element,
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    let node = element_to_ast_node(arena, element, image_num, image_saver)?;
+    if node.data.borrow().value.block() {
+        parent.append(node);
+    } else {
+        let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::Paragraph,
+            LineColumn { line: 0, column: 0 },
+        ))));
+        paragraph.append(node);
+        parent.append(paragraph);
+    }
+    Ok(())
+}
+
 fn element_to_ast_node<'a, F>(
     arena: &'a Arena<AstNode<'a>>,
     element: &Element,
@@ -492,19 +1091,19 @@ where
     F: Fn(&Bytes, &str) -> anyhow::Result<()>,
 {
     match element {
-        Element::Text { text, .. } => {
-            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                NodeValue::Text(text.to_string()),
+        Element::Text { text, style, .. } => {
+            let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Paragraph,
                 LineColumn { line: 0, column: 0 },
             ))));
-
-            Ok(node)
+            append_styled_text(arena, paragraph, text, style);
+            Ok(paragraph)
         }
 
         Element::Header { level, text } => {
             let heading = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Heading(NodeHeading {
-                    level: *level as u8,
+                    level: *level,
                     setext: false,
                 }),
                 LineColumn { line: 0, column: 0 },
@@ -524,8 +1123,7 @@ where
             ))));
 
             for child_element in elements {
-                let child_node = element_to_ast_node(arena, child_element, image_num, image_saver)?;
-                paragraph.append(child_node);
+                append_inline_element(arena, paragraph, child_element, image_num, image_saver)?;
             }
             Ok(paragraph)
         }
@@ -533,14 +1131,13 @@ where
         Element::List { elements, numbered } => {
             let list_node = create_list_node(arena, *numbered);
             for list_item in elements {
-                let item_node = create_item_node(arena, *numbered);
-
                 if is_parent_list(list_item) {
                     if let Element::List { elements, .. } = &list_item.element {
                         let first = elements.first();
                         let second = elements.get(1);
 
                         if let (Some(parent), Some(children)) = (first, second) {
+                            let item_node = create_item_node(arena, *numbered, parent.checked);
                             let children_node = element_to_ast_node(
                                 arena,
                                 &children.element,
@@ -564,6 +1161,7 @@ where
                         }
                     }
                 } else {
+                    let item_node = create_item_node(arena, *numbered, list_item.checked);
                     let list_item_element = text_to_paragraph(list_item.element.clone());
 
                     let list_item_content =
@@ -611,53 +1209,127 @@ where
             Ok(link_node)
         }
 
-        Element::Table { headers, rows } => {
-            let num_columns = headers.len() as u32;
+        Element::Blockquote { elements } => {
+            let quote_node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::BlockQuote,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for child in elements {
+                append_block_element(arena, quote_node, child, image_num, image_saver)?;
+            }
+            Ok(quote_node)
+        }
+
+        Element::ThematicBreak => Ok(arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::ThematicBreak,
+            LineColumn { line: 0, column: 0 },
+        ))))),
+
+        // Markdown has no page break syntax, the html one is understood by most renderers
+        Element::PageBreak => Ok(arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: format!("{PAGE_BREAK_HTML}\n"),
+            }),
+            LineColumn { line: 0, column: 0 },
+        ))))),
+
+        Element::Revision { .. } | Element::Footnote { .. } => {
+            let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Paragraph,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            append_inline_element(arena, paragraph, element, image_num, image_saver)?;
+            Ok(paragraph)
+        }
+
+        Element::Comment {
+            author,
+            date,
+            text,
+            anchor,
+        } => Ok(arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 2,
+                // "--" cannot appear inside an html comment
+                literal: format!(
+                    "<!-- {} -->\n",
+                    comment_summary(author, date, text, anchor).replace("--", "- -")
+                ),
+            }),
+            LineColumn { line: 0, column: 0 },
+        ))))),
+
+        Element::CodeBlock { language, code } => {
+            let code_block_node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::CodeBlock(NodeCodeBlock {
+                    fenced: true,
+                    fence_char: b'`',
+                    fence_length: 3,
+                    fence_offset: 0,
+                    info: language.clone().unwrap_or_default(),
+                    literal: code.clone(),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(code_block_node)
+        }
+
+        Element::Table { headers, rows, .. } => {
+            // markdown has no merged cells, spanning cells are followed by empty ones
+            let (header_columns, row_columns) = table_columns(headers, rows);
+            let mut header_grid = vec![];
+            for (header, column) in headers.iter().zip(&header_columns) {
+                place_in_grid(&mut header_grid, *column, header.colspan, &header.elements);
+            }
+            let mut row_grids = vec![];
+            for (row, columns) in rows.iter().zip(row_columns) {
+                let mut grid = vec![];
+                for (cell, column) in row.cells.iter().zip(columns) {
+                    place_in_grid(&mut grid, column, cell.colspan, &cell.elements);
+                }
+                row_grids.push(grid);
+            }
+            let num_columns = row_grids
+                .iter()
+                .map(Vec::len)
+                .chain([header_grid.len()])
+                .max()
+                .unwrap_or(0);
             let num_rows = rows.len() as u32 + 1;
 
-            let alignments = vec![TableAlignment::None; num_columns as usize];
+            let mut alignments = vec![TableAlignment::None; num_columns];
+            for (header, column) in headers.iter().zip(&header_columns) {
+                alignments[*column] = match header.alignment {
+                    ColumnAlignment::None => TableAlignment::None,
+                    ColumnAlignment::Left => TableAlignment::Left,
+                    ColumnAlignment::Center => TableAlignment::Center,
+                    ColumnAlignment::Right => TableAlignment::Right,
+                };
+            }
 
             let table_node = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Table(NodeTable {
                     alignments,
-                    num_columns: num_columns as usize,
+                    num_columns,
                     num_rows: num_rows as usize,
                     num_nonempty_cells: 0, // Adjust as needed
                 }),
                 LineColumn { line: 0, column: 0 },
             ))));
 
-            // Header row
-            let header_row_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                NodeValue::TableRow(true), // Indicate header row
-                LineColumn { line: 0, column: 0 },
-            ))));
-            for header in headers {
-                let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                    NodeValue::TableCell,
-                    LineColumn { line: 0, column: 0 },
-                ))));
-                let cell_content =
-                    element_to_ast_node(arena, &header.element, image_num, image_saver)?;
-                cell_node.append(cell_content);
-                header_row_node.append(cell_node);
-            }
-            table_node.append(header_row_node);
-
-            // Data rows
-            for row in rows {
+            for (index, mut grid) in [header_grid].into_iter().chain(row_grids).enumerate() {
                 let row_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                    NodeValue::TableRow(false), // Indicate data row
+                    NodeValue::TableRow(index == 0), // the first row is the header row
                     LineColumn { line: 0, column: 0 },
                 ))));
-                for cell in &row.cells {
+                grid.resize(num_columns, &[]);
+                for elements in grid {
                     let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
                         NodeValue::TableCell,
                         LineColumn { line: 0, column: 0 },
                     ))));
-                    let cell_content =
-                        element_to_ast_node(arena, &cell.element, image_num, image_saver)?;
-                    cell_node.append(cell_content);
+                    append_cell_elements(arena, cell_node, elements, image_num, image_saver)?;
                     row_node.append(cell_node);
                 }
                 table_node.append(row_node);
@@ -665,26 +1337,15 @@ where
 
             Ok(table_node)
         }
-
-        _ => {
-            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                NodeValue::Text("".to_string()),
-                LineColumn { line: 0, column: 0 },
-            ))));
-            Ok(node)
-        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use core::str;
-
     use log::debug;
     use log::info;
-    use serde_xml_rs::to_string;
 
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, text};
     use crate::core::*;
     use crate::html;
     use crate::markdown::*;
@@ -792,53 +1453,52 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
 | Paragraph   | Text        |
           "#;
         let elements = vec![Table {
+            name: None,
             headers: vec![
-                TableHeader {
-                    element: Text {
+                TableHeader::new(
+                    Text {
                         text: "Syntax".to_string(),
                         size: 14,
+                        style: TextStyle::default(),
                     },
-                    width: 30.0,
-                },
-                TableHeader {
-                    element: Text {
+                    30.0,
+                ),
+                TableHeader::new(
+                    Text {
                         text: "Description".to_string(),
                         size: 14,
+                        style: TextStyle::default(),
                     },
-                    width: 30.0,
-                },
+                    30.0,
+                ),
             ],
             rows: vec![
                 TableRow {
                     cells: vec![
-                        TableCell {
-                            element: Text {
-                                text: "Header".to_string(),
-                                size: 14,
-                            },
-                        },
-                        TableCell {
-                            element: Text {
-                                text: "Title".to_string(),
-                                size: 14,
-                            },
-                        },
+                        TableCell::new(Text {
+                            text: "Header".to_string(),
+                            size: 14,
+                            style: TextStyle::default(),
+                        }),
+                        TableCell::new(Text {
+                            text: "Title".to_string(),
+                            size: 14,
+                            style: TextStyle::default(),
+                        }),
                     ],
                 },
                 TableRow {
                     cells: vec![
-                        TableCell {
-                            element: Text {
-                                text: "Paragraph".to_string(),
-                                size: 14,
-                            },
-                        },
-                        TableCell {
-                            element: Text {
-                                text: "Text".to_string(),
-                                size: 14,
-                            },
-                        },
+                        TableCell::new(Text {
+                            text: "Paragraph".to_string(),
+                            size: 14,
+                            style: TextStyle::default(),
+                        }),
+                        TableCell::new(Text {
+                            text: "Text".to_string(),
+                            size: 14,
+                            style: TextStyle::default(),
+                        }),
                     ],
                 },
             ],
@@ -854,6 +1514,50 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         assert_eq!(parsed, result_doc)
     }
 
+    #[test]
+    fn test_parse_text_style() -> anyhow::Result<()> {
+        let document = "Plain **bold** *italic* ~~strike~~ <u>underline</u> `code`";
+        let text = |text: &str, style: TextStyle| Text {
+            text: text.to_string(),
+            size: 14,
+            style,
+        };
+        let expected = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                text("Plain ", TextStyle::default()),
+                text("bold", TextStyle::bold()),
+                text(" ", TextStyle::default()),
+                text("italic", TextStyle::italic()),
+                text(" ", TextStyle::default()),
+                text(
+                    "strike",
+                    TextStyle {
+                        strikethrough: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(" ", TextStyle::default()),
+                text(
+                    "underline",
+                    TextStyle {
+                        underline: true,
+                        ..TextStyle::default()
+                    },
+                ),
+                text(" ", TextStyle::default()),
+                text("code", TextStyle::code()),
+            ],
+        }]);
+
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        assert_eq!(parsed, expected);
+
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed, expected);
+        Ok(())
+    }
+
     #[test]
     fn test_html_to_markdown_to_cdm() -> anyhow::Result<()> {
         init_logger();
@@ -901,8 +1605,261 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
             Transformer::parse_with_loader(&parsed_html_bytes, disk_image_loader("test/data"));
         info!("{:#?}", doc_from_markdown);
         info!("{}", std::str::from_utf8(&parsed_html_bytes)?);
-        assert!(true);
+        assert!(doc_from_markdown.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_front_matter() -> anyhow::Result<()> {
+        let document = "---\ntitle: Report\nauthor: \"John Doe\"\nkeywords: [rust, shiva]\nlang: en\n---\n\nText\n";
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        let expected = DocumentMetadata {
+            title: Some("Report".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(parsed.metadata, expected);
+        assert_eq!(parsed.get_all_elements().len(), 1);
+
+        let generated = Transformer::generate(&parsed)?;
+        let generated_text = std::str::from_utf8(&generated)?;
+        assert!(generated_text.starts_with("---\ntitle: \"Report\"\n"));
+        assert_eq!(Transformer::parse(&generated)?, parsed);
+        Ok(())
+    }
 
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document =
+            "Text\n\n```rust\nfn main() {\n    println!(\"Hello\");\n}\n```\n\n```\n  plain\n```\n";
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[1],
+            &Element::CodeBlock {
+                language: Some("rust".to_string()),
+                code: "fn main() {\n    println!(\"Hello\");\n}\n".to_string(),
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::CodeBlock {
+                language: None,
+                code: "  plain\n".to_string(),
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let generated_text = std::str::from_utf8(&generated)?;
+        assert!(generated_text.contains("``` rust\n"));
+        assert_eq!(Transformer::parse(&generated)?, parsed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote_and_breaks() -> anyhow::Result<()> {
+        let document = "Before\n\n> Quoted *text*\n>\n> > Nested\n\n---\n\n<div style=\"page-break-after: always\"></div>\n\nAfter\n";
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        let text = |text: &str, style: TextStyle| Element::Text {
+            text: text.to_string(),
+            size: 14,
+            style,
+        };
+        let paragraph = |elements: Vec<Element>| Element::Paragraph { elements };
+        let expected = Document::new(vec![
+            paragraph(vec![text("Before", TextStyle::default())]),
+            Element::Blockquote {
+                elements: vec![
+                    paragraph(vec![
+                        text("Quoted ", TextStyle::default()),
+                        text("text", TextStyle::italic()),
+                    ]),
+                    Element::Blockquote {
+                        elements: vec![paragraph(vec![text("Nested", TextStyle::default())])],
+                    },
+                ],
+            },
+            Element::ThematicBreak,
+            Element::PageBreak,
+            paragraph(vec![text("After", TextStyle::default())]),
+        ]);
+        assert_eq!(parsed, expected);
+
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(Transformer::parse(&generated)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 14), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 14), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 14)).span(1, 2),
+                        TableCell::new(text("First", 14)),
+                        TableCell::new(text("1", 14)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 14)),
+                        TableCell::new(text("2", 14)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![TableCell::with_elements(vec![
+                        text("Sum", 14),
+                        text("of all", 14),
+                    ])
+                    .span(3, 1)],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let generated = str::from_utf8(&generated)?;
+        assert!(generated.contains("| Name |  | Total |"));
+        assert!(generated.contains("|  | Second | 2 |"));
+        assert!(generated.contains("| Sum<br>of all |  |  |"));
+
+        let parsed = Transformer::parse(&Bytes::from("| A | **B** c |\n|---|---|\n| 1 | 2 |\n"))?;
+        let Table { headers, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        assert_eq!(headers.len(), 2);
+        assert!(matches!(
+            headers[1].elements[..],
+            [Element::Paragraph { .. }]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gfm_extensions() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Read ", 14),
+                    Element::Hyperlink {
+                        title: "https://example.com".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: "".to_string(),
+                        size: 14,
+                    },
+                    text(" first", 14),
+                    Element::Footnote {
+                        label: "note".to_string(),
+                        elements: vec![Element::Paragraph {
+                            elements: vec![text("Checked twice.", 14)],
+                        }],
+                    },
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem::task(text("Write", 14), true),
+                    ListItem::task(text("Review", 14), false),
+                ],
+                numbered: false,
+            },
+            Element::Table {
+                name: None,
+                headers: vec![
+                    TableHeader::new(text("Item", 14), 30.0).align(ColumnAlignment::Left),
+                    TableHeader::new(text("Count", 14), 30.0).align(ColumnAlignment::Right),
+                ],
+                rows: vec![TableRow {
+                    cells: vec![
+                        TableCell::new(text("Apples", 14)),
+                        TableCell::new(text("3", 14)),
+                    ],
+                }],
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated_text = str::from_utf8(&generated)?;
+        assert!(generated_text.contains("Read <https://example.com> first[^note]"));
+        assert!(generated_text.contains("- [x] Write\n- [ ] Review\n"));
+        assert!(generated_text.contains("| :-- | --: |"));
+        assert!(generated_text.contains("[^note]:\n    Checked twice.\n"));
+        assert_eq!(Transformer::parse(&generated)?, document);
+
+        let parsed = Transformer::parse(&Bytes::from(
+            "~~Old~~ see www.example.com/a_(b), or <mail@example.com>.\n",
+        ))?;
+        let text = |text: &str, style: TextStyle| Text {
+            text: text.to_string(),
+            size: 14,
+            style,
+        };
+        let link = |title: &str, url: &str| Hyperlink {
+            title: title.to_string(),
+            url: url.to_string(),
+            alt: "".to_string(),
+            size: 14,
+        };
+        let strikethrough = TextStyle {
+            strikethrough: true,
+            ..TextStyle::default()
+        };
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![&Element::Paragraph {
+                elements: vec![
+                    text("Old", strikethrough),
+                    text(" see ", TextStyle::default()),
+                    link("www.example.com/a_(b)", "http://www.example.com/a_(b)"),
+                    text(", or ", TextStyle::default()),
+                    link("mail@example.com", "mailto:mail@example.com"),
+                    text(".", TextStyle::default()),
+                ],
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_review() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Payment within", 14),
+                    Element::Revision {
+                        kind: RevisionKind::Deletion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("30", 14)],
+                    },
+                    Element::Revision {
+                        kind: RevisionKind::Insertion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("60", 14)],
+                    },
+                    text("days", 14),
+                ],
+            },
+            Element::Comment {
+                author: Some("John Doe".to_string()),
+                date: None,
+                text: "Is this agreed?".to_string(),
+                anchor: "60 days".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = str::from_utf8(&generated)?;
+        assert!(generated.contains("Payment within<del>30</del><ins>60</ins>days"));
+        assert!(
+            generated.contains("<!-- Comment by John Doe about \"60 days\": Is this agreed? -->")
+        );
         Ok(())
     }
 }
@@ -2,9 +2,14 @@ use crate::core::Element::{Table, Text};
 use crate::core::*;
 use bytes::Bytes;
 use calamine::{open_workbook_from_rs, Ods, Reader};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use icu_locid::locale;
-use log::error;
-use spreadsheet_ods::{write_ods_buf, Sheet, WorkBook};
+use log::{error, warn};
+use spreadsheet_ods::metadata::Metadata;
+use spreadsheet_ods::style::units::{Margin, PrintOrientation};
+use spreadsheet_ods::style::{MasterPage, PageStyle, TableStyle, TableStyleRef};
+use spreadsheet_ods::{read_ods_buf, write_ods_buf, Length, Sheet, Value, WorkBook};
+use std::collections::HashSet;
 use std::io::Cursor;
 use std::vec;
 
@@ -20,44 +25,86 @@ impl TransformerTrait for Transformer {
         let mut workbook: Ods<Cursor<Bytes>> =
             open_workbook_from_rs(cursor).expect("Cannot open ods file from bytes");
 
+        // calamine exposes neither meta.xml nor merged cells, so the file is read again for them
+        let ods = read_ods_buf(document);
+        if let Err(err) = &ods {
+            warn!("Error reading ods metadata and merged cells: {}", err);
+        }
+
         let mut data: Vec<Element> = Vec::new();
 
-        for sheet_name in workbook.sheet_names().clone() {
+        for (sheet_index, sheet_name) in workbook.sheet_names().clone().into_iter().enumerate() {
             match workbook.worksheet_range(&sheet_name) {
                 Ok(range) => {
+                    let formulas = workbook.worksheet_formula(&sheet_name).ok();
+                    let sheet = ods
+                        .as_ref()
+                        .ok()
+                        .filter(|ods| sheet_index < ods.num_sheets())
+                        .map(|ods| ods.sheet(sheet_index));
+                    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+                    let mut covered: HashSet<(u32, u32)> = HashSet::new();
                     let mut table_rows: Vec<TableRow> = Vec::new();
                     let mut table_headers: Vec<TableHeader> = Vec::new();
                     let mut is_first_row = true;
 
-                    for row in range.rows() {
+                    for (row_index, row) in (first_row..).zip(range.rows()) {
+                        let mut cells = vec![];
+                        for (col_index, value) in (first_col..).zip(row.iter()) {
+                            if covered.contains(&(row_index, col_index)) {
+                                continue;
+                            }
+                            let (colspan, rowspan) = sheet.map_or((1, 1), |sheet| {
+                                (
+                                    sheet.col_span(row_index, col_index),
+                                    sheet.row_span(row_index, col_index),
+                                )
+                            });
+                            for r in row_index..row_index + rowspan {
+                                for c in col_index..col_index + colspan {
+                                    covered.insert((r, c));
+                                }
+                            }
+                            let formula = formulas
+                                .as_ref()
+                                .and_then(|formulas| formulas.get_value((row_index, col_index)));
+                            cells.push((value, formula, colspan as usize, rowspan as usize));
+                        }
                         if is_first_row {
-                            table_headers = row
-                                .iter()
-                                .map(|header| TableHeader {
-                                    element: Text {
-                                        text: header.to_string(),
+                            table_headers = cells
+                                .into_iter()
+                                .map(|(value, _, colspan, rowspan)| {
+                                    let text = Text {
+                                        text: value.to_string(),
                                         size: 8,
-                                    },
-                                    width: 10.0,
+                                        style: TextStyle::default(),
+                                    };
+                                    TableHeader::new(text, 10.0).span(colspan, rowspan)
                                 })
                                 .collect();
                             is_first_row = false;
                         } else {
-                            let cells = row
-                                .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
+                            let cells = cells
+                                .into_iter()
+                                .map(|(value, formula, colspan, rowspan)| {
+                                    let cell = match CellValue::from_calamine(value, formula) {
+                                        Some(value) => TableCell::with_value(value, 8),
+                                        None => TableCell::new(Text {
+                                            text: String::new(),
+                                            size: 8,
+                                            style: TextStyle::default(),
+                                        }),
+                                    };
+                                    cell.span(colspan, rowspan)
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
                         }
                     }
                     data.push(Table {
-                        headers: table_headers.clone(),
-                        rows: table_rows.clone(),
+                        name: Some(sheet_name.clone()),
+                        headers: table_headers,
+                        rows: table_rows,
                     });
                 }
                 Err(err) => {
@@ -66,7 +113,14 @@ impl TransformerTrait for Transformer {
             }
         }
 
-        Ok(Document::new(data))
+        let mut result = Document::new(data);
+        if let Ok(ods) = &ods {
+            result.set_metadata(parse_metadata(ods.metadata()));
+            if let Some(page) = parse_page_style(ods) {
+                result.set_page_dimensions(page);
+            }
+        }
+        Ok(result)
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes>
@@ -74,43 +128,49 @@ impl TransformerTrait for Transformer {
         Self: Sized,
     {
         let mut workbook = WorkBook::new(locale!("en_US"));
+        generate_metadata(&document.metadata, workbook.metadata_mut());
+        let table_style = generate_page_style(document, &mut workbook);
         fn generate_element(
             element: &Element,
             workbook: &mut WorkBook,
-            sheet_index: i32,
+            sheet_name: &str,
+            table_style: &TableStyleRef,
         ) -> anyhow::Result<()> {
-            match element {
-                Table { headers, rows } => {
-                    let mut worksheet = Sheet::new("Sheet".to_string() + &sheet_index.to_string());
-                    let mut row_index = 1;
-                    let mut col_index = 0;
-                    for header in headers {
-                        if let Text { text, .. } = header.element.clone() {
-                            worksheet.set_value(0, col_index, text);
-                            col_index += 1;
-                        }
-                    }
+            if let Table { headers, rows, .. } = element {
+                let mut worksheet = Sheet::new(sheet_name);
+                worksheet.set_style(table_style);
+                let (header_columns, row_columns) = table_columns(headers, rows);
+                for (header, col_index) in headers.iter().zip(header_columns) {
+                    write_cell(
+                        &mut worksheet,
+                        (0, col_index as u32),
+                        (header.colspan, header.rowspan),
+                        &header.elements,
+                        None,
+                    );
+                }
 
-                    for row in rows {
-                        let mut col_index = 0;
-                        for (_cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Text { text, .. } = cell.element.clone() {
-                                worksheet.set_value(row_index, col_index, text);
-                                col_index += 1;
-                            }
-                        }
-                        row_index += 1;
+                for (row_index, (row, columns)) in (1..).zip(rows.iter().zip(row_columns)) {
+                    for (cell, col_index) in row.cells.iter().zip(columns) {
+                        write_cell(
+                            &mut worksheet,
+                            (row_index, col_index as u32),
+                            (cell.colspan, cell.rowspan),
+                            &cell.elements,
+                            cell.value.as_ref(),
+                        );
                     }
-                    workbook.push_sheet(worksheet.clone());
                 }
-                _ => {}
+                workbook.push_sheet(worksheet.clone());
             }
             Ok(())
         }
-        let mut sheet_index = 1;
-        for element in &document.get_all_elements() {
-            generate_element(element, &mut workbook, sheet_index)?;
-            sheet_index += 1;
+        let elements = document.get_all_elements();
+        let tables = elements
+            .iter()
+            .filter(|element| matches!(element, Table { .. }));
+        for (element, sheet_name) in tables.zip(sheet_names(elements.iter().copied())) {
+            generate_element(element, &mut workbook, &sheet_name, &table_style)?;
         }
 
         let mut ods_data = vec![];
@@ -119,9 +179,159 @@ impl TransformerTrait for Transformer {
     }
 }
 
+// write the value of a cell, or its text, and the range it spans
+fn write_cell(
+    worksheet: &mut Sheet,
+    (row, col): (u32, u32),
+    (colspan, rowspan): (usize, usize),
+    elements: &[Element],
+    value: Option<&CellValue>,
+) {
+    match value {
+        Some(CellValue::Formula { formula, cached }) => {
+            worksheet.set_formula(row, col, format!("of:={formula}"));
+            if let Some(cached) = cached {
+                worksheet.set_value(row, col, ods_value(cached));
+            }
+        }
+        Some(value) => worksheet.set_value(row, col, ods_value(value)),
+        None => worksheet.set_value(row, col, plain_text(elements)),
+    }
+    if colspan > 1 {
+        worksheet.set_col_span(row, col, colspan as u32);
+    }
+    if rowspan > 1 {
+        worksheet.set_row_span(row, col, rowspan as u32);
+    }
+}
+
+// native ods value of a typed cell, errors are kept as their text
+fn ods_value(value: &CellValue) -> Value {
+    match value {
+        CellValue::Int(number) => Value::Number(*number as f64),
+        CellValue::Float(number) => Value::Number(*number),
+        CellValue::Bool(boolean) => Value::Boolean(*boolean),
+        CellValue::DateTime(datetime) => datetime
+            .parse::<NaiveDateTime>()
+            .or_else(|_| {
+                datetime
+                    .parse::<NaiveDate>()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            })
+            .map_or_else(|_| Value::Text(datetime.clone()), Value::DateTime),
+        CellValue::Duration(seconds) => {
+            Value::TimeDuration(Duration::milliseconds((seconds * 1000.0).round() as i64))
+        }
+        _ => Value::Text(value.to_string()),
+    }
+}
+
+fn parse_metadata(meta: &Metadata) -> DocumentMetadata {
+    let non_empty = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+    let date_format = "%Y-%m-%dT%H:%M:%S";
+    DocumentMetadata {
+        title: non_empty(&meta.title),
+        author: non_empty(&meta.creator).or_else(|| non_empty(&meta.initial_creator)),
+        subject: non_empty(&meta.subject),
+        description: non_empty(&meta.description),
+        keywords: meta
+            .keyword
+            .split(',')
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(str::to_string)
+            .collect(),
+        language: non_empty(&meta.language),
+        created: meta
+            .creation_date
+            .map(|date| date.format(date_format).to_string()),
+        modified: meta.date.map(|date| date.format(date_format).to_string()),
+    }
+}
+
+// Sheets take their page layout from the master page of their table style
+fn generate_page_style(document: &Document, workbook: &mut WorkBook) -> TableStyleRef {
+    let page = document.page_dimensions();
+    let mm = |value: f32| Length::Mm(value.into());
+    let mut page_style = PageStyle::new("pm1");
+    page_style.set_page_width(mm(page.page_width));
+    page_style.set_page_height(mm(page.page_height));
+    page_style.set_margin_top(Margin::Length(mm(page.page_margin_top)));
+    page_style.set_margin_bottom(Margin::Length(mm(page.page_margin_bottom)));
+    page_style.set_margin_left(Margin::Length(mm(page.page_margin_left)));
+    page_style.set_margin_right(Margin::Length(mm(page.page_margin_right)));
+    page_style.set_print_orientation(match document.orientation {
+        PageOrientation::Portrait => PrintOrientation::Portrait,
+        PageOrientation::Landscape => PrintOrientation::Landscape,
+    });
+    let page_style = workbook.add_pagestyle(page_style);
+
+    let mut master_page = MasterPage::new("Default");
+    master_page.set_pagestyle(&page_style);
+    let master_page = workbook.add_masterpage(master_page);
+
+    let mut table_style = TableStyle::new("ta1");
+    table_style.set_master_page(&master_page);
+    workbook.add_tablestyle(table_style)
+}
+
+fn parse_page_style(ods: &WorkBook) -> Option<PageDimensions> {
+    if ods.num_sheets() == 0 {
+        return None;
+    }
+    let table_style = ods.tablestyle(ods.sheet(0).style()?)?;
+    let master_page = table_style.attrmap().attr("style:master-page-name")?;
+    let page_style = ods.pagestyle(ods.masterpage(master_page)?.pagestyle()?)?;
+    let length = |name| page_style.style().attr(name).and_then(length_mm);
+    let default = PageFormat::default().dimensions();
+    Some(PageDimensions {
+        page_width: length("fo:page-width")?,
+        page_height: length("fo:page-height")?,
+        page_margin_top: length("fo:margin-top").unwrap_or(default.page_margin_top),
+        page_margin_bottom: length("fo:margin-bottom").unwrap_or(default.page_margin_bottom),
+        page_margin_left: length("fo:margin-left").unwrap_or(default.page_margin_left),
+        page_margin_right: length("fo:margin-right").unwrap_or(default.page_margin_right),
+    })
+}
+
+// ODF lengths are numbers with a unit, such as "21cm" or "0.7874in"
+fn length_mm(length: &str) -> Option<f32> {
+    let unit_start = length.find(|c: char| c.is_ascii_alphabetic())?;
+    let (value, unit) = length.split_at(unit_start);
+    let value: f32 = value.parse().ok()?;
+    let scale = match unit {
+        "mm" => 1.0,
+        "cm" => 10.0,
+        "in" => 25.4,
+        "pt" => 25.4 / 72.0,
+        "pc" => 25.4 / 6.0,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+fn generate_metadata(metadata: &DocumentMetadata, meta: &mut Metadata) {
+    // ODF dates are full date-times, plain dates are taken as midnight
+    fn parse_date<T: std::str::FromStr>(date: &str) -> Option<T> {
+        date.parse()
+            .ok()
+            .or_else(|| format!("{date}T00:00:00").parse().ok())
+    }
+
+    meta.title = metadata.title.clone().unwrap_or_default();
+    meta.creator = metadata.author.clone().unwrap_or_default();
+    meta.initial_creator = meta.creator.clone();
+    meta.subject = metadata.subject.clone().unwrap_or_default();
+    meta.description = metadata.description.clone().unwrap_or_default();
+    meta.keyword = metadata.keywords.join(", ");
+    meta.language = metadata.language.clone().unwrap_or_default();
+    meta.creation_date = metadata.created.as_deref().and_then(parse_date);
+    meta.date = metadata.modified.as_deref().and_then(parse_date);
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, text};
     use crate::ods::*;
     use anyhow::Ok;
     use bytes::Bytes;
@@ -166,4 +376,195 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_page_setup() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("Q1", 8))],
+            }],
+        }]);
+        document.set_page_format(PageFormat::Tabloid);
+        document.set_orientation(PageOrientation::Landscape);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.page_format, PageFormat::Tabloid);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Table {
+            name: None,
+            headers: vec![TableHeader::new(
+                Text {
+                    text: "Header".to_string(),
+                    size: 8,
+                    style: TextStyle::default(),
+                },
+                10.0,
+            )],
+            rows: vec![],
+        }]);
+        document.set_metadata(DocumentMetadata {
+            title: Some("Report".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en".to_string()),
+            created: Some("2024-05-01T10:30:00".to_string()),
+            ..Default::default()
+        });
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata, document.metadata);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        let header_spans: Vec<(usize, usize)> =
+            headers.iter().map(|h| (h.colspan, h.rowspan)).collect();
+        assert_eq!(header_spans, vec![(2, 1), (1, 1)]);
+        let spans: Vec<Vec<(usize, usize)>> = rows
+            .iter()
+            .map(|row| row.cells.iter().map(|c| (c.colspan, c.rowspan)).collect())
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                vec![(1, 2), (1, 1), (1, 1)],
+                vec![(1, 1), (1, 1)],
+                vec![(3, 1)]
+            ]
+        );
+        assert_eq!(rows[2].cells[0].text(), "Sum\nof all");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sheet_names() -> anyhow::Result<()> {
+        let document = Document::new(
+            ["Q1 Sales", "Q2 Sales"]
+                .into_iter()
+                .map(|name| Element::Table {
+                    name: Some(name.to_string()),
+                    headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text(&name[..2], 8))],
+                    }],
+                })
+                .collect(),
+        );
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let names: Vec<Option<String>> = parsed
+            .get_all_elements()
+            .into_iter()
+            .map(|element| match element {
+                Table { name, .. } => name.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![Some("Q1 Sales".to_string()), Some("Q2 Sales".to_string())]
+        );
+        Ok(())
+    }
+
+    const FORMULA: &str = "[.B2]*2";
+
+    #[test]
+    fn test_typed_cells() -> anyhow::Result<()> {
+        let values = vec![
+            CellValue::String("Apples".to_string()),
+            CellValue::Int(42),
+            CellValue::Float(2.5),
+            CellValue::Bool(true),
+            CellValue::DateTime("2024-05-01".to_string()),
+            CellValue::DateTime("2024-05-01T10:30:00".to_string()),
+            CellValue::Duration(45015.0),
+            CellValue::Formula {
+                formula: FORMULA.to_string(),
+                cached: Some(Box::new(CellValue::Float(84.0))),
+            },
+        ];
+        // the formula doubles the number in the second column
+        let headers = (1..=values.len())
+            .map(|column| TableHeader::new(text(&format!("Column {column}"), 8), 10.0))
+            .collect();
+        let cells = values
+            .into_iter()
+            .map(|value| TableCell::with_value(value, 8))
+            .collect();
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers,
+            rows: vec![TableRow { cells }],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let Table { rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        let values: Vec<Option<CellValue>> = rows[0]
+            .cells
+            .iter()
+            .map(|cell| cell.value.clone())
+            .collect();
+        // spreadsheets store every number as a float
+        assert_eq!(
+            values,
+            vec![
+                Some(CellValue::String("Apples".to_string())),
+                Some(CellValue::Float(42.0)),
+                Some(CellValue::Float(2.5)),
+                Some(CellValue::Bool(true)),
+                Some(CellValue::DateTime("2024-05-01".to_string())),
+                Some(CellValue::DateTime("2024-05-01T10:30:00".to_string())),
+                Some(CellValue::Duration(45015.0)),
+                Some(CellValue::Formula {
+                    formula: FORMULA.to_string(),
+                    cached: Some(Box::new(CellValue::Float(84.0))),
+                }),
+            ]
+        );
+        assert_eq!(rows[0].cells[6].text(), "12:30:15");
+        Ok(())
+    }
 }
@@ -1,287 +1,4127 @@
-use crate::core::Element::{List, Paragraph, Text};
-use crate::core::{Document, Element, ListItem, ParserError, TransformerTrait};
+use crate::core::Element::{
+    Blockquote, CodeBlock, Header, Hyperlink, Image, List, Paragraph, Table, Text,
+};
+use crate::core::{
+    ColumnAlignment, Document, DocumentMetadata, Element, ImageData, ImageDimension, ListItem,
+    ParserError, TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
+};
+pub use crate::typst::{clear_font_cache, evict_layout_cache, FontOptions};
+use crate::typst::{Layout, SourceRanges};
 
+use aes::cipher::consts::U16;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
+use aes::{Aes128, Aes256};
 use anyhow;
 use bytes::Bytes;
-use log::{debug, warn};
-use lopdf::content::Content;
-use lopdf::{Document as PdfDocument, Object, ObjectId};
-use std::collections::BTreeMap;
-use typst::{eval::Tracer, foundations::Smart};
+use log::warn;
+use lopdf::content::{Content, Operation};
+use lopdf::dictionary;
+use lopdf::xref::{XrefEntry, XrefType};
+use lopdf::{Dictionary, Document as PdfDocument, Object, ObjectId, ObjectStream, Reader, Stream};
+use rc4::consts::{U10, U11, U12, U13, U14, U15, U5, U6, U7, U8, U9};
+use rc4::{Rc4, StreamCipher};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Cursor;
+use std::ops::Range;
+use typst::foundations::Smart;
+use typst::layout::{Frame, FrameItem};
+use typst::text::TextItem;
 
 pub struct Transformer;
 impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        let mut elements: Vec<Element> = Vec::new();
-        let pdf_document = PdfDocument::load_mem(document)?;
+        Transformer::parse_with_options(document, &PdfParseOptions::default())
+    }
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PdfOptions::default())
+    }
+}
+
+/// Options of PDF parsing.
+///
+/// Encrypted documents are opened with the given password, or with the empty user password
+/// that documents restricted only by permissions have.
+#[derive(Debug, Clone, Default)]
+pub struct PdfParseOptions {
+    password: Option<String>,
+}
+
+impl PdfParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypts the document with this password, it is tried as user and as owner password
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+}
+
+impl Transformer {
+    /// Parses the PDF, a missing or wrong password of an encrypted PDF is reported
+    /// as [`ParserError::PasswordRequired`] or [`ParserError::IncorrectPassword`]
+    pub fn parse_with_options(
+        document: &Bytes,
+        options: &PdfParseOptions,
+    ) -> anyhow::Result<Document> {
+        let pdf_document = load_document(document, options)?;
+        let mut outline = outline_entries(&pdf_document);
+        let mut pages = vec![];
         for (_id, page_id) in pdf_document.get_pages() {
-            let objects = pdf_document.get_page_contents(page_id);
-            for object_id in objects {
-                let object = pdf_document.get_object(object_id)?;
-                parse_object(page_id, &pdf_document, object, &mut elements)?;
+            let mut page = read_page(&pdf_document, page_id)?;
+            page.outline = outline.remove(&page_id).unwrap_or_default();
+            pages.push(page);
+        }
+        let mut result = Document::new(layout_elements(pages));
+        result.set_metadata(parse_metadata(&pdf_document));
+        Ok(result)
+    }
+}
+
+/// Options of PDF generation.
+///
+/// Headers always become bookmarks in the outline of the PDF.
+#[derive(Debug, Clone, Default)]
+pub struct PdfOptions {
+    fonts: FontOptions,
+    table_of_contents: bool,
+    pdf_a: bool,
+    tagged: bool,
+}
+
+impl PdfOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lays out the document with fonts from these sources
+    pub fn with_fonts(mut self, fonts: FontOptions) -> Self {
+        self.fonts = fonts;
+        self
+    }
+
+    /// Starts the document with a page listing the headers, each entry links to its header
+    pub fn with_table_of_contents(mut self, enabled: bool) -> Self {
+        self.table_of_contents = enabled;
+        self
+    }
+
+    /// Conforms to PDF/A-2b for archiving, with an sRGB output intent and the standard named in the XMP metadata
+    pub fn with_pdf_a(mut self, enabled: bool) -> Self {
+        self.pdf_a = enabled;
+        self
+    }
+
+    /// Tags the structure for assistive technology following PDF/UA-1: headings H1–H6, paragraphs,
+    /// lists, tables and figures with the alt text of their images.
+    ///
+    /// The PDF only claims PDF/UA when the metadata names the language and all text of the body could be tagged.
+    pub fn with_tagged(mut self, enabled: bool) -> Self {
+        self.tagged = enabled;
+        self
+    }
+}
+
+impl Transformer {
+    /// Generates the PDF with fonts from the given sources instead of only the embedded ones
+    pub fn generate_with_fonts(document: &Document, fonts: &FontOptions) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PdfOptions::new().with_fonts(fonts.clone()))
+    }
+
+    pub fn generate_with_options(
+        document: &Document,
+        options: &PdfOptions,
+    ) -> anyhow::Result<Bytes> {
+        // accessible documents need a title, the first header stands in for a missing one
+        let titled;
+        let source = match document
+            .get_all_elements()
+            .iter()
+            .find_map(|element| match element {
+                Header { text, .. } if options.tagged && document.metadata.title.is_none() => {
+                    Some(text)
+                }
+                _ => None,
+            }) {
+            Some(title) => {
+                titled = Document {
+                    bands: document.bands.clone(),
+                    page_format: document.page_format.clone(),
+                    orientation: document.orientation.clone(),
+                    metadata: DocumentMetadata {
+                        title: Some(title.clone()),
+                        ..document.metadata.clone()
+                    },
+                };
+                &titled
+            }
+            None => document,
+        };
+        let layout =
+            crate::typst::compile_layout(source, &options.fonts, options.table_of_contents)?;
+
+        // Converting to pdf then to bytes
+        let mut pdf = typst_pdf::pdf(&layout.document, Smart::Auto, None);
+        if options.tagged || options.pdf_a {
+            pdf = conform(&pdf, source, &layout, options)?;
+        }
+
+        let bytes = Bytes::from(pdf);
+        Ok(bytes)
+    }
+}
+
+// Adds the structure tree and the PDF/A requirements that typst does not write.
+// The layout must come from the same document, its source ranges are tied to the elements.
+fn conform(
+    pdf: &[u8],
+    document: &Document,
+    layout: &Layout,
+    options: &PdfOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let mut pdf_document = PdfDocument::load_mem(pdf)?;
+    // typst names its default language, the document only has one when the metadata says so
+    let catalog = pdf_document.catalog_mut()?;
+    match &document.metadata.language {
+        Some(language) => catalog.set("Lang", text_string(language)),
+        None => {
+            catalog.remove(b"Lang");
+        }
+    }
+    let mut accessible = false;
+    if options.tagged {
+        let mut tree = StructTree::new(&layout.ranges);
+        let elements = document.get_all_elements();
+        if options.table_of_contents {
+            tree.add_table_of_contents(elements.iter().copied());
+        }
+        tree.add_elements(0, elements);
+        let untagged = tag_structure(&mut pdf_document, tree, layout)?;
+        // PDF/UA is only claimed when all content is tagged and its language is known
+        if untagged > 0 {
+            warn!("Not claiming PDF/UA, {untagged} glyphs are not tagged");
+        } else if document.metadata.language.is_none() {
+            warn!("Not claiming PDF/UA, the document has no language");
+        } else {
+            accessible = true;
+        }
+    }
+    if options.pdf_a {
+        add_output_intent(&mut pdf_document);
+        // annotations of PDF/A are printed with the page
+        for page_id in pdf_document.get_pages().into_values() {
+            for annotation_id in page_annotations(&mut pdf_document, page_id)? {
+                pdf_document
+                    .get_object_mut(annotation_id)?
+                    .as_dict_mut()?
+                    .set("F", 4);
+            }
+        }
+    }
+    identify_standards(&mut pdf_document, options.pdf_a, accessible)?;
+    // the binary comment is inserted into a cross-reference table, lopdf would write a stream as it was read
+    pdf_document.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    let mut bytes = vec![];
+    pdf_document.save_to(&mut bytes)?;
+    if options.pdf_a {
+        bytes = mark_binary(&bytes)?;
+    }
+    Ok(bytes)
+}
+
+// Puts a comment of bytes above 127 after the header, which PDF/A asks for so that the file is handled
+// as binary. lopdf only writes the version line, the offsets in the cross-reference table move along.
+fn mark_binary(pdf: &[u8]) -> anyhow::Result<Vec<u8>> {
+    const COMMENT: &[u8] = b"%\xE2\xE3\xCF\xD3\n";
+    let header = pdf
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .ok_or_else(|| anyhow::anyhow!("PDF without a header"))?
+        + 1;
+    let startxref = pdf
+        .windows(9)
+        .rposition(|window| window == b"startxref")
+        .ok_or_else(|| anyhow::anyhow!("PDF without startxref"))?;
+    let xref: usize = std::str::from_utf8(&pdf[startxref + 9..])?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .parse()?;
+    let trailer = pdf
+        .get(xref..startxref)
+        .and_then(|table| table.windows(7).position(|window| window == b"trailer"))
+        .ok_or_else(|| anyhow::anyhow!("PDF without a cross-reference table"))?
+        + xref;
+
+    // entries in use start with the ten digits of their offset
+    let mut table = String::new();
+    for line in std::str::from_utf8(&pdf[xref..trailer])?.split_inclusive('\n') {
+        match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [offset, generation, "n"] => {
+                let offset = offset.parse::<usize>()? + COMMENT.len();
+                table.push_str(&format!("{offset:010} {generation} n \n"));
+            }
+            _ => table.push_str(line),
+        }
+    }
+    let mut marked = Vec::with_capacity(pdf.len() + COMMENT.len() + 1);
+    marked.extend_from_slice(&pdf[..header]);
+    marked.extend_from_slice(COMMENT);
+    marked.extend_from_slice(&pdf[header..xref]);
+    marked.extend_from_slice(table.as_bytes());
+    marked.extend_from_slice(&pdf[trailer..startxref]);
+    marked.extend_from_slice(format!("startxref\n{}\n%%EOF", xref + COMMENT.len()).as_bytes());
+    Ok(marked)
+}
+
+// Annotations of the page, written as objects of their own so that they can be referenced
+fn page_annotations(
+    pdf_document: &mut PdfDocument,
+    page_id: ObjectId,
+) -> anyhow::Result<Vec<ObjectId>> {
+    let Ok(annotations) = pdf_document
+        .get_dictionary(page_id)
+        .and_then(|page| page.get_deref(b"Annots", pdf_document))
+        .and_then(Object::as_array)
+        .cloned()
+    else {
+        return Ok(vec![]);
+    };
+    let ids: Vec<ObjectId> = annotations
+        .into_iter()
+        .map(|annotation| match annotation {
+            Object::Reference(id) => id,
+            annotation => pdf_document.add_object(annotation),
+        })
+        .collect();
+    let references: Vec<Object> = ids.iter().map(|&id| Object::Reference(id)).collect();
+    pdf_document
+        .get_object_mut(page_id)?
+        .as_dict_mut()?
+        .set("Annots", references);
+    Ok(ids)
+}
+
+// Names the standards in the XMP metadata, PDF/A needs a schema description for the PDF/UA namespace
+fn identify_standards(
+    pdf_document: &mut PdfDocument,
+    pdf_a: bool,
+    pdf_ua: bool,
+) -> anyhow::Result<()> {
+    let metadata_id = pdf_document.catalog()?.get(b"Metadata")?.as_reference()?;
+    let stream = pdf_document.get_object_mut(metadata_id)?.as_stream_mut()?;
+    let mut xmp = String::from_utf8(
+        stream
+            .decompressed_content()
+            .unwrap_or(stream.content.clone()),
+    )?;
+    let mut descriptions = String::new();
+    if pdf_a {
+        descriptions.push_str(
+            r#"<rdf:Description rdf:about="" xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/"><pdfaid:part>2</pdfaid:part><pdfaid:conformance>B</pdfaid:conformance></rdf:Description>"#,
+        );
+    }
+    if pdf_ua {
+        descriptions.push_str(
+            r#"<rdf:Description rdf:about="" xmlns:pdfuaid="http://www.aiim.org/pdfua/ns/id/"><pdfuaid:part>1</pdfuaid:part></rdf:Description>"#,
+        );
+    }
+    if pdf_a && pdf_ua {
+        descriptions.push_str(concat!(
+            r#"<rdf:Description rdf:about="" xmlns:pdfaExtension="http://www.aiim.org/pdfa/ns/extension/" xmlns:pdfaSchema="http://www.aiim.org/pdfa/ns/schema#" xmlns:pdfaProperty="http://www.aiim.org/pdfa/ns/property#">"#,
+            r#"<pdfaExtension:schemas><rdf:Bag><rdf:li rdf:parseType="Resource">"#,
+            r#"<pdfaSchema:schema>PDF/UA Universal Accessibility Schema</pdfaSchema:schema>"#,
+            r#"<pdfaSchema:namespaceURI>http://www.aiim.org/pdfua/ns/id/</pdfaSchema:namespaceURI>"#,
+            r#"<pdfaSchema:prefix>pdfuaid</pdfaSchema:prefix>"#,
+            r#"<pdfaSchema:property><rdf:Seq><rdf:li rdf:parseType="Resource">"#,
+            r#"<pdfaProperty:name>part</pdfaProperty:name>"#,
+            r#"<pdfaProperty:valueType>Integer</pdfaProperty:valueType>"#,
+            r#"<pdfaProperty:category>internal</pdfaProperty:category>"#,
+            r#"<pdfaProperty:description>Part of ISO 14289 the document conforms to</pdfaProperty:description>"#,
+            r#"</rdf:li></rdf:Seq></pdfaSchema:property></rdf:li></rdf:Bag></pdfaExtension:schemas></rdf:Description>"#,
+        ));
+    }
+    let end = xmp
+        .rfind("</rdf:RDF>")
+        .ok_or_else(|| anyhow::anyhow!("XMP metadata without RDF"))?;
+    xmp.insert_str(end, &descriptions);
+    // the metadata of PDF/A stays readable without decoding filters
+    stream.dict.remove(b"Filter");
+    stream.dict.remove(b"DecodeParms");
+    stream.set_content(xmp.into_bytes());
+    Ok(())
+}
+
+fn add_output_intent(pdf_document: &mut PdfDocument) {
+    let mut profile = Stream::new(
+        dictionary! { "N" => 3 },
+        typst_assets::icc::S_RGB_V4.to_vec(),
+    );
+    let _ = profile.compress();
+    let profile_id = pdf_document.add_object(profile);
+    let intent = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal("sRGB IEC61966-2.1"),
+        "Info" => Object::string_literal("sRGB IEC61966-2.1"),
+        "DestOutputProfile" => profile_id,
+    };
+    if let Ok(catalog) = pdf_document.catalog_mut() {
+        catalog.set("OutputIntents", vec![Object::Dictionary(intent)]);
+    }
+}
+
+const HEADINGS: [&str; 6] = ["H1", "H2", "H3", "H4", "H5", "H6"];
+
+/// Element of the structure tree, created from the document in reading order
+struct StructNode {
+    role: &'static str,
+    parent: usize,
+    /// Shows text of its part of the source, other elements only contain elements
+    leaf: bool,
+    /// Kid of a leaf that takes its place among the content of the leaf once it has content of its own
+    inline: bool,
+    alt: Option<String>,
+    attributes: Option<Dictionary>,
+    kids: Vec<StructKid>,
+}
+
+enum StructKid {
+    Node(usize),
+    Content {
+        page: ObjectId,
+        mcid: i64,
+    },
+    Annotation {
+        page: ObjectId,
+        annotation: ObjectId,
+    },
+}
+
+/// Structure of the document, its leaves own the text typst laid out from their part of the source
+struct StructTree<'a> {
+    nodes: Vec<StructNode>,
+    source_ranges: &'a SourceRanges,
+    /// Ranges of the source shown by each leaf, nested ranges belong to nested leaves
+    leaves: Vec<(Range<usize>, usize)>,
+    headings: Vec<usize>,
+    figures: Vec<usize>,
+    links: Vec<(usize, String)>,
+    table_of_contents: Option<TableOfContents>,
+}
+
+struct TableOfContents {
+    heading: usize,
+    list: usize,
+    /// Entry of each heading, in the order of the headings
+    items: Vec<usize>,
+}
+
+impl<'a> StructTree<'a> {
+    fn new(source_ranges: &'a SourceRanges) -> Self {
+        let root = StructNode {
+            role: "Document",
+            parent: 0,
+            leaf: false,
+            inline: false,
+            alt: None,
+            attributes: None,
+            kids: vec![],
+        };
+        StructTree {
+            nodes: vec![root],
+            source_ranges,
+            leaves: vec![],
+            headings: vec![],
+            figures: vec![],
+            links: vec![],
+            table_of_contents: None,
+        }
+    }
+
+    fn add(&mut self, parent: usize, role: &'static str) -> usize {
+        let node = self.nodes.len();
+        let inline = self.nodes[parent].leaf;
+        self.nodes.push(StructNode {
+            role,
+            parent,
+            leaf: false,
+            inline,
+            alt: None,
+            attributes: None,
+            kids: vec![],
+        });
+        self.nodes[parent].kids.push(StructKid::Node(node));
+        node
+    }
+
+    fn add_leaf(&mut self, parent: usize, role: &'static str, element: &Element) -> usize {
+        let node = self.add(parent, role);
+        self.nodes[node].leaf = true;
+        if let Some(range) = self.source_ranges.get(&(element as *const Element)) {
+            self.leaves.push((range.clone(), node));
+        }
+        node
+    }
+
+    // The contents page of typst lists every header under a heading of its own
+    fn add_table_of_contents<'b>(&mut self, elements: impl IntoIterator<Item = &'b Element>) {
+        let heading = self.add(0, "H1");
+        let list = self.add(0, "TOC");
+        let items = elements
+            .into_iter()
+            .filter(|element| matches!(element, Header { .. }))
+            .map(|_| self.add(list, "TOCI"))
+            .collect();
+        self.table_of_contents = Some(TableOfContents {
+            heading,
+            list,
+            items,
+        });
+    }
+
+    fn add_elements<'b>(&mut self, parent: usize, elements: impl IntoIterator<Item = &'b Element>) {
+        for element in elements {
+            match element {
+                Header { level, .. } => {
+                    let role = HEADINGS[(*level).clamp(1, 6) as usize - 1];
+                    let heading = self.add_leaf(parent, role, element);
+                    self.headings.push(heading);
+                }
+                Paragraph { elements } => {
+                    let paragraph = self.add_leaf(parent, "P", element);
+                    for element in elements {
+                        match element {
+                            Image(image) => self.add_figure(paragraph, image),
+                            Hyperlink { url, .. } => self.add_link(paragraph, element, url),
+                            Element::Footnote { elements, .. } => {
+                                let note = self.add(paragraph, "Note");
+                                self.add_elements(note, elements);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Text { .. } | Element::Revision { .. } => {
+                    self.add_leaf(parent, "P", element);
+                }
+                Hyperlink { url, .. } => {
+                    let paragraph = self.add(parent, "P");
+                    self.add_link(paragraph, element, url);
+                }
+                List { elements, numbered } => self.add_list(parent, elements, *numbered),
+                Table {
+                    name,
+                    headers,
+                    rows,
+                } => {
+                    let table = self.add(parent, "Table");
+                    // the text of the table outside its cells is the name
+                    if name.is_some() {
+                        self.add_leaf(table, "Caption", element);
+                    }
+                    if !headers.is_empty() {
+                        let row = self.add(table, "TR");
+                        for header in headers {
+                            let cell = self.add_cell(row, "TH", header.colspan, header.rowspan);
+                            self.add_elements(cell, &header.elements);
+                        }
+                    }
+                    for row in rows {
+                        let table_row = self.add(table, "TR");
+                        for cell in &row.cells {
+                            let table_cell =
+                                self.add_cell(table_row, "TD", cell.colspan, cell.rowspan);
+                            self.add_elements(table_cell, &cell.elements);
+                        }
+                    }
+                }
+                Blockquote { elements } => {
+                    let quote = self.add(parent, "BlockQuote");
+                    self.add_elements(quote, elements);
+                }
+                CodeBlock { .. } => {
+                    let paragraph = self.add(parent, "P");
+                    self.add_leaf(paragraph, "Code", element);
+                }
+                Image(image) => self.add_figure(parent, image),
+                Element::Footnote { elements, .. } => {
+                    let note = self.add(parent, "Note");
+                    self.add_elements(note, elements);
+                }
+                Element::ThematicBreak | Element::PageBreak | Element::Comment { .. } => {}
+            }
+        }
+    }
+
+    // Nested lists belong to the body of the item before them
+    fn add_list(&mut self, parent: usize, items: &[ListItem], numbered: bool) {
+        let list = self.add(parent, "L");
+        let numbering = if numbered { "Decimal" } else { "Disc" };
+        self.nodes[list].attributes =
+            Some(dictionary! { "O" => "List", "ListNumbering" => numbering });
+        let mut body = None;
+        for item in items {
+            match &item.element {
+                List { elements, numbered } => {
+                    self.add_list(body.unwrap_or(list), elements, *numbered)
+                }
+                element => {
+                    let list_item = self.add(list, "LI");
+                    self.add(list_item, "Lbl");
+                    let item_body = self.add(list_item, "LBody");
+                    self.add_elements(item_body, std::slice::from_ref(element));
+                    body = Some(item_body);
+                }
+            }
+        }
+    }
+
+    fn add_cell(
+        &mut self,
+        row: usize,
+        role: &'static str,
+        colspan: usize,
+        rowspan: usize,
+    ) -> usize {
+        let cell = self.add(row, role);
+        let mut attributes = dictionary! { "O" => "Table" };
+        if role == "TH" {
+            attributes.set("Scope", "Column");
+        }
+        if colspan > 1 {
+            attributes.set("ColSpan", colspan as i64);
+        }
+        if rowspan > 1 {
+            attributes.set("RowSpan", rowspan as i64);
+        }
+        self.nodes[cell].attributes = Some(attributes);
+        cell
+    }
+
+    fn add_figure(&mut self, parent: usize, image: &ImageData) {
+        if image.bytes().is_empty() {
+            return;
+        }
+        let figure = self.add(parent, "Figure");
+        let alt = if image.alt().is_empty() {
+            image.title()
+        } else {
+            image.alt()
+        };
+        self.nodes[figure].alt = Some(alt.to_string());
+        self.figures.push(figure);
+    }
+
+    // The text of a link is part of the link element, next to its annotation
+    fn add_link(&mut self, parent: usize, element: &Element, url: &str) {
+        let link = self.add_leaf(parent, "Link", element);
+        self.links.push((link, url.to_string()));
+    }
+
+    // Moves the inline elements the node belongs to behind the content their leaf already has
+    fn place(&mut self, node: usize) {
+        let mut node = node;
+        while node != 0 {
+            let parent = self.nodes[node].parent;
+            if self.nodes[node].inline {
+                self.nodes[node].inline = false;
+                let kids = &mut self.nodes[parent].kids;
+                kids.retain(|kid| !matches!(kid, StructKid::Node(kid) if *kid == node));
+                kids.push(StructKid::Node(node));
+            }
+            node = parent;
+        }
+    }
+
+    // Innermost leaf whose part of the source contains the offset
+    fn leaf_at(&self, offset: usize) -> Option<usize> {
+        self.leaves
+            .iter()
+            .filter(|(range, _)| range.contains(&offset))
+            .min_by_key(|(range, _)| range.len())
+            .map(|&(_, leaf)| leaf)
+    }
+
+    // Entry of the table of contents that repeats the text of the heading
+    fn table_of_contents_item(&self, heading: usize) -> Option<usize> {
+        let position = self.headings.iter().position(|&node| node == heading)?;
+        self.table_of_contents
+            .as_ref()?
+            .items
+            .get(position)
+            .copied()
+    }
+
+    // Label of the list item the leaf belongs to
+    fn label(&self, leaf: usize) -> Option<usize> {
+        let mut node = leaf;
+        while node != 0 {
+            node = self.nodes[node].parent;
+            if self.nodes[node].role == "LI" {
+                return match self.nodes[node].kids.first() {
+                    Some(&StructKid::Node(label)) if self.nodes[label].role == "Lbl" => Some(label),
+                    _ => None,
+                };
+            }
+        }
+        None
+    }
+}
+
+/// Part of a content stream that is marked as a whole
+enum ContentUnit {
+    Text(Vec<Operation>),
+    Graphic(Vec<Operation>),
+    XObject(Operation),
+    Other(Operation),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ContentMark {
+    Node(usize),
+    Artifact,
+}
+
+// Splits the operations into text objects, painted paths and XObjects, existing marked content is dropped
+fn content_units(operations: Vec<Operation>) -> Vec<ContentUnit> {
+    const PATH: [&str; 7] = ["m", "l", "c", "v", "y", "h", "re"];
+    const PAINT: [&str; 10] = ["S", "s", "f", "F", "f*", "B", "B*", "b", "b*", "n"];
+    let mut units = vec![];
+    let mut operations = operations.into_iter().peekable();
+    while let Some(operation) = operations.next() {
+        match operation.operator.as_str() {
+            "BMC" | "BDC" | "EMC" => {}
+            "BT" => {
+                let mut text_operations = vec![operation];
+                for operation in operations.by_ref() {
+                    let end = operation.operator == "ET";
+                    text_operations.push(operation);
+                    if end {
+                        break;
+                    }
+                }
+                units.push(ContentUnit::Text(text_operations));
+            }
+            operator if PATH.contains(&operator) => {
+                let mut path = vec![operation];
+                let mut painted = false;
+                while let Some(operation) = operations.next_if(|operation| {
+                    !PAINT.contains(&operation.operator.as_str())
+                        || PATH.contains(&operation.operator.as_str())
+                }) {
+                    path.push(operation);
+                }
+                if let Some(operation) = operations.next() {
+                    painted = operation.operator != "n";
+                    path.push(operation);
+                }
+                if painted {
+                    units.push(ContentUnit::Graphic(path));
+                } else {
+                    units.extend(path.into_iter().map(ContentUnit::Other));
+                }
+            }
+            "sh" => units.push(ContentUnit::Graphic(vec![operation])),
+            "Do" => units.push(ContentUnit::XObject(operation)),
+            _ => units.push(ContentUnit::Other(operation)),
+        }
+    }
+    units
+}
+
+// Text items of the frame and its groups in the order typst writes them
+fn frame_texts<'a>(frame: &'a Frame, items: &mut Vec<&'a TextItem>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => frame_texts(&group.frame, items),
+            FrameItem::Text(text) => items.push(text),
+            _ => {}
+        }
+    }
+}
+
+// Marks the text object as a whole when its glyphs share a mark, otherwise the glyphs shown by its TJ
+// operations are split into parts of their own inside the text object
+fn split_text(
+    operations: Vec<Operation>,
+    marks: &[ContentMark],
+) -> Vec<(Option<ContentMark>, Vec<Operation>)> {
+    let Some(&first) = marks.first() else {
+        return vec![(Some(ContentMark::Artifact), operations)];
+    };
+    if marks.iter().all(|&mark| mark == first) {
+        return vec![(Some(first), operations)];
+    }
+    let mut parts = vec![];
+    let mut glyph = 0;
+    for operation in operations {
+        let Some(Object::Array(items)) = operation
+            .operands
+            .first()
+            .filter(|_| operation.operator == "TJ")
+        else {
+            parts.push((None, vec![operation]));
+            continue;
+        };
+        let mut mark = None;
+        let mut shown: Vec<Object> = vec![];
+        let mut string = vec![];
+        for item in items {
+            let Object::String(bytes, _) = item else {
+                if !string.is_empty() {
+                    shown.push(Object::string_literal(std::mem::take(&mut string)));
+                }
+                shown.push(item.clone());
+                continue;
+            };
+            // typst shows every glyph with two bytes
+            for code in bytes.chunks(2) {
+                let glyph_mark = marks.get(glyph).copied().unwrap_or(ContentMark::Artifact);
+                glyph += 1;
+                if mark.is_some_and(|mark| mark != glyph_mark) {
+                    if !string.is_empty() {
+                        shown.push(Object::string_literal(std::mem::take(&mut string)));
+                    }
+                    let operation = Operation::new("TJ", vec![std::mem::take(&mut shown).into()]);
+                    parts.push((mark, vec![operation]));
+                }
+                mark = Some(glyph_mark);
+                string.extend_from_slice(code);
+            }
+        }
+        if !string.is_empty() {
+            shown.push(Object::string_literal(string));
+        }
+        if !shown.is_empty() {
+            parts.push((
+                mark.or(Some(ContentMark::Artifact)),
+                vec![Operation::new("TJ", vec![shown.into()])],
+            ));
+        }
+    }
+    parts
+}
+
+// Marks the content of every page and writes the structure tree and the parent tree.
+// Returns how many glyphs of the document body could not be given to an element.
+fn tag_structure(
+    pdf_document: &mut PdfDocument,
+    mut tree: StructTree,
+    layout: &Layout,
+) -> anyhow::Result<usize> {
+    let pages: Vec<ObjectId> = pdf_document.get_pages().into_values().collect();
+    let body_page = layout.body_page();
+    let mut untagged = 0;
+    let mut figures = tree.figures.clone().into_iter();
+    let mut labelled = HashSet::new();
+    // owners of the marked content of each page, indexed by MCID
+    let mut page_owners = vec![];
+    for (index, &page_id) in pages.iter().enumerate() {
+        let content = Content::decode(&pdf_document.get_page_content(page_id)?)?;
+        let units = content_units(content.operations);
+
+        // typst writes one text object for each text item, in the order of the frame
+        let mut items = vec![];
+        if let Some(page) = layout.document.pages.get(index) {
+            frame_texts(&page.frame, &mut items);
+        }
+        let texts = units
+            .iter()
+            .enumerate()
+            .filter(|(_, unit)| matches!(unit, ContentUnit::Text(_)));
+        if texts.clone().count() != items.len() {
+            warn!("The text of page {} does not match its layout", index + 1);
+            untagged += items
+                .iter()
+                .map(|item| item.glyphs.len())
+                .sum::<usize>()
+                .max(1);
+            items.clear();
+        }
+
+        // the marks of the glyphs of each text object
+        let mut marks = vec![vec![]; units.len()];
+        let contents = match &tree.table_of_contents {
+            Some(contents) if index < body_page => Some(contents.heading),
+            _ => None,
+        };
+        let mut entry = None;
+        // glyphs typst adds itself, list markers become the label of the next item
+        let mut pending: Vec<(usize, usize)> = vec![];
+        for ((position, _), item) in texts.zip(items) {
+            marks[position] = vec![ContentMark::Artifact; item.glyphs.len()];
+            for (glyph, offset) in item
+                .glyphs
+                .iter()
+                .map(|glyph| layout.glyph_offset(glyph))
+                .enumerate()
+            {
+                let leaf = offset.and_then(|offset| tree.leaf_at(offset));
+                let node = match (offset, leaf, contents) {
+                    (_, Some(leaf), _) => match tree.table_of_contents_item(leaf) {
+                        Some(item) if contents.is_some() => {
+                            entry = Some(item);
+                            item
+                        }
+                        _ => {
+                            let label = tree.label(leaf).filter(|label| labelled.insert(*label));
+                            for (position, glyph) in pending.drain(..) {
+                                if let Some(label) = label {
+                                    marks[position][glyph] = ContentMark::Node(label);
+                                }
+                            }
+                            leaf
+                        }
+                    },
+                    // the title of the contents, then the leaders and page numbers of the entries
+                    (_, None, Some(heading)) => entry.unwrap_or(heading),
+                    (None, None, None) => {
+                        pending.push((position, glyph));
+                        continue;
+                    }
+                    // the header and the footer of the pages come before the body
+                    (Some(offset), None, None) => {
+                        if offset >= layout.body {
+                            untagged += 1;
+                        }
+                        continue;
+                    }
+                };
+                marks[position][glyph] = ContentMark::Node(node);
+            }
+        }
+
+        let mut owners = vec![];
+        let mut operations = vec![];
+        for (unit, marks) in units.into_iter().zip(marks) {
+            let parts = match unit {
+                ContentUnit::Other(operation) => {
+                    operations.push(operation);
+                    continue;
+                }
+                ContentUnit::Text(operations) => split_text(operations, &marks),
+                ContentUnit::Graphic(operations) => vec![(Some(ContentMark::Artifact), operations)],
+                ContentUnit::XObject(operation) => {
+                    let mark = figures
+                        .next()
+                        .map_or(ContentMark::Artifact, ContentMark::Node);
+                    vec![(Some(mark), vec![operation])]
+                }
+            };
+            for (mark, content) in parts {
+                match mark {
+                    Some(ContentMark::Node(node)) => {
+                        let mcid = owners.len() as i64;
+                        let mut properties = dictionary! { "MCID" => mcid };
+                        if let Some(alt) = &tree.nodes[node].alt {
+                            properties.set("Alt", text_string(alt));
+                        }
+                        operations.push(Operation::new(
+                            "BDC",
+                            vec![
+                                Object::Name(tree.nodes[node].role.into()),
+                                properties.into(),
+                            ],
+                        ));
+                        tree.place(node);
+                        tree.nodes[node].kids.push(StructKid::Content {
+                            page: page_id,
+                            mcid,
+                        });
+                        owners.push(node);
+                    }
+                    Some(ContentMark::Artifact) => {
+                        operations.push(Operation::new(
+                            "BMC",
+                            vec![Object::Name(b"Artifact".to_vec())],
+                        ));
+                    }
+                    None => {
+                        operations.extend(content);
+                        continue;
+                    }
+                }
+                operations.extend(content);
+                operations.push(Operation::new("EMC", vec![]));
+            }
+        }
+        let mut stream = Stream::new(Dictionary::new(), Content { operations }.encode()?);
+        let _ = stream.compress();
+        let content_id = pdf_document.add_object(stream);
+        let page = pdf_document.get_object_mut(page_id)?.as_dict_mut()?;
+        page.set("Contents", content_id);
+        page.set("StructParents", page_owners.len() as i64);
+        page.set("Tabs", "S");
+        page_owners.push(owners);
+    }
+
+    // annotations of links are part of the link element of their URL
+    let mut annotation_owners = vec![];
+    let mut links = tree.links.clone().into_iter();
+    for &page_id in &pages {
+        for annotation_id in page_annotations(pdf_document, page_id)? {
+            let url = pdf_document
+                .get_dictionary(annotation_id)
+                .and_then(|annotation| annotation.get_deref(b"A", pdf_document))
+                .and_then(Object::as_dict)
+                .and_then(|action| action.get(b"URI"))
+                .and_then(Object::as_str)
+                .map(decode_text_string)
+                .ok();
+            let link = match &url {
+                Some(url) => links
+                    .find(|(_, link_url)| link_url == url)
+                    .map(|(link, _)| link),
+                None => None,
+            };
+            let parent = tree
+                .table_of_contents
+                .as_ref()
+                .map_or(0, |contents| contents.list);
+            let link = link.unwrap_or_else(|| tree.add(parent, "Link"));
+            tree.nodes[link].kids.push(StructKid::Annotation {
+                page: page_id,
+                annotation: annotation_id,
+            });
+            let key = (page_owners.len() + annotation_owners.len()) as i64;
+            let annotation = pdf_document.get_object_mut(annotation_id)?.as_dict_mut()?;
+            annotation.set("StructParent", key);
+            if !annotation.has(b"Contents") {
+                let description = url.unwrap_or_else(|| "Link".to_string());
+                annotation.set("Contents", text_string(&description));
+            }
+            annotation_owners.push(link);
+        }
+    }
+
+    // elements without any content are left out
+    let mut ids: Vec<Option<ObjectId>> = vec![None; tree.nodes.len()];
+    for node in (0..tree.nodes.len()).rev() {
+        let used = tree.nodes[node].kids.iter().any(|kid| match kid {
+            StructKid::Node(kid) => ids.get(*kid).is_some_and(Option::is_some),
+            _ => true,
+        });
+        if used || node == 0 {
+            ids[node] = Some(pdf_document.new_object_id());
+        }
+    }
+    let root_id = pdf_document.new_object_id();
+    for (node, id) in tree.nodes.iter().zip(&ids) {
+        let Some(id) = *id else {
+            continue;
+        };
+        let kids: Vec<Object> = node
+            .kids
+            .iter()
+            .filter_map(|kid| match *kid {
+                StructKid::Node(kid) => ids.get(kid).copied().flatten().map(Object::Reference),
+                StructKid::Content { page, mcid } => {
+                    Some(dictionary! { "Type" => "MCR", "Pg" => page, "MCID" => mcid }.into())
+                }
+                StructKid::Annotation { page, annotation } => {
+                    Some(dictionary! { "Type" => "OBJR", "Pg" => page, "Obj" => annotation }.into())
+                }
+            })
+            .collect();
+        let parent = if node.role == "Document" {
+            root_id
+        } else {
+            ids[node.parent].unwrap_or(root_id)
+        };
+        let mut element = dictionary! {
+            "Type" => "StructElem",
+            "S" => node.role,
+            "P" => parent,
+            "K" => kids,
+        };
+        if let Some(alt) = &node.alt {
+            element.set("Alt", text_string(alt));
+        }
+        if let Some(attributes) = &node.attributes {
+            element.set("A", attributes.clone());
+        }
+        pdf_document.objects.insert(id, Object::Dictionary(element));
+    }
+
+    let reference = |node: &usize| ids[*node].map_or(Object::Null, Object::Reference);
+    let mut numbers = vec![];
+    for (key, owners) in page_owners.iter().enumerate() {
+        numbers.push(Object::Integer(key as i64));
+        numbers.push(owners.iter().map(reference).collect::<Vec<Object>>().into());
+    }
+    for (key, owner) in annotation_owners.iter().enumerate() {
+        numbers.push(Object::Integer((page_owners.len() + key) as i64));
+        numbers.push(reference(owner));
+    }
+    let parent_tree_id = pdf_document.add_object(dictionary! { "Nums" => numbers });
+    pdf_document.objects.insert(
+        root_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => ids[0].map_or(Object::Null, Object::Reference),
+            "ParentTree" => parent_tree_id,
+            "ParentTreeNextKey" => (page_owners.len() + annotation_owners.len()) as i64,
+        }),
+    );
+
+    let catalog = pdf_document.catalog_mut()?;
+    catalog.set("StructTreeRoot", root_id);
+    catalog.set("MarkInfo", dictionary! { "Marked" => true });
+    let mut preferences = catalog
+        .get(b"ViewerPreferences")
+        .and_then(Object::as_dict)
+        .cloned()
+        .unwrap_or_default();
+    preferences.set("DisplayDocTitle", true);
+    catalog.set("ViewerPreferences", preferences);
+    Ok(untagged)
+}
+
+// Text string in PDFDocEncoding when it is ASCII and in UTF-16BE otherwise
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
+// Loads the document and decrypts it when it is encrypted
+fn load_document(bytes: &[u8], options: &PdfParseOptions) -> anyhow::Result<PdfDocument> {
+    let pdf_document = PdfDocument::load_mem(bytes)?;
+    if !pdf_document.is_encrypted() {
+        return Ok(pdf_document);
+    }
+    let security = SecurityHandler::new(&pdf_document, options.password.as_deref())?;
+    let encrypt_id = pdf_document.trailer.get(b"Encrypt")?.as_reference()?;
+
+    // object streams are dropped while loading because their content is still encrypted
+    let containers: BTreeSet<u32> = pdf_document
+        .reference_table
+        .entries
+        .values()
+        .filter_map(|entry| match entry {
+            XrefEntry::Compressed { container, .. } => Some(*container),
+            _ => None,
+        })
+        .collect();
+    let reader = Reader {
+        buffer: bytes,
+        document: pdf_document,
+    };
+    let object_streams: Vec<(ObjectId, Object)> = containers
+        .into_iter()
+        .filter_map(|container| {
+            let id = (container, 0);
+            reader.get_object(id).ok().map(|object| (id, object))
+        })
+        .collect();
+    let mut pdf_document = reader.document;
+
+    for (&id, object) in pdf_document.objects.iter_mut() {
+        if id != encrypt_id {
+            security.decrypt_object(id, object);
+        }
+    }
+    for (id, mut object) in object_streams {
+        security.decrypt_object(id, &mut object);
+        let Ok(stream) = object.as_stream_mut() else {
+            continue;
+        };
+        match ObjectStream::new(stream) {
+            Ok(object_stream) => {
+                for (id, object) in object_stream.objects {
+                    pdf_document.objects.entry(id).or_insert(object);
+                }
+            }
+            Err(e) => warn!("Skipping object stream {:?}: {}", id, e),
+        }
+    }
+    pdf_document.trailer.remove(b"Encrypt");
+    Ok(pdf_document)
+}
+
+const PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CryptMethod {
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+// Standard security handler of the encryption dictionary with the file key for a password
+#[derive(Debug)]
+struct SecurityHandler {
+    revision: i64,
+    key_length: usize,
+    owner: Vec<u8>,
+    user: Vec<u8>,
+    permissions: i32,
+    file_id: Vec<u8>,
+    encrypt_metadata: bool,
+    strings: CryptMethod,
+    streams: CryptMethod,
+    key: Vec<u8>,
+}
+
+impl SecurityHandler {
+    fn new(pdf_document: &PdfDocument, password: Option<&str>) -> anyhow::Result<Self> {
+        let encrypt = pdf_document.get_encrypted()?;
+        let filter = encrypt.get(b"Filter").and_then(Object::as_name_str)?;
+        if filter != "Standard" {
+            return Err(anyhow::anyhow!(
+                "Unsupported PDF security handler {}",
+                filter
+            ));
+        }
+        let integer = |key: &[u8]| encrypt.get(key).and_then(Object::as_i64);
+        let version = integer(b"V").unwrap_or(0);
+        let revision = integer(b"R")?;
+        let (strings, streams) = match version {
+            1 | 2 => (CryptMethod::Rc4, CryptMethod::Rc4),
+            4 | 5 => {
+                let method = |key: &[u8]| {
+                    let name = encrypt.get(key).and_then(Object::as_name).ok();
+                    crypt_filter_method(encrypt, name.unwrap_or(b"Identity"))
+                };
+                (method(b"StrF")?, method(b"StmF")?)
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported PDF encryption version {}",
+                    version
+                ))
+            }
+        };
+        let key_length = match (revision, version) {
+            (2, _) => 5,
+            (_, 4) => 16,
+            (_, 5) => 32,
+            _ => integer(b"Length").unwrap_or(40).clamp(40, 128) as usize / 8,
+        };
+        let bytes = |key: &[u8]| {
+            encrypt
+                .get(key)
+                .and_then(Object::as_str)
+                .map(<[u8]>::to_vec)
+        };
+        let file_id = pdf_document
+            .trailer
+            .get(b"ID")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|id| id.first())
+            .and_then(|id| id.as_str().ok())
+            .unwrap_or_default()
+            .to_vec();
+        let mut handler = SecurityHandler {
+            revision,
+            key_length,
+            owner: bytes(b"O")?,
+            user: bytes(b"U")?,
+            permissions: integer(b"P")? as i32,
+            file_id,
+            encrypt_metadata: encrypt
+                .get(b"EncryptMetadata")
+                .and_then(Object::as_bool)
+                .unwrap_or(true),
+            strings,
+            streams,
+            key: vec![],
+        };
+        let key = if revision >= 5 {
+            let owner_key = bytes(b"OE")?;
+            let user_key = bytes(b"UE")?;
+            // a password with prohibited characters can't match
+            sasl_prep(password.unwrap_or_default()).and_then(|password| {
+                let password = password.as_bytes();
+                let password = &password[..password.len().min(127)];
+                handler.aes_file_key(password, &user_key, &owner_key)
+            })
+        } else if revision >= 2 {
+            let password = password_bytes(password.unwrap_or_default());
+            handler.rc4_user_key(pdf_document, &password).or_else(|| {
+                let user_password = handler.rc4_owner_password(&password);
+                handler.rc4_user_key(pdf_document, &user_password)
+            })
+        } else {
+            return Err(anyhow::anyhow!(
+                "Unsupported PDF encryption revision {}",
+                revision
+            ));
+        };
+        handler.key = match (key, password) {
+            (Some(key), _) => key,
+            (None, None) => return Err(ParserError::PasswordRequired.into()),
+            (None, Some(_)) => return Err(ParserError::IncorrectPassword.into()),
+        };
+        Ok(handler)
+    }
+
+    // Algorithm 2 of the standard security handler, the key computed from the user password.
+    // Only used for revision 4, lopdf computes the key of the earlier revisions
+    fn rc4_key(&self, password: &[u8]) -> Vec<u8> {
+        let mut context = md5::Context::new();
+        context.consume(padded_password(password));
+        context.consume(&self.owner[..self.owner.len().min(32)]);
+        context.consume(self.permissions.to_le_bytes());
+        context.consume(&self.file_id);
+        if self.revision >= 4 && !self.encrypt_metadata {
+            context.consume([0xFF; 4]);
+        }
+        let mut digest = context.compute().0;
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                digest = md5::compute(&digest[..self.key_length]).0;
+            }
+        }
+        digest[..self.key_length].to_vec()
+    }
+
+    // The key for the password if the password matches the /U entry
+    fn rc4_user_key(&self, pdf_document: &PdfDocument, password: &[u8]) -> Option<Vec<u8>> {
+        if self.revision <= 3 {
+            return lopdf::encryption::get_encryption_key(pdf_document, password, true).ok();
+        }
+        // revision 4, which lopdf does not support, checks the /U entry like revision 3
+        let key = self.rc4_key(password);
+        let mut context = md5::Context::new();
+        context.consume(PASSWORD_PADDING);
+        context.consume(&self.file_id);
+        let hash = rc4_rounds(&key, &context.compute().0, 0..=19);
+        (self.user.get(..16) == Some(&hash[..])).then_some(key)
+    }
+
+    // Algorithm 7, the user password recovered from the /O entry with the owner password
+    fn rc4_owner_password(&self, password: &[u8]) -> Vec<u8> {
+        let mut digest = md5::compute(padded_password(password)).0;
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                digest = md5::compute(digest).0;
+            }
+        }
+        let key = &digest[..self.key_length];
+        if self.revision == 2 {
+            rc4(key, &self.owner)
+        } else {
+            rc4_rounds(key, &self.owner, (0..=19).rev())
+        }
+    }
+
+    // Algorithms 2.A and 2.B, tries the password as user and then as owner password
+    fn aes_file_key(&self, password: &[u8], user_key: &[u8], owner_key: &[u8]) -> Option<Vec<u8>> {
+        if self.user.len() < 48 || self.owner.len() < 48 {
+            return None;
+        }
+        let hash =
+            |salt: &[u8], user: &[u8]| aes_password_hash(self.revision, password, salt, user);
+        let (key_salt, encrypted_key) = if hash(&self.user[32..40], &[]) == self.user[..32] {
+            (hash(&self.user[40..48], &[]), user_key)
+        } else if hash(&self.owner[32..40], &self.user[..48]) == self.owner[..32] {
+            (hash(&self.owner[40..48], &self.user[..48]), owner_key)
+        } else {
+            return None;
+        };
+        let key = aes_cbc_decrypt::<Aes256>(&key_salt, &[0; 16], encrypted_key);
+        (key.len() >= 32).then(|| key[..32].to_vec())
+    }
+
+    // Decrypts the strings and the stream content of an indirect object in place
+    fn decrypt_object(&self, id: ObjectId, object: &mut Object) {
+        if let Object::Stream(stream) = object {
+            if stream.dict.type_is(b"XRef") {
+                return;
+            }
+            let plain_metadata = stream.dict.type_is(b"Metadata") && !self.encrypt_metadata;
+            let crypt_filter = stream
+                .filters()
+                .is_ok_and(|filters| filters.iter().any(|filter| filter == "Crypt"));
+            if !crypt_filter && !plain_metadata {
+                let content = self.decrypt(self.streams, id, &stream.content);
+                stream.set_content(content);
+            }
+            for (_, value) in stream.dict.iter_mut() {
+                self.decrypt_strings(id, value);
+            }
+        } else {
+            self.decrypt_strings(id, object);
+        }
+    }
+
+    fn decrypt_strings(&self, id: ObjectId, object: &mut Object) {
+        match object {
+            Object::String(text, _) => *text = self.decrypt(self.strings, id, text),
+            Object::Array(array) => {
+                for object in array {
+                    self.decrypt_strings(id, object);
+                }
+            }
+            Object::Dictionary(dictionary) => {
+                for (_, object) in dictionary.iter_mut() {
+                    self.decrypt_strings(id, object);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn decrypt(&self, method: CryptMethod, id: ObjectId, data: &[u8]) -> Vec<u8> {
+        match method {
+            CryptMethod::Identity => data.to_vec(),
+            CryptMethod::Rc4 => {
+                let string = Object::string_literal(data);
+                lopdf::encryption::decrypt_object(&self.key, id, &string).unwrap_or_default()
+            }
+            CryptMethod::Aes128 => aes_decrypt::<Aes128>(&self.object_key(id), data),
+            CryptMethod::Aes256 => aes_decrypt::<Aes256>(&self.key, data),
+        }
+    }
+
+    // Algorithm 1 for AES-128, the file key extended with the object number and a salt
+    fn object_key(&self, id: ObjectId) -> Vec<u8> {
+        let mut context = md5::Context::new();
+        context.consume(&self.key);
+        context.consume(&id.0.to_le_bytes()[..3]);
+        context.consume(&id.1.to_le_bytes()[..2]);
+        context.consume(b"sAlT");
+        context.compute()[..(self.key.len() + 5).min(16)].to_vec()
+    }
+}
+
+// Method of the crypt filter with this name in the /CF dictionary
+fn crypt_filter_method(encrypt: &Dictionary, name: &[u8]) -> anyhow::Result<CryptMethod> {
+    if name == b"Identity" {
+        return Ok(CryptMethod::Identity);
+    }
+    let method = encrypt
+        .get(b"CF")
+        .and_then(Object::as_dict)
+        .and_then(|filters| filters.get(name))
+        .and_then(Object::as_dict)
+        .and_then(|filter| filter.get(b"CFM"))
+        .and_then(Object::as_name);
+    match method {
+        Ok(b"V2") => Ok(CryptMethod::Rc4),
+        Ok(b"AESV2") => Ok(CryptMethod::Aes128),
+        Ok(b"AESV3") => Ok(CryptMethod::Aes256),
+        Ok(b"None") | Err(_) => Ok(CryptMethod::Identity),
+        Ok(method) => Err(anyhow::anyhow!(
+            "Unsupported PDF crypt filter method {}",
+            String::from_utf8_lossy(method)
+        )),
+    }
+}
+
+// Characters of PDFDocEncoding that are not at their Latin-1 position
+const PDF_DOC_ENCODING: [(u8, char); 40] = [
+    (0x18, '\u{2d8}'),
+    (0x19, '\u{2c7}'),
+    (0x1A, '\u{2c6}'),
+    (0x1B, '\u{2d9}'),
+    (0x1C, '\u{2dd}'),
+    (0x1D, '\u{2db}'),
+    (0x1E, '\u{2da}'),
+    (0x1F, '\u{2dc}'),
+    (0x80, '\u{2022}'),
+    (0x81, '\u{2020}'),
+    (0x82, '\u{2021}'),
+    (0x83, '\u{2026}'),
+    (0x84, '\u{2014}'),
+    (0x85, '\u{2013}'),
+    (0x86, '\u{192}'),
+    (0x87, '\u{2044}'),
+    (0x88, '\u{2039}'),
+    (0x89, '\u{203a}'),
+    (0x8A, '\u{2212}'),
+    (0x8B, '\u{2030}'),
+    (0x8C, '\u{201e}'),
+    (0x8D, '\u{201c}'),
+    (0x8E, '\u{201d}'),
+    (0x8F, '\u{2018}'),
+    (0x90, '\u{2019}'),
+    (0x91, '\u{201a}'),
+    (0x92, '\u{2122}'),
+    (0x93, '\u{fb01}'),
+    (0x94, '\u{fb02}'),
+    (0x95, '\u{141}'),
+    (0x96, '\u{152}'),
+    (0x97, '\u{160}'),
+    (0x98, '\u{178}'),
+    (0x99, '\u{17d}'),
+    (0x9A, '\u{131}'),
+    (0x9B, '\u{142}'),
+    (0x9C, '\u{153}'),
+    (0x9D, '\u{161}'),
+    (0x9E, '\u{17e}'),
+    (0xA0, '\u{20ac}'),
+];
+
+// Passwords of revisions before AES-256 are in PDFDocEncoding, passwords it can't
+// encode keep their UTF-8 bytes like other readers do
+fn password_bytes(password: &str) -> Vec<u8> {
+    let pdf_doc_byte = |c: char| match c {
+        '\t' | '\n' | '\r' | ' '..='~' => Some(c as u8),
+        '\u{a1}'..='\u{ff}' if c != '\u{ad}' => Some(c as u8),
+        _ => PDF_DOC_ENCODING
+            .iter()
+            .find(|(_, mapped)| *mapped == c)
+            .map(|(byte, _)| *byte),
+    };
+    password
+        .chars()
+        .map(pdf_doc_byte)
+        .collect::<Option<Vec<u8>>>()
+        .unwrap_or_else(|| password.as_bytes().to_vec())
+}
+
+// SASLprep (RFC 4013) of AES-256 passwords, None when the password has prohibited characters.
+// Unassigned code points are allowed as in a query
+fn sasl_prep(password: &str) -> Option<String> {
+    use unicode_bidi::{bidi_class, BidiClass};
+    use unicode_normalization::UnicodeNormalization;
+
+    let non_ascii_space = |c: char| {
+        matches!(
+            c,
+            '\u{a0}' | '\u{1680}' | '\u{2000}'..='\u{200b}' | '\u{202f}' | '\u{205f}' | '\u{3000}'
+        )
+    };
+    // table B.1, commonly mapped to nothing
+    let mapped_to_nothing = |c: char| {
+        matches!(
+            c,
+            '\u{ad}'
+                | '\u{34f}'
+                | '\u{1806}'
+                | '\u{180b}'..='\u{180d}'
+                | '\u{200b}'..='\u{200d}'
+                | '\u{2060}'
+                | '\u{fe00}'..='\u{fe0f}'
+                | '\u{feff}'
+        )
+    };
+    let prepared: String = password
+        .chars()
+        .filter(|&c| !mapped_to_nothing(c))
+        .map(|c| if non_ascii_space(c) { ' ' } else { c })
+        .nfkc()
+        .collect();
+
+    // tables C.1.2 to C.9
+    let prohibited = |c: char| {
+        non_ascii_space(c)
+            || c.is_control()
+            || matches!(
+                c,
+                // control, formatting and change of direction characters
+                '\u{340}'
+                    | '\u{341}'
+                    | '\u{6dd}'
+                    | '\u{70f}'
+                    | '\u{180e}'
+                    | '\u{200c}'..='\u{200f}'
+                    | '\u{2028}'..='\u{202e}'
+                    | '\u{2060}'..='\u{2063}'
+                    | '\u{206a}'..='\u{206f}'
+                    | '\u{feff}'
+                    | '\u{1d173}'..='\u{1d17a}'
+                    // ideographic descriptions, replacement and tagging characters
+                    | '\u{2ff0}'..='\u{2ffb}'
+                    | '\u{fff9}'..='\u{fffd}'
+                    | '\u{e0001}'
+                    | '\u{e0020}'..='\u{e007f}'
+                    // private use and non-characters
+                    | '\u{e000}'..='\u{f8ff}'
+                    | '\u{f0000}'..='\u{ffffd}'
+                    | '\u{100000}'..='\u{10fffd}'
+                    | '\u{fdd0}'..='\u{fdef}'
+            )
+            || (c as u32) & 0xFFFE == 0xFFFE
+    };
+    if prepared.chars().any(prohibited) {
+        return None;
+    }
+
+    // text with right-to-left characters has no left-to-right ones and starts and ends right-to-left
+    let right_to_left = |c: char| matches!(bidi_class(c), BidiClass::R | BidiClass::AL);
+    if prepared.chars().any(right_to_left)
+        && (prepared.chars().any(|c| bidi_class(c) == BidiClass::L)
+            || !prepared.chars().next().is_some_and(right_to_left)
+            || !prepared.chars().last().is_some_and(right_to_left))
+    {
+        return None;
+    }
+    Some(prepared)
+}
+
+fn padded_password(password: &[u8]) -> [u8; 32] {
+    let mut padded = PASSWORD_PADDING;
+    let length = password.len().min(32);
+    padded.copy_within(..32 - length, length);
+    padded[..length].copy_from_slice(&password[..length]);
+    padded
+}
+
+// RC4 with a key of 5 to 16 bytes, the lengths the standard security handler allows
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    fn apply<KeySize>(key: &[u8], data: &mut [u8])
+    where
+        Rc4<KeySize>: KeyInit + StreamCipher,
+    {
+        if let Ok(mut cipher) = Rc4::<KeySize>::new_from_slice(key) {
+            cipher.apply_keystream(data);
+        }
+    }
+
+    let mut data = data.to_vec();
+    match key.len() {
+        5 => apply::<U5>(key, &mut data),
+        6 => apply::<U6>(key, &mut data),
+        7 => apply::<U7>(key, &mut data),
+        8 => apply::<U8>(key, &mut data),
+        9 => apply::<U9>(key, &mut data),
+        10 => apply::<U10>(key, &mut data),
+        11 => apply::<U11>(key, &mut data),
+        12 => apply::<U12>(key, &mut data),
+        13 => apply::<U13>(key, &mut data),
+        14 => apply::<U14>(key, &mut data),
+        15 => apply::<U15>(key, &mut data),
+        16 => apply::<U16>(key, &mut data),
+        length => warn!("Unsupported RC4 key length {}", length),
+    }
+    data
+}
+
+// RC4 applied once per round with the key xor-ed with the round number
+fn rc4_rounds(key: &[u8], data: &[u8], rounds: impl Iterator<Item = u8>) -> Vec<u8> {
+    rounds.fold(data.to_vec(), |data, round| {
+        let key: Vec<u8> = key.iter().map(|byte| byte ^ round).collect();
+        rc4(&key, &data)
+    })
+}
+
+// Algorithm 2.B, revision 5 uses a single SHA-256
+fn aes_password_hash(revision: i64, password: &[u8], salt: &[u8], user: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(user)
+        .finalize()
+        .to_vec();
+    if revision == 5 {
+        return hash;
+    }
+    let mut round = 0;
+    loop {
+        let block = [password, &hash, user].concat().repeat(64);
+        let encrypted = aes_cbc_encrypt::<Aes128>(&hash[..16], &hash[16..32], &block);
+        hash = match encrypted[..16].iter().map(|&byte| byte as u32).sum::<u32>() % 3 {
+            0 => Sha256::digest(&encrypted).to_vec(),
+            1 => Sha384::digest(&encrypted).to_vec(),
+            _ => Sha512::digest(&encrypted).to_vec(),
+        };
+        round += 1;
+        if round >= 64
+            && encrypted
+                .last()
+                .is_some_and(|&last| last as u32 + 32 <= round)
+        {
+            return hash[..32].to_vec();
+        }
+    }
+}
+
+// Data starts with the initialization vector and is padded as in PKCS#7
+fn aes_decrypt<C: BlockDecrypt + KeyInit + BlockSizeUser<BlockSize = U16>>(
+    key: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    if data.len() < 32 {
+        return vec![];
+    }
+    let mut decrypted = aes_cbc_decrypt::<C>(key, &data[..16], &data[16..]);
+    let padding = decrypted.last().copied().unwrap_or_default() as usize;
+    if (1..=16).contains(&padding) && padding <= decrypted.len() {
+        decrypted.truncate(decrypted.len() - padding);
+    }
+    decrypted
+}
+
+fn aes_cbc_decrypt<C: BlockDecrypt + KeyInit + BlockSizeUser<BlockSize = U16>>(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    let Ok(cipher) = C::new_from_slice(key) else {
+        return vec![];
+    };
+    let mut previous = aes::Block::clone_from_slice(iv);
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(16) {
+        let mut block = aes::Block::clone_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+        output.extend(block.iter().zip(previous.iter()).map(|(a, b)| a ^ b));
+        previous = aes::Block::clone_from_slice(chunk);
+    }
+    output
+}
+
+fn aes_cbc_encrypt<C: BlockEncrypt + KeyInit + BlockSizeUser<BlockSize = U16>>(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    let Ok(cipher) = C::new_from_slice(key) else {
+        return vec![];
+    };
+    let mut previous = aes::Block::clone_from_slice(iv);
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(16) {
+        let mut block = aes::Block::clone_from_slice(chunk);
+        block
+            .iter_mut()
+            .zip(previous.iter())
+            .for_each(|(a, b)| *a ^= b);
+        cipher.encrypt_block(&mut block);
+        output.extend_from_slice(&block);
+        previous = block;
+    }
+    output
+}
+
+// Reads the document information dictionary referenced by the trailer
+fn parse_metadata(pdf_document: &PdfDocument) -> DocumentMetadata {
+    let mut metadata = DocumentMetadata::default();
+    let Ok(info) = pdf_document
+        .trailer
+        .get_deref(b"Info", pdf_document)
+        .and_then(Object::as_dict)
+    else {
+        return metadata;
+    };
+    let field = |key: &[u8]| match info.get_deref(key, pdf_document) {
+        Ok(Object::String(bytes, _)) => Some(decode_text_string(bytes)),
+        _ => None,
+    };
+    metadata.title = field(b"Title");
+    metadata.author = field(b"Author");
+    metadata.subject = field(b"Subject");
+    metadata.keywords = field(b"Keywords")
+        .map(|keywords| {
+            keywords
+                .split([',', ';'])
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    metadata.created = field(b"CreationDate").map(|date| pdf_date_to_iso(&date));
+    metadata.modified = field(b"ModDate").map(|date| pdf_date_to_iso(&date));
+    metadata
+}
+
+// Text strings are either UTF-16BE with a byte order mark or PDFDocEncoding
+fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+// Converts a PDF date (D:YYYYMMDDHHmmSS) into ISO-8601, unknown formats are kept as is
+fn pdf_date_to_iso(date: &str) -> String {
+    let digits = date.strip_prefix("D:").unwrap_or(date);
+    if digits.len() < 8 || !digits[..8].bytes().all(|b| b.is_ascii_digit()) {
+        return date.to_string();
+    }
+    let mut iso = format!("{}-{}-{}", &digits[0..4], &digits[4..6], &digits[6..8]);
+    if digits.len() >= 14 && digits[8..14].bytes().all(|b| b.is_ascii_digit()) {
+        iso.push_str(&format!(
+            "T{}:{}:{}",
+            &digits[8..10],
+            &digits[10..12],
+            &digits[12..14]
+        ));
+    }
+    iso
+}
+
+// Reads the text runs and ruling lines of a page by interpreting its content stream
+fn read_page(pdf_document: &PdfDocument, page_id: ObjectId) -> anyhow::Result<PageContent> {
+    let resources = page_resources(pdf_document, page_id);
+    let content = Content::decode(&pdf_document.get_page_content(page_id)?)?;
+    let mut page = PageContent {
+        links: page_links(pdf_document, page_id),
+        ..PageContent::default()
+    };
+    read_content(
+        pdf_document,
+        &content.operations,
+        &resources,
+        GraphicsState::default(),
+        &mut page,
+        0,
+    )?;
+    Ok(page)
+}
+
+// Resource dictionaries of the page and of the pages it inherits from
+fn page_resources(pdf_document: &PdfDocument, page_id: ObjectId) -> Vec<&Dictionary> {
+    let (inline_resources, resource_ids) = pdf_document.get_page_resources(page_id);
+    inline_resources
+        .into_iter()
+        .chain(
+            resource_ids
+                .into_iter()
+                .filter_map(|id| pdf_document.get_dictionary(id).ok()),
+        )
+        .collect()
+}
+
+/// Text, ruling lines, images and links of a page, in default user space (y grows upwards)
+#[derive(Default)]
+struct PageContent {
+    spans: Vec<TextSpan>,
+    rules: Vec<Rule>,
+    images: Vec<PlacedImage>,
+    links: Vec<Link>,
+    /// Bookmarks pointing into the page
+    outline: Vec<OutlineEntry>,
+}
+
+impl PageContent {
+    fn push_span(&mut self, span: Option<TextSpan>) {
+        if let Some(span) = span.filter(|span| !span.text.trim().is_empty()) {
+            self.spans.push(span);
+        }
+    }
+
+    fn link_at(&self, x: f32, y: f32) -> Option<String> {
+        self.links
+            .iter()
+            .find(|link| link.x0 <= x && x <= link.x1 && link.y0 <= y && y <= link.y1)
+            .map(|link| link.url.clone())
+    }
+}
+
+/// Area of a link annotation pointing to a URI
+struct Link {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    url: String,
+}
+
+/// Bookmark of the document outline
+struct OutlineEntry {
+    title: String,
+    /// Depth in the outline, starting at 1
+    level: usize,
+    /// Top of the destination view, if the destination sets one
+    top: Option<f32>,
+}
+
+/// Image XObject and the area it is drawn in
+struct PlacedImage {
+    x0: f32,
+    x1: f32,
+    /// Top edge
+    y1: f32,
+    image: ImageData,
+}
+
+/// Text shown by one text operator with one font
+#[derive(Debug, Clone)]
+struct TextSpan {
+    text: String,
+    x0: f32,
+    x1: f32,
+    /// Baseline
+    y: f32,
+    size: f32,
+    style: TextStyle,
+    /// Target of the link annotation the text is placed in
+    link: Option<String>,
+}
+
+/// Horizontal or vertical line drawn on the page, such as a table border
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Rule {
+    // Thin rectangles and axis aligned segments, other shapes are not rules
+    fn new((x0, y0): (f32, f32), (x1, y1): (f32, f32)) -> Option<Rule> {
+        let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+        let thin = width.min(height) <= 2.5 && width.max(height) > 2.0 * width.min(height);
+        thin.then_some(Rule {
+            x0: x0.min(x1),
+            y0: y0.min(y1),
+            x1: x0.max(x1),
+            y1: y0.max(y1),
+        })
+    }
+
+    fn is_horizontal(&self) -> bool {
+        self.x1 - self.x0 > self.y1 - self.y0
+    }
+
+    // Position across the rule
+    fn offset(&self) -> f32 {
+        if self.is_horizontal() {
+            (self.y0 + self.y1) / 2.0
+        } else {
+            (self.x0 + self.x1) / 2.0
+        }
+    }
+
+    fn touches(&self, other: &Rule) -> bool {
+        const TOLERANCE: f32 = 2.0;
+        self.x0 <= other.x1 + TOLERANCE
+            && other.x0 <= self.x1 + TOLERANCE
+            && self.y0 <= other.y1 + TOLERANCE
+            && other.y0 <= self.y1 + TOLERANCE
+    }
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m1: &Matrix, m2: &Matrix) -> Matrix {
+    [
+        m1[0] * m2[0] + m1[1] * m2[2],
+        m1[0] * m2[1] + m1[1] * m2[3],
+        m1[2] * m2[0] + m1[3] * m2[2],
+        m1[2] * m2[1] + m1[3] * m2[3],
+        m1[4] * m2[0] + m1[5] * m2[2] + m2[4],
+        m1[4] * m2[1] + m1[5] * m2[3] + m2[5],
+    ]
+}
+
+fn translate(x: f32, y: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, x, y]
+}
+
+fn transform(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (x * m[0] + y * m[2] + m[4], x * m[1] + y * m[3] + m[5])
+}
+
+fn to_matrix(numbers: &[f32]) -> Option<Matrix> {
+    numbers.try_into().ok()
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Vec<u8>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    scaling: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            ctm: IDENTITY,
+            font: vec![],
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scaling: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+/// Path under construction, in user space
+#[derive(Default)]
+struct Path {
+    segments: Vec<((f32, f32), (f32, f32))>,
+    start: Option<(f32, f32)>,
+    current: Option<(f32, f32)>,
+}
+
+impl Path {
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.start = Some(point);
+        self.current = Some(point);
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        if let Some(current) = self.current {
+            self.segments.push((current, point));
+        }
+        self.current = Some(point);
+    }
+
+    fn close(&mut self) {
+        if let (Some(start), Some(_)) = (self.start, self.current) {
+            self.line_to(start);
+        }
+    }
+
+    fn rules(&mut self) -> Vec<Rule> {
+        let rules = self
+            .segments
+            .iter()
+            .filter_map(|&(from, to)| Rule::new(from, to))
+            .collect();
+        *self = Path::default();
+        rules
+    }
+}
+
+fn read_content(
+    pdf_document: &PdfDocument,
+    operations: &[Operation],
+    resources: &[&Dictionary],
+    mut state: GraphicsState,
+    page: &mut PageContent,
+    depth: usize,
+) -> anyhow::Result<()> {
+    let fonts = resource_fonts(pdf_document, resources);
+    let mut saved_states = vec![];
+    let mut text_matrix = IDENTITY;
+    let mut line_matrix = IDENTITY;
+    let mut path = Path::default();
+    // alternate descriptions of the open marked content sequences
+    let mut alternates: Vec<Option<String>> = vec![];
+    // thin filled rectangles are drawn as table borders too
+    let mut rectangles = vec![];
+
+    for operation in operations {
+        let numbers: Vec<f32> = operation
+            .operands
+            .iter()
+            .filter_map(|operand| operand.as_float().ok())
+            .collect();
+        let point = |x: f32, y: f32| transform(&state.ctm, x, y);
+        match (operation.operator.as_str(), numbers.as_slice()) {
+            ("q", _) => saved_states.push(state.clone()),
+            ("Q", _) => state = saved_states.pop().unwrap_or_default(),
+            ("cm", numbers) => {
+                if let Some(matrix) = to_matrix(numbers) {
+                    state.ctm = multiply(&matrix, &state.ctm);
+                }
+            }
+            ("BMC", _) => alternates.push(None),
+            ("BDC", _) => alternates.push(match operation.operands.get(1) {
+                Some(Object::Dictionary(properties)) => match properties.get(b"Alt") {
+                    Ok(Object::String(alt, _)) => Some(decode_text_string(alt)),
+                    _ => None,
+                },
+                _ => None,
+            }),
+            ("EMC", _) => {
+                alternates.pop();
+            }
+            ("BT", _) => {
+                text_matrix = IDENTITY;
+                line_matrix = IDENTITY;
+            }
+            ("Tf", [size]) => {
+                if let Some(Ok(name)) = operation.operands.first().map(Object::as_name) {
+                    state.font = name.to_vec();
+                }
+                state.font_size = *size;
+            }
+            ("Tc", [spacing]) => state.char_spacing = *spacing,
+            ("Tw", [spacing]) => state.word_spacing = *spacing,
+            ("Tz", [scaling]) => state.scaling = scaling / 100.0,
+            ("TL", [leading]) => state.leading = *leading,
+            ("Ts", [rise]) => state.rise = *rise,
+            (operator @ ("Td" | "TD"), [x, y]) => {
+                if operator == "TD" {
+                    state.leading = -y;
+                }
+                line_matrix = multiply(&translate(*x, *y), &line_matrix);
+                text_matrix = line_matrix;
+            }
+            ("Tm", numbers) => {
+                if let Some(matrix) = to_matrix(numbers) {
+                    line_matrix = matrix;
+                    text_matrix = matrix;
+                }
+            }
+            (operator @ ("T*" | "'" | "\"" | "Tj" | "TJ"), numbers) => {
+                if operator == "\"" {
+                    if let [word_spacing, char_spacing] = numbers {
+                        state.word_spacing = *word_spacing;
+                        state.char_spacing = *char_spacing;
+                    }
+                }
+                if matches!(operator, "T*" | "'" | "\"") {
+                    line_matrix = multiply(&translate(0.0, -state.leading), &line_matrix);
+                    text_matrix = line_matrix;
+                }
+                let Some(font) = fonts.get(&state.font) else {
+                    continue;
+                };
+                let mut span = None;
+                for operand in &operation.operands {
+                    match operand {
+                        Object::String(bytes, _) => {
+                            show_text(font, bytes, &state, &mut text_matrix, &mut span, page)
+                        }
+                        Object::Array(items) => {
+                            for item in items {
+                                match item {
+                                    Object::String(bytes, _) => show_text(
+                                        font,
+                                        bytes,
+                                        &state,
+                                        &mut text_matrix,
+                                        &mut span,
+                                        page,
+                                    ),
+                                    item => {
+                                        let Ok(adjustment) = item.as_float() else {
+                                            continue;
+                                        };
+                                        let shift =
+                                            -adjustment / 1000.0 * state.font_size * state.scaling;
+                                        // wide adjustments are used instead of space glyphs
+                                        if let Some(span) = &mut span {
+                                            if shift > 0.2 * state.font_size
+                                                && !span.text.ends_with(char::is_whitespace)
+                                            {
+                                                span.text.push(' ');
+                                            }
+                                        }
+                                        text_matrix =
+                                            multiply(&translate(shift, 0.0), &text_matrix);
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                page.push_span(span);
+            }
+            ("m", [x, y]) => path.move_to(point(*x, *y)),
+            ("l", [x, y]) => path.line_to(point(*x, *y)),
+            ("c", [.., x, y]) | ("v" | "y", [_, _, x, y]) => path.current = Some(point(*x, *y)),
+            ("h", _) => path.close(),
+            ("re", [x, y, width, height]) => {
+                let corners = [
+                    point(*x, *y),
+                    point(x + width, *y),
+                    point(x + width, y + height),
+                    point(*x, y + height),
+                ];
+                path.move_to(corners[0]);
+                for corner in &corners[1..] {
+                    path.line_to(*corner);
+                }
+                path.close();
+                rectangles.push((corners[0], corners[2]));
+            }
+            ("S" | "s", _) => {
+                if operation.operator == "s" {
+                    path.close();
+                }
+                page.rules.extend(path.rules());
+                rectangles.clear();
+            }
+            ("f" | "F" | "f*", _) => {
+                page.rules.extend(
+                    rectangles
+                        .drain(..)
+                        .filter_map(|(from, to)| Rule::new(from, to)),
+                );
+                path = Path::default();
+            }
+            ("B" | "B*" | "b" | "b*", _) => {
+                page.rules.extend(path.rules());
+                rectangles.clear();
+            }
+            ("n", _) => {
+                path = Path::default();
+                rectangles.clear();
+            }
+            ("Do", _) if depth < 8 => {
+                let Some(Ok(name)) = operation.operands.first().map(Object::as_name) else {
+                    continue;
+                };
+                let Some(form) = resource_xobject(pdf_document, resources, name) else {
+                    continue;
+                };
+                let subtype = form.dict.get(b"Subtype").and_then(Object::as_name).ok();
+                if subtype == Some(b"Image") {
+                    // images fill the unit square of the current transformation
+                    let corners = [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0), (1.0, 0.0)]
+                        .map(|(x, y)| transform(&state.ctm, x, y));
+                    let xs = corners.map(|(x, _)| x);
+                    let ys = corners.map(|(_, y)| y);
+                    let (x0, x1) = (
+                        xs.into_iter().fold(f32::MAX, f32::min),
+                        xs.into_iter().fold(f32::MIN, f32::max),
+                    );
+                    let (y0, y1) = (
+                        ys.into_iter().fold(f32::MAX, f32::min),
+                        ys.into_iter().fold(f32::MIN, f32::max),
+                    );
+                    let alt = alternates.iter().rev().flatten().next();
+                    if let Some(image) = decode_image(pdf_document, form, alt, x1 - x0, y1 - y0) {
+                        page.images.push(PlacedImage { x0, x1, y1, image });
+                    }
+                    continue;
+                }
+                if subtype != Some(b"Form") {
+                    continue;
+                }
+                let content = form
+                    .decompressed_content()
+                    .unwrap_or_else(|_| form.content.clone());
+                let Ok(content) = Content::decode(&content) else {
+                    warn!("Skipping form XObject with an unreadable content stream");
+                    continue;
+                };
+                let mut form_state = state.clone();
+                let matrix = form
+                    .dict
+                    .get(b"Matrix")
+                    .and_then(Object::as_array)
+                    .ok()
+                    .and_then(|matrix| {
+                        let numbers: Vec<f32> =
+                            matrix.iter().filter_map(|n| n.as_float().ok()).collect();
+                        to_matrix(&numbers)
+                    });
+                if let Some(matrix) = matrix {
+                    form_state.ctm = multiply(&matrix, &state.ctm);
+                }
+                // forms without their own resources use the ones of the page
+                let form_resources = form
+                    .dict
+                    .get_deref(b"Resources", pdf_document)
+                    .and_then(Object::as_dict)
+                    .map_or_else(|_| resources.to_vec(), |resources| vec![resources]);
+                read_content(
+                    pdf_document,
+                    &content.operations,
+                    &form_resources,
+                    form_state,
+                    page,
+                    depth + 1,
+                )?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Adds the glyphs of a string to the span and moves the text matrix past them
+fn show_text(
+    font: &PdfFont,
+    bytes: &[u8],
+    state: &GraphicsState,
+    text_matrix: &mut Matrix,
+    span: &mut Option<TextSpan>,
+    page: &mut PageContent,
+) {
+    for code in font.codes(bytes) {
+        let rendering = multiply(text_matrix, &state.ctm);
+        let mut advance = font.width(code) / 1000.0 * state.font_size + state.char_spacing;
+        if !font.two_byte && code == 32 {
+            advance += state.word_spacing;
+        }
+        advance *= state.scaling;
+        let (x0, y) = transform(&rendering, 0.0, state.rise);
+        let (x1, _) = transform(&rendering, advance, state.rise);
+        let size = state.font_size * rendering[2].hypot(rendering[3]);
+        let text = font.text(code);
+        // links can start and end inside of a string
+        let link = page.link_at((x0 + x1) / 2.0, y + 0.3 * size);
+        if span.as_ref().is_some_and(|span| span.link != link) {
+            page.push_span(span.take());
+        }
+        match span {
+            Some(span) => {
+                span.text.push_str(&text);
+                span.x1 = span.x1.max(x1);
+            }
+            None => {
+                *span = Some(TextSpan {
+                    text,
+                    x0,
+                    x1,
+                    y,
+                    size,
+                    style: font.style.clone(),
+                    link,
+                })
+            }
+        }
+        *text_matrix = multiply(&translate(advance, 0.0), text_matrix);
+    }
+}
+
+fn resource_fonts(
+    pdf_document: &PdfDocument,
+    resources: &[&Dictionary],
+) -> HashMap<Vec<u8>, PdfFont> {
+    let mut fonts = HashMap::new();
+    for resource in resources.iter().rev() {
+        let Ok(font_dictionary) = resource
+            .get_deref(b"Font", pdf_document)
+            .and_then(Object::as_dict)
+        else {
+            continue;
+        };
+        for (name, font) in font_dictionary {
+            if let Ok(font) = pdf_document
+                .dereference(font)
+                .and_then(|(_, font)| font.as_dict())
+            {
+                fonts.insert(name.clone(), PdfFont::load(pdf_document, font));
+            }
+        }
+    }
+    fonts
+}
+
+fn resource_xobject<'a>(
+    pdf_document: &'a PdfDocument,
+    resources: &[&'a Dictionary],
+    name: &[u8],
+) -> Option<&'a Stream> {
+    resources.iter().find_map(|resource| {
+        resource
+            .get_deref(b"XObject", pdf_document)
+            .and_then(Object::as_dict)
+            .and_then(|xobjects| xobjects.get_deref(name, pdf_document))
+            .and_then(Object::as_stream)
+            .ok()
+    })
+}
+
+// Bookmarks of the document outline by the page they point to
+fn outline_entries(pdf_document: &PdfDocument) -> HashMap<ObjectId, Vec<OutlineEntry>> {
+    let mut entries = HashMap::new();
+    let first = pdf_document
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"Outlines", pdf_document))
+        .and_then(Object::as_dict)
+        .and_then(|outlines| outlines.get(b"First"));
+    if let Ok(first) = first {
+        outline_items(pdf_document, first, 1, &mut entries, &mut HashSet::new());
+    }
+    entries
+}
+
+fn outline_items(
+    pdf_document: &PdfDocument,
+    first: &Object,
+    level: usize,
+    entries: &mut HashMap<ObjectId, Vec<OutlineEntry>>,
+    visited: &mut HashSet<ObjectId>,
+) {
+    let mut next = first.as_reference().ok();
+    // broken files can link items in a cycle
+    while let Some(id) = next.filter(|id| visited.insert(*id)) {
+        let Ok(item) = pdf_document.get_dictionary(id) else {
+            break;
+        };
+        let title = match item.get_deref(b"Title", pdf_document) {
+            Ok(Object::String(title, _)) => decode_text_string(title),
+            _ => String::new(),
+        };
+        let destination = item.get_deref(b"Dest", pdf_document).ok().or_else(|| {
+            let action = item
+                .get_deref(b"A", pdf_document)
+                .and_then(Object::as_dict)
+                .ok()?;
+            (action.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo"))
+                .then(|| action.get_deref(b"D", pdf_document).ok())
+                .flatten()
+        });
+        if let Some((page_id, top)) =
+            destination.and_then(|destination| resolve_destination(pdf_document, destination, 0))
+        {
+            entries
+                .entry(page_id)
+                .or_default()
+                .push(OutlineEntry { title, level, top });
+        }
+        if let Ok(child) = item.get(b"First") {
+            outline_items(pdf_document, child, level + 1, entries, visited);
+        }
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+}
+
+// Page and top of an explicit destination, named destinations are looked up first
+fn resolve_destination(
+    pdf_document: &PdfDocument,
+    destination: &Object,
+    depth: usize,
+) -> Option<(ObjectId, Option<f32>)> {
+    if depth > 8 {
+        return None;
+    }
+    match pdf_document.dereference(destination).ok()?.1 {
+        Object::Array(view) => {
+            let page_id = view.first()?.as_reference().ok()?;
+            let top = match view.get(1).and_then(|kind| kind.as_name().ok()) {
+                Some(b"XYZ") => view.get(3),
+                Some(b"FitH" | b"FitBH") => view.get(2),
+                Some(b"FitR") => view.get(5),
+                _ => None,
+            };
+            Some((page_id, top.and_then(|top| top.as_float().ok())))
+        }
+        Object::Dictionary(destination) => {
+            resolve_destination(pdf_document, destination.get(b"D").ok()?, depth + 1)
+        }
+        Object::String(name, _) | Object::Name(name) => {
+            let destination = named_destination(pdf_document, name)?;
+            resolve_destination(pdf_document, &destination, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+// Destinations are named in the Dests dictionary of older files and in the Dests name tree of newer ones
+fn named_destination(pdf_document: &PdfDocument, name: &[u8]) -> Option<Object> {
+    let catalog = pdf_document.catalog().ok()?;
+    if let Ok(destination) = catalog
+        .get_deref(b"Dests", pdf_document)
+        .and_then(Object::as_dict)
+        .and_then(|dests| dests.get(name))
+    {
+        return Some(destination.clone());
+    }
+    let tree = catalog
+        .get_deref(b"Names", pdf_document)
+        .and_then(Object::as_dict)
+        .and_then(|names| names.get_deref(b"Dests", pdf_document))
+        .and_then(Object::as_dict)
+        .ok()?;
+    find_name(pdf_document, tree, name, 0)
+}
+
+fn find_name(
+    pdf_document: &PdfDocument,
+    node: &Dictionary,
+    name: &[u8],
+    depth: usize,
+) -> Option<Object> {
+    if depth > 32 {
+        return None;
+    }
+    if let Ok(names) = node
+        .get_deref(b"Names", pdf_document)
+        .and_then(Object::as_array)
+    {
+        let value = names
+            .chunks(2)
+            .find(|pair| matches!(&pair[0], Object::String(key, _) if key == name))
+            .and_then(|pair| pair.get(1));
+        if let Some(value) = value {
+            return Some(value.clone());
+        }
+    }
+    let kids = node
+        .get_deref(b"Kids", pdf_document)
+        .and_then(Object::as_array)
+        .ok()?;
+    kids.iter().find_map(|kid| {
+        let kid = pdf_document.dereference(kid).ok()?.1.as_dict().ok()?;
+        find_name(pdf_document, kid, name, depth + 1)
+    })
+}
+
+// Link annotations of the page that open a URI
+fn page_links(pdf_document: &PdfDocument, page_id: ObjectId) -> Vec<Link> {
+    let Ok(annotations) = pdf_document
+        .get_dictionary(page_id)
+        .and_then(|page| page.get_deref(b"Annots", pdf_document))
+        .and_then(Object::as_array)
+    else {
+        return vec![];
+    };
+    annotations
+        .iter()
+        .filter_map(|annotation| {
+            let (_, annotation) = pdf_document.dereference(annotation).ok()?;
+            let annotation = annotation.as_dict().ok()?;
+            if annotation.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link") {
+                return None;
+            }
+            let action = annotation
+                .get_deref(b"A", pdf_document)
+                .and_then(Object::as_dict)
+                .ok()?;
+            if action.get(b"S").and_then(Object::as_name).ok() != Some(b"URI") {
+                return None;
+            }
+            let Ok(Object::String(url, _)) = action.get_deref(b"URI", pdf_document) else {
+                return None;
+            };
+            let rect: Vec<f32> = annotation
+                .get_deref(b"Rect", pdf_document)
+                .and_then(Object::as_array)
+                .ok()?
+                .iter()
+                .filter_map(|value| value.as_float().ok())
+                .collect();
+            let [x0, y0, x1, y1] = rect[..] else {
+                return None;
+            };
+            Some(Link {
+                x0: x0.min(x1),
+                y0: y0.min(y1),
+                x1: x0.max(x1),
+                y1: y0.max(y1),
+                url: decode_text_string(url),
+            })
+        })
+        .collect()
+}
+
+// Keeps JPEG data as it is and converts other images to PNG, the size is the drawn size in pixels
+fn decode_image(
+    pdf_document: &PdfDocument,
+    image: &Stream,
+    alt: Option<&String>,
+    width: f32,
+    height: f32,
+) -> Option<ImageData> {
+    let (bytes, image_type) = match image_bytes(pdf_document, image) {
+        Ok(image) => image,
+        Err(error) => {
+            warn!("Skipping image: {error}");
+            return None;
+        }
+    };
+    let pixels = |points: f32| Some(format!("{}", (points * 96.0 / 72.0).round()));
+    Some(ImageData::new(
+        Bytes::from(bytes),
+        String::new(),
+        alt.cloned().unwrap_or_default(),
+        image_type.to_string(),
+        String::new(),
+        ImageDimension {
+            width: pixels(width),
+            height: pixels(height),
+        },
+    ))
+}
+
+fn image_bytes(
+    pdf_document: &PdfDocument,
+    image: &Stream,
+) -> anyhow::Result<(Vec<u8>, &'static str)> {
+    if image
+        .dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false)
+    {
+        return Err(anyhow::anyhow!("stencil masks only paint a color"));
+    }
+    let filters = image.filters().unwrap_or_default();
+    // data compressed with an image codec is kept, the filters before it are undone
+    let codec = filters
+        .iter()
+        .position(|filter| filter == "DCTDecode" || filter == "JPXDecode");
+    let data = stream_data(image, &filters[..codec.unwrap_or(filters.len())])?;
+    match codec.map(|index| filters[index].as_str()) {
+        Some("DCTDecode") => return Ok((data, "jpeg")),
+        Some(filter) => return Err(anyhow::anyhow!("{filter} images are not supported")),
+        None => {}
+    }
+
+    let (width, height, mut pixels) = image_pixels(pdf_document, image, &data)?;
+    let gray = pixels.iter().all(|[r, g, b]| r == g && g == b);
+    // soft masks are grayscale images with the alpha channel
+    let alpha = image
+        .dict
+        .get_deref(b"SMask", pdf_document)
+        .and_then(Object::as_stream)
+        .ok()
+        .and_then(|mask| {
+            let mask_pixels = stream_data(mask, &mask.filters().unwrap_or_default())
+                .and_then(|data| image_pixels(pdf_document, mask, &data));
+            let (mask_width, mask_height, alpha) = mask_pixels
+                .map_err(|error| warn!("Skipping image soft mask: {error}"))
+                .ok()?;
+            (mask_width == width && mask_height == height).then(|| {
+                alpha
+                    .into_iter()
+                    .map(|[alpha, _, _]| alpha)
+                    .collect::<Vec<u8>>()
+            })
+        });
+
+    let image = match alpha {
+        Some(alpha) => image::RgbaImage::from_raw(
+            width,
+            height,
+            pixels
+                .iter()
+                .zip(alpha)
+                .flat_map(|([r, g, b], a)| [*r, *g, *b, a])
+                .collect(),
+        )
+        .map(image::DynamicImage::ImageRgba8),
+        None if gray => image::GrayImage::from_raw(
+            width,
+            height,
+            pixels.drain(..).map(|[gray, _, _]| gray).collect(),
+        )
+        .map(image::DynamicImage::ImageLuma8),
+        None => image::RgbImage::from_raw(width, height, pixels.concat())
+            .map(image::DynamicImage::ImageRgb8),
+    }
+    .ok_or_else(|| anyhow::anyhow!("image data does not match its size"))?;
+    let mut png = vec![];
+    image.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+    Ok((png, "png"))
+}
+
+// Undoes the given filters of a stream
+fn stream_data(stream: &Stream, filters: &[String]) -> anyhow::Result<Vec<u8>> {
+    if filters.is_empty() {
+        return Ok(stream.content.clone());
+    }
+    // lopdf does not decompress image streams, so the data is decoded as a plain stream
+    let mut plain = stream.clone();
+    plain.dict.remove(b"Subtype");
+    plain.dict.set(
+        "Filter",
+        filters
+            .iter()
+            .map(|filter| Object::Name(filter.as_bytes().to_vec()))
+            .collect::<Vec<Object>>(),
+    );
+    Ok(plain.decompressed_content()?)
+}
+
+// Samples of an image converted to 8 bit RGB
+fn image_pixels(
+    pdf_document: &PdfDocument,
+    image: &Stream,
+    data: &[u8],
+) -> anyhow::Result<(u32, u32, Vec<[u8; 3]>)> {
+    let number = |key: &[u8]| {
+        image
+            .dict
+            .get_deref(key, pdf_document)
+            .and_then(Object::as_i64)
+            .ok()
+    };
+    let (Some(width), Some(height)) = (number(b"Width"), number(b"Height")) else {
+        return Err(anyhow::anyhow!("image without a size"));
+    };
+    let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err(anyhow::anyhow!("image size {width}x{height} is invalid"));
+    };
+    if width == 0 || height == 0 {
+        return Err(anyhow::anyhow!("image size {width}x{height} is empty"));
+    }
+    let bits = match number(b"BitsPerComponent").unwrap_or(8) {
+        bits @ (1 | 2 | 4 | 8 | 16) => bits as usize,
+        bits => {
+            return Err(anyhow::anyhow!(
+                "{bits} bits per component are not supported"
+            ))
+        }
+    };
+    let color_space = match image.dict.get(b"ColorSpace") {
+        Ok(color_space) => ColorSpace::new(pdf_document, color_space)?,
+        Err(_) => ColorSpace::Gray,
+    };
+    let components = color_space.components();
+    // rows start on a byte boundary, the size is checked against the data before allocating
+    let row_bytes = (width as usize)
+        .checked_mul(components)
+        .and_then(|samples| samples.checked_mul(bits))
+        .map(|row_bits| row_bits.div_ceil(8));
+    let image_bytes = row_bytes.and_then(|row_bytes| row_bytes.checked_mul(height as usize));
+    let (Some(row_bytes), Some(image_bytes)) = (row_bytes, image_bytes) else {
+        return Err(anyhow::anyhow!("image size {width}x{height} is too large"));
+    };
+    if data.len() < image_bytes {
+        return Err(anyhow::anyhow!(
+            "image data of {} bytes is shorter than its size {width}x{height} needs",
+            data.len()
+        ));
+    }
+    let (width, height) = (width as usize, height as usize);
+    let max = (1u32 << bits) - 1;
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut samples = vec![0u32; components];
+    for row in data.chunks(row_bytes).take(height) {
+        for x in 0..width {
+            for (component, sample) in samples.iter_mut().enumerate() {
+                let bit = (x * components + component) * bits;
+                *sample = match bits {
+                    16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]]) as u32,
+                    _ => (row[bit / 8] as u32 >> (8 - bits - bit % 8)) & max,
+                };
+            }
+            pixels.push(color_space.rgb(&samples, max));
+        }
+    }
+    Ok((width as u32, height as u32, pixels))
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Palette of base color space values, 8 bits each
+    Indexed(Box<ColorSpace>, Vec<u8>),
+}
+
+impl ColorSpace {
+    fn new(pdf_document: &PdfDocument, color_space: &Object) -> anyhow::Result<ColorSpace> {
+        let (_, color_space) = pdf_document.dereference(color_space)?;
+        let (family, parameters) = match color_space {
+            Object::Name(name) => (name.as_slice(), &[][..]),
+            Object::Array(array) => match array.split_first() {
+                Some((family, parameters)) => (family.as_name()?, parameters),
+                None => return Err(anyhow::anyhow!("empty color space")),
+            },
+            _ => return Err(anyhow::anyhow!("invalid color space")),
+        };
+        Ok(match family {
+            b"DeviceGray" | b"CalGray" | b"G" => ColorSpace::Gray,
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => ColorSpace::Rgb,
+            b"DeviceCMYK" | b"CMYK" => ColorSpace::Cmyk,
+            // ICC profiles are approximated by the device space with as many components
+            b"ICCBased" => {
+                let profile = parameters
+                    .first()
+                    .map(|profile| pdf_document.dereference(profile))
+                    .ok_or_else(|| anyhow::anyhow!("ICC color space without a profile"))??
+                    .1
+                    .as_stream()?;
+                match profile.dict.get(b"N").and_then(Object::as_i64) {
+                    Ok(1) => ColorSpace::Gray,
+                    Ok(4) => ColorSpace::Cmyk,
+                    _ => ColorSpace::Rgb,
+                }
+            }
+            b"Indexed" | b"I" => {
+                let [base, _, lookup] = parameters else {
+                    return Err(anyhow::anyhow!("invalid indexed color space"));
+                };
+                let palette = match pdf_document.dereference(lookup)?.1 {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => {
+                        stream_data(stream, &stream.filters().unwrap_or_default())?
+                    }
+                    _ => return Err(anyhow::anyhow!("invalid color palette")),
+                };
+                ColorSpace::Indexed(Box::new(ColorSpace::new(pdf_document, base)?), palette)
+            }
+            family => {
+                return Err(anyhow::anyhow!(
+                    "{} color space is not supported",
+                    String::from_utf8_lossy(family)
+                ))
+            }
+        })
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed(..) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    // Samples range from 0 to max, palette indices are not scaled
+    fn rgb(&self, samples: &[u32], max: u32) -> [u8; 3] {
+        let scale = |sample: u32| (sample * 255 / max) as u8;
+        match self {
+            ColorSpace::Gray => [scale(samples[0]); 3],
+            ColorSpace::Rgb => [scale(samples[0]), scale(samples[1]), scale(samples[2])],
+            ColorSpace::Cmyk => {
+                let k = 255 - scale(samples[3]) as u32;
+                let channel = |sample: u32| ((255 - scale(sample) as u32) * k / 255) as u8;
+                [
+                    channel(samples[0]),
+                    channel(samples[1]),
+                    channel(samples[2]),
+                ]
+            }
+            ColorSpace::Indexed(base, palette) => {
+                let components = base.components();
+                let start = samples[0] as usize * components;
+                let entry: Vec<u32> = (start..start + components)
+                    .map(|index| palette.get(index).copied().unwrap_or(0) as u32)
+                    .collect();
+                base.rgb(&entry, 255)
+            }
+        }
+    }
+}
+
+/// Decoding and metrics of a font used by the page
+struct PdfFont {
+    /// Type0 fonts use two byte character codes
+    two_byte: bool,
+    encoding: Option<String>,
+    to_unicode: HashMap<u32, String>,
+    /// Glyph widths in thousandths of the font size
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    style: TextStyle,
+}
+
+impl PdfFont {
+    fn load(pdf_document: &PdfDocument, font: &Dictionary) -> PdfFont {
+        let two_byte = font.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0");
+        let descendant = two_byte
+            .then(|| {
+                let descendants = font
+                    .get_deref(b"DescendantFonts", pdf_document)
+                    .and_then(Object::as_array)
+                    .ok()?;
+                pdf_document
+                    .dereference(descendants.first()?)
+                    .and_then(|(_, font)| font.as_dict())
+                    .ok()
+            })
+            .flatten();
+        let descriptor = descendant
+            .unwrap_or(font)
+            .get_deref(b"FontDescriptor", pdf_document)
+            .and_then(Object::as_dict)
+            .ok();
+        let number = |dictionary: Option<&Dictionary>, key: &[u8]| {
+            dictionary
+                .and_then(|dictionary| dictionary.get_deref(key, pdf_document).ok())
+                .and_then(|value| value.as_float().ok())
+        };
+        let numbers = |value: &Object| -> Vec<f32> {
+            pdf_document
+                .dereference(value)
+                .and_then(|(_, value)| value.as_array())
+                .map(|values| values.iter().filter_map(|v| v.as_float().ok()).collect())
+                .unwrap_or_default()
+        };
+
+        let style = font_style(font, descendant, descriptor, number(descriptor, b"Flags"));
+        let mut widths = HashMap::new();
+        let default_width;
+        if let Some(descendant) = descendant {
+            default_width = number(Some(descendant), b"DW").unwrap_or(1000.0);
+            // entries are either `first [w1 w2 ...]` or `first last w`
+            let entries = descendant
+                .get_deref(b"W", pdf_document)
+                .and_then(Object::as_array)
+                .map(|entries| entries.as_slice())
+                .unwrap_or_default();
+            let mut index = 0;
+            while index + 1 < entries.len() {
+                let Ok(first) = entries[index].as_float() else {
+                    break;
+                };
+                let first = first as u32;
+                match pdf_document.dereference(&entries[index + 1]) {
+                    Ok((_, Object::Array(_))) => {
+                        for (code, width) in (first..).zip(numbers(&entries[index + 1])) {
+                            widths.insert(code, width);
+                        }
+                        index += 2;
+                    }
+                    Ok((_, last)) => {
+                        let last = last.as_float().unwrap_or(0.0) as u32;
+                        let width = entries.get(index + 2).and_then(|w| w.as_float().ok());
+                        if let Some(width) = width {
+                            for code in first..=last.min(first + 0xFFFF) {
+                                widths.insert(code, width);
+                            }
+                        }
+                        index += 3;
+                    }
+                    Err(_) => break,
+                }
+            }
+        } else {
+            default_width = number(descriptor, b"MissingWidth")
+                .filter(|width| *width > 0.0)
+                .unwrap_or(if style.code { 600.0 } else { 500.0 });
+            let first_char = number(Some(font), b"FirstChar").unwrap_or(0.0) as u32;
+            if let Ok(font_widths) = font.get(b"Widths") {
+                for (code, width) in (first_char..).zip(numbers(font_widths)) {
+                    widths.insert(code, width);
+                }
+            }
+        }
+
+        let to_unicode = font
+            .get_deref(b"ToUnicode", pdf_document)
+            .and_then(Object::as_stream)
+            .map(|cmap| {
+                let content = cmap
+                    .decompressed_content()
+                    .unwrap_or_else(|_| cmap.content.clone());
+                parse_to_unicode(&content)
+            })
+            .unwrap_or_default();
+        let encoding = font
+            .get(b"Encoding")
+            .and_then(Object::as_name_str)
+            .ok()
+            .map(str::to_string);
+
+        PdfFont {
+            two_byte,
+            encoding,
+            to_unicode,
+            widths,
+            default_width,
+            style,
+        }
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_byte {
+            bytes
+                .chunks(2)
+                .map(|pair| pair.iter().fold(0, |code, byte| code << 8 | *byte as u32))
+                .collect()
+        } else {
+            bytes.iter().map(|byte| *byte as u32).collect()
+        }
+    }
+
+    fn text(&self, code: u32) -> String {
+        let text = if let Some(text) = self.to_unicode.get(&code) {
+            text.clone()
+        } else if self.two_byte {
+            char::from_u32(code).map(String::from).unwrap_or_default()
+        } else {
+            PdfDocument::decode_text(self.encoding.as_deref(), &[code as u8])
+        };
+        expand_ligature(&text)
+    }
+
+    fn width(&self, code: u32) -> f32 {
+        self.widths
+            .get(&code)
+            .copied()
+            .unwrap_or(self.default_width)
+    }
+}
+
+// Typographic ligatures are read as the letters they join
+fn expand_ligature(text: &str) -> String {
+    match text {
+        "\u{FB00}" => "ff".to_string(),
+        "\u{FB01}" => "fi".to_string(),
+        "\u{FB02}" => "fl".to_string(),
+        "\u{FB03}" => "ffi".to_string(),
+        "\u{FB04}" => "ffl".to_string(),
+        "\u{FB05}" | "\u{FB06}" => "st".to_string(),
+        text => text.to_string(),
+    }
+}
+
+// Bold, italic and monospace fonts are recognized by their name or descriptor
+fn font_style(
+    font: &Dictionary,
+    descendant: Option<&Dictionary>,
+    descriptor: Option<&Dictionary>,
+    flags: Option<f32>,
+) -> TextStyle {
+    let base_font = descendant
+        .into_iter()
+        .chain([font])
+        .find_map(|font| font.get(b"BaseFont").and_then(Object::as_name_str).ok())
+        .unwrap_or_default();
+    // subset fonts are prefixed with a tag like "ABCDEF+"
+    let name = base_font
+        .split_once('+')
+        .map_or(base_font, |(_, name)| name)
+        .to_lowercase();
+    let flags = flags.unwrap_or(0.0) as u32;
+    let descriptor_number = |key: &[u8]| {
+        descriptor
+            .and_then(|descriptor| descriptor.get(key).ok())
+            .and_then(|value| value.as_float().ok())
+    };
+    let bold = ["bold", "black", "heavy", "semibold", "demi"]
+        .iter()
+        .any(|weight| name.contains(weight))
+        || descriptor_number(b"FontWeight").is_some_and(|weight| weight >= 600.0)
+        // typst derives the stem width from the font weight, 600 and more is bold
+        || descriptor_number(b"StemV").is_some_and(|stem| stem >= 140.0)
+        || flags & (1 << 18) != 0;
+    let italic = name.contains("italic")
+        || name.contains("oblique")
+        || flags & (1 << 6) != 0
+        || descriptor_number(b"ItalicAngle").is_some_and(|angle| angle != 0.0);
+    let code = flags & 1 != 0
+        || ["mono", "courier", "consol", "code"]
+            .iter()
+            .any(|family| name.contains(family));
+    TextStyle {
+        bold,
+        italic,
+        code,
+        ..TextStyle::default()
+    }
+}
+
+// Maps character codes to text with the bfchar and bfrange sections of a ToUnicode CMap
+fn parse_to_unicode(cmap: &[u8]) -> HashMap<u32, String> {
+    enum Token {
+        Hex(Vec<u8>),
+        Word(String),
+        ArrayStart,
+        ArrayEnd,
+    }
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < cmap.len() {
+        match cmap[index] {
+            b'<' => {
+                let end = cmap[index..]
+                    .iter()
+                    .position(|&byte| byte == b'>')
+                    .map_or(cmap.len(), |end| index + end);
+                let digits: Vec<u8> = cmap[index + 1..end]
+                    .iter()
+                    .filter(|byte| byte.is_ascii_hexdigit())
+                    .copied()
+                    .collect();
+                let bytes = digits
+                    .chunks(2)
+                    .filter_map(|pair| {
+                        let pair = std::str::from_utf8(pair).ok()?;
+                        u8::from_str_radix(&format!("{pair:0<2}"), 16).ok()
+                    })
+                    .collect();
+                tokens.push(Token::Hex(bytes));
+                index = end + 1;
+            }
+            b'[' => {
+                tokens.push(Token::ArrayStart);
+                index += 1;
+            }
+            b']' => {
+                tokens.push(Token::ArrayEnd);
+                index += 1;
+            }
+            byte if byte.is_ascii_whitespace() => index += 1,
+            _ => {
+                let end = cmap[index..]
+                    .iter()
+                    .position(|byte| byte.is_ascii_whitespace() || b"<[]".contains(byte))
+                    .map_or(cmap.len(), |end| index + end);
+                tokens.push(Token::Word(
+                    String::from_utf8_lossy(&cmap[index..end]).to_string(),
+                ));
+                index = end;
+            }
+        }
+    }
+
+    fn code(bytes: &[u8]) -> u32 {
+        bytes.iter().fold(0, |code, byte| code << 8 | *byte as u32)
+    }
+    fn utf16(bytes: &[u8]) -> String {
+        let units: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| pair.iter().fold(0, |unit, byte| unit << 8 | *byte as u16))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+
+    let mut map = HashMap::new();
+    let mut section = "";
+    let mut index = 0;
+    while index < tokens.len() {
+        match (&tokens[index], section) {
+            (Token::Word(word), _) => {
+                section = match word.as_str() {
+                    "beginbfchar" => "bfchar",
+                    "beginbfrange" => "bfrange",
+                    _ => "",
+                };
+                index += 1;
+            }
+            (Token::Hex(source), "bfchar") => {
+                if let Some(Token::Hex(target)) = tokens.get(index + 1) {
+                    map.insert(code(source), utf16(target));
+                }
+                index += 2;
+            }
+            (Token::Hex(first), "bfrange") => {
+                let (Some(Token::Hex(last)), Some(target)) =
+                    (tokens.get(index + 1), tokens.get(index + 2))
+                else {
+                    break;
+                };
+                let (first, last) = (code(first), code(last));
+                match target {
+                    // consecutive codes map to consecutive values of the last unit
+                    Token::Hex(target) => {
+                        let text = utf16(target);
+                        let mut units: Vec<u16> = text.encode_utf16().collect();
+                        for code in first..=last.min(first + 0xFFFF) {
+                            map.insert(code, String::from_utf16_lossy(&units));
+                            if let Some(unit) = units.last_mut() {
+                                *unit = unit.wrapping_add(1);
+                            }
+                        }
+                        index += 3;
+                    }
+                    Token::ArrayStart => {
+                        index += 3;
+                        let mut code = first;
+                        while let Some(Token::Hex(target)) = tokens.get(index) {
+                            map.insert(code, utf16(target));
+                            code += 1;
+                            index += 1;
+                        }
+                        index += 1;
+                    }
+                    _ => index += 3,
+                }
+            }
+            _ => index += 1,
+        }
+    }
+    map
+}
+
+/// Spans on one baseline that are close enough to be read as one run of text
+#[derive(Debug, Clone)]
+struct Fragment {
+    spans: Vec<TextSpan>,
+    x0: f32,
+    x1: f32,
+    y: f32,
+    size: f32,
+    /// Level of the outline entry the text is the title of
+    heading: Option<usize>,
+}
+
+impl Fragment {
+    fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    fn is_bold(&self) -> bool {
+        self.spans.iter().all(|span| span.style.bold)
+    }
+}
+
+// Groups spans into baselines and splits baselines at gaps wider than a word space
+fn fragments(mut spans: Vec<TextSpan>) -> Vec<Fragment> {
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x0.total_cmp(&b.x0)));
+    let mut baselines: Vec<Vec<TextSpan>> = vec![];
+    for span in spans {
+        match baselines.last_mut() {
+            Some(baseline)
+                if (baseline[0].y - span.y).abs() <= 0.3 * span.size.min(baseline[0].size) =>
+            {
+                baseline.push(span)
+            }
+            _ => baselines.push(vec![span]),
+        }
+    }
+
+    let mut fragments = vec![];
+    for mut baseline in baselines {
+        baseline.sort_by(|a, b| a.x0.total_cmp(&b.x0));
+        let mut fragment: Option<Fragment> = None;
+        for span in baseline {
+            if let Some(current) = &mut fragment {
+                let gap = span.x0 - current.x1;
+                let size = current.size.max(span.size);
+                if gap <= size {
+                    let last = current
+                        .spans
+                        .last_mut()
+                        .expect("a fragment starts with a span");
+                    if gap > 0.15 * size
+                        && !last.text.ends_with(char::is_whitespace)
+                        && !span.text.starts_with(char::is_whitespace)
+                    {
+                        last.text.push(' ');
+                    }
+                    current.x1 = current.x1.max(span.x1);
+                    current.size = size;
+                    current.spans.push(span);
+                    continue;
+                }
+                fragments.extend(fragment.take());
+            }
+            fragment = Some(Fragment {
+                x0: span.x0,
+                x1: span.x1,
+                y: span.y,
+                size: span.size,
+                spans: vec![span],
+                heading: None,
+            });
+        }
+        fragments.extend(fragment);
+    }
+    fragments
+}
+
+/// Something placed on the page, read as a whole
+struct Block {
+    x0: f32,
+    x1: f32,
+    top: f32,
+    content: BlockContent,
+}
+
+enum BlockContent {
+    Text(Fragment),
+    /// Table or image
+    Element(Element),
+}
+
+impl Block {
+    fn text(fragment: Fragment) -> Block {
+        Block {
+            x0: fragment.x0,
+            x1: fragment.x1,
+            top: fragment.y + 0.75 * fragment.size,
+            content: BlockContent::Text(fragment),
+        }
+    }
+
+    fn width(&self) -> f32 {
+        self.x1 - self.x0
+    }
+}
+
+// Tables drawn with borders on all sides of their cells, the spans inside are taken from the page
+fn ruled_tables(rules: &[Rule], spans: &mut Vec<TextSpan>) -> Vec<Block> {
+    // pages with a lot of vector graphics are not searched for tables
+    if rules.len() > 2000 {
+        return vec![];
+    }
+    let mut groups: Vec<usize> = (0..rules.len()).collect();
+    fn root(groups: &mut [usize], mut index: usize) -> usize {
+        while groups[index] != index {
+            groups[index] = groups[groups[index]];
+            index = groups[index];
+        }
+        index
+    }
+    for (i, rule) in rules.iter().enumerate() {
+        for (j, other) in rules.iter().enumerate().skip(i + 1) {
+            if rule.touches(other) {
+                let (a, b) = (root(&mut groups, i), root(&mut groups, j));
+                groups[a] = b;
+            }
+        }
+    }
+    let mut components: BTreeMap<usize, Vec<Rule>> = BTreeMap::new();
+    for (index, rule) in rules.iter().enumerate() {
+        components
+            .entry(root(&mut groups, index))
+            .or_default()
+            .push(*rule);
+    }
+
+    let mut tables = vec![];
+    for rules in components.into_values() {
+        let (horizontal, vertical): (Vec<Rule>, Vec<Rule>) =
+            rules.into_iter().partition(Rule::is_horizontal);
+        let xs = distinct_offsets(&vertical);
+        let mut ys = distinct_offsets(&horizontal);
+        ys.reverse();
+        let (rows, columns) = (ys.len().saturating_sub(1), xs.len().saturating_sub(1));
+        // a single framed box is not a table
+        if rows * columns < 2 {
+            continue;
+        }
+
+        let crosses = |rules: &[Rule], offset: f32, along: f32| {
+            rules.iter().any(|rule| {
+                let (from, to) = if rule.is_horizontal() {
+                    (rule.x0, rule.x1)
+                } else {
+                    (rule.y0, rule.y1)
+                };
+                (rule.offset() - offset).abs() <= 2.0 && from <= along && along <= to
+            })
+        };
+        let merged_right = |row: usize, column: usize| {
+            column + 1 < columns
+                && !crosses(&vertical, xs[column + 1], (ys[row] + ys[row + 1]) / 2.0)
+        };
+        let merged_down = |row: usize, column: usize| {
+            row + 1 < rows
+                && !crosses(
+                    &horizontal,
+                    ys[row + 1],
+                    (xs[column] + xs[column + 1]) / 2.0,
+                )
+        };
+
+        let (table_spans, rest): (Vec<TextSpan>, Vec<TextSpan>) =
+            std::mem::take(spans).into_iter().partition(|span| {
+                let (x, y) = ((span.x0 + span.x1) / 2.0, span.y + 0.3 * span.size);
+                xs[0] < x && x < xs[columns] && ys[rows] < y && y < ys[0]
+            });
+        *spans = rest;
+
+        let mut covered = vec![vec![false; columns]; rows];
+        let mut grid: Vec<Vec<(TableCell, f32, bool)>> = vec![];
+        for row in 0..rows {
+            let mut cells = vec![];
+            for column in 0..columns {
+                if covered[row][column] {
+                    continue;
+                }
+                let mut colspan = 1;
+                while merged_right(row, column + colspan - 1) {
+                    colspan += 1;
+                }
+                let mut rowspan = 1;
+                while merged_down(row + rowspan - 1, column) {
+                    rowspan += 1;
+                }
+                for covered_row in &mut covered[row..row + rowspan] {
+                    covered_row[column..column + colspan].fill(true);
+                }
+                let (left, right) = (xs[column], xs[column + colspan]);
+                let (top, bottom) = (ys[row], ys[row + rowspan]);
+                let cell_spans: Vec<TextSpan> = table_spans
+                    .iter()
+                    .filter(|span| {
+                        let (x, y) = ((span.x0 + span.x1) / 2.0, span.y + 0.3 * span.size);
+                        left < x && x < right && bottom < y && y < top
+                    })
+                    .cloned()
+                    .collect();
+                let bold = !cell_spans.is_empty() && cell_spans.iter().all(|s| s.style.bold);
+                let lines = text_lines(fragments(cell_spans));
+                let runs = line_runs(&lines);
+                let elements = match runs.len() {
+                    0 => vec![],
+                    1 => runs,
+                    _ => vec![Paragraph { elements: runs }],
+                };
+                cells.push((
+                    TableCell::with_elements(elements).span(colspan, rowspan),
+                    right - left,
+                    bold,
+                ));
+            }
+            grid.push(cells);
+        }
+
+        // a bold first row is the header
+        let mut headers = vec![];
+        if grid[0].iter().all(|(_, _, bold)| *bold) {
+            headers = grid
+                .remove(0)
+                .into_iter()
+                .map(|(cell, width, _)| TableHeader {
+                    elements: header_elements(cell.elements),
+                    width,
+                    colspan: cell.colspan,
+                    rowspan: cell.rowspan,
+                    alignment: ColumnAlignment::None,
+                })
+                .collect();
+        }
+        let rows = grid
+            .into_iter()
+            .map(|cells| TableRow {
+                cells: cells.into_iter().map(|(cell, _, _)| cell).collect(),
+            })
+            .collect();
+        tables.push(Block {
+            x0: xs[0],
+            x1: xs[columns],
+            top: ys[0],
+            content: BlockContent::Element(Table {
+                name: None,
+                headers,
+                rows,
+            }),
+        });
+    }
+    tables
+}
+
+// Positions of parallel rules, rules closer than a border width are one line
+fn distinct_offsets(rules: &[Rule]) -> Vec<f32> {
+    let mut offsets: Vec<f32> = rules.iter().map(Rule::offset).collect();
+    offsets.sort_by(f32::total_cmp);
+    offsets.dedup_by(|next, previous| *next - *previous <= 2.0);
+    offsets
+}
+
+// Recursive XY-cut: columns are read one after the other, blocks spanning columns cut them into bands
+fn reading_order(blocks: Vec<Block>, body_size: f32, leaves: &mut Vec<Vec<Block>>) {
+    if blocks.len() < 4 {
+        leaves.push(blocks);
+        return;
+    }
+    let region_width = blocks.iter().map(|b| b.x1).fold(f32::MIN, f32::max)
+        - blocks.iter().map(|b| b.x0).fold(f32::MAX, f32::min);
+
+    if let Some(gutter) = gutter(blocks.iter().collect(), region_width, body_size) {
+        let (left, right) = blocks.into_iter().partition(|block| block.x1 <= gutter);
+        reading_order(left, body_size, leaves);
+        reading_order(right, body_size, leaves);
+        return;
+    }
+
+    let narrow = blocks
+        .iter()
+        .filter(|block| block.width() < 0.6 * region_width)
+        .collect();
+    if let Some(gutter) = gutter(narrow, region_width, body_size) {
+        let mut blocks = blocks;
+        blocks.sort_by(|a, b| b.top.total_cmp(&a.top));
+        let mut bands: Vec<(bool, Vec<Block>)> = vec![];
+        for block in blocks {
+            let spanning = block.x0 < gutter && gutter < block.x1;
+            match bands.last_mut() {
+                Some((band_spanning, band)) if *band_spanning == spanning => band.push(block),
+                _ => bands.push((spanning, vec![block])),
+            }
+        }
+        if bands.len() > 1 {
+            for (_, band) in bands {
+                reading_order(band, body_size, leaves);
+            }
+            return;
+        }
+        leaves.extend(bands.into_iter().map(|(_, band)| band));
+        return;
+    }
+    leaves.push(blocks);
+}
+
+// Widest vertical gap between blocks with running text on both sides
+fn gutter(mut blocks: Vec<&Block>, region_width: f32, body_size: f32) -> Option<f32> {
+    blocks.sort_by(|a, b| a.x0.total_cmp(&b.x0));
+    let mut right_edge = blocks.first()?.x1;
+    let mut best: Option<(f32, f32)> = None;
+    for block in &blocks[1..] {
+        let gap = block.x0 - right_edge;
+        if gap >= body_size {
+            let at = (right_edge + block.x0) / 2.0;
+            let side_ok = |left: bool| {
+                let side: Vec<&&Block> = blocks
+                    .iter()
+                    .filter(|block| (block.x1 <= at) == left)
+                    .collect();
+                side.len() >= 2
+                    && side
+                        .iter()
+                        .any(|block| block.width() >= 0.25 * region_width)
+            };
+            if side_ok(true) && side_ok(false) && best.is_none_or(|(width, _)| gap > width) {
+                best = Some((gap, at));
+            }
+        }
+        right_edge = right_edge.max(block.x1);
+    }
+    best.map(|(_, at)| at)
+}
+
+/// Fragments sharing a baseline, read left to right
+struct Line {
+    fragments: Vec<Fragment>,
+    y: f32,
+    size: f32,
+}
+
+impl Line {
+    fn x0(&self) -> f32 {
+        self.fragments[0].x0
+    }
+
+    fn text(&self) -> String {
+        let texts: Vec<String> = self.fragments.iter().map(Fragment::text).collect();
+        texts.join(" ")
+    }
+
+    fn spans(&self) -> impl Iterator<Item = &TextSpan> {
+        self.fragments.iter().flat_map(|fragment| &fragment.spans)
+    }
+
+    fn is_bold(&self) -> bool {
+        self.fragments.iter().all(Fragment::is_bold)
+    }
+
+    fn is_code(&self) -> bool {
+        self.spans().all(|span| span.style.code)
+    }
+
+    fn outline_level(&self) -> Option<usize> {
+        let level = self.fragments[0].heading?;
+        self.fragments
+            .iter()
+            .all(|fragment| fragment.heading == Some(level))
+            .then_some(level)
+    }
+}
+
+fn text_lines(mut fragments: Vec<Fragment>) -> Vec<Line> {
+    fragments.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x0.total_cmp(&b.x0)));
+    let mut lines: Vec<Line> = vec![];
+    for fragment in fragments {
+        match lines.last_mut() {
+            Some(line) if (line.y - fragment.y).abs() <= 0.3 * line.size.min(fragment.size) => {
+                line.size = line.size.max(fragment.size);
+                line.fragments.push(fragment);
+            }
+            _ => lines.push(Line {
+                y: fragment.y,
+                size: fragment.size,
+                fragments: vec![fragment],
+            }),
+        }
+    }
+    // a bullet set apart from its text with a tab still starts the item
+    for line in &mut lines {
+        if line.fragments.len() > 1
+            && list_marker(&line.fragments[0].text())
+                .is_some_and(|(_, length)| length >= line.fragments[0].text().trim_end().len())
+        {
+            let marker = line.fragments.remove(0);
+            let first = &mut line.fragments[0];
+            let mut spans = marker.spans;
+            if let Some(last) = spans.last_mut() {
+                if !last.text.ends_with(char::is_whitespace) {
+                    last.text.push(' ');
+                }
+            }
+            spans.append(&mut first.spans);
+            first.spans = spans;
+            first.x0 = marker.x0;
+        }
+    }
+    lines
+}
+
+// Inline text of lines, spans with the same style are one run
+fn line_runs(lines: &[Line]) -> Vec<Element> {
+    let mut runs: Vec<Element> = vec![];
+    // word space owed before the next span
+    let mut space = false;
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            match runs.last_mut() {
+                Some(Text { text, .. }) => {
+                    let next_lowercase = line
+                        .text()
+                        .trim_start()
+                        .starts_with(|c: char| c.is_lowercase());
+                    let hyphenated = text.len() > 1
+                        && text.ends_with('-')
+                        && text[..text.len() - 1].ends_with(char::is_alphabetic);
+                    if hyphenated && next_lowercase {
+                        text.pop();
+                    } else if !text.ends_with(char::is_whitespace) {
+                        text.push(' ');
+                    }
+                }
+                Some(_) => space = true,
+                None => {}
+            }
+        }
+        for (position, fragment) in line.fragments.iter().enumerate() {
+            for (span_index, span) in fragment.spans.iter().enumerate() {
+                space |= position > 0 && span_index == 0;
+                let size = span.size.round() as u8;
+                if let Some(url) = &span.link {
+                    link_run(&mut runs, span, url, &mut space);
+                    continue;
+                }
+                let mut span_text = span.text.clone();
+                if space && !span_text.starts_with(char::is_whitespace) {
+                    span_text.insert(0, ' ');
+                }
+                space = false;
+                match runs.last_mut() {
+                    Some(Text {
+                        text,
+                        size: run_size,
+                        style,
+                    }) if *style == span.style && *run_size == size => text.push_str(&span_text),
+                    _ => runs.push(Text {
+                        text: span_text,
+                        size,
+                        style: span.style.clone(),
+                    }),
+                }
+            }
+        }
+    }
+    if let Some(Text { text, .. }) = runs.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Text { text, .. }) = runs.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    runs.retain(|run| !matches!(run, Text { text, .. } if text.is_empty()));
+    runs
+}
+
+// Adds the text of a span under a link annotation, spaces around the link stay outside of its title
+fn link_run(runs: &mut Vec<Element>, span: &TextSpan, url: &str, space: &mut bool) {
+    *space |= span.text.starts_with(char::is_whitespace);
+    let part = span.text.trim();
+    match runs.last_mut() {
+        Some(Hyperlink {
+            title, url: last, ..
+        }) if last == url => {
+            if *space {
+                title.push(' ');
+            }
+            title.push_str(part);
+        }
+        last => {
+            if *space {
+                match last {
+                    Some(Text { text, .. }) if !text.ends_with(char::is_whitespace) => {
+                        text.push(' ')
+                    }
+                    Some(Text { .. }) | None => {}
+                    Some(_) => runs.push(Text {
+                        text: " ".to_string(),
+                        size: span.size.round() as u8,
+                        style: TextStyle::default(),
+                    }),
+                }
+            }
+            runs.push(Hyperlink {
+                title: part.to_string(),
+                url: url.to_string(),
+                alt: String::new(),
+                size: span.size.round() as u8,
+            });
+        }
+    }
+    *space = span.text.ends_with(char::is_whitespace);
+}
+
+// Bullet or number starting a list item: whether it is numbered and the byte length of the marker
+fn list_marker(text: &str) -> Option<(bool, usize)> {
+    const BULLETS: [char; 13] = [
+        '•', '◦', '▪', '▫', '‣', '∙', '●', '○', '■', '□', '–', '-', '*',
+    ];
+    let rest = text.trim_start();
+    let offset = text.len() - rest.len();
+    let first = rest.chars().next()?;
+    if BULLETS.contains(&first) {
+        let after = &rest[first.len_utf8()..];
+        return (after.is_empty() || after.starts_with(char::is_whitespace))
+            .then_some((false, offset + first.len_utf8()));
+    }
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let after = rest[digits..].strip_prefix(['.', ')'])?;
+    ((1..=3).contains(&digits) && (after.is_empty() || after.starts_with(char::is_whitespace)))
+        .then_some((true, offset + digits + 1))
+}
+
+// Removes the first `length` bytes of text from the runs
+fn strip_prefix(runs: &mut Vec<Element>, mut length: usize) {
+    while length > 0 {
+        let Some(Text { text, .. }) = runs.first_mut() else {
+            return;
+        };
+        if text.len() <= length {
+            length -= text.len();
+            runs.remove(0);
+        } else {
+            *text = text[length..].to_string();
+            length = 0;
+        }
+    }
+    if let Some(Text { text, .. }) = runs.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+}
+
+/// Font sizes used on the pages, to tell headings from body text
+struct FontSizes {
+    body: f32,
+    /// Sizes larger than the body text, largest first
+    headings: Vec<f32>,
+}
+
+impl FontSizes {
+    fn new<'a>(spans: impl Iterator<Item = &'a TextSpan>) -> FontSizes {
+        // the most used size, weighted by the amount of text, is the body text
+        let mut usage: BTreeMap<i32, usize> = BTreeMap::new();
+        for span in spans {
+            *usage.entry((span.size * 2.0).round() as i32).or_default() += span.text.len();
+        }
+        let body = usage
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map_or(12.0, |(size, _)| *size as f32 / 2.0);
+        let headings = usage
+            .keys()
+            .rev()
+            .map(|size| *size as f32 / 2.0)
+            .filter(|size| *size >= body * 1.15)
+            .collect();
+        FontSizes { body, headings }
+    }
+
+    fn heading_level(&self, size: f32) -> Option<usize> {
+        self.headings
+            .iter()
+            .position(|heading| (heading - size).abs() <= 0.5)
+            .map(|index| (index + 1).min(6))
+    }
+}
+
+#[derive(PartialEq)]
+enum LineKind {
+    Heading(usize),
+    Code,
+    Item,
+    Text,
+}
+
+// Turns the lines of a column into headings, paragraphs, lists, code blocks and borderless tables
+fn line_elements(lines: Vec<Line>, sizes: &FontSizes, elements: &mut Vec<Element>) {
+    let mut groups: Vec<(LineKind, Vec<Line>)> = vec![];
+    let mut lines = lines.into_iter().peekable();
+    while let Some(mut line) = lines.next() {
+        // rows of aligned fragments separated by wide gaps
+        if line.fragments.len() > 1 && line.outline_level().is_none() {
+            let mut rows = vec![line];
+            while let Some(next) = lines.next_if(|next| {
+                rows.last()
+                    .is_some_and(|previous| is_aligned_row(previous, next))
+            }) {
+                rows.push(next);
+            }
+            match <[Line; 1]>::try_from(rows) {
+                Ok([single]) => line = single,
+                Err(rows) => {
+                    flush_groups(&mut groups, sizes, elements);
+                    elements.push(aligned_table(rows));
+                    continue;
+                }
+            }
+        }
+        push_line(&mut groups, line, sizes);
+    }
+    flush_groups(&mut groups, sizes, elements);
+}
+
+// Whether the next line continues a table of the previous one: fragments line up and it is close below
+fn is_aligned_row(previous: &Line, next: &Line) -> bool {
+    let aligned = next.fragments.len() == previous.fragments.len()
+        && next
+            .fragments
+            .iter()
+            .zip(&previous.fragments)
+            .all(|(a, b)| {
+                let tolerance = a.size.max(b.size);
+                (a.x0 - b.x0).abs() <= tolerance
+                    || (a.x1 - b.x1).abs() <= tolerance
+                    || ((a.x0 + a.x1) - (b.x0 + b.x1)).abs() <= 2.0 * tolerance
+            });
+    aligned && previous.y - next.y <= 2.5 * previous.size.max(next.size)
+}
+
+fn push_line(groups: &mut Vec<(LineKind, Vec<Line>)>, line: Line, sizes: &FontSizes) {
+    let text = line.text();
+    let mut kind = if let Some(level) = line.outline_level().or(sizes.heading_level(line.size)) {
+        LineKind::Heading(level.min(6))
+    } else if list_marker(&text).is_some() {
+        LineKind::Item
+    } else if line.is_code() {
+        LineKind::Code
+    } else {
+        LineKind::Text
+    };
+    if let Some((group_kind, group)) = groups.last_mut() {
+        let previous = group.last().expect("a group starts with a line");
+        let size = previous.size.max(line.size);
+        let step = previous.y - line.y;
+        let close = step > 0.0 && step <= 1.6 * size;
+        // lines indented under a list item continue it
+        let continues_item = *group_kind == LineKind::Item
+            && kind == LineKind::Text
+            && close
+            && line.x0() > group[0].x0() + 0.5 * line.size;
+        if continues_item {
+            kind = LineKind::Item;
+        }
+        let same_block = match kind {
+            LineKind::Item => *group_kind == LineKind::Item && step <= 3.0 * size,
+            LineKind::Text => {
+                *group_kind == LineKind::Text && close && (previous.size - line.size).abs() <= 0.75
             }
+            _ => *group_kind == kind && close,
+        };
+        if same_block {
+            group.push(line);
+            return;
         }
-        Ok(Document::new(elements))
     }
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let (text, img_map) = crate::typst::generate_document(document)?;
+    groups.push((kind, vec![line]));
+}
+
+fn flush_groups(
+    groups: &mut Vec<(LineKind, Vec<Line>)>,
+    sizes: &FontSizes,
+    elements: &mut Vec<Element>,
+) {
+    for (kind, lines) in groups.drain(..) {
+        match kind {
+            LineKind::Heading(level) => elements.push(Header {
+                level: level as u8,
+                text: line_texts(&lines),
+            }),
+            LineKind::Code => {
+                // indentation is measured in widths of a monospace character
+                let x0 = lines.iter().map(Line::x0).fold(f32::MAX, f32::min);
+                let code: Vec<String> = lines
+                    .iter()
+                    .map(|line| {
+                        let span = &line.fragments[0].spans[0];
+                        let char_width =
+                            (span.x1 - span.x0) / span.text.chars().count().max(1) as f32;
+                        let indent = ((line.x0() - x0) / char_width.max(0.1)).round() as usize;
+                        format!("{}{}", " ".repeat(indent), line.text().trim_end())
+                    })
+                    .collect();
+                elements.push(CodeBlock {
+                    language: None,
+                    code: code.join("\n"),
+                });
+            }
+            LineKind::Item => list(lines, elements),
+            LineKind::Text => {
+                let text = line_texts(&lines);
+                // a short bold line on its own reads as a heading of the body text size
+                if lines.len() == 1
+                    && lines[0].is_bold()
+                    && text.chars().count() <= 80
+                    && !text.ends_with(['.', ':', ','])
+                {
+                    elements.push(Header {
+                        level: (sizes.headings.len() + 1).min(6) as u8,
+                        text,
+                    });
+                    continue;
+                }
+                elements.push(Paragraph {
+                    elements: line_runs(&lines),
+                });
+            }
+        }
+    }
+}
 
-        let world = crate::typst::ShivaWorld::new(text, img_map);
-        let mut tracer = Tracer::default();
+fn line_texts(lines: &[Line]) -> String {
+    let texts: Vec<String> = lines
+        .iter()
+        .map(|line| line.text().trim().to_string())
+        .collect();
+    texts.join(" ")
+}
 
-        let document = typst::compile(&world, &mut tracer).unwrap();
-        let warnings = tracer.warnings();
+// Nests list items by the indentation of their markers
+fn list(lines: Vec<Line>, elements: &mut Vec<Element>) {
+    let mut items: Vec<(f32, bool, Vec<Line>)> = vec![];
+    for line in lines {
+        let marker = list_marker(&line.text());
+        match (marker, items.last_mut()) {
+            (None, Some((_, _, item_lines))) => item_lines.push(line),
+            (marker, _) => items.push((
+                line.x0(),
+                marker.is_some_and(|(numbered, _)| numbered),
+                vec![line],
+            )),
+        }
+    }
 
-        if !warnings.is_empty() {
-            // Trowing any warnings if necessary
-            for warn in warnings {
-                warn!("Warning - {}", warn.message);
+    // open lists, from the outermost: marker position, numbered and items
+    let mut stack: Vec<(f32, bool, Vec<ListItem>)> = vec![];
+    let mut close = |stack: &mut Vec<(f32, bool, Vec<ListItem>)>| {
+        let Some((_, numbered, items)) = stack.pop() else {
+            return;
+        };
+        let list = List {
+            elements: items,
+            numbered,
+        };
+        match stack.last_mut() {
+            Some((_, _, parent)) => parent.push(ListItem::new(list)),
+            None => elements.push(list),
+        }
+    };
+    for (x, numbered, item_lines) in items {
+        let tolerance = item_lines[0].size * 0.5;
+        while stack.len() > 1
+            && stack
+                .last()
+                .is_some_and(|(list_x, _, _)| x < list_x - tolerance)
+        {
+            close(&mut stack);
+        }
+        // bullets and numbers at the same position are separate lists
+        if stack.last().is_some_and(|(list_x, list_numbered, _)| {
+            *list_numbered != numbered && x <= list_x + tolerance
+        }) {
+            close(&mut stack);
+        }
+        let mut runs = line_runs(&item_lines);
+        if let Some((_, length)) = list_marker(&item_lines[0].text()) {
+            strip_prefix(&mut runs, length);
+        }
+        let element = match runs.len() {
+            1 => runs.remove(0),
+            _ => Paragraph { elements: runs },
+        };
+        match stack.last_mut() {
+            Some((list_x, _, list_items)) if x <= *list_x + tolerance => {
+                list_items.push(ListItem::new(element))
             }
+            _ => stack.push((x, numbered, vec![ListItem::new(element)])),
         }
+    }
+    while !stack.is_empty() {
+        close(&mut stack);
+    }
+}
 
-        // Converting to pdf then to bytes
-        let pdf = typst_pdf::pdf(&document, Smart::Auto, None);
+// Header cells are bold anyway, their runs are kept plain
+fn header_elements(mut elements: Vec<Element>) -> Vec<Element> {
+    for element in &mut elements {
+        match element {
+            Text { style, .. } => style.bold = false,
+            Paragraph { elements } => *elements = header_elements(std::mem::take(elements)),
+            _ => {}
+        }
+    }
+    elements
+}
 
-        let bytes = Bytes::from(pdf);
-        Ok(bytes)
+// Table of rows of aligned fragments, drawn without borders
+fn aligned_table(rows: Vec<Line>) -> Element {
+    let header = rows[0].is_bold() && rows.len() > 1 && !rows[1].is_bold();
+    let cell = |fragment: &Fragment| {
+        let line = Line {
+            y: fragment.y,
+            size: fragment.size,
+            fragments: vec![fragment.clone()],
+        };
+        TableCell::with_elements(line_runs(&[line]))
+    };
+    let mut rows = rows.into_iter().peekable();
+    let headers = if let Some(first) = rows.next_if(|_| header) {
+        first
+            .fragments
+            .iter()
+            .map(|fragment| TableHeader {
+                elements: header_elements(cell(fragment).elements),
+                width: fragment.x1 - fragment.x0,
+                colspan: 1,
+                rowspan: 1,
+                alignment: ColumnAlignment::None,
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    Table {
+        name: None,
+        headers,
+        rows: rows
+            .map(|row| TableRow {
+                cells: row.fragments.iter().map(cell).collect(),
+            })
+            .collect(),
     }
 }
 
-fn parse_object(
-    page_id: ObjectId,
-    pdf_document: &PdfDocument,
-    _object: &Object,
-    elements: &mut Vec<Element>,
-) -> anyhow::Result<()> {
-    fn collect_text(
-        text: &mut String,
-        encoding: Option<&str>,
-        operands: &[Object],
-        elements: &mut Vec<Element>,
-    ) -> anyhow::Result<()> {
-        for operand in operands.iter() {
-            debug!("2 {:?}", operand);
-            match *operand {
-                Object::String(ref bytes, _) => {
-                    let decoded_text = PdfDocument::decode_text(encoding, bytes);
-                    text.push_str(&decoded_text);
-                    if bytes.len() == 1 && bytes[0] == 1 {
-                        match elements.last() {
-                            None => {
-                                let list_element = List {
-                                    elements: vec![],
-                                    numbered: false,
-                                };
-                                elements.push(list_element);
-                            }
-                            Some(el) => {
-                                match el {
-                                    List { .. } => {
-                                        let old_list = elements.pop().unwrap();
-                                        // let list = old_list.list_as_ref()?;
-                                        if let List {
-                                            elements: list_elements,
-                                            numbered,
-                                        } = old_list
-                                        {
-                                            let mut list_item_elements = list_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            let new_list_item_element = ListItem {
-                                                element: text_element,
-                                            };
-                                            list_item_elements.push(new_list_item_element);
-                                            let new_list = List {
-                                                elements: list_item_elements,
-                                                numbered,
-                                            };
-                                            elements.push(new_list);
-                                            text.clear();
-                                        }
-                                    }
-                                    Paragraph { .. } => {
-                                        let old_paragraph = elements.pop().unwrap();
-                                        // let paragraph = old_paragraph.paragraph_as_ref()?;
-                                        if let Paragraph {
-                                            elements: paragraph_elements,
-                                        } = old_paragraph
-                                        {
-                                            let mut paragraph_elements = paragraph_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            paragraph_elements.push(text_element);
-                                            let new_paragraph = Paragraph {
-                                                elements: paragraph_elements,
-                                            };
-                                            elements.push(new_paragraph);
-                                            text.clear();
-
-                                            let list_element = List {
-                                                elements: vec![],
-                                                numbered: false,
-                                            };
-                                            elements.push(list_element);
-                                        }
-                                    }
-                                    _ => {
-                                        let list_element = List {
-                                            elements: vec![],
-                                            numbered: false,
-                                        };
-                                        elements.push(*Box::new(list_element));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Object::Array(ref arr) => {
-                    let _ = collect_text(text, encoding, arr, elements);
+// Marks the text of outline entries as headings, entries without their title on the page become headers of their own
+fn outline_headers(fragments: &mut [Fragment], outline: Vec<OutlineEntry>) -> Vec<Block> {
+    let normalize = |text: &str| {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let mut order: Vec<usize> = (0..fragments.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&fragments[a], &fragments[b]);
+        b.y.total_cmp(&a.y).then(a.x0.total_cmp(&b.x0))
+    });
+    let texts: Vec<String> = fragments
+        .iter()
+        .map(|fragment| normalize(&fragment.text()))
+        .collect();
+    // headers without their text on the page span the text of the page
+    let x0 = fragments
+        .iter()
+        .map(|f| f.x0)
+        .reduce(f32::min)
+        .unwrap_or(0.0);
+    let x1 = fragments
+        .iter()
+        .map(|f| f.x1)
+        .reduce(f32::max)
+        .unwrap_or(0.0);
+
+    let mut headers = vec![];
+    for entry in outline {
+        let title = normalize(&entry.title);
+        if title.is_empty() {
+            continue;
+        }
+        // the title starts in a fragment and can continue in the following ones
+        let title_from = |start: usize, fragments: &[Fragment]| {
+            let mut text = String::new();
+            let mut used = vec![];
+            for &index in order[start..]
+                .iter()
+                .filter(|&&index| fragments[index].heading.is_none())
+            {
+                if !text.is_empty() {
                     text.push(' ');
                 }
-                Object::Integer(i) => {
-                    if i < -100 {
-                        text.push(' ');
-                    }
+                text.push_str(&texts[index]);
+                used.push(index);
+                if text == title {
+                    return Some(used);
                 }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
-    let mut text = String::new();
-
-    let fonts = pdf_document.get_page_fonts(page_id);
-    let encodings = fonts
-        .into_iter()
-        .map(|(name, font)| (name, font.get_font_encoding()))
-        .collect::<BTreeMap<Vec<u8>, &str>>();
-
-    let vec = pdf_document.get_page_content(page_id)?;
-    let content = Content::decode(&vec)?;
-    let mut current_encoding = None;
-    for operation in &content.operations {
-        debug!("1 {:?}", operation.operator);
-        match operation.operator.as_ref() {
-            "Tm" => {
-                let text_element = Text {
-                    text: text.clone(),
-                    size: 8,
-                };
-                match elements.last() {
-                    None => {
-                        let paragraph_element = Paragraph {
-                            elements: vec![text_element],
-                        };
-                        elements.push(paragraph_element);
-                    }
-                    Some(el) => match el {
-                        Paragraph { .. } => {
-                            let old_paragraph = elements.pop().unwrap();
-                            if let Paragraph {
-                                elements: paragraph_elements,
-                            } = old_paragraph
-                            {
-                                let mut paragraph_elements = paragraph_elements.clone();
-                                paragraph_elements.push(text_element);
-                                let new_paragraph = Paragraph {
-                                    elements: paragraph_elements,
-                                };
-                                elements.push(new_paragraph);
-                            }
-                        }
-                        _ => {
-                            elements.push(text_element);
-                        }
-                    },
+                if !title.starts_with(&format!("{text} ")) {
+                    return None;
                 }
-                text.clear();
-            }
-            "Tf" => {
-                let current_font = operation
-                    .operands
-                    .first()
-                    .ok_or(ParserError::Common)?
-                    .as_name()?;
-                current_encoding = encodings.get(current_font).cloned();
-            }
-            "Tj" | "TJ" => {
-                _ = collect_text(&mut text, current_encoding, &operation.operands, elements);
             }
-            "ET" => {
-                if !text.ends_with('\n') {
-                    text.push('\n')
+            None
+        };
+        // titles are looked for below the top of the destination first
+        let below_top = |position: &usize| {
+            let fragment = &fragments[order[*position]];
+            entry
+                .top
+                .is_none_or(|top| fragment.y <= top + fragment.size)
+        };
+        let starts: Vec<usize> = (0..order.len())
+            .filter(below_top)
+            .chain(0..order.len())
+            .collect();
+        match starts
+            .into_iter()
+            .filter(|&start| fragments[order[start]].heading.is_none())
+            .find_map(|start| title_from(start, fragments))
+        {
+            Some(used) => {
+                for index in used {
+                    fragments[index].heading = Some(entry.level);
                 }
             }
-            _ => {}
+            None => headers.push(Block {
+                x0,
+                x1,
+                top: entry.top.unwrap_or(f32::MAX),
+                content: BlockContent::Element(Header {
+                    level: entry.level.min(6) as u8,
+                    text: entry.title.trim().to_string(),
+                }),
+            }),
         }
     }
+    headers
+}
 
-    if !text.is_empty() {
-        let text_element = Text {
-            text: text.clone(),
-            size: 8,
-        };
-        match elements.last() {
-            None => {
-                let paragraph_element = Paragraph {
-                    elements: vec![text_element],
-                };
-                elements.push(*Box::new(paragraph_element));
-            }
-            Some(el) => {
-                match el {
-                    Paragraph { .. } => {
-                        let old_paragraph = elements.pop().unwrap();
-                        if let Paragraph {
-                            elements: paragraph_elements,
-                        } = old_paragraph
-                        {
-                            let mut paragraph_elements = paragraph_elements.clone();
-                            paragraph_elements.push(text_element);
-                            let new_paragraph = Paragraph {
-                                elements: paragraph_elements,
-                            };
-                            elements.push(*Box::new(new_paragraph));
-                        }
-                    }
-                    List { .. } => {
-                        let old_list = elements.pop().unwrap();
-                        // let list = old_list.list_as_ref()?;
-                        if let List {
-                            elements: list_elements,
-                            numbered,
-                        } = old_list
-                        {
-                            let mut list_item_elements = list_elements.clone();
-                            let new_list_item_element = ListItem {
-                                element: text_element,
-                            };
-                            list_item_elements.push(new_list_item_element);
-                            let new_list = List {
-                                elements: list_item_elements,
-                                numbered,
-                            };
-                            elements.push(*Box::new(new_list));
-                        }
+// Builds the document elements of all pages, body text and heading sizes are shared by the pages
+fn layout_elements(pages: Vec<PageContent>) -> Vec<Element> {
+    let sizes = FontSizes::new(pages.iter().flat_map(|page| &page.spans));
+    let mut elements = vec![];
+    let mut image_count = 0;
+    for page in pages {
+        let mut spans = page.spans;
+        let mut blocks = ruled_tables(&page.rules, &mut spans);
+        let mut fragments = fragments(spans);
+        blocks.extend(outline_headers(&mut fragments, page.outline));
+        blocks.extend(fragments.into_iter().map(Block::text));
+        for mut placed in page.images {
+            // generators store images by title, so every image gets its own
+            image_count += 1;
+            placed.image.set_image_title(&format!("image{image_count}"));
+            blocks.push(Block {
+                x0: placed.x0,
+                x1: placed.x1,
+                top: placed.y1,
+                content: BlockContent::Element(Image(placed.image)),
+            });
+        }
+
+        let mut leaves = vec![];
+        reading_order(blocks, sizes.body, &mut leaves);
+        for mut leaf in leaves {
+            leaf.sort_by(|a, b| b.top.total_cmp(&a.top).then(a.x0.total_cmp(&b.x0)));
+            let mut fragments = vec![];
+            for block in leaf {
+                match block.content {
+                    BlockContent::Text(fragment) => fragments.push(fragment),
+                    BlockContent::Element(element) => {
+                        line_elements(
+                            text_lines(std::mem::take(&mut fragments)),
+                            &sizes,
+                            &mut elements,
+                        );
+                        elements.push(element);
                     }
-                    _ => {}
                 }
             }
+            line_elements(text_lines(fragments), &sizes, &mut elements);
         }
     }
-
-    Ok(())
+    elements
 }
 
 #[cfg(test)]
 mod tests {
     use crate::core::*;
-    use crate::pdf::Transformer;
+    use crate::pdf::{PdfOptions, PdfParseOptions, Transformer};
     use crate::{markdown, pdf};
     use bytes::Bytes;
     use log::{debug, info};
@@ -338,11 +4178,13 @@ mod tests {
         parsed_document.set_page_header(vec![Element::Text {
             text: "header".to_string(),
             size: 10,
+            style: TextStyle::default(),
         }]);
 
         parsed_document.set_page_footer(vec![Element::Text {
             text: "footer".to_string(),
             size: 10,
+            style: TextStyle::default(),
         }]);
         let generated_result = Transformer::generate(&parsed_document);
         assert!(generated_result.is_ok());
@@ -360,14 +4202,17 @@ mod tests {
                     Text {
                         text: "Line 1".to_owned(),
                         size: 8,
+                        style: TextStyle::default(),
                     },
                     Text {
                         text: "Line 2".to_owned(),
                         size: 8,
+                        style: TextStyle::default(),
                     },
                     Text {
                         text: "Line 3".to_owned(),
                         size: 8,
+                        style: TextStyle::default(),
                     },
                 ],
             },
@@ -412,4 +4257,812 @@ mod tests {
 
         std::fs::write("test/data/test.pdf", output_bytes).unwrap();
     }
+
+    #[test]
+    fn test_generate_with_fonts() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Only local fonts".to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        }]);
+        let fonts = pdf::FontOptions::new()
+            .with_embedded_fonts(false)
+            .with_path("fonts");
+        let pdf = Transformer::generate_with_fonts(&document, &fonts)?;
+        assert_eq!(lopdf::Document::load_mem(&pdf)?.get_pages().len(), 1);
+        // the default text font is found in the font directory
+        assert!(pdf.windows(9).any(|name| name == b"Libertine"));
+
+        let no_fonts = pdf::FontOptions::new().with_embedded_fonts(false);
+        assert!(Transformer::generate_with_fonts(&document, &no_fonts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_format() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Wide page".to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        }]);
+        document.set_page_format(PageFormat::Letter);
+        document.set_orientation(PageOrientation::Landscape);
+        let pdf = lopdf::Document::load_mem(&Transformer::generate(&document)?)?;
+        let page_id = *pdf.get_pages().values().next().unwrap();
+        let media_box = pdf.get_dictionary(page_id)?.get(b"MediaBox")?.as_array()?;
+        let size: Vec<f32> = media_box
+            .iter()
+            .map(|value| value.as_float())
+            .collect::<Result<_, _>>()?;
+        // 279 x 216 mm in points
+        assert!((size[2] - 790.87).abs() < 0.1);
+        assert!((size[3] - 612.28).abs() < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout() -> anyhow::Result<()> {
+        let markdown = "# Title\n\nSome **bold** text.\n\n## Section\n\n\
+                        - first\n- second\n  - nested\n\n1. one\n2. two\n\n\
+                        | Name | Value |\n|------|-------|\n| a | 1 |\n| b | 2 |\n";
+        let document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        debug!("{:?}", parsed.get_all_elements());
+
+        // headings, styles, lists and the table come back as they were written
+        assert_eq!(
+            markdown::Transformer::generate(&parsed)?,
+            markdown::Transformer::generate(&document)?
+        );
+        let Element::Paragraph { elements: runs } = parsed.get_all_elements()[1] else {
+            panic!("expected a paragraph");
+        };
+        assert!(
+            matches!(&runs[1], Element::Text { text, style, .. } if text == "bold" && style.bold)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_images_and_links() -> anyhow::Result<()> {
+        let picture = Bytes::from(std::fs::read("test/data/picture.png")?);
+        let mut jpeg = vec![];
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(16, 8)).write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageOutputFormat::Jpeg(90),
+        )?;
+        let jpeg = Bytes::from(jpeg);
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 11,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("See"),
+                    Element::Hyperlink {
+                        title: "the site".to_string(),
+                        url: "https://example.com/page".to_string(),
+                        alt: String::new(),
+                        size: 11,
+                    },
+                    text("for more."),
+                ],
+            },
+            Element::Image(ImageData::new(
+                picture,
+                "picture".to_string(),
+                "A picture".to_string(),
+                "picture.png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+            text("After the picture."),
+            Element::Image(ImageData::new(
+                jpeg,
+                "photo".to_string(),
+                String::new(),
+                "photo.jpeg".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+        ]);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let elements = parsed.get_all_elements();
+        debug!("{:?}", elements);
+
+        let Element::Paragraph { elements: runs } = elements[0] else {
+            panic!("expected a paragraph, got {:?}", elements[0]);
+        };
+        assert!(runs.iter().any(|run| matches!(run,
+            Element::Hyperlink { title, url, .. }
+                if title == "the site" && url == "https://example.com/page")));
+        let Element::Image(image) = elements[1] else {
+            panic!("expected an image, got {:?}", elements[1]);
+        };
+        assert_eq!(image.image_type(), &ImageType::Png);
+        assert_eq!(image.alt(), "A picture");
+        let decoded = image::load_from_memory(image.bytes())?;
+        assert_eq!((decoded.width(), decoded.height()), (256, 256));
+        assert!(matches!(elements[2], Element::Paragraph { elements }
+            if plain_text(elements) == "After the picture."));
+        let Element::Image(photo) = elements[3] else {
+            panic!("expected an image, got {:?}", elements[3]);
+        };
+        assert_eq!(photo.image_type(), &ImageType::Jpeg);
+        let decoded = image::load_from_memory(photo.bytes())?;
+        assert_eq!((decoded.width(), decoded.height()), (16, 8));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_pixels_size() {
+        use lopdf::{dictionary, Stream};
+
+        let pdf = lopdf::Document::with_version("1.5");
+        let pixels = |width: i64, height: i64, bits: i64, data: &[u8]| {
+            let image = Stream::new(
+                dictionary! {
+                    "Width" => width,
+                    "Height" => height,
+                    "BitsPerComponent" => bits,
+                    "ColorSpace" => "DeviceRGB",
+                },
+                data.to_vec(),
+            );
+            super::image_pixels(&pdf, &image, data)
+        };
+
+        let (width, height, rgb) = pixels(2, 1, 8, &[255, 0, 0, 0, 0, 255]).unwrap();
+        assert_eq!((width, height, rgb), (2, 1, vec![[255, 0, 0], [0, 0, 255]]));
+        // sizes that overflow or don't match the data are errors, not panics or huge allocations
+        assert!(pixels(i64::MAX, i64::MAX, 16, &[0; 6]).is_err());
+        assert!(pixels(1 << 31, 1 << 31, 8, &[0; 6]).is_err());
+        assert!(pixels(-1, 1, 8, &[0; 6]).is_err());
+        assert!(pixels(0, 1, 8, &[]).is_err());
+        assert!(pixels(100_000, 100_000, 8, &[0; 6]).is_err());
+        assert!(pixels(2, 1, 3, &[0; 6]).is_err());
+    }
+
+    // Single page PDF with Helvetica text runs at the given positions, the catalog is returned for additions
+    fn text_pdf(
+        texts: &[(f32, f32, f32, &str)],
+    ) -> anyhow::Result<(lopdf::Document, lopdf::ObjectId, lopdf::ObjectId)> {
+        use lopdf::content::{Content, Operation};
+        use lopdf::{dictionary, Object, Stream};
+
+        let mut pdf = lopdf::Document::with_version("1.5");
+        let font_id = pdf.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let mut operations = vec![];
+        for &(x, y, size, text) in texts {
+            operations.extend([
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), size.into()]),
+                Operation::new("Td", vec![x.into(), y.into()]),
+                Operation::new("Tj", vec![Object::string_literal(text)]),
+                Operation::new("ET", vec![]),
+            ]);
+        }
+        let content_id = pdf.add_object(Stream::new(
+            dictionary! {},
+            Content { operations }.encode()?,
+        ));
+        let pages_id = pdf.new_object_id();
+        let page_id = pdf.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        pdf.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = pdf.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        pdf.trailer.set("Root", catalog_id);
+        Ok((pdf, catalog_id, page_id))
+    }
+
+    fn block_texts(document: &Document) -> Vec<String> {
+        document
+            .get_all_elements()
+            .iter()
+            .map(|element| match element {
+                Element::Header { level, text } => format!("h{level} {text}"),
+                Element::Paragraph { elements } => plain_text(elements),
+                element => panic!("unexpected element {element:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_columns() -> anyhow::Result<()> {
+        let mut texts = vec![(72.0, 760.0, 18.0, "Two columns".to_string())];
+        for (line, y) in [700.0, 686.0, 672.0].into_iter().enumerate() {
+            texts.push((72.0, y, 10.0, format!("left column line {line}")));
+            texts.push((320.0, y, 10.0, format!("right column line {line}")));
+        }
+        let texts: Vec<(f32, f32, f32, &str)> = texts
+            .iter()
+            .map(|(x, y, size, text)| (*x, *y, *size, text.as_str()))
+            .collect();
+        let (mut pdf, _, _) = text_pdf(&texts)?;
+        let mut bytes = vec![];
+        pdf.save_to(&mut bytes)?;
+
+        let parsed = Transformer::parse(&Bytes::from(bytes))?;
+        assert_eq!(
+            block_texts(&parsed),
+            [
+                "h1 Two columns",
+                "left column line 0 left column line 1 left column line 2",
+                "right column line 0 right column line 1 right column line 2",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_outline_generation() -> anyhow::Result<()> {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+        };
+        let document = Document::new(vec![
+            header(1, "Report"),
+            header(2, "Findings"),
+            header(3, "Details"),
+            header(1, "Appendix"),
+        ]);
+        let pdf = lopdf::Document::load_mem(&Transformer::generate(&document)?)?;
+        let outlines = pdf
+            .catalog()?
+            .get_deref(b"Outlines", &pdf)?
+            .as_dict()?
+            .clone();
+        let first = pdf.get_dictionary(outlines.get(b"First")?.as_reference()?)?;
+        assert_eq!(first.get(b"Title")?.as_str()?, b"Report");
+        let child = pdf.get_dictionary(first.get(b"First")?.as_reference()?)?;
+        assert_eq!(child.get(b"Title")?.as_str()?, b"Findings");
+        let last = pdf.get_dictionary(outlines.get(b"Last")?.as_reference()?)?;
+        assert_eq!(last.get(b"Title")?.as_str()?, b"Appendix");
+
+        // the contents page links to every header and comes back as text when parsed
+        let options = PdfOptions::new().with_table_of_contents(true);
+        let bytes = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&bytes)?;
+        let pages = pdf.get_pages();
+        assert_eq!(pages.len(), 2);
+        let contents = pdf.get_dictionary(pages[&1])?;
+        assert!(contents.get(b"Annots")?.as_array()?.len() >= 4);
+        let parsed = Transformer::parse(&bytes)?;
+        let texts = plain_text(
+            &parsed
+                .get_all_elements()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        assert!(texts.starts_with("Contents"));
+        assert!(texts.ends_with("Report\nFindings\nDetails\nAppendix"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_outline_parse() -> anyhow::Result<()> {
+        use lopdf::{dictionary, Object};
+
+        // all text has the same size, only the outline tells the headings apart
+        let (mut pdf, catalog_id, page_id) = text_pdf(&[
+            (72.0, 760.0, 10.0, "Introduction"),
+            (72.0, 740.0, 10.0, "Some text of the introduction."),
+            (72.0, 700.0, 10.0, "Scope of the"),
+            (72.0, 686.0, 10.0, "work"),
+            (72.0, 666.0, 10.0, "More text about the scope."),
+        ])?;
+        let outline_id = pdf.new_object_id();
+        let item = |title: &str, top: f32| {
+            dictionary! {
+                "Title" => Object::string_literal(title),
+                "Parent" => outline_id,
+                "Dest" => vec![page_id.into(), "XYZ".into(), 72.into(), top.into(), Object::Null],
+            }
+        };
+        let introduction_id = pdf.new_object_id();
+        let scope_id = pdf.add_object(item("Scope of the work", 712.0));
+        let missing_id = pdf.add_object(item("Results", 600.0));
+        let mut introduction = item("Introduction", 772.0);
+        introduction.set("First", scope_id);
+        introduction.set("Last", scope_id);
+        introduction.set("Next", missing_id);
+        pdf.objects
+            .insert(introduction_id, Object::Dictionary(introduction));
+        pdf.objects.insert(
+            outline_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => introduction_id,
+                "Last" => missing_id,
+            }),
+        );
+        pdf.get_object_mut(catalog_id)?
+            .as_dict_mut()?
+            .set("Outlines", outline_id);
+        let mut bytes = vec![];
+        pdf.save_to(&mut bytes)?;
+
+        let parsed = Transformer::parse(&Bytes::from(bytes))?;
+        assert_eq!(
+            block_texts(&parsed),
+            [
+                "h1 Introduction",
+                "Some text of the introduction.",
+                "h2 Scope of the work",
+                "More text about the scope.",
+                "h1 Results",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Text".to_string(),
+            size: 8,
+            style: TextStyle::default(),
+        }]);
+        document.set_metadata(DocumentMetadata {
+            title: Some("Report \"2024\"".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en-US".to_string()),
+            created: Some("2024-05-01".to_string()),
+            ..Default::default()
+        });
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata.title, document.metadata.title);
+        assert_eq!(parsed.metadata.author, document.metadata.author);
+        assert_eq!(parsed.metadata.keywords, document.metadata.keywords);
+        assert_eq!(
+            parsed.metadata.created.as_deref().map(|date| &date[..10]),
+            Some("2024-05-01")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted() -> anyhow::Result<()> {
+        // written by test/data/make_encrypted_pdfs.py, which encrypts with the RC4 and AES of
+        // Python's cryptography package, not with the code under test
+        let fixtures = [
+            ("encrypted_rc4.pdf", "\u{20ac}uro\u{2022}"),
+            ("encrypted_aes128.pdf", "user"),
+            ("encrypted_aes256_r5.pdf", "user"),
+            ("encrypted_aes256_r6.pdf", "pass w\u{f6}rd"),
+        ];
+        for (file, user_password) in fixtures {
+            let bytes = Bytes::from(std::fs::read(format!("test/data/{file}"))?);
+            for password in [user_password, "owner"] {
+                let options = PdfParseOptions::new().with_password(password);
+                let parsed = Transformer::parse_with_options(&bytes, &options)?;
+                assert_eq!(block_texts(&parsed), ["Confidential terms"], "{file}");
+                assert_eq!(parsed.metadata.title.as_deref(), Some("Contract"), "{file}");
+            }
+            let error = Transformer::parse(&bytes).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ParserError>(),
+                Some(ParserError::PasswordRequired)
+            ));
+            let options = PdfParseOptions::new().with_password("guess");
+            let error = Transformer::parse_with_options(&bytes, &options).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ParserError>(),
+                Some(ParserError::IncorrectPassword)
+            ));
+        }
+
+        // lopdf opens the RC4 fixture with the password in PDFDocEncoding as well
+        let mut pdf = lopdf::Document::load("test/data/encrypted_rc4.pdf")?;
+        pdf.decrypt([0xA0, b'u', b'r', b'o', 0x80])?;
+        let page_id = pdf.get_pages()[&1];
+        assert!(String::from_utf8_lossy(&pdf.get_page_content(page_id)?).contains("Confidential"));
+
+        // AES-256 passwords are compared after SASLprep, a no-break space maps to a space
+        // and a decomposed umlaut is composed
+        let bytes = Bytes::from(std::fs::read("test/data/encrypted_aes256_r6.pdf")?);
+        let options = PdfParseOptions::new().with_password("pass\u{a0}wo\u{308}rd");
+        let parsed = Transformer::parse_with_options(&bytes, &options)?;
+        assert_eq!(block_texts(&parsed), ["Confidential terms"]);
+
+        // documents restricted only by permissions have an empty user password
+        let bytes = Bytes::from(std::fs::read("test/data/encrypted_permissions.pdf")?);
+        let parsed = Transformer::parse(&bytes)?;
+        assert_eq!(block_texts(&parsed), ["Confidential terms"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_encoding() {
+        assert_eq!(
+            super::password_bytes("\u{20ac}uro\u{2022}\u{e9}"),
+            vec![0xA0, b'u', b'r', b'o', 0x80, 0xE9]
+        );
+        // characters PDFDocEncoding lacks keep the UTF-8 bytes
+        assert_eq!(super::password_bytes("\u{3b1}"), "\u{3b1}".as_bytes());
+
+        assert_eq!(
+            super::sasl_prep("I\u{ad}X").as_deref(),
+            Some("IX"),
+            "soft hyphen maps to nothing"
+        );
+        assert_eq!(super::sasl_prep("\u{2168}").as_deref(), Some("IX"));
+        assert_eq!(super::sasl_prep("a\u{2003}b").as_deref(), Some("a b"));
+        assert_eq!(
+            super::sasl_prep("\u{7}"),
+            None,
+            "control characters are prohibited"
+        );
+        assert_eq!(
+            super::sasl_prep("\u{5d0}1"),
+            None,
+            "mixed direction needs RandAL at the ends"
+        );
+        assert_eq!(super::sasl_prep("\u{5d0}a\u{5d0}"), None);
+        assert_eq!(
+            super::sasl_prep("\u{5d0}1\u{5d0}").as_deref(),
+            Some("\u{5d0}1\u{5d0}")
+        );
+    }
+
+    // Roles of the structure elements in reading order, with the text of their marked content
+    fn structure_texts(pdf: &lopdf::Document) -> anyhow::Result<Vec<(String, String)>> {
+        use lopdf::content::Content;
+        use lopdf::Object;
+
+        let mut marked = HashMap::new();
+        for page_id in pdf.get_pages().into_values() {
+            let fonts = pdf::resource_fonts(pdf, &pdf::page_resources(pdf, page_id));
+            let content = Content::decode(&pdf.get_page_content(page_id)?)?;
+            let (mut font, mut mcid) = (None, None);
+            let mut saved_fonts = vec![];
+            for operation in content.operations {
+                match operation.operator.as_str() {
+                    "q" => saved_fonts.push(font),
+                    "Q" => font = saved_fonts.pop().flatten(),
+                    "BDC" => {
+                        mcid = operation.operands[1]
+                            .as_dict()
+                            .and_then(|properties| properties.get(b"MCID"))
+                            .and_then(Object::as_i64)
+                            .ok();
+                    }
+                    "EMC" => mcid = None,
+                    "Tf" => font = fonts.get(operation.operands[0].as_name()?),
+                    "TJ" | "Tj" => {
+                        let (Some(font), Some(mcid)) = (font, mcid) else {
+                            continue;
+                        };
+                        let strings = match &operation.operands[0] {
+                            Object::Array(items) => items.clone(),
+                            string => vec![string.clone()],
+                        };
+                        let text: &mut String = marked.entry((page_id, mcid)).or_default();
+                        for string in strings {
+                            if let Object::String(bytes, _) = string {
+                                for code in font.codes(&bytes) {
+                                    text.push_str(&font.text(code));
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let root = pdf
+            .catalog()?
+            .get_deref(b"StructTreeRoot", pdf)?
+            .as_dict()?;
+        let mut texts = vec![];
+        structure_element(pdf, root.get(b"K")?.as_reference()?, &marked, &mut texts)?;
+        Ok(texts)
+    }
+
+    // Adds the element and its descendants, the text of an element includes the text of its kids
+    fn structure_element(
+        pdf: &lopdf::Document,
+        id: lopdf::ObjectId,
+        marked: &HashMap<(lopdf::ObjectId, i64), String>,
+        texts: &mut Vec<(String, String)>,
+    ) -> anyhow::Result<String> {
+        use lopdf::Object;
+
+        let element = pdf.get_dictionary(id)?;
+        let position = texts.len();
+        let role = String::from_utf8(element.get(b"S")?.as_name()?.to_vec())?;
+        texts.push((role, String::new()));
+        let mut text = String::new();
+        for kid in element.get(b"K")?.as_array()? {
+            match kid {
+                Object::Reference(kid) => {
+                    text.push_str(&structure_element(pdf, *kid, marked, texts)?)
+                }
+                Object::Dictionary(kid) if kid.has(b"MCID") => {
+                    let page = kid.get(b"Pg")?.as_reference()?;
+                    let mcid = kid.get(b"MCID")?.as_i64()?;
+                    text.push_str(marked.get(&(page, mcid)).map_or("", String::as_str));
+                }
+                _ => {}
+            }
+        }
+        texts[position].1 = text.clone();
+        Ok(text)
+    }
+
+    #[test]
+    fn test_tagged() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 11,
+            style: TextStyle::default(),
+        };
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+        };
+        let picture = Bytes::from(std::fs::read("test/data/picture.png")?);
+        let mut document = Document::new(vec![
+            header(1, "Report"),
+            Element::Paragraph {
+                elements: vec![text("The numbers of the year.")],
+            },
+            header(2, "Results"),
+            Element::Table {
+                name: None,
+                headers: vec![
+                    TableHeader::new(text("Name"), 50.0),
+                    TableHeader::new(text("Value"), 50.0),
+                ],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("Apples")), TableCell::new(text("7"))],
+                }],
+            },
+            Element::List {
+                numbered: false,
+                elements: vec![
+                    ListItem::new(text("First point")),
+                    ListItem::new(text("Second point")),
+                ],
+            },
+            Element::Image(ImageData::new(
+                picture,
+                "picture".to_string(),
+                "Chart of the results".to_string(),
+                "picture.png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+        ]);
+        document.metadata.language = Some("en-GB".to_string());
+        let options = PdfOptions::new().with_tagged(true);
+        let bytes = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&bytes)?;
+        let catalog = pdf.catalog()?;
+        let mark_info = catalog.get_deref(b"MarkInfo", &pdf)?.as_dict()?;
+        assert!(mark_info.get(b"Marked")?.as_bool()?);
+        assert_eq!(catalog.get(b"Lang")?.as_str()?, b"en-GB");
+        assert!(xmp_metadata(&pdf)?.contains("<pdfuaid:part>1</pdfuaid:part>"));
+
+        let texts = structure_texts(&pdf)?;
+        debug!("{:?}", texts);
+        let text_of = |role: &str| -> Vec<&str> {
+            texts
+                .iter()
+                .filter(|(element_role, _)| element_role == role)
+                .map(|(_, text)| text.as_str())
+                .collect()
+        };
+        assert_eq!(texts[0].0, "Document");
+        assert_eq!(text_of("H1"), ["Report"]);
+        assert_eq!(text_of("H2"), ["Results"]);
+        assert_eq!(text_of("TH"), ["Name", "Value"]);
+        assert_eq!(text_of("TD"), ["Apples", "7"]);
+        assert_eq!(text_of("TR").len(), 2);
+        assert_eq!(text_of("LI").len(), 2);
+        assert_eq!(text_of("LBody"), ["First point", "Second point"]);
+        assert_eq!(text_of("Lbl").len(), 2);
+        assert_eq!(text_of("Figure").len(), 1);
+
+        // the alt text of the figure is kept on the element and on its marked content
+        let root = catalog.get_deref(b"StructTreeRoot", &pdf)?.as_dict()?;
+        assert!(root.has(b"ParentTree"));
+        let figure = pdf
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .find(|element| {
+                element.get(b"S").and_then(lopdf::Object::as_name).ok() == Some(b"Figure")
+            })
+            .ok_or_else(|| anyhow::anyhow!("no figure element"))?;
+        assert_eq!(figure.get(b"Alt")?.as_str()?, b"Chart of the results");
+
+        // the first header stands in for the missing title
+        let parsed = Transformer::parse(&bytes)?;
+        assert_eq!(parsed.metadata.title.as_deref(), Some("Report"));
+        let Element::Image(image) = parsed
+            .get_all_elements()
+            .into_iter()
+            .find(|element| matches!(element, Element::Image(_)))
+            .ok_or_else(|| anyhow::anyhow!("no image parsed"))?
+        else {
+            unreachable!()
+        };
+        assert_eq!(image.alt(), "Chart of the results");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tagged_contents() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 11,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Summary".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("Read "),
+                    Element::Hyperlink {
+                        title: "the terms".to_string(),
+                        url: "https://example.com/terms".to_string(),
+                        alt: String::new(),
+                        size: 11,
+                    },
+                    text(" first."),
+                ],
+            },
+            Element::Header {
+                level: 2,
+                text: "Details".to_string(),
+            },
+            Element::List {
+                numbered: true,
+                elements: vec![ListItem::new(text("Summary of the details"))],
+            },
+        ]);
+        let options = PdfOptions::new()
+            .with_tagged(true)
+            .with_table_of_contents(true);
+        let bytes = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&bytes)?;
+        let texts = structure_texts(&pdf)?;
+        debug!("{:?}", texts);
+        let text_of = |role: &str| -> Vec<String> {
+            texts
+                .iter()
+                .filter(|(element_role, _)| element_role == role)
+                .map(|(_, text)| text.trim().to_string())
+                .collect()
+        };
+        // entries of the contents repeat the headings, they are told apart by the page they are on
+        assert_eq!(text_of("H1"), ["Contents", "Summary"]);
+        assert_eq!(text_of("H2"), ["Details"]);
+        let entries = text_of("TOCI");
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].starts_with("Summary") && entries[1].starts_with("Details"));
+        // the links of the entries are found by their annotations alone
+        let links: Vec<String> = text_of("Link")
+            .into_iter()
+            .filter(|text| !text.is_empty())
+            .collect();
+        assert_eq!(links, ["the terms"]);
+        assert_eq!(
+            text_of("P"),
+            ["Read the terms first.", "Summary of the details"]
+        );
+        assert_eq!(text_of("Lbl"), ["1."]);
+        assert_eq!(text_of("LBody"), ["Summary of the details"]);
+
+        // without a language the document is tagged but does not claim PDF/UA
+        assert!(!pdf.catalog()?.has(b"Lang"));
+        assert!(!xmp_metadata(&pdf)?.contains("pdfuaid"));
+        Ok(())
+    }
+
+    fn xmp_metadata(pdf: &lopdf::Document) -> anyhow::Result<String> {
+        let metadata = pdf.get_object(pdf.catalog()?.get(b"Metadata")?.as_reference()?)?;
+        Ok(String::from_utf8(metadata.as_stream()?.content.clone())?)
+    }
+
+    #[test]
+    fn test_pdf_a() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Archive".to_string(),
+            },
+            Element::Hyperlink {
+                title: "the site".to_string(),
+                url: "https://example.com/".to_string(),
+                alt: String::new(),
+                size: 11,
+            },
+        ]);
+        let mut document = document;
+        document.metadata.language = Some("en".to_string());
+        let options = PdfOptions::new().with_pdf_a(true).with_tagged(true);
+        let bytes = Transformer::generate_with_options(&document, &options)?;
+        let comment = bytes
+            .split(|&byte| byte == b'\n')
+            .nth(1)
+            .unwrap_or_default();
+        assert!(comment.starts_with(b"%") && comment[1..5].iter().all(|&byte| byte > 127));
+        // the objects are still where the cross-reference table says
+        let startxref = bytes
+            .windows(9)
+            .rposition(|window| window == b"startxref")
+            .unwrap_or_default();
+        let xref: usize = String::from_utf8_lossy(&bytes[startxref + 9..])
+            .trim()
+            .trim_end_matches("%%EOF")
+            .trim()
+            .parse()?;
+        let mut id = 0;
+        for line in String::from_utf8_lossy(&bytes[xref..startxref])
+            .lines()
+            .skip(1)
+        {
+            match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                [first, _] => id = first.parse()?,
+                [offset, _, kind] => {
+                    if kind == "n" {
+                        let offset: usize = offset.parse()?;
+                        assert!(bytes[offset..].starts_with(format!("{id} 0 obj").as_bytes()));
+                    }
+                    id += 1;
+                }
+                _ => break,
+            }
+        }
+
+        let pdf = lopdf::Document::load_mem(&bytes)?;
+        let catalog = pdf.catalog()?;
+        let intents = catalog.get(b"OutputIntents")?.as_array()?;
+        let intent = intents[0].as_dict()?;
+        assert_eq!(intent.get(b"S")?.as_name()?, b"GTS_PDFA1");
+        assert!(intent.get(b"DestOutputProfile")?.as_reference().is_ok());
+
+        let metadata = pdf.get_object(catalog.get(b"Metadata")?.as_reference()?)?;
+        let metadata = metadata.as_stream()?;
+        assert!(!metadata.dict.has(b"Filter"));
+        let xmp = String::from_utf8(metadata.content.clone())?;
+        assert!(xmp.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(xmp.contains("<pdfuaid:part>1</pdfuaid:part>"));
+        assert!(xmp.contains("<pdfaSchema:prefix>pdfuaid</pdfaSchema:prefix>"));
+
+        let page = pdf.get_dictionary(pdf.get_pages()[&1])?;
+        let annotation = pdf.get_dictionary(page.get(b"Annots")?.as_array()?[0].as_reference()?)?;
+        assert_eq!(annotation.get(b"F")?.as_i64()?, 4);
+        assert!(annotation.has(b"StructParent"));
+        let texts = structure_texts(&pdf)?;
+        assert!(texts.contains(&("Link".to_string(), "the site".to_string())));
+        Ok(())
+    }
 }
@@ -0,0 +1,87 @@
+use crate::core::{Document, TransformerTrait};
+pub use crate::svg::ImageOptions;
+
+use anyhow;
+use bytes::Bytes;
+use typst::visualize::Color;
+
+/// Renders the pages of a document as PNG images.
+///
+/// A single page is returned as it is, several pages are packed into a ZIP archive
+/// with one `page-N.png` file per page.
+pub struct Transformer;
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("Parsing PNG images is not supported"))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &ImageOptions::default())
+    }
+}
+
+impl Transformer {
+    pub fn generate_with_options(
+        document: &Document,
+        options: &ImageOptions,
+    ) -> anyhow::Result<Bytes> {
+        let pages = Transformer::generate_pages(document, options)?;
+        crate::svg::page_archive(pages, "png")
+    }
+
+    /// One PNG image per page
+    pub fn generate_pages(
+        document: &Document,
+        options: &ImageOptions,
+    ) -> anyhow::Result<Vec<Bytes>> {
+        crate::svg::layout_pages(document, options)?
+            .iter()
+            .map(|frame| {
+                let pixmap = typst_render::render(frame, options.pixels_per_point, Color::WHITE);
+                Ok(Bytes::from(pixmap.encode_png()?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::png::{ImageOptions, Transformer};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let picture = Bytes::from(std::fs::read("test/data/picture.png")?);
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Quarterly report".to_string(),
+            },
+            Element::Image(ImageData::new(
+                picture,
+                "picture".to_string(),
+                "A picture".to_string(),
+                "picture.png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+        ]);
+        let png = document.generate(DocumentType::PNG)?;
+        let image = tiny_skia::Pixmap::decode_png(&png)?;
+        // A4 at 2 pixels per point
+        assert_eq!((image.width(), image.height()), (1191, 1684));
+        let inked = image
+            .pixels()
+            .iter()
+            .filter(|pixel| [pixel.red(), pixel.green(), pixel.blue()] != [255; 3])
+            .count();
+        assert!(inked > 1000);
+
+        let options = ImageOptions::new().with_page(1).with_pixels_per_point(0.5);
+        let thumbnail = Transformer::generate_with_options(&document, &options)?;
+        let thumbnail = tiny_skia::Pixmap::decode_png(&thumbnail)?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), (298, 421));
+        Ok(())
+    }
+}
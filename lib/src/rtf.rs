@@ -1,12 +1,17 @@
-use crate::core::{Document, Element, TableHeader, TableRow, TransformerTrait};
+use crate::core::{
+    mm_to_twips, plain_text, twips_to_mm, Band, ColumnAlignment, Document, Element, ImageData,
+    ImageDimension, ImageType, ListItem, PageDimensions, PageOrientation, RevisionKind, TableCell,
+    TableHeader, TableRow, TextStyle, TransformerTrait, PAGE_COUNT, PAGE_NUMBER,
+};
 use bytes::Bytes;
 use image::io::Reader as ImageReader;
 use image::GenericImageView;
 use log::warn;
+use std::collections::HashMap;
 use std::io::Cursor;
 
 use rtf_parser::lexer::Lexer;
-use rtf_parser::parser::Parser;
+use rtf_parser::tokens::{ControlWord, Property, Token};
 
 pub struct Transformer;
 
@@ -56,6 +61,248 @@ fn re_size_picture(image_bytes: &Bytes) -> ImageSize {
     }
 }
 
+// font number of the monospace font in the generated font table
+const MONOSPACE_FONT: u16 = 1;
+
+// color number of hyperlinks in the generated color table
+const LINK_COLOR: u16 = 1;
+
+//escape the characters that have a special meaning in RTF,
+//characters beyond ASCII are written as \'hh in the Latin-1 range of \ansicpg1252 and as \uN otherwise
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    // rtf-parser drops text made only of spaces between control words, such spaces are written as \'20
+    let mut after_control = true;
+    let mut spaces = 0;
+    for c in text.chars() {
+        if c == ' ' {
+            spaces += 1;
+            continue;
+        }
+        if c.is_ascii_control() && c != '\t' && c != '\n' {
+            continue;
+        }
+        let plain = c.is_ascii() && !matches!(c, '\t' | '\n' | '\\' | '{' | '}');
+        let space = if after_control && !plain {
+            "\\'20"
+        } else {
+            " "
+        };
+        escaped.push_str(&space.repeat(std::mem::take(&mut spaces)));
+        match c {
+            '\\' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push_str("\\tab "),
+            '\n' => escaped.push_str("\\line "),
+            c if c.is_ascii() => escaped.push(c),
+            // rtf-parser lexes \'hh and \uN alike, a \uN below 256 would not be known by its fallback
+            '\u{a0}'..='\u{ff}' => escaped.push_str(&format!("\\'{:02x}", c as u32)),
+            c => {
+                // \u takes a signed 16 bit number, other planes are written as surrogate pairs;
+                // the ? for readers without unicode is written as \'3f, rtf-parser drops a space after a bare ?
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{}\\'3f", *unit as i16));
+                }
+            }
+        }
+        after_control = matches!(c, '\t' | '\n') || !c.is_ascii();
+    }
+    let space = if after_control { "\\'20" } else { " " };
+    escaped.push_str(&space.repeat(spaces));
+    escaped
+}
+
+//format a code block as a single monospace paragraph, keeping its line breaks
+fn code_block(code: &str) -> String {
+    format!(
+        "{{\\f{}\\fs20 {}}}\\par ",
+        MONOSPACE_FONT,
+        escape_text(code.trim_end_matches('\n'))
+    )
+}
+
+//hyperlink field, the text is underlined in the link color
+fn hyperlink(title: &str, url: &str) -> String {
+    format!(
+        "{{\\field{{\\*\\fldinst HYPERLINK \"{}\" }}{{\\fldrslt {{\\ul\\cf{} {}}}}}}}",
+        escape_text(&url.replace('"', "%22")),
+        LINK_COLOR,
+        escape_text(title)
+    )
+}
+
+//picture group of a PNG or JPEG image, GIF images are converted to PNG
+fn picture(image: &ImageData) -> Option<String> {
+    let (bytes, blip) = match image.image_type() {
+        ImageType::Png => (image.bytes().clone(), "pngblip"),
+        ImageType::Jpeg => (image.bytes().clone(), "jpegblip"),
+        ImageType::Gif => {
+            let mut png = Cursor::new(vec![]);
+            image::load_from_memory(image.bytes())
+                .and_then(|gif| gif.write_to(&mut png, image::ImageOutputFormat::Png))
+                .map_err(|err| warn!("Failed to convert GIF image {}: {}", image.title(), err))
+                .ok()?;
+            (Bytes::from(png.into_inner()), "pngblip")
+        }
+        ImageType::SVG => {
+            warn!("SVG images are not supported in RTF: {}", image.title());
+            return None;
+        }
+    };
+    let image_size = re_size_picture(&bytes);
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<String>();
+    Some(format!(
+        "{{\\pict\\{}\\picw{}\\pich{}\\picwgoal{}\\pichgoal{} {}}}",
+        blip,
+        image_size.output_width / 15,
+        image_size.output_height / 15,
+        image_size.output_width,
+        image_size.output_height,
+        hex
+    ))
+}
+
+//text with the page number placeholders written as PAGE and NUMPAGES fields
+fn field_text(text: &str, size: u8, style: &TextStyle) -> String {
+    let mut content = String::new();
+    let mut rest = text;
+    while let Some((index, placeholder)) = [PAGE_NUMBER, PAGE_COUNT]
+        .into_iter()
+        .filter_map(|placeholder| Some((rest.find(placeholder)?, placeholder)))
+        .min()
+    {
+        if index > 0 {
+            content.push_str(&styled_text(&rest[..index], size, style));
+        }
+        let (instruction, result) = if placeholder == PAGE_NUMBER {
+            ("PAGE", "1")
+        } else {
+            ("NUMPAGES", "1")
+        };
+        content.push_str(&format!(
+            "{{\\field{{\\*\\fldinst {}}}{{\\fldrslt {}}}}}",
+            instruction,
+            styled_text(result, size, style)
+        ));
+        rest = &rest[index + placeholder.len()..];
+    }
+    if !rest.is_empty() {
+        content.push_str(&styled_text(rest, size, style));
+    }
+    content
+}
+
+//page header or footer group written from a band, one paragraph per element
+fn page_band(destination: &str, elements: &[&Element]) -> String {
+    let mut band = format!("{{\\{}\\pard\\plain ", destination);
+    for element in elements {
+        let runs = match element {
+            Element::Paragraph { elements } => elements.as_slice(),
+            element => std::slice::from_ref(*element),
+        };
+        for run in runs {
+            match run {
+                Element::Text { text, size, style } => {
+                    band.push_str(&field_text(text, *size, style))
+                }
+                Element::Hyperlink { title, url, .. } => band.push_str(&hyperlink(title, url)),
+                run => band.push_str(&escape_text(&plain_text(std::slice::from_ref(run)))),
+            }
+        }
+        band.push_str("\\par ");
+    }
+    band.push('}');
+    band
+}
+
+//format a blockquote as indented paragraphs, nested quotes are indented further
+fn blockquote(elements: &[Element], indent: u32) -> String {
+    let mut content = format!("{{\\pard\\li{} ", indent);
+    for element in elements {
+        match element {
+            Element::Text { text, size, style } => {
+                content.push_str(&styled_text(text, *size, style));
+                content.push_str("\\par ");
+            }
+            Element::Paragraph { elements } => {
+                for elem in elements {
+                    if let Element::Text { text, size, style } = elem {
+                        content.push_str(&styled_text(text, *size, style));
+                    }
+                }
+                content.push_str("\\par ");
+            }
+            Element::Header { text, .. } => {
+                content.push_str(&format!("{{\\b {}\\b0}}\\par ", escape_text(text)));
+            }
+            Element::CodeBlock { code, .. } => content.push_str(&code_block(code)),
+            Element::Blockquote { elements } => {
+                content.push_str(&blockquote(elements, indent + 720));
+                content.push_str(&format!("\\li{} ", indent));
+            }
+            _ => warn!("Unsupported element in blockquote"),
+        }
+    }
+    content.push_str("}\\pard ");
+    content
+}
+
+//format a text run with its size and inline formatting
+fn styled_text(text: &str, size: u8, style: &TextStyle) -> String {
+    let mut control_words = format!("\\fs{}", size as i32 * 2);
+    if style.bold {
+        control_words.push_str("\\b");
+    }
+    if style.italic {
+        control_words.push_str("\\i");
+    }
+    if style.underline {
+        control_words.push_str("\\ul");
+    }
+    if style.strikethrough {
+        control_words.push_str("\\strike");
+    }
+    if style.code {
+        control_words.push_str(&format!("\\f{}", MONOSPACE_FONT));
+    }
+    format!("{{{} {}}}", control_words, escape_text(text))
+}
+
+//tracked changes are shown the way Word shows them, insertions underlined and deletions struck through
+fn revision_text(kind: RevisionKind, elements: &[Element]) -> String {
+    let mut text = String::new();
+    for element in elements {
+        if let Element::Text {
+            text: run,
+            size,
+            style,
+        } = element
+        {
+            let mut style = style.clone();
+            match kind {
+                RevisionKind::Insertion => style.underline = true,
+                RevisionKind::Deletion => style.strikethrough = true,
+            }
+            text.push_str(&styled_text(run, *size, &style));
+        }
+    }
+    text
+}
+
+//automatically numbered reference mark followed by the note, which word processors show at the page bottom
+fn footnote(elements: &[Element]) -> String {
+    format!(
+        "{{\\super\\chftn}}{{\\footnote\\pard\\plain{{\\super\\chftn}} {}}}",
+        escape_text(&plain_text(elements))
+    )
+}
+
 fn detect_element_in_list(
     rtf_content: &mut String,
     element: &Element,
@@ -64,7 +311,7 @@ fn detect_element_in_list(
     depth: usize,
 ) {
     match element {
-        Element::Text { text, size } => {
+        Element::Text { text, size, style } => {
             let indent = " ".repeat(depth * 4); // 4 пробела для каждого уровня вложенности
             let modified_text = if numbered {
                 let numbering = parent_indices
@@ -76,8 +323,8 @@ fn detect_element_in_list(
             } else {
                 format!("{}- {}", indent, text)
             };
-            rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, modified_text));
-            rtf_content.push_str("\\par ");
+            rtf_content.push_str(&styled_text(&modified_text, *size, style));
+            rtf_content.push_str(" \\par ");
         }
 
         Element::Header { level, text } => {
@@ -95,7 +342,8 @@ fn detect_element_in_list(
             };
             rtf_content.push_str(&format!(
                 "{{\\fs{}\\b {} \\b0}}\\par ",
-                header_size, modified_text
+                header_size,
+                escape_text(&modified_text)
             ));
         }
 
@@ -111,21 +359,11 @@ fn detect_element_in_list(
             } else {
                 format!("{}- {}", indent, title)
             };
-            rtf_content.push_str(&format!(
-                "{{\\field{{\\*\\fldinst HYPERLINK \"{}\" }}{{\\fldrslt {{\\ul\\cf1 {}}}}}}}",
-                url, modified_title
-            ));
+            rtf_content.push_str(&hyperlink(&modified_title, url));
             rtf_content.push_str("\\par ");
         }
 
         Element::Image(image) => {
-            let image_bytes = image.bytes();
-            let image_size = re_size_picture(image_bytes);
-            let image = image_bytes
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<String>();
-
             let indent = " ".repeat(depth * 4);
             let modified_image_caption = if numbered {
                 let numbering = parent_indices
@@ -140,11 +378,10 @@ fn detect_element_in_list(
 
             rtf_content.push_str(&format!("{{\\fs24 {}}}\\par ", modified_image_caption));
 
-            rtf_content.push_str(&format!(
-                "{{{{\\pict\\jpegblip\\picwgoal{}\\pichgoal{} {} }}}}",
-                image_size.output_width, image_size.output_height, image
-            ));
-            rtf_content.push_str("\\par ");
+            if let Some(picture) = picture(image) {
+                rtf_content.push_str(&picture);
+                rtf_content.push_str("\\par ");
+            }
         }
 
         Element::List { elements, numbered } => {
@@ -176,31 +413,13 @@ fn detect_element_in_list(
 
 impl TransformerTrait for Transformer {
     fn parse(document: &bytes::Bytes) -> anyhow::Result<Document> {
-        let data_str = std::str::from_utf8(document).unwrap();
-        let tokens = Lexer::scan(&data_str).unwrap();
-
-        // keeping the document in a box since it might contain huge data and also
-        // for easy manipulation
-        let mut document: Document = Document::new(vec![]);
-        // initializing header levels
-        let mut level = 1;
-        for styleblock in Parser::new(tokens).parse().unwrap().body.as_slice() {
-            if styleblock.painter.font_size >= 30 && styleblock.painter.bold == true {
-                document.add_element(Element::Header {
-                    level: level,
-                    text: styleblock.text.to_owned(),
-                });
-                level += 1
-            } else {
-                {
-                    document.add_element(Element::Paragraph {
-                        elements: vec![Element::Text {
-                            text: styleblock.text.to_owned(),
-                            size: styleblock.painter.font_size as u8,
-                        }],
-                    })
-                }
-            }
+        let data_str = std::str::from_utf8(document)?;
+        let tokens = Lexer::scan(data_str)?;
+        let page = page_dimensions(&tokens);
+
+        let mut document = Reader::new().read(&tokens);
+        if let Some(page) = page {
+            document.set_page_dimensions(page);
         }
         Ok(document)
     }
@@ -209,30 +428,103 @@ impl TransformerTrait for Transformer {
         let mut rtf_content = String::new();
         let mut parent_indices = Vec::new();
 
-        rtf_content.push_str("{\\rtf1\\ansi\\deff0"); //the standard title of an RTF document, which indicates that it is an RTF document using ANSI characters and the default font
-        for element in &document.get_all_elements() {
+        rtf_content.push_str("{\\rtf1\\ansi\\ansicpg1252\\deff0"); //the standard title of an RTF document, which indicates that it is an RTF document using ANSI characters and the default font
+        rtf_content.push_str(&format!(
+            "{{\\fonttbl{{\\f0\\froman\\fcharset0 Times New Roman;}}{{\\f{}\\fmodern\\fcharset0 Courier New;}}}}",
+            MONOSPACE_FONT
+        ));
+        // the first entry is the automatic color, then the hyperlink color
+        rtf_content.push_str("{\\colortbl;\\red5\\green99\\blue193;}");
+        rtf_content.push_str(&page_setup(document));
+
+        let page_header = document.get_page_header();
+        if !page_header.is_empty() {
+            rtf_content.push_str(&page_band("header", &page_header));
+        }
+        let page_footer = document.get_page_footer();
+        if !page_footer.is_empty() {
+            rtf_content.push_str(&page_band("footer", &page_footer));
+        }
+
+        // page header and footer bands are written to their own groups
+        let body_elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(Band::elements);
+        for element in body_elements {
             match element {
                 Element::Header { level, text } => {
                     let header_size = 30 + (level);
 
                     //formatting the string RTF
-                    rtf_content
-                        .push_str(&format!("{{\\fs{}\\b {} \\b0}}\\par ", header_size, text));
+                    rtf_content.push_str(&format!(
+                        "{{\\fs{}\\b {} \\b0}}\\par ",
+                        header_size,
+                        escape_text(text)
+                    ));
                 }
 
-                Element::Text { text, size } => {
-                    rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, text));
+                Element::Text { text, size, style } => {
+                    rtf_content.push_str(&styled_text(text, *size, style));
+                    rtf_content.push(' ');
                 }
 
                 Element::Paragraph { elements } => {
                     for elem in elements {
-                        if let Element::Text { text, size } = elem {
-                            rtf_content.push_str(&format!("{{\\fs{} {}}}", *size as i32 * 2, text));
+                        match elem {
+                            Element::Text { text, size, style } => {
+                                rtf_content.push_str(&styled_text(text, *size, style))
+                            }
+                            Element::Revision { kind, elements, .. } => {
+                                rtf_content.push_str(&revision_text(*kind, elements))
+                            }
+                            Element::Hyperlink { title, url, .. } => {
+                                rtf_content.push_str(&hyperlink(title, url))
+                            }
+                            Element::Image(image) => {
+                                if let Some(picture) = picture(image) {
+                                    rtf_content.push_str(&picture);
+                                }
+                            }
+                            Element::Footnote { elements, .. } => {
+                                rtf_content.push_str(&footnote(elements))
+                            }
+                            _ => {}
                         }
                     }
                     rtf_content.push_str("\\par ");
                 }
 
+                Element::Revision { kind, elements, .. } => {
+                    rtf_content.push_str(&revision_text(*kind, elements));
+                    rtf_content.push(' ');
+                }
+
+                Element::Footnote { elements, .. } => {
+                    rtf_content.push_str(&footnote(elements));
+                    rtf_content.push(' ');
+                }
+
+                // comments are not written
+                Element::Comment { .. } => {}
+
+                Element::CodeBlock { code, .. } => {
+                    rtf_content.push_str(&code_block(code));
+                }
+
+                Element::Blockquote { elements } => {
+                    rtf_content.push_str(&blockquote(elements, 720));
+                }
+
+                Element::ThematicBreak => {
+                    rtf_content.push_str("{\\pard\\brdrb\\brdrs\\brdrw10\\brsp20 \\par}\\pard ");
+                }
+
+                Element::PageBreak => {
+                    rtf_content.push_str("\\page ");
+                }
+
                 Element::List { elements, numbered } => {
                     if *numbered {
                         parent_indices.push(0); // Начинаем с 0 для нового списка
@@ -267,96 +559,1076 @@ impl TransformerTrait for Transformer {
                     alt: _,
                     size: _,
                 } => {
-                    rtf_content.push_str(&format!(
-                        "{{\\field{{\\*\\fldinst HYPERLINK \"{}\" }}{{\\fldrslt {{\\ul\\cf1 {}}}}}}}",
-                        url,
-                        title
-                    ));
+                    rtf_content.push_str(&hyperlink(title, url));
                     rtf_content.push_str("\\par ");
                 }
 
                 Element::Image(image) => {
-                    let image_bytes = image.bytes();
-
-                    let image_size = re_size_picture(image_bytes);
-
-                    let image = image_bytes
-                        .iter()
-                        .map(|b| format!("{:02X}", b))
-                        .collect::<String>();
-
-                    rtf_content.push_str(&format!(
-                        "{{{{\\pict\\jpegblip\\picwgoal{}\\pichgoal{} {} }}}}",
-                        image_size.output_width, image_size.output_height, image
-                    ));
-                    rtf_content.push_str("\\par ");
+                    if let Some(picture) = picture(image) {
+                        rtf_content.push_str(&picture);
+                        rtf_content.push_str("\\par ");
+                    }
                 }
 
-                Element::Table { headers, rows } => {
+                Element::Table { headers, rows, .. } => {
                     let column_widths = calculate_column_widths(headers, rows);
-                    let mut current_x = 0;
 
-                    rtf_content.push_str("\\trowd\\trgaph108\\trleft-108");
-                    for (_, width) in column_widths.iter().enumerate() {
-                        current_x += width;
-                        rtf_content.push_str("\\clbrdrt\\brdrs\\brdrw10\\clbrdrl\\brdrs\\brdrw10\\clbrdrb\\brdrs\\brdrw10\\clbrdrr\\brdrs\\brdrw10");
-                        rtf_content.push_str(&format!("\\cellx{}", current_x));
-                    }
-                    rtf_content.push_str("\\intbl");
-
-                    for header in headers {
-                        if let Element::Text { text, size } = &header.element {
-                            rtf_content.push_str(&format!(
-                                "{{\\fs{} {}}}\\cell",
-                                *size as i32 * 2,
-                                text
-                            ));
+                    if !headers.is_empty() {
+                        rtf_content.push_str(&row_definition(&column_widths, true));
+                        for header in headers {
+                            rtf_content.push_str(&cell_text(&header.elements));
+                            rtf_content.push_str("\\cell");
                         }
+                        rtf_content.push_str("\\row");
                     }
-                    rtf_content.push_str("\\row");
 
                     for row in rows {
+                        rtf_content.push_str(&row_definition(&column_widths, false));
                         for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
-                                rtf_content.push_str(&format!(
-                                    "{{\\fs{} {}}}\\cell",
-                                    *size as i32 * 2,
-                                    text
-                                ));
-                            }
+                            rtf_content.push_str(&cell_text(&cell.elements));
+                            rtf_content.push_str("\\cell");
                         }
                         rtf_content.push_str("\\row");
                     }
+                    // paragraphs after the table are out of it
+                    rtf_content.push_str("\\pard ");
                 }
+            }
+        }
+
+        rtf_content.push('}');
 
-                _other_element => {
-                    warn!("Unknown element in list: {:?}", element);
+        Ok(bytes::Bytes::from(rtf_content.into_bytes()))
+    }
+}
+
+//format the content of a table cell, a single text run keeps its formatting
+// Document formatting control words, the paper size is the one of the printed page
+fn page_setup(document: &Document) -> String {
+    let page = document.page_dimensions();
+    let mut setup = format!(
+        "\\paperw{}\\paperh{}\\margl{}\\margr{}\\margt{}\\margb{}",
+        mm_to_twips(page.page_width),
+        mm_to_twips(page.page_height),
+        mm_to_twips(page.page_margin_left),
+        mm_to_twips(page.page_margin_right),
+        mm_to_twips(page.page_margin_top),
+        mm_to_twips(page.page_margin_bottom),
+    );
+    if document.orientation == PageOrientation::Landscape {
+        setup.push_str("\\landscape");
+    }
+    setup.push(' ');
+    setup
+}
+
+// rtf-parser skips the page setup control words, they are read from the tokens
+fn page_dimensions(tokens: &[Token]) -> Option<PageDimensions> {
+    // defaults of the RTF specification
+    let mut twips = [12240, 15840, 1440, 1440, 1800, 1800];
+    let mut found = false;
+    for token in tokens {
+        let Token::ControlSymbol((ControlWord::Unknown(name), Property::Value(value))) = token
+        else {
+            continue;
+        };
+        let index = match *name {
+            "\\paperw" => 0,
+            "\\paperh" => 1,
+            "\\margt" => 2,
+            "\\margb" => 3,
+            "\\margl" => 4,
+            "\\margr" => 5,
+            _ => continue,
+        };
+        twips[index] = *value;
+        found = true;
+    }
+    let [width, height, top, bottom, left, right] = twips.map(twips_to_mm);
+    found.then_some(PageDimensions {
+        page_width: width,
+        page_height: height,
+        page_margin_top: top,
+        page_margin_bottom: bottom,
+        page_margin_left: left,
+        page_margin_right: right,
+    })
+}
+
+// characters of the bytes 0x80 to 0x9F in the Windows-1252 code page of \ansi documents
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+fn ansi_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => WINDOWS_1252[byte as usize - 0x80],
+        _ => byte as char,
+    }
+}
+
+//the lexer keeps the fallback of \uN? and the text after it in the control word
+fn unicode_word(name: &str) -> Option<(i32, &str)> {
+    let rest = name.strip_prefix("\\u")?;
+    let digits = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+        .unwrap_or(rest.len());
+    let value = rest[..digits].parse().ok()?;
+    Some((value, &rest[digits..]))
+}
+
+//level of a "Heading N" style
+fn heading_number(name: &str) -> Option<u8> {
+    let name = name.trim().trim_end_matches(';').to_lowercase();
+    let level: u8 = name.strip_prefix("heading")?.trim().parse().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+//list text like "1." or "a)" numbers the items, other symbols are bullets
+fn numbered_marker(marker: &str) -> bool {
+    let marker = marker.trim();
+    marker.chars().any(|c| c.is_ascii_digit()) || marker.ends_with(['.', ')'])
+}
+
+//arguments of a field instruction, quoted arguments may contain spaces
+fn field_arguments(instruction: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut argument = String::new();
+    let mut quoted = false;
+    for c in instruction.chars() {
+        match c {
+            '"' => {
+                if quoted || !argument.is_empty() {
+                    arguments.push(std::mem::take(&mut argument));
+                }
+                quoted = !quoted;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !argument.is_empty() {
+                    arguments.push(std::mem::take(&mut argument));
                 }
             }
+            c => argument.push(c),
         }
+    }
+    if !argument.is_empty() {
+        arguments.push(argument);
+    }
+    arguments
+}
 
-        rtf_content.push_str("}");
+fn runs_text(runs: &[Element]) -> String {
+    runs.iter()
+        .map(|run| plain_text(std::slice::from_ref(run)))
+        .collect()
+}
 
-        Ok(bytes::Bytes::from(rtf_content.into_bytes()))
+//a paragraph holding only a text, an image or a link is that element
+fn single_element(mut runs: Vec<Element>) -> Element {
+    match runs.as_slice() {
+        [Element::Text { .. } | Element::Image(_) | Element::Hyperlink { .. }] => runs.remove(0),
+        _ => Element::Paragraph { elements: runs },
     }
 }
 
-fn calculate_column_widths(headers: &Vec<TableHeader>, rows: &Vec<TableRow>) -> Vec<i32> {
+// character formatting of a group, the size is in half points like \fs
+#[derive(Debug, Clone, PartialEq)]
+struct CharacterFormat {
+    size: i32,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    font: i32,
+}
+
+impl Default for CharacterFormat {
+    fn default() -> Self {
+        CharacterFormat {
+            size: 24,
+            bold: false,
+            italic: false,
+            underline: false,
+            strike: false,
+            font: 0,
+        }
+    }
+}
+
+// what the text and control words of a group are read as
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Destination {
+    Text,
+    Skip,
+    FontTable,
+    StyleSheet,
+    // the number or bullet in front of a list item, \pntext or \listtext
+    ListText,
+    // old style list properties, \pn
+    ListFormat,
+    FieldInstruction,
+    // drawing objects, only their pictures are read
+    Shape,
+    Picture,
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    format: CharacterFormat,
+    destination: Destination,
+    // characters standing in for a \u character, \uc
+    unicode_skip: usize,
+    // number of the font or style defined by the group
+    definition: i32,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Group {
+            format: CharacterFormat::default(),
+            destination: Destination::Text,
+            unicode_skip: 1,
+            definition: 0,
+        }
+    }
+}
+
+// paragraph properties, reset by \pard
+#[derive(Debug, Clone, Default)]
+struct ParagraphFormat {
+    style: i32,
+    in_table: bool,
+    list: bool,
+    list_level: i32,
+    numbered: Option<bool>,
+    outline_level: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+struct Style {
+    name: String,
+    outline_level: Option<i32>,
+}
+
+// cell of a row definition, \cellx gives its right boundary
+#[derive(Debug, Clone, Default)]
+struct CellDefinition {
+    right: i32,
+    merge_left: bool,
+    merge_up: bool,
+}
+
+#[derive(Debug)]
+struct Row {
+    header: bool,
+    definitions: Vec<CellDefinition>,
+    cells: Vec<Vec<Element>>,
+}
+
+#[derive(Debug, Default)]
+struct Picture {
+    format: Option<&'static str>,
+    hex: String,
+    width: i32,
+    height: i32,
+    width_goal: i32,
+    height_goal: i32,
+    scale_x: i32,
+    scale_y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PageBand {
+    Header,
+    Footer,
+}
+
+// the body elements put aside while a page header or footer is read
+#[derive(Debug)]
+struct BandGroup {
+    band: PageBand,
+    depth: usize,
+    elements: Vec<Element>,
+    paragraph: ParagraphFormat,
+}
+
+#[derive(Debug)]
+struct Field {
+    // number of open groups when the field started
+    depth: usize,
+    instruction: String,
+    // first run of the field result
+    start: usize,
+}
+
+// reads the elements of a document from the tokens, rtf-parser keeps only the styled text
+#[derive(Debug, Default)]
+struct Reader {
+    groups: Vec<Group>,
+    // set by \*, the next control word names a destination that may be skipped
+    ignorable: bool,
+    unicode_pending: usize,
+    // first half of a surrogate pair written as two \u characters
+    high_surrogate: Option<u32>,
+    default_font: i32,
+    fonts: HashMap<i32, String>,
+    styles: HashMap<i32, Style>,
+    paragraph: ParagraphFormat,
+    list_marker: Option<String>,
+    runs: Vec<Element>,
+    // size of the first text of the paragraph, in half points
+    first_size: Option<i32>,
+    // open lists from the outermost: level, numbered and items
+    lists: Vec<(i32, bool, Vec<ListItem>)>,
+    cell: Vec<Element>,
+    row: Vec<Vec<Element>>,
+    header_row: bool,
+    definitions: Vec<CellDefinition>,
+    definition: CellDefinition,
+    table: Vec<Row>,
+    picture: Option<Picture>,
+    images: usize,
+    fields: Vec<Field>,
+    band: Option<BandGroup>,
+    page_header: Vec<Element>,
+    page_footer: Vec<Element>,
+    elements: Vec<Element>,
+}
+
+impl Reader {
+    fn new() -> Reader {
+        Reader {
+            groups: vec![Group::default()],
+            ..Reader::default()
+        }
+    }
+
+    fn read(mut self, tokens: &[Token]) -> Document {
+        for token in tokens {
+            match token {
+                Token::OpeningBracket => {
+                    let mut group = self.group().clone();
+                    group.definition = 0;
+                    self.groups.push(group);
+                }
+                Token::ClosingBracket => self.close_group(),
+                Token::IgnorableDestination => self.ignorable = true,
+                Token::PlainText(text) => self.text(text),
+                Token::CRLF => self.control_word(&ControlWord::Par, &Property::None),
+                Token::ControlSymbol((word, property)) => self.control_word(word, property),
+                Token::Empty => {}
+            }
+        }
+        self.end_blocks();
+        let mut document = Document::new(self.elements);
+        if !self.page_header.is_empty() {
+            document.set_page_header(self.page_header);
+        }
+        if !self.page_footer.is_empty() {
+            document.set_page_footer(self.page_footer);
+        }
+        document
+    }
+
+    //ends the open paragraph, table and lists
+    fn end_blocks(&mut self) {
+        self.end_paragraph();
+        self.flush_table();
+        self.flush_lists();
+    }
+
+    fn start_band(&mut self, band: PageBand) {
+        self.end_blocks();
+        self.band = Some(BandGroup {
+            band,
+            depth: self.groups.len(),
+            elements: std::mem::take(&mut self.elements),
+            paragraph: std::mem::take(&mut self.paragraph),
+        });
+    }
+
+    fn end_band(&mut self) {
+        self.end_blocks();
+        let Some(band) = self.band.take() else {
+            return;
+        };
+        // band paragraphs of a single run are kept as that run, like in the DOCX page bands
+        let elements = std::mem::replace(&mut self.elements, band.elements)
+            .into_iter()
+            .map(|element| match element {
+                Element::Paragraph { elements } => single_element(elements),
+                element => element,
+            });
+        self.paragraph = band.paragraph;
+        match band.band {
+            PageBand::Header => self.page_header.extend(elements),
+            PageBand::Footer => self.page_footer.extend(elements),
+        }
+    }
+
+    fn group(&self) -> &Group {
+        self.groups
+            .last()
+            .expect("the document group is never closed")
+    }
+
+    fn group_mut(&mut self) -> &mut Group {
+        self.groups
+            .last_mut()
+            .expect("the document group is never closed")
+    }
+
+    fn close_group(&mut self) {
+        if self.groups.len() == 1 {
+            return;
+        }
+        let closed = self.groups.pop().map(|group| group.destination);
+        if closed == Some(Destination::Picture) && self.group().destination != Destination::Picture
+        {
+            self.end_picture();
+        }
+        if self
+            .fields
+            .last()
+            .is_some_and(|field| field.depth > self.groups.len())
+        {
+            self.end_field();
+        }
+        if self
+            .band
+            .as_ref()
+            .is_some_and(|band| band.depth > self.groups.len())
+        {
+            self.end_band();
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let skip = std::mem::take(&mut self.unicode_pending);
+        let mut chars = text.chars();
+        // the characters after a \u character stand in for it
+        for _ in 0..skip {
+            chars.next();
+        }
+        let text = chars.as_str();
+        if !text.is_empty() {
+            self.push_str(text);
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        let definition = self.group().definition;
+        match self.group().destination {
+            Destination::Text => self.push_run(text),
+            Destination::FontTable => self.fonts.entry(definition).or_default().push_str(text),
+            Destination::StyleSheet => self
+                .styles
+                .entry(definition)
+                .or_default()
+                .name
+                .push_str(text),
+            Destination::ListText => self
+                .list_marker
+                .get_or_insert_with(String::new)
+                .push_str(text),
+            Destination::FieldInstruction => {
+                if let Some(field) = self.fields.last_mut() {
+                    field.instruction.push_str(text);
+                }
+            }
+            Destination::Picture => {
+                if let Some(picture) = &mut self.picture {
+                    picture
+                        .hex
+                        .extend(text.chars().filter(char::is_ascii_hexdigit));
+                }
+            }
+            Destination::Skip | Destination::ListFormat | Destination::Shape => {}
+        }
+    }
+
+    fn push_run(&mut self, text: &str) {
+        let format = self.group().format.clone();
+        let style = TextStyle {
+            bold: format.bold,
+            italic: format.italic,
+            underline: format.underline,
+            strikethrough: format.strike,
+            code: self
+                .fonts
+                .get(&format.font)
+                .is_some_and(|name| name.contains("Courier")),
+        };
+        let size = (format.size / 2).clamp(1, u8::MAX as i32) as u8;
+        self.first_size.get_or_insert(format.size);
+        // the result of a field starts its own run
+        let field_start = self
+            .fields
+            .last()
+            .is_some_and(|field| field.start == self.runs.len());
+        if let (
+            false,
+            Some(Element::Text {
+                text: last,
+                size: last_size,
+                style: last_style,
+            }),
+        ) = (field_start, self.runs.last_mut())
+        {
+            if *last_size == size && *last_style == style {
+                last.push_str(text);
+                return;
+            }
+        }
+        self.runs.push(Element::Text {
+            text: text.to_string(),
+            size,
+            style,
+        });
+    }
+
+    //\'hh bytes and \uN characters are both lexed as unicode control words
+    fn character(&mut self, value: i32) {
+        match value {
+            0..=255 if self.unicode_pending > 0 => self.unicode_pending -= 1,
+            0..=255 => self.push_str(&ansi_char(value as u8).to_string()),
+            _ => {
+                let code = if value < 0 { value + 65536 } else { value } as u32;
+                self.unicode_pending = self.group().unicode_skip;
+                let character = match (self.high_surrogate.take(), code) {
+                    (_, 0xD800..=0xDBFF) => {
+                        self.high_surrogate = Some(code);
+                        return;
+                    }
+                    (Some(high), 0xDC00..=0xDFFF) => {
+                        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00))
+                    }
+                    _ => char::from_u32(code),
+                };
+                if let Some(c) = character {
+                    self.push_str(&c.to_string());
+                }
+            }
+        }
+    }
+
+    fn control_word(&mut self, word: &ControlWord, property: &Property) {
+        let value = match property {
+            Property::Value(value) => *value,
+            _ => 0,
+        };
+        let destination = self.group().destination;
+        if std::mem::take(&mut self.ignorable) {
+            let ignorable = match word {
+                ControlWord::Unknown("\\fldinst") => Destination::FieldInstruction,
+                ControlWord::Unknown("\\shpinst") => Destination::Shape,
+                // Word pictures, shown instead of the \nonshppict fallback
+                ControlWord::Unknown("\\shppict") => destination,
+                ControlWord::Unknown("\\pn") => {
+                    self.paragraph.list = true;
+                    Destination::ListFormat
+                }
+                _ => Destination::Skip,
+            };
+            if destination != Destination::Skip {
+                self.group_mut().destination = ignorable;
+            }
+            return;
+        }
+        if destination == Destination::Skip {
+            return;
+        }
+
+        match word {
+            ControlWord::Unicode => return self.character(value),
+            ControlWord::UnicodeIgnoreCount => {
+                self.group_mut().unicode_skip = value.max(0) as usize;
+                return;
+            }
+            ControlWord::Unknown(name) => {
+                if let Some((character, rest)) = unicode_word(name) {
+                    self.character(character);
+                    // digits at the end of the text are lexed as the value of the word
+                    return match property {
+                        Property::Value(_) => self.text(&format!("{}{}", rest, value)),
+                        _ => self.text(rest),
+                    };
+                }
+            }
+            _ => {}
+        }
+
+        let new_destination = match word {
+            ControlWord::FontTable => Some(Destination::FontTable),
+            ControlWord::StyleSheet => Some(Destination::StyleSheet),
+            ControlWord::ColorTable | ControlWord::FileTable => Some(Destination::Skip),
+            ControlWord::Unknown(name) => match *name {
+                // the headers and footers of left and first pages are left out
+                "\\header" | "\\headerr" | "\\footer" | "\\footerr" if self.band.is_none() => {
+                    self.start_band(if name.starts_with("\\header") {
+                        PageBand::Header
+                    } else {
+                        PageBand::Footer
+                    });
+                    Some(Destination::Text)
+                }
+                "\\info"
+                | "\\listtable"
+                | "\\listoverridetable"
+                | "\\revtbl"
+                | "\\header"
+                | "\\headerl"
+                | "\\headerr"
+                | "\\headerf"
+                | "\\footer"
+                | "\\footerl"
+                | "\\footerr"
+                | "\\footerf"
+                | "\\footnote"
+                | "\\nonshppict"
+                | "\\shprslt"
+                | "\\shptxt"
+                | "\\object" => Some(Destination::Skip),
+                "\\pntext" | "\\listtext" => {
+                    self.list_marker = Some(String::new());
+                    Some(Destination::ListText)
+                }
+                "\\pn" => {
+                    self.paragraph.list = true;
+                    Some(Destination::ListFormat)
+                }
+                "\\pict" => {
+                    self.picture = Some(Picture::default());
+                    Some(Destination::Picture)
+                }
+                "\\shp" | "\\shpinst" => Some(Destination::Shape),
+                "\\fldinst" => Some(Destination::FieldInstruction),
+                "\\fldrslt" => Some(Destination::Text),
+                "\\field" => {
+                    self.fields.push(Field {
+                        depth: self.groups.len(),
+                        instruction: String::new(),
+                        start: self.runs.len(),
+                    });
+                    None
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(new_destination) = new_destination {
+            self.group_mut().destination = new_destination;
+            return;
+        }
+
+        match destination {
+            Destination::Text => self.text_control_word(word, property, value),
+            Destination::Picture => self.picture_control_word(word, value),
+            Destination::ListFormat => match word {
+                ControlWord::Unknown("\\pnlvlblt") => self.paragraph.numbered = Some(false),
+                ControlWord::Unknown("\\pnlvlbody") => self.paragraph.numbered = Some(true),
+                ControlWord::Unknown("\\pnlvl") => self.paragraph.list_level = value - 1,
+                _ => {}
+            },
+            Destination::FontTable if *word == ControlWord::FontNumber => {
+                self.group_mut().definition = value
+            }
+            Destination::StyleSheet => match word {
+                ControlWord::ParStyle => self.group_mut().definition = value,
+                ControlWord::Unknown("\\outlinelevel") => {
+                    let definition = self.group().definition;
+                    self.styles.entry(definition).or_default().outline_level = Some(value);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn text_control_word(&mut self, word: &ControlWord, property: &Property, value: i32) {
+        let default_font = self.default_font;
+        let format = &mut self.group_mut().format;
+        match word {
+            ControlWord::Bold => format.bold = property.as_bool(),
+            ControlWord::Italic => format.italic = property.as_bool(),
+            ControlWord::Underline => format.underline = property.as_bool(),
+            ControlWord::UnderlineNone => format.underline = false,
+            ControlWord::Strikethrough => format.strike = property.as_bool(),
+            ControlWord::FontSize => format.size = value,
+            ControlWord::FontNumber => format.font = value,
+            ControlWord::Plain => {
+                *format = CharacterFormat {
+                    font: default_font,
+                    ..CharacterFormat::default()
+                }
+            }
+            ControlWord::Par => self.end_paragraph(),
+            ControlWord::Pard => self.paragraph = ParagraphFormat::default(),
+            ControlWord::ParStyle => self.paragraph.style = value,
+            ControlWord::Unknown(name) => match *name {
+                "\\deff" => self.default_font = value,
+                "\\line" => self.push_run("\n"),
+                "\\tab" => self.push_run("\t"),
+                "\\emdash" => self.push_run("—"),
+                "\\endash" => self.push_run("–"),
+                "\\bullet" => self.push_run("•"),
+                "\\lquote" => self.push_run("‘"),
+                "\\rquote" => self.push_run("’"),
+                "\\ldblquote" => self.push_run("“"),
+                "\\rdblquote" => self.push_run("”"),
+                "\\chpgn" => self.push_run(PAGE_NUMBER),
+                "\\page" => {
+                    self.end_blocks();
+                    self.elements.push(Element::PageBreak);
+                }
+                "\\sect" => self.end_paragraph(),
+                "\\intbl" => self.paragraph.in_table = true,
+                "\\trowd" => {
+                    self.definitions.clear();
+                    self.definition = CellDefinition::default();
+                    self.header_row = false;
+                }
+                "\\trhdr" => self.header_row = true,
+                "\\clmrg" => self.definition.merge_left = true,
+                "\\clvmrg" => self.definition.merge_up = true,
+                "\\cellx" => {
+                    self.definition.right = value;
+                    let definition = std::mem::take(&mut self.definition);
+                    self.definitions.push(definition);
+                }
+                "\\cell" => self.end_cell(),
+                "\\row" => self.end_row(),
+                "\\ls" => self.paragraph.list = true,
+                "\\ilvl" => self.paragraph.list_level = value,
+                "\\outlinelevel" => self.paragraph.outline_level = Some(value),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn picture_control_word(&mut self, word: &ControlWord, value: i32) {
+        let (Some(picture), ControlWord::Unknown(name)) = (&mut self.picture, word) else {
+            return;
+        };
+        match *name {
+            "\\pngblip" => picture.format = Some("png"),
+            "\\jpegblip" => picture.format = Some("jpeg"),
+            "\\picw" => picture.width = value,
+            "\\pich" => picture.height = value,
+            "\\picwgoal" => picture.width_goal = value,
+            "\\pichgoal" => picture.height_goal = value,
+            "\\picscalex" => picture.scale_x = value,
+            "\\picscaley" => picture.scale_y = value,
+            _ => {}
+        }
+    }
+
+    fn end_picture(&mut self) {
+        let Some(picture) = self.picture.take() else {
+            return;
+        };
+        let bytes: Vec<u8> = (0..picture.hex.len() / 2)
+            .filter_map(|i| u8::from_str_radix(&picture.hex[i * 2..i * 2 + 2], 16).ok())
+            .collect();
+        // the signature wins over the blip, older versions of shiva wrote \jpegblip for all images
+        let image_type = match (bytes.as_slice(), picture.format) {
+            ([0x89, b'P', b'N', b'G', ..], _) => "png",
+            ([0xFF, 0xD8, ..], _) => "jpeg",
+            (_, Some(format)) => format,
+            _ => {
+                warn!("Unsupported picture format in RTF document");
+                return;
+            }
+        };
+        // sizes are in twips, or in pixels without a goal size
+        let pixels = |goal: i32, size: i32, scale: i32| {
+            let twips = if goal > 0 { goal } else { size * 15 };
+            let scale = if scale > 0 { scale } else { 100 };
+            (twips > 0).then(|| (twips * scale / 100 / 15).to_string())
+        };
+        self.images += 1;
+        self.runs.push(Element::Image(ImageData::new(
+            Bytes::from(bytes),
+            format!("image{}", self.images),
+            String::new(),
+            image_type.to_string(),
+            String::new(),
+            ImageDimension {
+                width: pixels(picture.width_goal, picture.width, picture.scale_x),
+                height: pixels(picture.height_goal, picture.height, picture.scale_y),
+            },
+        )));
+    }
+
+    fn end_field(&mut self) {
+        let Some(field) = self.fields.pop() else {
+            return;
+        };
+        let result = self.runs.split_off(field.start.min(self.runs.len()));
+        let mut arguments = field_arguments(&field.instruction).into_iter();
+        match arguments.next().map(|name| name.to_uppercase()).as_deref() {
+            Some("HYPERLINK") => {
+                let mut url = String::new();
+                let mut alt = String::new();
+                while let Some(argument) = arguments.next() {
+                    match argument.as_str() {
+                        "\\l" => url = format!("#{}", arguments.next().unwrap_or_default()),
+                        "\\o" => alt = arguments.next().unwrap_or_default(),
+                        switch if switch.starts_with('\\') => {}
+                        _ if url.is_empty() => url = argument,
+                        _ => {}
+                    }
+                }
+                let size = result
+                    .iter()
+                    .find_map(|run| match run {
+                        Element::Text { size, .. } => Some(*size),
+                        _ => None,
+                    })
+                    .unwrap_or((self.group().format.size / 2) as u8);
+                self.runs.push(Element::Hyperlink {
+                    title: runs_text(&result),
+                    url,
+                    alt,
+                    size,
+                });
+            }
+            Some("PAGE") => self.push_run(PAGE_NUMBER),
+            Some("NUMPAGES") => self.push_run(PAGE_COUNT),
+            _ => self.runs.extend(result),
+        }
+    }
+
+    fn heading_level(&self, runs: &[Element], first_size: Option<i32>) -> Option<u8> {
+        let style = self.styles.get(&self.paragraph.style);
+        let outline_level = self
+            .paragraph
+            .outline_level
+            .or(style.and_then(|style| style.outline_level));
+        if let Some(level) = outline_level {
+            // level 9 is body text
+            return (0..9).contains(&level).then(|| (level + 1).min(6) as u8);
+        }
+        if let Some(level) = style.and_then(|style| heading_number(&style.name)) {
+            return Some(level);
+        }
+        // without styles a heading is large bold text, shiva writes level N with \fs(30+N)
+        let bold = runs
+            .iter()
+            .all(|run| matches!(run, Element::Text { style, .. } if style.bold));
+        let size = first_size?;
+        (bold && size >= 30).then(|| match size {
+            31..=36 => (size - 30) as u8,
+            _ => 1,
+        })
+    }
+
+    fn end_paragraph(&mut self) {
+        let runs = std::mem::take(&mut self.runs);
+        let marker = self.list_marker.take();
+        let first_size = self.first_size.take();
+        if self.paragraph.in_table {
+            if !runs.is_empty() {
+                self.cell.push(single_element(runs));
+            }
+            return;
+        }
+        self.flush_table();
+        if runs.is_empty() {
+            return;
+        }
+
+        if let Some(level) = self.heading_level(&runs, first_size) {
+            self.flush_lists();
+            self.elements.push(Element::Header {
+                level,
+                text: runs_text(&runs).trim().to_string(),
+            });
+        } else if self.paragraph.list || marker.is_some() {
+            let numbered = self
+                .paragraph
+                .numbered
+                .unwrap_or_else(|| marker.is_some_and(|marker| numbered_marker(&marker)));
+            self.push_list_item(self.paragraph.list_level, numbered, single_element(runs));
+        } else {
+            self.flush_lists();
+            self.elements.push(match single_element(runs) {
+                element @ (Element::Image(_) | Element::Hyperlink { .. }) => element,
+                Element::Paragraph { elements } => Element::Paragraph { elements },
+                text => Element::Paragraph {
+                    elements: vec![text],
+                },
+            });
+        }
+    }
+
+    fn push_list_item(&mut self, level: i32, numbered: bool, element: Element) {
+        // deeper lists end and become an item of their parent list
+        while let Some((last_level, ..)) = self.lists.last() {
+            if *last_level <= level {
+                break;
+            }
+            let (_, list_numbered, items) = self.lists.pop().unwrap();
+            let list = ListItem::new(Element::List {
+                elements: items,
+                numbered: list_numbered,
+            });
+            match self.lists.last_mut() {
+                Some((parent_level, _, parent_items)) if *parent_level >= level => {
+                    parent_items.push(list)
+                }
+                _ => self.lists.push((level, numbered, vec![list])),
+            }
+        }
+        // a list of the other type starts a new list
+        if let [(last_level, last_numbered, _)] = self.lists.as_slice() {
+            if *last_level == level && *last_numbered != numbered {
+                self.flush_lists();
+            }
+        }
+        match self.lists.last_mut() {
+            Some((last_level, _, items)) if *last_level == level => {
+                items.push(ListItem::new(element))
+            }
+            _ => self
+                .lists
+                .push((level, numbered, vec![ListItem::new(element)])),
+        }
+    }
+
+    fn flush_lists(&mut self) {
+        while let Some((_, numbered, items)) = self.lists.pop() {
+            let list = Element::List {
+                elements: items,
+                numbered,
+            };
+            match self.lists.last_mut() {
+                Some((_, _, parent_items)) => parent_items.push(ListItem::new(list)),
+                None => self.elements.push(list),
+            }
+        }
+    }
+
+    fn end_cell(&mut self) {
+        let runs = std::mem::take(&mut self.runs);
+        self.list_marker = None;
+        self.first_size = None;
+        if !runs.is_empty() {
+            self.cell.push(single_element(runs));
+        }
+        self.flush_lists();
+        let cell = std::mem::take(&mut self.cell);
+        self.row.push(cell);
+    }
+
+    fn end_row(&mut self) {
+        let cells = std::mem::take(&mut self.row);
+        self.table.push(Row {
+            header: self.header_row,
+            definitions: self.definitions.clone(),
+            cells,
+        });
+    }
+
+    fn flush_table(&mut self) {
+        if self.table.is_empty() {
+            return;
+        }
+        let mut headers = vec![];
+        let mut rows: Vec<TableRow> = vec![];
+        // vertically merged cells still open, by column: (row, cell) index
+        let mut merged_cells: HashMap<usize, (usize, usize)> = HashMap::new();
+        for (index, row) in std::mem::take(&mut self.table).into_iter().enumerate() {
+            // only a first row repeated on each page is a header
+            let header = index == 0 && row.header;
+            let mut cells: Vec<TableCell> = vec![];
+            let mut widths: Vec<f32> = vec![];
+            let mut left = 0;
+            for (column, elements) in row.cells.into_iter().enumerate() {
+                let definition = row.definitions.get(column).cloned().unwrap_or_default();
+                let width = twips_to_mm((definition.right - left).max(0));
+                left = definition.right;
+                if let (true, Some(cell), Some(cell_width)) =
+                    (definition.merge_left, cells.last_mut(), widths.last_mut())
+                {
+                    cell.colspan += 1;
+                    cell.elements.extend(elements);
+                    *cell_width += width;
+                    continue;
+                }
+                if definition.merge_up {
+                    if let Some(&(row_index, cell_index)) = merged_cells.get(&column) {
+                        rows[row_index].cells[cell_index].rowspan += 1;
+                        continue;
+                    }
+                }
+                if !header {
+                    merged_cells.insert(column, (rows.len(), cells.len()));
+                }
+                cells.push(TableCell::with_elements(elements));
+                widths.push(width);
+            }
+            if header {
+                headers = cells
+                    .into_iter()
+                    .zip(widths)
+                    .map(|(cell, width)| TableHeader {
+                        elements: cell.elements,
+                        width,
+                        colspan: cell.colspan,
+                        rowspan: 1,
+                        alignment: ColumnAlignment::None,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow { cells });
+            }
+        }
+        self.elements.push(Element::Table {
+            name: None,
+            headers,
+            rows,
+        });
+    }
+}
+
+fn cell_text(elements: &[Element]) -> String {
+    match elements {
+        [Element::Text { text, size, style }] => styled_text(text, *size, style),
+        _ => escape_text(&plain_text(elements)),
+    }
+}
+
+//row properties with the right boundary of each cell, a header row is repeated on each page
+fn row_definition(column_widths: &[i32], header: bool) -> String {
+    let mut definition = String::from("\\trowd\\trgaph108\\trleft-108");
+    if header {
+        definition.push_str("\\trhdr");
+    }
+    let mut current_x = 0;
+    for width in column_widths {
+        current_x += width;
+        definition.push_str("\\clbrdrt\\brdrs\\brdrw10\\clbrdrl\\brdrs\\brdrw10\\clbrdrb\\brdrs\\brdrw10\\clbrdrr\\brdrs\\brdrw10");
+        definition.push_str(&format!("\\cellx{}", current_x));
+    }
+    definition.push_str("\\intbl ");
+    definition
+}
+
+fn calculate_column_widths(headers: &[TableHeader], rows: &[TableRow]) -> Vec<i32> {
     let max_width = 9700;
     let mut column_widths: Vec<i32> = headers.iter().map(|_| 0).collect();
     let mut column_content_lengths: Vec<usize> = headers.iter().map(|_| 0).collect();
 
     for (i, header) in headers.iter().enumerate() {
-        if let Element::Text { text, .. } = &header.element {
-            column_content_lengths[i] = text.len().max(column_content_lengths[i]);
-        }
+        column_content_lengths[i] = header.text().len().max(column_content_lengths[i]);
     }
 
     for row in rows {
-        for (i, cell) in row.cells.iter().enumerate() {
-            if let Element::Text { text, .. } = &cell.element {
-                column_content_lengths[i] = text.len().max(column_content_lengths[i]);
-            }
+        for (length, cell) in column_content_lengths.iter_mut().zip(row.cells.iter()) {
+            *length = cell.text().len().max(*length);
         }
     }
 
@@ -375,9 +1647,10 @@ fn calculate_column_widths(headers: &Vec<TableHeader>, rows: &Vec<TableRow>) ->
 }
 
 #[cfg(test)]
-
 mod tests {
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::{
+        disk_image_loader, ImageType, PageFormat, TransformerWithImageLoaderSaverTrait,
+    };
     use crate::markdown;
     use bytes::Bytes;
 
@@ -395,4 +1668,304 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_page_setup() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Wide".to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        }]);
+        document.set_page_format(PageFormat::A4);
+        document.set_orientation(PageOrientation::Landscape);
+        let generated = crate::rtf::Transformer::generate(&document)?;
+        let rtf = std::str::from_utf8(&generated)?;
+        assert!(rtf.contains("\\paperw16838\\paperh11906\\margl567"));
+        assert!(rtf.contains("\\landscape"));
+
+        let parsed = crate::rtf::Transformer::parse(&generated)?;
+        assert_eq!(parsed.page_format, PageFormat::A4);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_style() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                Element::Text {
+                    text: "plain ".to_string(),
+                    size: 12,
+                    style: TextStyle::default(),
+                },
+                Element::Text {
+                    text: "bold italic".to_string(),
+                    size: 12,
+                    style: TextStyle {
+                        bold: true,
+                        italic: true,
+                        ..TextStyle::default()
+                    },
+                },
+                Element::Text {
+                    text: " code".to_string(),
+                    size: 12,
+                    style: TextStyle::code(),
+                },
+            ],
+        }]);
+        let generated = crate::rtf::Transformer::generate(&document)?;
+        let parsed = crate::rtf::Transformer::parse(&generated)?;
+
+        let styles: Vec<(String, TextStyle)> = parsed
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Paragraph { elements } => Some(elements.clone()),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|element| match element {
+                Element::Text { text, style, .. } => Some((text.trim().to_string(), style)),
+                _ => None,
+            })
+            .collect();
+        assert!(styles.contains(&("plain".to_string(), TextStyle::default())));
+        assert!(styles.contains(&(
+            "bold italic".to_string(),
+            TextStyle {
+                bold: true,
+                italic: true,
+                ..TextStyle::default()
+            }
+        )));
+        assert!(styles.contains(&("code".to_string(), TextStyle::code())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_structure() -> anyhow::Result<()> {
+        let png = std::fs::read("test/data/small.png")?;
+        let hex: String = png.iter().map(|b| format!("{:02x}", b)).collect();
+        let rtf = format!(
+            r#"{{\rtf1\ansi\deff0{{\fonttbl{{\f0 Arial;}}{{\f1\fmodern Courier New;}}}}
+{{\stylesheet{{\s0 Normal;}}{{\s1\b\fs40 heading 1;}}}}
+\pard\s1\b\fs40 Caf\'e9s cost \u8364?5\par
+\pard\plain\fs24 Price {{\b 10}}\par
+\pard\plain{{\pntext\f0\'b7\tab}}{{\*\pn\pnlvlblt{{\pntxtb\'b7}}}}\fi-360\li720 One\par
+{{\pntext\f0\'b7\tab}}Two\par
+\pard\plain{{\listtext 1.\tab}}\ls2\ilvl1 Nested\par
+\pard\plain\trowd\trhdr\cellx2000\clmgf\cellx4000\clmrg\cellx6000\intbl Name\cell Total\cell\cell\row
+\trowd\clvmgf\cellx2000\cellx4000\cellx6000\intbl A\cell 1\cell {{\f1 x}}\cell\row
+\trowd\clvmrg\cellx2000\cellx4000\cellx6000\intbl\cell 2\cell 3\cell\row
+\pard\plain See {{\field{{\*\fldinst {{HYPERLINK "https://example.com" }}}}{{\fldrslt {{\ul example}}}}}}\par
+\pard{{\*\shppict{{\pict\pngblip\picwgoal1500\pichgoal750 {}}}}}{{\nonshppict{{\pict\wmetafile8 00}}}}\par
+}}"#,
+            hex
+        );
+        let parsed = crate::rtf::Transformer::parse(&Bytes::from(rtf))?;
+        let elements = parsed.get_all_elements();
+        let text = |text: &str, style: TextStyle| Element::Text {
+            text: text.to_string(),
+            size: 12,
+            style,
+        };
+        let plain = |value: &str| text(value, TextStyle::default());
+
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Cafés cost €5".to_string()
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Paragraph {
+                elements: vec![
+                    plain("Price "),
+                    text(
+                        "10",
+                        TextStyle {
+                            bold: true,
+                            ..TextStyle::default()
+                        }
+                    )
+                ]
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::List {
+                elements: vec![
+                    ListItem::new(plain("One")),
+                    ListItem::new(plain("Two")),
+                    ListItem::new(Element::List {
+                        elements: vec![ListItem::new(plain("Nested"))],
+                        numbered: true
+                    }),
+                ],
+                numbered: false
+            }
+        );
+
+        let Element::Table { headers, rows, .. } = elements[3] else {
+            panic!("expected a table, got {:?}", elements[3]);
+        };
+        let header_texts: Vec<(String, usize)> = headers
+            .iter()
+            .map(|header| (header.text(), header.colspan))
+            .collect();
+        assert_eq!(
+            header_texts,
+            vec![("Name".to_string(), 1), ("Total".to_string(), 2)]
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].cells[0].rowspan, 2);
+        assert_eq!(
+            rows[0].cells[2].elements,
+            vec![text("x", TextStyle::code())]
+        );
+        let second_row: Vec<String> = rows[1].cells.iter().map(|cell| cell.text()).collect();
+        assert_eq!(second_row, vec!["2", "3"]);
+
+        assert_eq!(
+            elements[4],
+            &Element::Paragraph {
+                elements: vec![
+                    plain("See "),
+                    Element::Hyperlink {
+                        title: "example".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 12
+                    }
+                ]
+            }
+        );
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.bytes().as_ref(), png.as_slice());
+        assert_eq!(image.image_type(), &ImageType::Png);
+        assert_eq!(image.size().width.as_deref(), Some("100"));
+        assert_eq!(image.size().height.as_deref(), Some("50"));
+        assert_eq!(elements.len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_document() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.rtf")?;
+        let parsed = crate::rtf::Transformer::parse(&Bytes::from(document))?;
+        let elements = parsed.get_all_elements();
+
+        let headers: Vec<(u8, &str)> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Header { level, text } => Some((*level, text.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(headers, vec![(1, "Header 1."), (2, "Header 1.2.")]);
+
+        let table_rows = elements.iter().find_map(|element| match element {
+            Element::Table { rows, .. } => Some(rows.len()),
+            _ => None,
+        });
+        assert_eq!(table_rows, Some(4));
+
+        let lists: Vec<(bool, usize)> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::List { elements, numbered } => Some((*numbered, elements.len())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lists, vec![(false, 3), (true, 3)]);
+
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Paragraph { elements } if elements.iter().any(|run| matches!(
+                run,
+                Element::Hyperlink { url, .. } if url == "https://google.com/"
+            ))
+        )));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Image(image) if *image.image_type() == ImageType::Png
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escaping_images_and_bands() -> anyhow::Result<()> {
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        };
+        let content = "Привет, {мир} мир C:\\temp\tcafé 😀 ";
+        let mut document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![text(content)],
+            },
+            Element::Image(ImageData::new(
+                png.clone(),
+                "small".to_string(),
+                String::new(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+            Element::Hyperlink {
+                title: "Ссылка".to_string(),
+                url: "https://example.com/".to_string(),
+                alt: String::new(),
+                size: 12,
+            },
+        ]);
+        document.set_page_header(vec![text("Report")]);
+        document.set_page_footer(vec![text(&format!(
+            "Page {} of {}",
+            PAGE_NUMBER, PAGE_COUNT
+        ))]);
+
+        let generated = crate::rtf::Transformer::generate(&document)?;
+        assert!(generated.is_ascii());
+        let rtf = std::str::from_utf8(&generated)?;
+        assert!(rtf.contains("\\u1055\\'3f\\u1088\\'3f"));
+        assert!(rtf.contains("\\u-10179\\'3f\\u-8704\\'3f"));
+        assert!(rtf.contains("\\{"));
+        assert!(rtf.contains("\\pngblip"));
+        assert!(!rtf.contains("\\jpegblip"));
+        assert!(rtf.contains("{\\colortbl;"));
+        assert!(rtf.contains("{\\header\\pard\\plain "));
+        assert!(rtf.contains("{\\field{\\*\\fldinst NUMPAGES}"));
+
+        let parsed = crate::rtf::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_page_header(), vec![&text("Report")]);
+        assert_eq!(
+            parsed.get_page_footer(),
+            vec![&text("Page {page} of {pages}")]
+        );
+        let body = parsed.get_elements_by_band(&Band::Detail(vec![]));
+        assert_eq!(
+            body[0],
+            &Element::Paragraph {
+                elements: vec![text(content)]
+            }
+        );
+        let Element::Image(image) = body[1] else {
+            panic!("expected an image, got {:?}", body[1]);
+        };
+        assert_eq!(image.bytes(), &png);
+        assert_eq!(image.image_type(), &ImageType::Png);
+        assert!(matches!(
+            body[2],
+            Element::Hyperlink { title, url, .. } if title == "Ссылка" && url == "https://example.com/"
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,180 @@
+use crate::core::{Document, TransformerTrait};
+pub use crate::typst::FontOptions;
+
+use anyhow;
+use bytes::Bytes;
+use std::io::{Cursor, Write};
+use typst::layout::Frame;
+
+/// Renders the pages of a document as SVG images.
+///
+/// A single page is returned as it is, several pages are packed into a ZIP archive
+/// with one `page-N.svg` file per page.
+pub struct Transformer;
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("Parsing SVG images is not supported"))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &ImageOptions::default())
+    }
+}
+
+/// Options of page images.
+///
+/// All pages are rendered unless a page is selected, PNG images have 2 pixels per point by default.
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    pub(crate) fonts: FontOptions,
+    pub(crate) page: Option<usize>,
+    pub(crate) pixels_per_point: f32,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            fonts: FontOptions::default(),
+            page: None,
+            pixels_per_point: 2.0,
+        }
+    }
+}
+
+impl ImageOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_fonts(mut self, fonts: FontOptions) -> Self {
+        self.fonts = fonts;
+        self
+    }
+
+    /// Renders only the page with this number, counting from 1
+    pub fn with_page(mut self, page: usize) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    /// Resolution of PNG images, 1 pixel per point is 72 dpi
+    pub fn with_pixels_per_point(mut self, pixels_per_point: f32) -> Self {
+        self.pixels_per_point = pixels_per_point;
+        self
+    }
+}
+
+impl Transformer {
+    pub fn generate_with_options(
+        document: &Document,
+        options: &ImageOptions,
+    ) -> anyhow::Result<Bytes> {
+        let pages = Transformer::generate_pages(document, options)?;
+        page_archive(pages, "svg")
+    }
+
+    /// One SVG image per page
+    pub fn generate_pages(
+        document: &Document,
+        options: &ImageOptions,
+    ) -> anyhow::Result<Vec<Bytes>> {
+        Ok(layout_pages(document, options)?
+            .iter()
+            .map(|frame| Bytes::from(typst_svg::svg(frame)))
+            .collect())
+    }
+}
+
+// Frames of the selected pages
+pub(crate) fn layout_pages(
+    document: &Document,
+    options: &ImageOptions,
+) -> anyhow::Result<Vec<Frame>> {
+    let document = crate::typst::compile_document(document, &options.fonts, false)?;
+    let mut pages: Vec<Frame> = document.pages.into_iter().map(|page| page.frame).collect();
+    if let Some(number) = options.page {
+        if number == 0 || number > pages.len() {
+            return Err(anyhow::anyhow!(
+                "Page {} does not exist, the document has {} pages",
+                number,
+                pages.len()
+            ));
+        }
+        pages = vec![pages.swap_remove(number - 1)];
+    }
+    Ok(pages)
+}
+
+// The only page as it is, several pages as ZIP archive
+pub(crate) fn page_archive(mut pages: Vec<Bytes>, extension: &str) -> anyhow::Result<Bytes> {
+    if pages.len() == 1 {
+        return Ok(pages.remove(0));
+    }
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (index, page) in pages.iter().enumerate() {
+        archive.start_file(format!("page-{}.{extension}", index + 1), options)?;
+        archive.write_all(page)?;
+    }
+    let archive = archive.finish()?;
+    Ok(Bytes::from(archive.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::svg::{ImageOptions, Transformer};
+    use std::io::Read;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Quarterly report".to_string(),
+            },
+            Element::Text {
+                text: "Sales grew in every region.".to_string(),
+                size: 11,
+                style: TextStyle::default(),
+            },
+        ]);
+        let svg = document.generate(DocumentType::SVG)?;
+        let svg = String::from_utf8(svg.to_vec())?;
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(r#"width="595.27"#));
+        assert!(svg.contains("<use xlink:href=\"#"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pages() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 11,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![text("First"), Element::PageBreak, text("Second")]);
+        let pages = Transformer::generate_pages(&document, &ImageOptions::new())?;
+        assert_eq!(pages.len(), 2);
+
+        // several pages come as ZIP archive
+        let archive = document.generate(DocumentType::SVG)?;
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(archive.to_vec()))?;
+        assert_eq!(archive.len(), 2);
+        let mut second = String::new();
+        archive.by_name("page-2.svg")?.read_to_string(&mut second)?;
+        assert_eq!(second.as_bytes(), &pages[1][..]);
+
+        let options = ImageOptions::new().with_page(2);
+        let page = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(page, pages[1]);
+        assert!(
+            Transformer::generate_with_options(&document, &ImageOptions::new().with_page(3))
+                .is_err()
+        );
+        Ok(())
+    }
+}
@@ -20,10 +20,12 @@ impl TransformerTrait for Transformer {
             elements.push(Element::Text {
                 text: line,
                 size: 8,
+                style: TextStyle::default(),
             });
             elements.push(Element::Text {
                 text: "\n".to_string(),
                 size: 8,
+                style: TextStyle::default(),
             });
             i += 1;
         }
@@ -70,6 +72,11 @@ impl TransformerTrait for Transformer {
                 markdown.push_str(&"  ".repeat(list_depth - 1));
                 if let Element::Text { .. } = element.element {
                     markdown.push_str(&prefix);
+                    match element.checked {
+                        Some(true) => markdown.push_str("[x] "),
+                        Some(false) => markdown.push_str("[ ] "),
+                        None => {}
+                    }
                 }
                 generate_element(
                     &element.element,
@@ -128,12 +135,67 @@ impl TransformerTrait for Transformer {
                         markdown.push('\n');
                     }
                 }
-                Element::Text { text, size: _ } => {
+                Element::Text { text, size: _, .. } => {
                     markdown.push_str(text);
                     if !text.ends_with(' ') {
                         markdown.push(' ');
                     }
                 }
+                Element::Blockquote { elements } => {
+                    let mut quote = String::new();
+                    for child in elements {
+                        generate_element(
+                            child,
+                            &mut quote,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                    for line in quote.trim_end().lines() {
+                        markdown.push_str(format!("> {line}").trim_end());
+                        markdown.push('\n');
+                    }
+                    markdown.push('\n');
+                }
+                Element::ThematicBreak => {
+                    markdown.push_str(&"-".repeat(40));
+                    markdown.push_str("\n\n");
+                }
+                // form feed is the plain text page break
+                Element::PageBreak => markdown.push('\u{c}'),
+                // the text reads as if all changes were accepted, comments are left out
+                Element::Revision {
+                    kind: RevisionKind::Insertion,
+                    elements,
+                    ..
+                } => {
+                    for child in elements {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                }
+                Element::Revision { .. } | Element::Comment { .. } => {}
+                // the note is kept in brackets where it is referenced
+                Element::Footnote { elements, .. } => {
+                    markdown.push_str(&format!("[{}] ", plain_text(elements).replace('\n', " ")));
+                }
+                Element::CodeBlock { code, .. } => {
+                    markdown.push_str(code);
+                    if !code.ends_with('\n') {
+                        markdown.push('\n');
+                    }
+                    markdown.push('\n');
+                }
                 Element::Hyperlink {
                     title, url, alt, ..
                 } => {
@@ -154,33 +216,30 @@ impl TransformerTrait for Transformer {
                     images.insert(image_path.to_string(), image.bytes().clone());
                     *image_num += 1;
                 }
-                Table { headers, rows } => {
+                Table { headers, rows, .. } => {
                     let mut max_lengths: Vec<usize> = Vec::new();
 
+                    // the grid is plain text, cells are kept on a single line
+                    let cell_text = |elements: &[Element]| plain_text(elements).replace('\n', " ");
                     for header in headers {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            max_lengths.push(text.len());
-                        }
+                        max_lengths.push(cell_text(&header.elements).len());
                     }
                     for row in rows {
                         for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                if cell_index < max_lengths.len() {
-                                    max_lengths[cell_index] =
-                                        max_lengths[cell_index].max(text.len());
-                                }
+                            if cell_index < max_lengths.len() {
+                                max_lengths[cell_index] =
+                                    max_lengths[cell_index].max(cell_text(&cell.elements).len());
                             }
                         }
                     }
 
                     for (index, header) in headers.iter().enumerate() {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            let padding = max_lengths[index] - text.len();
-                            markdown.push_str("| ");
-                            markdown.push_str(text.as_str());
-                            markdown.push_str(&" ".repeat(padding));
-                            markdown.push(' ');
-                        }
+                        let text = cell_text(&header.elements);
+                        let padding = max_lengths[index] - text.len();
+                        markdown.push_str("| ");
+                        markdown.push_str(text.as_str());
+                        markdown.push_str(&" ".repeat(padding));
+                        markdown.push(' ');
                     }
                     markdown.push_str("|\n");
 
@@ -192,13 +251,12 @@ impl TransformerTrait for Transformer {
 
                     for row in rows {
                         for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                let padding = max_lengths[cell_index] - text.len();
-                                markdown.push_str("| ");
-                                markdown.push_str(text.as_str());
-                                markdown.push_str(&" ".repeat(padding));
-                                markdown.push(' ');
-                            }
+                            let text = cell_text(&cell.elements);
+                            let padding = max_lengths[cell_index] - text.len();
+                            markdown.push_str("| ");
+                            markdown.push_str(text.as_str());
+                            markdown.push_str(&" ".repeat(padding));
+                            markdown.push(' ');
                         }
                         markdown.push_str("|\n");
                     }
@@ -1,149 +1,327 @@
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
+use crate::core::Element::{
+    Blockquote, CodeBlock, Comment, Footnote, Header, Hyperlink, Image, List, PageBreak, Paragraph,
+    Revision, Table, Text, ThematicBreak,
+};
 
-use crate::core::{Document, Element, ListItem, TableHeader, TableRow, TransformerTrait};
+use crate::core::{
+    disk_image_loader, disk_image_saver, plain_text, table_columns, ColumnAlignment, Document,
+    DocumentMetadata, Element, ImageData, ImageDimension, ImageType, ListItem, PageDimensions,
+    PageFormat, PageOrientation, RevisionKind, TableCell, TableHeader, TableRow, TextStyle,
+    TransformerTrait, TransformerWithImageLoaderSaverTrait,
+};
 use anyhow;
 use bytes::Bytes;
 use comemo::Prehashed;
-use log::warn;
-use std::path::Path;
-use std::{collections::HashMap, io::Cursor};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 use time::{OffsetDateTime, UtcOffset};
 
 use typst::{
     diag::{FileError, FileResult},
     foundations::Datetime,
-    syntax::{FileId, Source},
+    syntax::{ast, ast::AstNode, ast::Expr, FileId, Source},
     text::{Font, FontBook},
     Library, World,
 };
 
 type TypstString = String;
 
-pub struct ShivaWorld {
-    fonts: Vec<Font>,
-    book: Prehashed<FontBook>,
-    library: Prehashed<Library>,
-    source: Source,
-    img_map: HashMap<String, typst::foundations::Bytes>,
+/// Where the fonts for laying out PDF output come from.
+///
+/// By default only the fonts compiled in with the `embed-fonts` feature are used,
+/// nothing is downloaded or read from disk.
+/// Fonts are loaded once per process for each distinct set of options, see [`clear_font_cache`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FontOptions {
+    paths: Vec<PathBuf>,
+    data: Vec<FontData>,
+    system_fonts: bool,
+    embedded_fonts: bool,
 }
 
-impl ShivaWorld {
-    pub fn new(source: String, img_map: HashMap<String, typst::foundations::Bytes>) -> Self {
-        let source = Source::detached(source);
-
-        let folder = "fonts";
-
-        // Check if the "fonts" folder exists
-        if !std::path::Path::new(folder).exists() {
-            // Create the "fonts" folder
-            std::fs::create_dir_all(folder).expect("Failed to create folder");
-
-            // Download fonts
-            let font_info = vec![
-                ("DejaVuSansMono-Bold.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/DejaVuSansMono-Bold.ttf"),
-                ("DejaVuSansMono.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/DejaVuSansMono.ttf"),
-                ("FiraMath-Regular.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/FiraMath-Regular.otf"),
-                ("IBMPlexSerif-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/IBMPlexSerif-Regular.ttf"),
-                ("InriaSerif-BoldItalic.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/InriaSerif-BoldItalic.ttf"),
-                ("InriaSerif-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/InriaSerif-Regular.ttf"),
-                ("LinLibertine_R.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/LinLibertine_R.ttf"),
-                ("LinLibertine_RB.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/LinLibertine_RB.ttf"),
-                ("LinLibertine_RBI.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/LinLibertine_RBI.ttf"),
-                ("LinLibertine_RI.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/LinLibertine_RI.ttf"),
-                ("Nerd.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/Nerd.ttf"),
-                ("NewCM10-Bold.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NewCM10-Bold.otf"),
-                ("NewCM10-Regular.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NewCM10-Regular.otf"),
-                ("NewCMMath-Book.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NewCMMath-Book.otf"),
-                ("NewCMMath-Regular.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NewCMMath-Regular.otf"),
-                ("NotoColorEmoji.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoColorEmoji.ttf"),
-                ("NotoSansArabic-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoSansArabic-Regular.ttf"),
-                ("NotoSansSymbols2-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoSansSymbols2-Regular.ttf"),
-                ("NotoSerifCJKsc-Regular.otf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoSerifCJKsc-Regular.otf"),
-                ("NotoSerifHebrew-Bold.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoSerifHebrew-Bold.ttf"),
-                ("NotoSerifHebrew-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/NotoSerifHebrew-Regular.ttf"),
-                ("PTSans-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/PTSans-Regular.ttf"),
-                ("Roboto-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/Roboto-Regular.ttf"),
-                ("TwitterColorEmoji.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/TwitterColorEmoji.ttf"),
-                ("Ubuntu-Regular.ttf", "https://github.com/igumnoff/shiva/raw/main/lib/fonts/Ubuntu-Regular.ttf"),
-            ];
-
-            for (filename, url) in font_info {
-                download_font(url, folder, filename);
-            }
-        }
-
-        let fonts = std::fs::read_dir(folder)
-            .unwrap()
-            .map(Result::unwrap)
-            .flat_map(|entry| {
-                let path = entry.path();
-                let bytes = std::fs::read(&path).unwrap();
-                let buffer = typst::foundations::Bytes::from(bytes);
-                let face_count = ttf_parser::fonts_in_collection(&buffer).unwrap_or(1);
-                (0..face_count).map(move |face| {
-                    Font::new(buffer.clone(), face).unwrap_or_else(|| {
-                        panic!("failed to load font from {path:?} (face index {face})");
-                    })
-                })
-            })
-            .collect::<Vec<Font>>();
+impl Default for FontOptions {
+    fn default() -> Self {
+        FontOptions {
+            paths: vec![],
+            data: vec![],
+            system_fonts: false,
+            embedded_fonts: cfg!(feature = "embed-fonts"),
+        }
+    }
+}
 
-        Self {
-            book: Prehashed::new(FontBook::from_fonts(&fonts)),
-            fonts,
-            library: Prehashed::new(Library::default()),
-            source,
-            img_map,
+impl FontOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a font file, or a directory that is searched recursively
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Adds the contents of a font file or font collection
+    pub fn with_data(mut self, data: Bytes) -> Self {
+        self.data.push(FontData {
+            hash: typst::util::hash128(&data),
+            data,
+        });
+        self
+    }
+
+    /// Searches the fonts installed on the system, requires the `system-fonts` feature
+    pub fn with_system_fonts(mut self, enabled: bool) -> Self {
+        self.system_fonts = enabled;
+        self
+    }
+
+    /// Uses the fonts of the `embed-fonts` feature
+    pub fn with_embedded_fonts(mut self, enabled: bool) -> Self {
+        self.embedded_fonts = enabled;
+        self
+    }
+
+    // Fonts of these options, loaded on first use and shared afterwards
+    fn cached(&self) -> anyhow::Result<Arc<FontStore>> {
+        let cache = FONT_CACHE.get_or_init(Default::default);
+        // a panic while loading leaves no entry behind, so the map is still consistent
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(store) = cache.get(self) {
+            return Ok(store.clone());
+        }
+        let (book, slots) = self.load()?;
+        let store = Arc::new(FontStore {
+            book: Prehashed::new(book),
+            slots,
+        });
+        cache.insert(self.clone(), store.clone());
+        Ok(store)
+    }
+
+    fn load(&self) -> anyhow::Result<(FontBook, Vec<FontSlot>)> {
+        let mut book = FontBook::new();
+        let mut slots = vec![];
+        let mut add = |font: Font| {
+            book.push(font.info().clone());
+            slots.push(FontSlot::loaded(font));
+        };
+
+        if self.embedded_fonts {
+            #[cfg(feature = "embed-fonts")]
+            for data in typst_assets::fonts() {
+                Font::iter(typst::foundations::Bytes::from_static(data)).for_each(&mut add);
+            }
+            #[cfg(not(feature = "embed-fonts"))]
+            return Err(anyhow::anyhow!(
+                "Embedded fonts require the `embed-fonts` feature"
+            ));
+        }
+        for FontData { data, .. } in &self.data {
+            let fonts: Vec<Font> = Font::iter(data.to_vec().into()).collect();
+            if fonts.is_empty() {
+                return Err(anyhow::anyhow!("Font data is not a supported font"));
+            }
+            fonts.into_iter().for_each(&mut add);
+        }
+        for path in &self.paths {
+            for file in font_files(path)? {
+                let data = std::fs::read(&file)
+                    .map_err(|e| anyhow::anyhow!("Can't read font {}: {e}", file.display()))?;
+                Font::iter(data.into()).for_each(&mut add);
+            }
+        }
+
+        if self.system_fonts {
+            #[cfg(feature = "system-fonts")]
+            {
+                // system fonts are only read once a document uses them
+                let mut database = fontdb::Database::new();
+                database.load_system_fonts();
+                for face in database.faces() {
+                    let path = match &face.source {
+                        fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => path,
+                        fontdb::Source::Binary(_) => continue,
+                    };
+                    let info = database
+                        .with_face_data(face.id, typst::text::FontInfo::new)
+                        .flatten();
+                    if let Some(info) = info {
+                        book.push(info);
+                        slots.push(FontSlot::lazy(path.clone(), face.index));
+                    }
+                }
+            }
+            #[cfg(not(feature = "system-fonts"))]
+            return Err(anyhow::anyhow!(
+                "System fonts require the `system-fonts` feature"
+            ));
+        }
+
+        if slots.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No fonts available for PDF generation, enable the `embed-fonts` feature \
+                 or add fonts with `FontOptions`"
+            ));
         }
+        Ok((book, slots))
+    }
+}
+
+/// Font data identified by a hash of its contents, computed once when it is added,
+/// so looking up the cached fonts does not compare the data byte for byte
+#[derive(Debug, Clone)]
+struct FontData {
+    data: Bytes,
+    hash: u128,
+}
+
+impl PartialEq for FontData {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
     }
 }
 
-#[cfg(target_arch = "wasm32")]
-fn download_font(url: &str, folder: &str, filename: &str) {
-    use log::info;
+impl Eq for FontData {}
+
+impl std::hash::Hash for FontData {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+// Font files of a path, directories are searched recursively
+fn font_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        if !path.exists() {
+            return Err(anyhow::anyhow!(
+                "Font path {} does not exist",
+                path.display()
+            ));
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    // the order of the fonts decides which face wins between equal candidates
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            files.extend(font_files(&entry)?);
+        } else if entry.extension().is_some_and(|extension| {
+            let extension = extension.to_string_lossy().to_lowercase();
+            matches!(extension.as_str(), "ttf" | "otf" | "ttc" | "otc")
+        }) {
+            files.push(entry);
+        }
+    }
+    Ok(files)
+}
 
-    let font_path = Path::new(folder).join(filename);
+static FONT_CACHE: OnceLock<Mutex<HashMap<FontOptions, Arc<FontStore>>>> = OnceLock::new();
 
-    info!("Downloading font file {}...", font_path.display());
+static LIBRARY: OnceLock<Prehashed<Library>> = OnceLock::new();
 
-    let request = ehttp::Request::get(url);
-    ehttp::fetch(request, move |result: ehttp::Result<ehttp::Response>| {
-        let mut reader = Cursor::new(result.unwrap().bytes);
-        let f = std::fs::File::create(&font_path).unwrap();
-        let mut writer = std::io::BufWriter::new(f);
+/// Drops the cached fonts, so fonts changed on disk are read again by the next generation
+pub fn clear_font_cache() {
+    if let Some(cache) = FONT_CACHE.get() {
+        cache.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
 
-        let _bytes_io_count = std::io::copy(&mut reader, &mut writer).unwrap();
+// Generations a memoized typst layout survives without being reused, like typst-cli in watch mode
+const LAYOUT_CACHE_AGE: usize = 10;
 
-        info!("Font file {} downloaded successfully!", font_path.display());
-    });
+/// Drops the memoized typst layouts that were not reused by the last `max_age` generations.
+///
+/// Every generation already drops those older than 10 generations, a memory constrained
+/// embedder can drop more, e.g. all of them with `evict_layout_cache(0)`.
+/// The memoization is shared by everything in the process that uses typst.
+pub fn evict_layout_cache(max_age: usize) {
+    comemo::evict(max_age);
+}
+
+struct FontStore {
+    book: Prehashed<FontBook>,
+    slots: Vec<FontSlot>,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn download_font(url: &str, folder: &str, filename: &str) {
-    use log::info;
+/// A font that is loaded on first use
+struct FontSlot {
+    path: Option<PathBuf>,
+    index: u32,
+    font: OnceLock<Option<Font>>,
+}
 
-    let font_path = Path::new(folder).join(filename);
+impl FontSlot {
+    fn loaded(font: Font) -> Self {
+        FontSlot {
+            path: None,
+            index: font.index(),
+            font: OnceLock::from(Some(font)),
+        }
+    }
+
+    #[cfg(feature = "system-fonts")]
+    fn lazy(path: PathBuf, index: u32) -> Self {
+        FontSlot {
+            path: Some(path),
+            index,
+            font: OnceLock::new(),
+        }
+    }
 
-    info!("Downloading font file {}...", font_path.display());
+    fn get(&self) -> Option<Font> {
+        self.font
+            .get_or_init(|| {
+                let data = std::fs::read(self.path.as_ref()?).ok()?;
+                Font::new(data.into(), self.index)
+            })
+            .clone()
+    }
+}
 
-    let request = ehttp::Request::get(url);
-    let response = ehttp::fetch_blocking(&request);
-    let mut reader = Cursor::new(response.unwrap().bytes);
-    let f = std::fs::File::create(&font_path).unwrap();
-    let mut writer = std::io::BufWriter::new(f);
+pub struct ShivaWorld {
+    fonts: Arc<FontStore>,
+    library: &'static Prehashed<Library>,
+    source: Source,
+    img_map: HashMap<String, typst::foundations::Bytes>,
+}
 
-    let _bytes_io_count = std::io::copy(&mut reader, &mut writer).unwrap();
+impl ShivaWorld {
+    pub fn new(
+        source: String,
+        img_map: HashMap<String, typst::foundations::Bytes>,
+    ) -> anyhow::Result<Self> {
+        Self::with_fonts(source, img_map, &FontOptions::default())
+    }
 
-    info!("Font file {} downloaded successfully!", font_path.display());
+    pub fn with_fonts(
+        source: String,
+        img_map: HashMap<String, typst::foundations::Bytes>,
+        fonts: &FontOptions,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            fonts: fonts.cached()?,
+            library: LIBRARY.get_or_init(|| Prehashed::new(Library::default())),
+            source: Source::detached(source),
+            img_map,
+        })
+    }
 }
 
 impl World for ShivaWorld {
     fn book(&self) -> &Prehashed<FontBook> {
-        &self.book
+        &self.fonts.book
     }
 
     fn library(&self) -> &Prehashed<Library> {
-        &self.library
+        self.library
     }
 
     fn main(&self) -> Source {
@@ -155,15 +333,21 @@ impl World for ShivaWorld {
     }
 
     fn font(&self, id: usize) -> Option<Font> {
-        self.fonts.get(id).cloned()
+        self.fonts.slots.get(id)?.get()
     }
 
     // need to think how to implement path and file extraction
     fn file(&self, id: FileId) -> Result<typst::foundations::Bytes, FileError> {
         let path = id.vpath();
 
-        let key = path.as_rootless_path().to_str().unwrap();
-        let img = self.img_map.get(key).unwrap();
+        let key = path
+            .as_rootless_path()
+            .to_str()
+            .ok_or(FileError::InvalidUtf8)?;
+        let img = self
+            .img_map
+            .get(key)
+            .ok_or_else(|| FileError::NotFound(path.as_rootless_path().to_path_buf()))?;
 
         Ok(img.clone())
     }
@@ -180,26 +364,880 @@ impl World for ShivaWorld {
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    #[allow(unused)]
     fn parse(document: &bytes::Bytes) -> anyhow::Result<Document> {
-        todo!()
+        Transformer::parse_with_loader(document, disk_image_loader("."))
     }
 
     fn generate(document: &Document) -> anyhow::Result<bytes::Bytes> {
-        let (text, _) = generate_document(document)?;
-        let bytes = Bytes::from(text);
-        Ok(bytes)
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let text = std::str::from_utf8(document)?;
+        let root = typst::syntax::parse(text);
+        if let Some(error) = root.errors().first() {
+            return Err(anyhow::anyhow!("Typst syntax error: {}", error.message));
+        }
+        let markup: ast::Markup = root
+            .cast()
+            .ok_or_else(|| anyhow::anyhow!("Typst source is not markup"))?;
+
+        let mut parser = SourceParser {
+            image_loader: &image_loader,
+            metadata: DocumentMetadata::default(),
+            page_header: vec![],
+            page_footer: vec![],
+            page: None,
+        };
+        let elements = parser.blocks(markup)?;
+
+        let mut document = Document::new(elements);
+        document.metadata = parser.metadata;
+        if !parser.page_header.is_empty() {
+            document.set_page_header(parser.page_header);
+        }
+        if !parser.page_footer.is_empty() {
+            document.set_page_footer(parser.page_footer);
+        }
+        if let Some(page) = parser.page {
+            document.set_page_dimensions(page);
+        }
+        Ok(document)
+    }
+
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let (text, img_map) = generate_document(document)?;
+        // images are referenced by path, so they are saved next to the source
+        for (path, bytes) in img_map {
+            image_saver(&Bytes::from(bytes.to_vec()), &path)?;
+        }
+        Ok(Bytes::from(text))
+    }
+}
+
+const TEXT_SIZE: u8 = 12;
+
+/// Content produced by a function call
+enum Parsed {
+    Blocks(Vec<Element>),
+    Inline(Vec<Element>),
+}
+
+/// Maps the typst syntax tree back into document elements
+struct SourceParser<'f, F> {
+    image_loader: &'f F,
+    metadata: DocumentMetadata,
+    page_header: Vec<Element>,
+    page_footer: Vec<Element>,
+    /// Printed page size and margins, when set by a page rule
+    page: Option<PageDimensions>,
+}
+
+impl<F> SourceParser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn blocks(&mut self, markup: ast::Markup) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        // inline runs of the current paragraph
+        let mut runs = vec![];
+        // list items only continue the previous list when nothing else came in between
+        let mut list_open = false;
+        // the generator writes a table name as `#strong("name")` right before the table
+        let mut table_name: Option<String> = None;
+
+        for expr in markup.exprs() {
+            match expr {
+                Expr::Space(_) | Expr::Parbreak(_) if runs.is_empty() => continue,
+                Expr::Parbreak(_) => flush_paragraph(&mut blocks, &mut runs),
+                Expr::Heading(heading) => {
+                    flush_paragraph(&mut blocks, &mut runs);
+                    let mut text = vec![];
+                    self.inline_markup(heading.body(), &TextStyle::default(), &mut text)?;
+                    blocks.push(Header {
+                        level: heading.depth().get().min(u8::MAX as usize) as u8,
+                        text: inline_text(text),
+                    });
+                }
+                Expr::List(item) => {
+                    flush_paragraph(&mut blocks, &mut runs);
+                    self.list_item(&mut blocks, item.body(), false, list_open)?;
+                    list_open = true;
+                    continue;
+                }
+                Expr::Enum(item) => {
+                    flush_paragraph(&mut blocks, &mut runs);
+                    self.list_item(&mut blocks, item.body(), true, list_open)?;
+                    list_open = true;
+                    continue;
+                }
+                Expr::Raw(raw) if raw.block() => {
+                    flush_paragraph(&mut blocks, &mut runs);
+                    blocks.push(CodeBlock {
+                        language: raw.lang().map(|lang| lang.get().to_string()),
+                        code: raw_text(raw),
+                    });
+                }
+                Expr::Set(rule) => {
+                    self.set_rule(rule)?;
+                    continue;
+                }
+                Expr::FuncCall(call) => {
+                    let name = string_arg(call.args());
+                    match self.call(call, &TextStyle::default())? {
+                        Parsed::Blocks(mut elements) => {
+                            if let Some(Table { name, .. }) = elements.first_mut() {
+                                if name.is_none() && is_bold_text(&runs, table_name.as_deref()) {
+                                    *name = table_name.take();
+                                    runs.clear();
+                                }
+                            }
+                            flush_paragraph(&mut blocks, &mut runs);
+                            blocks.extend(elements);
+                        }
+                        Parsed::Inline(elements) => {
+                            let start = runs.is_empty();
+                            runs.extend(elements);
+                            if start && callee(call) == "strong" {
+                                list_open = false;
+                                table_name = name;
+                                continue;
+                            }
+                        }
+                    }
+                }
+                expr => self.inline(expr, &TextStyle::default(), &mut runs)?,
+            }
+            if !matches!(expr, Expr::Space(_)) {
+                list_open = false;
+                table_name = None;
+            }
+        }
+        flush_paragraph(&mut blocks, &mut runs);
+        Ok(blocks)
+    }
+
+    fn list_item(
+        &mut self,
+        blocks: &mut Vec<Element>,
+        body: ast::Markup,
+        numbered: bool,
+        list_open: bool,
+    ) -> anyhow::Result<()> {
+        // nested lists of the item body become items of their own
+        let items: Vec<ListItem> = self
+            .blocks(body)?
+            .into_iter()
+            .map(|element| ListItem::new(unwrap_paragraph(element)))
+            .collect();
+        if list_open {
+            if let Some(List {
+                elements,
+                numbered: list_numbered,
+            }) = blocks.last_mut()
+            {
+                if *list_numbered == numbered {
+                    elements.extend(items);
+                    return Ok(());
+                }
+            }
+        }
+        blocks.push(List {
+            elements: items,
+            numbered,
+        });
+        Ok(())
+    }
+
+    fn inline_markup(
+        &mut self,
+        markup: ast::Markup,
+        style: &TextStyle,
+        runs: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        for expr in markup.exprs() {
+            self.inline(expr, style, runs)?;
+        }
+        Ok(())
+    }
+
+    fn inline(
+        &mut self,
+        expr: Expr,
+        style: &TextStyle,
+        runs: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        match expr {
+            Expr::Text(text) => push_text(runs, text.get(), style),
+            Expr::Space(_) => push_text(runs, " ", style),
+            Expr::Linebreak(_) | Expr::Parbreak(_) => push_text(runs, "\n", style),
+            Expr::Escape(escape) => push_text(runs, &escape.get().to_string(), style),
+            Expr::Shorthand(shorthand) => push_text(runs, &shorthand.get().to_string(), style),
+            Expr::SmartQuote(quote) => {
+                push_text(runs, if quote.double() { "\"" } else { "'" }, style)
+            }
+            Expr::Strong(strong) => {
+                let style = TextStyle {
+                    bold: true,
+                    ..style.clone()
+                };
+                self.inline_markup(strong.body(), &style, runs)?;
+            }
+            Expr::Emph(emph) => {
+                let style = TextStyle {
+                    italic: true,
+                    ..style.clone()
+                };
+                self.inline_markup(emph.body(), &style, runs)?;
+            }
+            Expr::Raw(raw) => {
+                let style = TextStyle {
+                    code: true,
+                    ..style.clone()
+                };
+                push_text(runs, &raw_text(raw), &style);
+            }
+            Expr::Link(link) => runs.push(Hyperlink {
+                title: link.get().to_string(),
+                url: link.get().to_string(),
+                alt: String::new(),
+                size: TEXT_SIZE,
+            }),
+            Expr::FuncCall(call) => match self.call(call, style)? {
+                Parsed::Inline(elements) => runs.extend(elements),
+                Parsed::Blocks(elements) => {
+                    for element in elements {
+                        match element {
+                            Paragraph { elements } => runs.extend(elements),
+                            element => runs.push(element),
+                        }
+                    }
+                }
+            },
+            // block markup in inline position and equations are kept as source text
+            Expr::Heading(_)
+            | Expr::List(_)
+            | Expr::Enum(_)
+            | Expr::Term(_)
+            | Expr::Equation(_) => push_text(runs, &expr.to_untyped().clone().into_text(), style),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, call: ast::FuncCall, style: &TextStyle) -> anyhow::Result<Parsed> {
+        let args = call.args();
+        let parsed = match callee(call).as_str() {
+            "strong" | "emph" | "underline" | "strike" => {
+                let function = callee(call);
+                let style = TextStyle {
+                    bold: style.bold || function == "strong",
+                    italic: style.italic || function == "emph",
+                    underline: style.underline || function == "underline",
+                    strikethrough: style.strikethrough || function == "strike",
+                    ..style.clone()
+                };
+                let mut runs = vec![];
+                for expr in positional(args) {
+                    self.content(expr, &style, &mut runs)?;
+                }
+                Parsed::Inline(runs)
+            }
+            "raw" => {
+                let code = string_arg(args).unwrap_or_default();
+                if matches!(named(args, "block"), Some(Expr::Bool(block)) if block.get()) {
+                    Parsed::Blocks(vec![CodeBlock {
+                        language: named_string(args, "lang"),
+                        code,
+                    }])
+                } else {
+                    let style = TextStyle {
+                        code: true,
+                        ..style.clone()
+                    };
+                    let mut runs = vec![];
+                    push_text(&mut runs, &code, &style);
+                    Parsed::Inline(runs)
+                }
+            }
+            "link" => {
+                let url = string_arg(args).unwrap_or_default();
+                let mut title = vec![];
+                for expr in positional(args).filter(|expr| matches!(expr, Expr::Content(_))) {
+                    self.content(expr, style, &mut title)?;
+                }
+                let title = inline_text(title);
+                Parsed::Inline(vec![Hyperlink {
+                    title: if title.is_empty() { url.clone() } else { title },
+                    url,
+                    alt: String::new(),
+                    size: TEXT_SIZE,
+                }])
+            }
+            "image" => {
+                let Some(path) = string_arg(args) else {
+                    return Ok(Parsed::Blocks(vec![]));
+                };
+                Parsed::Blocks(vec![self.image(&path, named_string(args, "alt"))?])
+            }
+            "table" => Parsed::Blocks(vec![self.table(args)?]),
+            "quote" => Parsed::Blocks(vec![Blockquote {
+                elements: self.content_blocks(args)?,
+            }]),
+            "line" => Parsed::Blocks(vec![ThematicBreak]),
+            "pagebreak" => Parsed::Blocks(vec![PageBreak]),
+            "heading" => {
+                let level = match named(args, "level") {
+                    Some(Expr::Int(level)) => level.get().clamp(1, u8::MAX as i64) as u8,
+                    _ => 1,
+                };
+                let text = plain_text(&self.content_blocks(args)?);
+                Parsed::Blocks(vec![Header { level, text }])
+            }
+            // containers such as `align`, `block` or `figure` only contribute their content
+            _ => {
+                let mut elements = self.content_blocks(args)?;
+                match elements.as_mut_slice() {
+                    [] => Parsed::Inline(vec![]),
+                    [Paragraph { elements }] => Parsed::Inline(std::mem::take(elements)),
+                    _ => Parsed::Blocks(elements),
+                }
+            }
+        };
+        Ok(parsed)
+    }
+
+    // Inline content of a function argument
+    fn content(
+        &mut self,
+        expr: Expr,
+        style: &TextStyle,
+        runs: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        match expr {
+            Expr::Content(content) => self.inline_markup(content.body(), style, runs),
+            Expr::Str(text) => {
+                push_text(runs, &text.get(), style);
+                Ok(())
+            }
+            Expr::FuncCall(_) => self.inline(expr, style, runs),
+            _ => Ok(()),
+        }
+    }
+
+    // Block content of the positional arguments of a function
+    fn content_blocks(&mut self, args: ast::Args) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        for expr in positional(args) {
+            match expr {
+                Expr::Content(content) => blocks.extend(self.blocks(content.body())?),
+                Expr::Str(text) => blocks.push(Paragraph {
+                    elements: vec![Text {
+                        text: text.get().to_string(),
+                        size: TEXT_SIZE,
+                        style: TextStyle::default(),
+                    }],
+                }),
+                Expr::FuncCall(call) => match self.call(call, &TextStyle::default())? {
+                    Parsed::Blocks(elements) => blocks.extend(elements),
+                    Parsed::Inline(elements) => blocks.push(Paragraph { elements }),
+                },
+                _ => {}
+            }
+        }
+        Ok(blocks)
+    }
+
+    fn image(&mut self, path: &str, alt: Option<String>) -> anyhow::Result<Element> {
+        let bytes = (self.image_loader)(path)?;
+        let path = Path::new(path);
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // unknown extensions fall back to the default image type
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let image_type = match extension.as_str() {
+            "jpg" => "jpeg".to_string(),
+            extension if ImageType::from_str(extension).is_ok() => extension.to_string(),
+            _ => String::new(),
+        };
+        Ok(Image(ImageData::new(
+            bytes,
+            title,
+            alt.unwrap_or_default(),
+            image_type,
+            String::new(),
+            ImageDimension::default(),
+        )))
+    }
+
+    fn table(&mut self, args: ast::Args) -> anyhow::Result<Element> {
+        let columns = match named(args, "columns") {
+            Some(Expr::Int(columns)) => columns.get().max(1) as usize,
+            Some(Expr::Array(columns)) => columns.items().count().max(1),
+            _ => 1,
+        };
+        let mut header_cells = vec![];
+        let mut cells = vec![];
+        for expr in positional(args) {
+            match expr {
+                Expr::FuncCall(call) if callee(call) == "table.header" => {
+                    for expr in positional(call.args()) {
+                        header_cells.extend(self.table_cell(expr)?);
+                    }
+                }
+                expr => cells.extend(self.table_cell(expr)?),
+            }
+        }
+
+        let mut rows = grid_rows(cells, columns);
+        let header_row = if !header_cells.is_empty() {
+            grid_rows(header_cells, columns).into_iter().next()
+        } else if rows
+            .first()
+            .is_some_and(|row| row.iter().all(|cell| all_bold(&cell.elements)))
+        {
+            // the generator writes headers as a bold first row
+            Some(rows.remove(0))
+        } else {
+            None
+        };
+        let headers = header_row
+            .unwrap_or_default()
+            .into_iter()
+            .map(|cell| TableHeader {
+                elements: cell.elements.into_iter().map(unbold).collect(),
+                width: 10.0,
+                colspan: cell.colspan,
+                rowspan: cell.rowspan,
+                alignment: ColumnAlignment::None,
+            })
+            .collect();
+        Ok(Table {
+            name: None,
+            headers,
+            rows: rows.into_iter().map(|cells| TableRow { cells }).collect(),
+        })
+    }
+
+    fn table_cell(&mut self, expr: Expr) -> anyhow::Result<Option<TableCell>> {
+        let cell = match expr {
+            Expr::Content(content) => cell_with_blocks(self.blocks(content.body())?),
+            Expr::Str(text) => TableCell::new(Text {
+                text: text.get().to_string(),
+                size: TEXT_SIZE,
+                style: TextStyle::default(),
+            }),
+            Expr::FuncCall(call) if callee(call) == "table.cell" => {
+                let span = |name| match named(call.args(), name) {
+                    Some(Expr::Int(span)) => span.get().max(1) as usize,
+                    _ => 1,
+                };
+                cell_with_blocks(self.content_blocks(call.args())?)
+                    .span(span("colspan"), span("rowspan"))
+            }
+            Expr::FuncCall(call) if callee(call).starts_with("table.") => return Ok(None),
+            Expr::FuncCall(call) => match self.call(call, &TextStyle::default())? {
+                Parsed::Blocks(elements) => cell_with_blocks(elements),
+                Parsed::Inline(elements) => cell_with_blocks(vec![Paragraph { elements }]),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(cell))
+    }
+
+    fn set_rule(&mut self, rule: ast::SetRule) -> anyhow::Result<()> {
+        let args = rule.args();
+        match rule.target().to_untyped().clone().into_text().as_str() {
+            "document" => {
+                if let Some(title) = self.named_text(args, "title")? {
+                    self.metadata.title = Some(title);
+                }
+                match named(args, "author") {
+                    Some(Expr::Array(authors)) => {
+                        let authors = array_strings(authors);
+                        if !authors.is_empty() {
+                            self.metadata.author = Some(authors.join(", "));
+                        }
+                    }
+                    Some(Expr::Str(author)) => {
+                        self.metadata.author = Some(author.get().to_string())
+                    }
+                    _ => {}
+                }
+                match named(args, "keywords") {
+                    Some(Expr::Array(keywords)) => self.metadata.keywords = array_strings(keywords),
+                    Some(Expr::Str(keyword)) => {
+                        self.metadata.keywords = vec![keyword.get().to_string()]
+                    }
+                    _ => {}
+                }
+                if let Some(Expr::FuncCall(date)) = named(args, "date") {
+                    let part = |name| match named(date.args(), name) {
+                        Some(Expr::Int(value)) => Some(value.get()),
+                        _ => None,
+                    };
+                    if let (Some(year), Some(month), Some(day)) =
+                        (part("year"), part("month"), part("day"))
+                    {
+                        self.metadata.created = Some(format!("{year:04}-{month:02}-{day:02}"));
+                    }
+                }
+            }
+            "text" => {
+                if let Some(language) = named_string(args, "lang") {
+                    self.metadata.language = Some(language);
+                }
+            }
+            "page" => {
+                if let Some(header) = self.named_text(args, "header")? {
+                    self.page_header = vec![page_text(header)];
+                }
+                if let Some(footer) = self.named_text(args, "footer")? {
+                    self.page_footer = vec![page_text(footer)];
+                }
+                self.page_setup(args);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn page_setup(&mut self, args: ast::Args) {
+        let paper = named_string(args, "paper").and_then(|paper| match paper.as_str() {
+            "a4" => Some(PageFormat::A4),
+            "us-letter" => Some(PageFormat::Letter),
+            "us-legal" => Some(PageFormat::Legal),
+            "us-tabloid" => Some(PageFormat::Tabloid),
+            _ => None,
+        });
+        let width = named(args, "width").and_then(length_mm);
+        let height = named(args, "height").and_then(length_mm);
+        let margin = named(args, "margin");
+        let flipped = matches!(named(args, "flipped"), Some(Expr::Bool(flipped)) if flipped.get());
+        if paper.is_none() && width.is_none() && height.is_none() && margin.is_none() && !flipped {
+            return;
+        }
+
+        let mut page = self
+            .page
+            .take()
+            .or_else(|| paper.as_ref().map(PageFormat::dimensions))
+            .unwrap_or_else(|| PageFormat::default().dimensions());
+        if let Some(paper) = paper {
+            let size = paper.dimensions();
+            page.page_width = size.page_width;
+            page.page_height = size.page_height;
+        }
+        page.page_width = width.unwrap_or(page.page_width);
+        page.page_height = height.unwrap_or(page.page_height);
+        match margin {
+            Some(Expr::Dict(sides)) => {
+                let mut sides: Vec<ast::Named> = sides
+                    .items()
+                    .filter_map(|item| match item {
+                        ast::DictItem::Named(side) => Some(side),
+                        _ => None,
+                    })
+                    .collect();
+                // `rest` and then `x` or `y` only apply to sides that are not given explicitly
+                sides.sort_by_key(|side| match side.name().as_str() {
+                    "rest" => 0,
+                    "x" | "y" => 1,
+                    _ => 2,
+                });
+                for side in sides {
+                    let Some(length) = length_mm(side.expr()) else {
+                        continue;
+                    };
+                    let sides: &[&str] = match side.name().as_str() {
+                        "x" => &["left", "right"],
+                        "y" => &["top", "bottom"],
+                        "rest" => &["top", "bottom", "left", "right"],
+                        name => &[name],
+                    };
+                    for side in sides {
+                        match *side {
+                            "top" => page.page_margin_top = length,
+                            "bottom" => page.page_margin_bottom = length,
+                            "left" => page.page_margin_left = length,
+                            "right" => page.page_margin_right = length,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Some(expr) => {
+                if let Some(length) = length_mm(expr) {
+                    page.page_margin_top = length;
+                    page.page_margin_bottom = length;
+                    page.page_margin_left = length;
+                    page.page_margin_right = length;
+                }
+            }
+            None => {}
+        }
+        if flipped {
+            std::mem::swap(&mut page.page_width, &mut page.page_height);
+        }
+        self.page = Some(page);
+    }
+
+    // Text of a named string or content argument, None when missing or empty
+    fn named_text(&mut self, args: ast::Args, name: &str) -> anyhow::Result<Option<String>> {
+        let mut runs = vec![];
+        if let Some(expr) = named(args, name) {
+            self.content(expr, &TextStyle::default(), &mut runs)?;
+        }
+        let text = inline_text(runs);
+        Ok((!text.is_empty()).then_some(text))
+    }
+}
+
+fn callee(call: ast::FuncCall) -> String {
+    call.callee().to_untyped().clone().into_text().to_string()
+}
+
+fn named<'a>(args: ast::Args<'a>, name: &str) -> Option<Expr<'a>> {
+    args.items().find_map(|arg| match arg {
+        ast::Arg::Named(named) if named.name().as_str() == name => Some(named.expr()),
+        _ => None,
+    })
+}
+
+// Absolute lengths in mm, relative lengths like `2em` or `10%` are ignored
+fn length_mm(expr: Expr) -> Option<f32> {
+    let Expr::Numeric(numeric) = expr else {
+        return None;
+    };
+    let (value, unit) = numeric.get();
+    let mm = match unit {
+        ast::Unit::Mm => value,
+        ast::Unit::Cm => value * 10.0,
+        ast::Unit::In => value * 25.4,
+        ast::Unit::Pt => value * 25.4 / 72.0,
+        _ => return None,
+    };
+    Some(mm as f32)
+}
+
+fn named_string(args: ast::Args, name: &str) -> Option<String> {
+    match named(args, name) {
+        Some(Expr::Str(text)) => Some(text.get().to_string()),
+        _ => None,
+    }
+}
+
+fn positional(args: ast::Args) -> impl Iterator<Item = Expr> {
+    args.items().filter_map(|arg| match arg {
+        ast::Arg::Pos(expr) => Some(expr),
+        _ => None,
+    })
+}
+
+// First positional string argument, such as the path of `image` or the url of `link`
+fn string_arg(args: ast::Args) -> Option<String> {
+    positional(args).find_map(|expr| match expr {
+        Expr::Str(text) => Some(text.get().to_string()),
+        _ => None,
+    })
+}
+
+fn array_strings(array: ast::Array) -> Vec<String> {
+    array
+        .items()
+        .filter_map(|item| match item {
+            ast::ArrayItem::Pos(Expr::Str(text)) => Some(text.get().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn raw_text(raw: ast::Raw) -> String {
+    raw.lines()
+        .map(|line| line.get().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Appends text to the last run when it has the same style
+fn push_text(runs: &mut Vec<Element>, text: &str, style: &TextStyle) {
+    if let Some(Text {
+        text: last,
+        style: last_style,
+        ..
+    }) = runs.last_mut()
+    {
+        if last_style == style {
+            last.push_str(text);
+            return;
+        }
+    }
+    runs.push(Text {
+        text: text.to_string(),
+        size: TEXT_SIZE,
+        style: style.clone(),
+    });
+}
+
+// Turns the collected runs into a paragraph without surrounding whitespace
+fn flush_paragraph(blocks: &mut Vec<Element>, runs: &mut Vec<Element>) {
+    if let Some(Text { text, .. }) = runs.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Text { text, .. }) = runs.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    runs.retain(|run| !matches!(run, Text { text, .. } if text.is_empty()));
+    if !runs.is_empty() {
+        blocks.push(Paragraph {
+            elements: std::mem::take(runs),
+        });
+    }
+    runs.clear();
+}
+
+fn inline_text(runs: Vec<Element>) -> String {
+    plain_text(&[Paragraph { elements: runs }])
+        .trim()
+        .to_string()
+}
+
+fn unwrap_paragraph(element: Element) -> Element {
+    match element {
+        Paragraph { mut elements } if elements.len() == 1 => elements.remove(0),
+        element => element,
+    }
+}
+
+fn is_bold_text(runs: &[Element], name: Option<&str>) -> bool {
+    let runs: Vec<&Element> = runs
+        .iter()
+        .filter(|run| !matches!(run, Text { text, .. } if text.trim().is_empty()))
+        .collect();
+    matches!(
+        (runs.as_slice(), name),
+        ([Text { text, style, .. }], Some(name)) if text == name && style.bold
+    )
+}
+
+fn all_bold(elements: &[Element]) -> bool {
+    elements.iter().all(|element| match element {
+        Text { style, .. } => style.bold,
+        Paragraph { elements } => all_bold(elements),
+        _ => true,
+    })
+}
+
+fn unbold(element: Element) -> Element {
+    match element {
+        Text { text, size, style } => Text {
+            text,
+            size,
+            style: TextStyle {
+                bold: false,
+                ..style
+            },
+        },
+        Paragraph { elements } => Paragraph {
+            elements: elements.into_iter().map(unbold).collect(),
+        },
+        element => element,
+    }
+}
+
+fn cell_with_blocks(blocks: Vec<Element>) -> TableCell {
+    TableCell::with_elements(blocks.into_iter().map(unwrap_paragraph).collect())
+}
+
+fn page_text(text: String) -> Element {
+    Text {
+        text,
+        size: TEXT_SIZE,
+        style: TextStyle::default(),
+    }
+}
+
+// Places cells row by row like typst does, skipping positions covered by spans
+fn grid_rows(cells: Vec<TableCell>, columns: usize) -> Vec<Vec<TableCell>> {
+    let mut covered = HashSet::new();
+    let mut rows: Vec<Vec<TableCell>> = vec![];
+    let (mut row, mut column) = (0, 0);
+    for cell in cells {
+        loop {
+            if column >= columns {
+                row += 1;
+                column = 0;
+            }
+            if !covered.contains(&(row, column)) {
+                break;
+            }
+            column += 1;
+        }
+        let colspan = cell.colspan.min(columns - column);
+        for covered_row in row..row + cell.rowspan {
+            for covered_column in column..column + colspan {
+                covered.insert((covered_row, covered_column));
+            }
+        }
+        while rows.len() <= row {
+            rows.push(vec![]);
+        }
+        rows[row].push(cell);
+        column += colspan;
     }
+    rows
 }
 
 /// Converts Document into a typst::model::Document
 pub fn generate_document(
     document: &Document,
 ) -> anyhow::Result<(TypstString, HashMap<String, typst::foundations::Bytes>)> {
+    generate_document_with_outline(document, false)
+}
+
+/// Converts Document into typst source that starts with a table of contents page when `outline` is set
+pub fn generate_document_with_outline(
+    document: &Document,
+    outline: bool,
+) -> anyhow::Result<(TypstString, HashMap<String, typst::foundations::Bytes>)> {
+    let generated = generate_source(document, outline)?;
+    Ok((generated.text, generated.img_map))
+}
+
+/// Ranges of the generated typst source that show each element.
+///
+/// Elements are known by their address, so the ranges only apply to the document the source was generated from.
+pub(crate) type SourceRanges = HashMap<*const Element, std::ops::Range<usize>>;
+
+// Label of the metadata that starts the body after the table of contents
+const BODY_LABEL: &str = "shiva-body";
+
+struct GeneratedSource {
+    text: TypstString,
+    img_map: HashMap<String, typst::foundations::Bytes>,
+    ranges: SourceRanges,
+    // offset of the first element, the page setup and the table of contents come before it
+    body: usize,
+}
+
+fn generate_source(document: &Document, outline: bool) -> anyhow::Result<GeneratedSource> {
     // Array of methods to process Document object into a typst string repr
     fn process_header(source: &mut TypstString, level: usize, text: &str) -> anyhow::Result<()> {
         let header_depth = "=".repeat(level);
-        let header_text = format!("{header_depth} {text}");
+        let header_text = format!("{header_depth} {}", escape_markup(text));
         source.push_str(&header_text);
         source.push('\n');
 
@@ -210,107 +1248,176 @@ pub fn generate_document(
         source: &mut TypstString,
         _size: u8,
         text: &str,
-        is_bold: bool,
+        style: &TextStyle,
     ) -> anyhow::Result<()> {
-        if is_bold {
-            let bold_text = format!("*{text}*");
-            source.push_str(&bold_text);
+        let mut styled_text = if style.code {
+            format!("#raw({})", typst_str(text))
         } else {
-            source.push_str(text);
+            escape_markup(text)
+        };
+
+        let functions = [
+            (style.strikethrough, "strike"),
+            (style.underline, "underline"),
+            (style.italic, "emph"),
+            (style.bold, "strong"),
+        ];
+        for (enabled, function) in functions {
+            if enabled {
+                styled_text = format!("#{function}[{styled_text}]");
+            }
+        }
+        source.push_str(&styled_text);
+        if !style.is_plain() {
+            // terminate the embedded expression so following text is not parsed as its arguments
+            source.push(';');
         }
 
         Ok(())
     }
 
-    fn process_link(source: &mut TypstString, url: &str) -> anyhow::Result<()> {
-        let link = format!("#link(\"{url}\")");
+    fn process_link(source: &mut TypstString, url: &str, title: &str) -> anyhow::Result<()> {
+        let mut link = format!("#link({})", typst_str(url));
+        if !title.is_empty() && title != url {
+            link.push_str(&format!("[{}]", escape_markup(title)));
+        }
 
         source.push_str(&link);
+        // terminate the embedded expression so following text is not parsed as its arguments
+        source.push(';');
 
         Ok(())
     }
 
     fn process_table(
         source: &mut TypstString,
-        headers: &Vec<TableHeader>,
-        rows: &Vec<TableRow>,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
+        name: Option<&str>,
+        headers: &[TableHeader],
+        rows: &[TableRow],
     ) -> anyhow::Result<()> {
-        let mut headers_text = TypstString::new();
-
+        // the name, such as the sheet of a spreadsheet, is shown above the table
+        if let Some(name) = name {
+            source.push_str(&format!("#strong({})", typst_str(name)));
+        }
+        let (header_columns, row_columns) = table_columns(headers, rows);
+        let spans =
+            headers
+                .iter()
+                .map(|header| header.colspan)
+                .zip(header_columns)
+                .chain(rows.iter().zip(row_columns).flat_map(|(row, columns)| {
+                    row.cells.iter().map(|cell| cell.colspan).zip(columns)
+                }));
+        let columns = spans
+            .map(|(colspan, column)| column + colspan)
+            .max()
+            .unwrap_or(headers.len());
+
+        // cells are written in place so that their ranges in the source stay right
+        source.push_str(&format!(
+            "\n        #table(\n            columns:{columns},\n            "
+        ));
         for header in headers {
-            match &header.element {
-                Text { text, size } => {
-                    headers_text.push('[');
-                    process_text(&mut headers_text, *size, text, true)?;
-                    headers_text.push(']');
-                    headers_text.push(',');
-                }
-                _ => {
-                    warn!(
-                        "Should implement element for processing in inside table header - {:?}",
-                        header.element
-                    );
-                }
-            }
+            process_cell(
+                source,
+                img_map,
+                ranges,
+                &header.elements,
+                (header.colspan, header.rowspan),
+                true,
+            )?;
         }
-
-        let mut cells_text = TypstString::new();
-
+        source.push_str("\n            ");
         for row in rows {
             for cell in &row.cells {
-                match &cell.element {
-                    Text { text, size } => {
-                        cells_text.push('[');
-                        process_text(&mut cells_text, *size, text, false)?;
-                        cells_text.push(']');
-                        cells_text.push(',');
-                    }
-                    _ => {
-                        warn!(
-                            "Should implement element for processing in inside cell - {:?}",
-                            cell.element
-                        );
-                    }
-                }
+                process_cell(
+                    source,
+                    img_map,
+                    ranges,
+                    &cell.elements,
+                    (cell.colspan, cell.rowspan),
+                    false,
+                )?;
             }
 
-            cells_text.push('\n');
+            source.push('\n');
         }
+        source.push_str("\n        )\n        ");
+        Ok(())
+    }
 
-        let columns = headers.len();
-        let table_text = format!(
-            r#"
-        #table(
-            columns:{columns},
-            {headers_text}
-            {cells_text}
-        )
-        "#
-        );
-
-        source.push_str(&table_text);
+    fn process_cell(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
+        elements: &[Element],
+        (colspan, rowspan): (usize, usize),
+        is_header: bool,
+    ) -> anyhow::Result<()> {
+        if colspan > 1 || rowspan > 1 {
+            source.push_str(&format!(
+                "table.cell(colspan: {}, rowspan: {})",
+                colspan, rowspan
+            ));
+        }
+        source.push('[');
+        for (index, element) in elements.iter().enumerate() {
+            // block elements of a cell are separated by paragraph breaks
+            if index > 0 {
+                source.push_str("\n\n");
+            }
+            match element {
+                Text { text, size, style } => {
+                    let style = TextStyle {
+                        bold: style.bold || is_header,
+                        ..style.clone()
+                    };
+                    let start = source.len();
+                    process_text(source, *size, text, &style)?;
+                    ranges.insert(element, start..source.len());
+                }
+                _ => process_element(source, img_map, ranges, element)?,
+            }
+        }
+        source.push(']');
+        source.push(',');
         Ok(())
     }
 
     fn process_list(
         source: &mut TypstString,
         img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
         list: &Vec<ListItem>,
         numbered: bool,
         depth: usize,
     ) -> anyhow::Result<()> {
-        source.push_str(&" ".repeat(depth));
+        let indent = "  ".repeat(depth);
         for el in list {
             if let List { elements, numbered } = &el.element {
-                process_list(source, img_map, elements, *numbered, depth + 1)?;
+                process_list(source, img_map, ranges, elements, *numbered, depth + 1)?;
             } else {
-                if numbered {
-                    source.push_str("+ ")
-                } else {
-                    source.push_str("- ")
+                let marker = if numbered { "+ " } else { "- " };
+                let mut item = TypstString::new();
+                let mut item_ranges = SourceRanges::new();
+                process_element(&mut item, img_map, &mut item_ranges, &el.element)?;
+                // continuation lines are indented past the marker to stay in the item
+                let leading = item.len() - item.trim_start().len();
+                let item = item.trim();
+                let continuation = format!("\n{indent}  ");
+                source.push_str(&format!("{indent}{marker}"));
+                let start = source.len();
+                let position = |offset: usize| {
+                    let offset = offset.saturating_sub(leading).min(item.len());
+                    start + offset + item[..offset].matches('\n').count() * (continuation.len() - 1)
                 };
-
-                process_element(source, img_map, &el.element)?;
+                for (element, range) in item_ranges {
+                    ranges.insert(element, position(range.start)..position(range.end));
+                }
+                source.push_str(&item.replace('\n', &continuation));
+                source.push('\n');
             }
         }
 
@@ -320,15 +1427,16 @@ pub fn generate_document(
     fn process_image(
         source: &mut TypstString,
         bytes: &Bytes,
-        title: &str,
+        path: &str,
         alt: &str,
-        image_type: &str,
     ) -> anyhow::Result<()> {
         if !bytes.is_empty() {
             let image_text = format!(
                 "
-            #image(\"{title}{image_type}\", alt: \"{alt}\")
-            "
+            #image({}, alt: {})
+            ",
+                typst_str(path),
+                typst_str(alt)
             );
             source.push_str(&image_text);
         }
@@ -339,59 +1447,142 @@ pub fn generate_document(
     fn process_element(
         source: &mut TypstString,
         img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
         element: &Element,
     ) -> anyhow::Result<()> {
+        let start = source.len();
         match element {
             Header { level, text } => process_header(source, *level as usize, text),
             Paragraph { elements } => {
+                // the runs are written back to back, a line break between them would render as a space
                 for paragraph_element in elements {
-                    process_element(source, img_map, paragraph_element)?;
+                    process_inline(source, img_map, ranges, paragraph_element)?;
                 }
+                source.push('\n');
 
                 Ok(())
             }
-            Text { text, size } => {
-                process_text(source, *size, text, false)?;
+            Text { .. } | Hyperlink { .. } | Revision { .. } => {
+                process_inline(source, img_map, ranges, element)?;
                 source.push('\n');
 
                 Ok(())
             }
             List { elements, numbered } => {
-                process_list(source, img_map, elements, *numbered, 0)?;
+                process_list(source, img_map, ranges, elements, *numbered, 0)?;
                 Ok(())
             }
-            Hyperlink {
-                url,
-                title: _,
-                alt: _,
-                size: _,
+            Table {
+                name,
+                headers,
+                rows,
             } => {
-                process_link(source, url)?;
+                process_table(source, img_map, ranges, name.as_deref(), headers, rows)?;
+                Ok(())
+            }
+            Blockquote { elements } => {
+                source.push_str("#quote(block: true)[\n");
+                process_blocks(source, img_map, ranges, elements)?;
+                source.push_str("]\n");
+                Ok(())
+            }
+            ThematicBreak => {
+                source.push_str("#line(length: 100%)\n");
+                Ok(())
+            }
+            PageBreak => {
+                source.push_str("#pagebreak()\n");
+                Ok(())
+            }
+            // comments are not part of the page
+            Comment { .. } => Ok(()),
+            Footnote { .. } => {
+                // the marker follows the preceding text without a space
+                if source.ends_with('\n') {
+                    source.pop();
+                }
+                process_inline(source, img_map, ranges, element)?;
                 source.push('\n');
-
                 Ok(())
             }
-            Table { headers, rows } => {
-                process_table(source, headers, rows)?;
+            CodeBlock { language, code } => {
+                let lang = match language {
+                    Some(language) => format!(", lang: {}", typst_str(language)),
+                    None => String::new(),
+                };
+                source.push_str(&format!("#raw({}, block: true{lang})\n", typst_str(code)));
                 Ok(())
             }
             Image(image) => {
-                let key = format!("{}{}", image.title(), image.image_type());
-                img_map.insert(key, typst::foundations::Bytes::from(image.bytes().to_vec()));
-                process_image(
-                    source,
-                    image.bytes(),
-                    image.title(),
-                    image.alt(),
-                    &image.image_type().to_string(),
-                )?;
+                let path = image_file_name(image.title(), &image.image_type().to_string());
+                process_image(source, image.bytes(), &path, image.alt())?;
+                img_map.insert(
+                    path,
+                    typst::foundations::Bytes::from(image.bytes().to_vec()),
+                );
                 source.push('\n');
                 Ok(())
             } // _ => {
               //     warn!("Should implement element - {:?}", element);
               //     Ok(())
               // }
+        }?;
+        ranges.insert(element, start..source.len());
+        Ok(())
+    }
+
+    // writes a run of a paragraph, without a line break after it
+    fn process_inline(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
+        element: &Element,
+    ) -> anyhow::Result<()> {
+        let start = source.len();
+        match element {
+            Text { text, size, style } => process_text(source, *size, text, style)?,
+            Hyperlink { url, title, .. } => process_link(source, url, title)?,
+            // tracked changes are shown the way Word shows them, insertions underlined and deletions struck through
+            Revision { kind, elements, .. } => {
+                for element in elements {
+                    if let Text { text, size, style } = element {
+                        let mut style = style.clone();
+                        match kind {
+                            RevisionKind::Insertion => style.underline = true,
+                            RevisionKind::Deletion => style.strikethrough = true,
+                        }
+                        process_text(source, *size, text, &style)?;
+                    }
+                }
+            }
+            Footnote { elements, .. } => {
+                source.push_str("#footnote[\n");
+                process_blocks(source, img_map, ranges, elements)?;
+                source.push(']');
+            }
+            _ => return process_element(source, img_map, ranges, element),
+        }
+        ranges.insert(element, start..source.len());
+        Ok(())
+    }
+
+    fn process_blocks<'a>(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        ranges: &mut SourceRanges,
+        elements: impl IntoIterator<Item = &'a Element>,
+    ) -> anyhow::Result<()> {
+        let text = |element: &Element| matches!(element, Paragraph { .. } | Text { .. });
+        let mut previous_text = false;
+        for element in elements {
+            // typst joins text on consecutive lines, so paragraphs need a blank line between them
+            if previous_text && text(element) {
+                source.push('\n');
+            }
+            previous_text = text(element);
+            process_element(source, img_map, ranges, element)?;
         }
+        Ok(())
     }
 
     // String to build off of
@@ -401,37 +1592,227 @@ pub fn generate_document(
 
     // Converting both headers and footers into a string repr of them in Typst
     let mut header_text = String::new();
-    document.get_page_header().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
+    document.get_page_header().iter().for_each(|el| {
+        if let Text { text, size: _, .. } = el {
             header_text.push_str(text);
         }
-        _ => {}
     });
     let mut footer_text = String::new();
-    document.get_page_footer().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
+    document.get_page_footer().iter().for_each(|el| {
+        if let Text { text, size: _, .. } = el {
             footer_text.push_str(text);
         }
-        _ => {}
     });
-    let footer_header_text = format!(
+    let PageDimensions {
+        page_width,
+        page_height,
+        page_margin_top,
+        page_margin_bottom,
+        page_margin_left,
+        page_margin_right,
+    } = document.page_format.dimensions();
+    let flipped = document.orientation == PageOrientation::Landscape;
+    let page_settings = format!(
         "#set page(
-        header: \"{header_text}\",
-        footer: \"{footer_text}\",
-    )\n"
+        width: {page_width}mm,
+        height: {page_height}mm,
+        flipped: {flipped},
+        margin: (top: {page_margin_top}mm, bottom: {page_margin_bottom}mm, left: {page_margin_left}mm, right: {page_margin_right}mm),
+        header: {},
+        footer: {},
+    )\n",
+        typst_str(&header_text),
+        typst_str(&footer_text),
     );
 
     // Converting Document repr to one of typst string
-    source.push_str(&footer_header_text);
-    for element in &document.get_all_elements() {
-        process_element(&mut source, &mut img_map, element)?;
+    source.push_str(&generate_metadata(&document.metadata));
+    source.push_str(&page_settings);
+    if outline {
+        // entries link to their headings, the metadata marks the page the body starts on
+        source.push_str(&format!(
+            "#outline()\n#pagebreak()\n#metadata(none) <{BODY_LABEL}>\n"
+        ));
+    }
+    let body = source.len();
+    let mut ranges = SourceRanges::new();
+    process_blocks(
+        &mut source,
+        &mut img_map,
+        &mut ranges,
+        document.get_all_elements(),
+    )?;
+
+    Ok(GeneratedSource {
+        text: source,
+        img_map,
+        ranges,
+        body,
+    })
+}
+
+/// Document laid out by typst, with the source it was compiled from
+// only the tagging of PDF output reads what ties the pages to the elements
+#[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+pub(crate) struct Layout {
+    pub document: typst::model::Document,
+    pub source: Source,
+    pub ranges: SourceRanges,
+    pub body: usize,
+}
+
+#[cfg(feature = "pdf")]
+impl Layout {
+    /// Offset in the source of the text the glyph shows, none for text typst adds itself such as list markers
+    pub fn glyph_offset(&self, glyph: &typst::text::Glyph) -> Option<usize> {
+        let (span, offset) = glyph.span;
+        self.source
+            .range(span)
+            .map(|range| range.start + offset as usize)
+    }
+
+    /// Index of the first page after the table of contents
+    pub fn body_page(&self) -> usize {
+        let introspector = &self.document.introspector;
+        introspector
+            .query_label(typst::foundations::Label::new(BODY_LABEL))
+            .ok()
+            .and_then(|marker| marker.location())
+            .map_or(0, |location| introspector.page(location).get() - 1)
+    }
+}
+
+/// Lays out Document into pages with typst, for the exporters of PDF and page images
+pub fn compile_document(
+    document: &Document,
+    fonts: &FontOptions,
+    outline: bool,
+) -> anyhow::Result<typst::model::Document> {
+    compile_layout(document, fonts, outline).map(|layout| layout.document)
+}
+
+/// Lays out Document like [`compile_document`] and keeps what ties the pages to the elements
+pub(crate) fn compile_layout(
+    document: &Document,
+    fonts: &FontOptions,
+    outline: bool,
+) -> anyhow::Result<Layout> {
+    let generated = generate_source(document, outline)?;
+    let world = ShivaWorld::with_fonts(generated.text, generated.img_map, fonts)?;
+    let mut tracer = typst::eval::Tracer::default();
+
+    let compiled = typst::compile(&world, &mut tracer);
+    // keeps the memoized layouts of a long running process bounded
+    comemo::evict(LAYOUT_CACHE_AGE);
+    let document = compiled.map_err(|errors| {
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        anyhow::anyhow!("Typst compilation failed: {}", messages.join("; "))
+    })?;
+    for warning in tracer.warnings() {
+        log::warn!("Warning - {}", warning.message);
+    }
+    Ok(Layout {
+        document,
+        source: world.source,
+        ranges: generated.ranges,
+        body: generated.body,
+    })
+}
+
+// File an image is saved as next to the source, only the last path component of the title is kept
+// so a document cannot place files outside the directory it is saved to
+fn image_file_name(title: &str, image_type: &str) -> String {
+    let name = title.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = match name {
+        "" | "." | ".." => "image",
+        name => name,
+    };
+    format!("{name}.{image_type}")
+}
+
+// Quotes text as a typst string literal
+fn typst_str(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+// Escapes characters that typst would read as markup
+fn escape_markup(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+    let mut line_start = true;
+    let mut leading_digits = false;
+    for (index, &c) in chars.iter().enumerate() {
+        let next = chars.get(index + 1).copied();
+        let special = match c {
+            '\\' | '#' | '*' | '_' | '`' | '$' | '<' | '@' | '[' | ']' | '~' => true,
+            // heading, list and enum markers
+            '=' | '-' | '+' if line_start => true,
+            // shorthands like "--", "-?", "-1" and "...", comments and automatic links
+            '-' => matches!(next, Some('-' | '?')) || next.is_some_and(|c| c.is_numeric()),
+            '.' => leading_digits || (next == Some('.') && chars.get(index + 2) == Some(&'.')),
+            '/' => matches!(next, Some('/' | '*')),
+            _ => false,
+        };
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        leading_digits = c.is_ascii_digit() && (line_start || leading_digits);
+        line_start = c == '\n' || (line_start && c.is_whitespace());
+    }
+    escaped
+}
+
+// Document settings are written by typst-pdf into the PDF information dictionary
+fn generate_metadata(metadata: &DocumentMetadata) -> String {
+    let mut settings = Vec::new();
+    if let Some(title) = &metadata.title {
+        settings.push(format!("title: {}", typst_str(title)));
+    }
+    if let Some(author) = &metadata.author {
+        settings.push(format!("author: {}", typst_str(author)));
     }
+    if !metadata.keywords.is_empty() {
+        let keywords: Vec<String> = metadata.keywords.iter().map(|k| typst_str(k)).collect();
+        // trailing comma keeps a single keyword an array
+        settings.push(format!("keywords: ({},)", keywords.join(", ")));
+    }
+    if let Some(date) = metadata.created.as_deref().and_then(typst_date) {
+        settings.push(format!("date: {date}"));
+    }
+
+    let mut source = String::new();
+    if !settings.is_empty() {
+        source.push_str(&format!("#set document({})\n", settings.join(", ")));
+    }
+    // typst only accepts ISO 639 codes, region subtags are dropped
+    if let Some(language) = &metadata.language {
+        let lang = language.split(['-', '_']).next().unwrap_or_default();
+        if (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_alphabetic()) {
+            source.push_str(&format!("#set text(lang: \"{}\")\n", lang.to_lowercase()));
+        }
+    }
+    source
+}
 
-    Ok((source, img_map))
+// Converts the date part of an ISO-8601 string into a typst datetime
+fn typst_date(date: &str) -> Option<String> {
+    let mut parts = date.get(..10)?.split('-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day: u8 = parts.next()?.parse().ok()?;
+    Some(format!(
+        "datetime(year: {year}, month: {month}, day: {day})"
+    ))
 }
 
 #[cfg(test)]
 mod test {
+    use crate::core::tests::text;
     use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
     use crate::markdown;
     use bytes::Bytes;
@@ -445,7 +1826,9 @@ mod test {
             &documents_bytes,
             disk_image_loader("test/data"),
         )?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
+        // the source only refers to the images, they are not written next to it here
+        let generated_result =
+            crate::typst::Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
         std::fs::write("test/data/document_from_md.typ", generated_result)?;
 
         Ok(())
@@ -456,9 +1839,544 @@ mod test {
         let document = std::fs::read("test/data/document.xml")?;
         let documents_bytes = Bytes::from(document);
         let parsed = crate::xml::Transformer::parse(&documents_bytes)?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
+        let generated_result =
+            crate::typst::Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
         std::fs::write("test/data/document_from_xml.typ", generated_result)?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_generate_text_style() -> anyhow::Result<()> {
+        let document = Document::new(vec![Paragraph {
+            elements: vec![
+                Text {
+                    text: "bold italic".to_string(),
+                    size: 12,
+                    style: TextStyle {
+                        bold: true,
+                        italic: true,
+                        ..TextStyle::default()
+                    },
+                },
+                Text {
+                    text: " and ".to_string(),
+                    size: 12,
+                    style: TextStyle::default(),
+                },
+                Text {
+                    text: "let s = \"x\";".to_string(),
+                    size: 12,
+                    style: TextStyle::code(),
+                },
+            ],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains(r#"#strong[#emph[bold italic]]; and #raw("let s = \"x\";");"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_adjacent_runs() -> anyhow::Result<()> {
+        let parsed = markdown::Transformer::parse(&Bytes::from(
+            "Some **bold**, and *it*alic [link](https://example.com).",
+        ))?;
+        let (source, _) = generate_document(&parsed)?;
+        assert!(source.contains(
+            "Some #strong[bold];, and #emph[it];alic #link(\"https://example.com\")[link];.\n"
+        ));
+
+        let pdf = crate::pdf::Transformer::generate(&parsed)?;
+        let parsed_pdf = crate::pdf::Transformer::parse(&pdf)?;
+        let elements: Vec<Element> = parsed_pdf.get_detail().into_iter().cloned().collect();
+        let text = crate::core::plain_text(&elements);
+        assert_eq!(text.trim(), "Some bold, and italic link.");
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_file_names() -> anyhow::Result<()> {
+        let image = |title: &str| {
+            Image(ImageData::new(
+                Bytes::from(std::fs::read("test/data/image0.png").unwrap()),
+                title.to_string(),
+                String::new(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            ))
+        };
+        let document = Document::new(vec![image("../../outside"), image("C:\\temp\\..")]);
+        let saved = std::cell::RefCell::new(vec![]);
+        let source = Transformer::generate_with_saver(&document, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let mut saved = saved.into_inner();
+        saved.sort();
+        assert_eq!(saved, ["image.png", "outside.png"]);
+        let source = std::str::from_utf8(&source)?;
+        assert!(source.contains(r#"#image("outside.png""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_code_block() -> anyhow::Result<()> {
+        let document = Document::new(vec![CodeBlock {
+            language: Some("rust".to_string()),
+            code: "fn main() {\n    println!(\"Hello\");\n}\n".to_string(),
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains(
+            r#"#raw("fn main() {\n    println!(\"Hello\");\n}\n", block: true, lang: "rust")"#
+        ));
+        crate::pdf::Transformer::generate(&document)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_breaks() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            Blockquote {
+                elements: vec![Paragraph {
+                    elements: vec![text("Quoted")],
+                }],
+            },
+            ThematicBreak,
+            text("First page"),
+            PageBreak,
+            text("Second page"),
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("#quote(block: true)[\nQuoted\n]"));
+        assert!(source.contains("#pagebreak()"));
+        let pdf = crate::pdf::Transformer::generate(&document)?;
+        let pages = lopdf::Document::load_mem(&pdf)?.get_pages().len();
+        assert_eq!(pages, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_ranges() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            size: 12,
+            style: TextStyle::default(),
+        };
+        let document = Document::new(vec![
+            List {
+                numbered: false,
+                elements: vec![
+                    ListItem::new(Paragraph {
+                        elements: vec![text("First line"), text("next line")],
+                    }),
+                    ListItem::new(List {
+                        numbered: true,
+                        elements: vec![ListItem::new(text("Nested"))],
+                    }),
+                ],
+            },
+            Table {
+                name: None,
+                headers: vec![TableHeader::new(text("Name"), 50.0)],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("Apples"))],
+                }],
+            },
+        ]);
+        let generated = generate_source(&document, true)?;
+        // the ranges of list items and cells follow the indentation and the table around them
+        let elements = document.get_all_elements();
+        let mut texts = vec![];
+        let mut visit = vec![elements[0], elements[1]];
+        while let Some(element) = visit.pop() {
+            match element {
+                List { elements, .. } => visit.extend(elements.iter().map(|item| &item.element)),
+                Paragraph { elements } => visit.extend(elements),
+                Table { headers, rows, .. } => {
+                    visit.extend(headers.iter().flat_map(|header| &header.elements));
+                    visit.extend(
+                        rows.iter()
+                            .flat_map(|row| &row.cells)
+                            .flat_map(|cell| &cell.elements),
+                    );
+                }
+                Text { text, .. } => {
+                    let range = generated.ranges[&(element as *const Element)].clone();
+                    assert!(generated.text[range].contains(text.as_str()));
+                    texts.push(text.as_str());
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(texts.len(), 5);
+        assert!(generated.body > generated.text.find("#outline()").unwrap_or_default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_font_options() -> anyhow::Result<()> {
+        let world =
+            |fonts: &FontOptions| ShivaWorld::with_fonts(String::new(), HashMap::new(), fonts);
+
+        let Err(error) = world(&FontOptions::new().with_embedded_fonts(false)) else {
+            panic!("expected an error without fonts");
+        };
+        assert!(error.to_string().contains("No fonts available"));
+        assert!(world(&FontOptions::new().with_path("no/such/fonts")).is_err());
+        assert!(world(&FontOptions::new().with_data(Bytes::from_static(b"not a font"))).is_err());
+
+        let fonts = FontOptions::new()
+            .with_embedded_fonts(false)
+            .with_data(Bytes::from(std::fs::read("fonts/Roboto-Regular.ttf")?))
+            .with_path("fonts/DejaVuSansMono.ttf");
+        let world = world(&fonts)?;
+        let families: Vec<&str> = world.book().families().map(|(family, _)| family).collect();
+        assert_eq!(families, vec!["DejaVu Sans Mono", "Roboto"]);
+        assert!(world.font(0).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_file() -> anyhow::Result<()> {
+        let world = ShivaWorld::new("#image(\"missing.png\")".to_string(), HashMap::new())?;
+        let id = FileId::new(None, typst::syntax::VirtualPath::new("missing.png"));
+        assert!(matches!(world.file(id), Err(FileError::NotFound(_))));
+
+        let Err(errors) = typst::compile(&world, &mut typst::eval::Tracer::default()) else {
+            panic!("expected the missing image to fail the compilation");
+        };
+        assert!(errors[0].message.contains("missing.png"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_font_cache() -> anyhow::Result<()> {
+        let fonts = FontOptions::new()
+            .with_embedded_fonts(false)
+            .with_path("fonts/PTSans-Regular.ttf");
+        let first = fonts.cached()?;
+        assert!(Arc::ptr_eq(&first, &fonts.cached()?));
+        let other = fonts.clone().with_path("fonts/Ubuntu-Regular.ttf");
+        assert!(!Arc::ptr_eq(&first, &other.cached()?));
+
+        // font data is looked up by its contents, not by the buffer holding it
+        let data = std::fs::read("fonts/Ubuntu-Regular.ttf")?;
+        let with_data = |data: Vec<u8>| {
+            FontOptions::new()
+                .with_embedded_fonts(false)
+                .with_data(Bytes::from(data))
+        };
+        let from_data = with_data(data.clone()).cached()?;
+        assert!(Arc::ptr_eq(&from_data, &with_data(data).cached()?));
+
+        clear_font_cache();
+        assert!(!Arc::ptr_eq(&first, &fonts.cached()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"#set document(title: "Report", author: ("Ann", "Bob"), keywords: ("a", "b"), date: datetime(year: 2024, month: 5, day: 7))
+#set text(lang: "de")
+#set page(header: "Top", footer: [Bottom])
+
+= First *header*
+
+Some *bold* and _italic_ text with `code`,
+a #link("https://example.com")[link] and \#hash.
+
+- one
+- two
+  - nested
++ first
+
+```rust
+fn main() {}
+```
+
+#quote(block: true)[Quoted]
+#line(length: 100%)
+#pagebreak()
+#image("picture.png", alt: "Alt")
+
+#strong("Sheet")
+#table(
+  columns: 2,
+  table.header([A], [B]),
+  table.cell(colspan: 2)[wide],
+  [1], [2],
+)
+"#;
+        let loader = |path: &str| -> anyhow::Result<Bytes> {
+            assert_eq!(path, "picture.png");
+            Ok(Bytes::from_static(b"png"))
+        };
+        let document = Transformer::parse_with_loader(&Bytes::from(source), loader)?;
+        let text = |text: &str, style: TextStyle| Text {
+            text: text.to_string(),
+            size: 12,
+            style,
+        };
+        let plain = |value: &str| text(value, TextStyle::default());
+
+        assert_eq!(document.metadata.title.as_deref(), Some("Report"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ann, Bob"));
+        assert_eq!(document.metadata.keywords, vec!["a", "b"]);
+        assert_eq!(document.metadata.created.as_deref(), Some("2024-05-07"));
+        assert_eq!(document.metadata.language.as_deref(), Some("de"));
+        assert_eq!(document.get_page_header(), vec![&plain("Top")]);
+        assert_eq!(document.get_page_footer(), vec![&plain("Bottom")]);
+
+        let elements = document.get_detail();
+        assert_eq!(
+            elements[0],
+            &Header {
+                level: 1,
+                text: "First header".to_string()
+            }
+        );
+        let bold = TextStyle {
+            bold: true,
+            ..TextStyle::default()
+        };
+        let italic = TextStyle {
+            italic: true,
+            ..TextStyle::default()
+        };
+        assert_eq!(
+            elements[1],
+            &Paragraph {
+                elements: vec![
+                    plain("Some "),
+                    text("bold", bold),
+                    plain(" and "),
+                    text("italic", italic),
+                    plain(" text with "),
+                    text("code", TextStyle::code()),
+                    plain(", a "),
+                    Hyperlink {
+                        title: "link".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 12,
+                    },
+                    plain(" and #hash."),
+                ]
+            }
+        );
+        let item = ListItem::new;
+        assert_eq!(
+            elements[2],
+            &List {
+                elements: vec![
+                    item(plain("one")),
+                    item(plain("two")),
+                    item(List {
+                        elements: vec![item(plain("nested"))],
+                        numbered: false
+                    }),
+                ],
+                numbered: false
+            }
+        );
+        assert!(matches!(elements[3], List { numbered: true, .. }));
+        assert_eq!(
+            elements[4],
+            &CodeBlock {
+                language: Some("rust".to_string()),
+                code: "fn main() {}".to_string()
+            }
+        );
+        assert!(matches!(elements[5], Blockquote { .. }));
+        assert_eq!(elements[6], &ThematicBreak);
+        assert_eq!(elements[7], &PageBreak);
+        let Image(image) = elements[8] else {
+            panic!("expected an image, got {:?}", elements[8]);
+        };
+        assert_eq!((image.title(), image.alt()), ("picture", "Alt"));
+        assert_eq!(image.bytes().as_ref(), b"png");
+
+        let Table {
+            name,
+            headers,
+            rows,
+        } = elements[9]
+        else {
+            panic!("expected a table, got {:?}", elements[9]);
+        };
+        assert_eq!(name.as_deref(), Some("Sheet"));
+        let headers: Vec<String> = headers.iter().map(|header| header.text()).collect();
+        assert_eq!(headers, vec!["A", "B"]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].cells[0].colspan, 2);
+        assert_eq!(rows[1].cells[1].text(), "2");
+        assert_eq!(elements.len(), 10);
+        Ok(())
+    }
+
+    /// Table with merged cells in the text size the typst parser reads back
+    fn spanned_table() -> Element {
+        Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 12), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 12), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 12)).span(1, 2),
+                        TableCell::new(text("First", 12)),
+                        TableCell::new(text("1", 12)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 12)),
+                        TableCell::new(text("2", 12)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![TableCell::with_elements(vec![
+                        text("Sum", 12),
+                        text("of all", 12),
+                    ])
+                    .span(3, 1)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_round_trip() -> anyhow::Result<()> {
+        let mut table = spanned_table();
+        if let Table { name, .. } = &mut table {
+            *name = Some("Totals".to_string());
+        }
+        let elements = vec![
+            Header {
+                level: 2,
+                text: "Chapter #1 = [draft]".to_string(),
+            },
+            Paragraph {
+                elements: vec![
+                    text("Costs -5 $ or 1/2 *each* - see ", 12),
+                    Hyperlink {
+                        title: "the site".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 12,
+                    },
+                ],
+            },
+            Paragraph {
+                elements: vec![text("1. not a list // nor a comment...", 12)],
+            },
+            List {
+                elements: vec![
+                    ListItem::new(text("outer", 12)),
+                    ListItem::new(List {
+                        elements: vec![ListItem::new(text("inner", 12))],
+                        numbered: true,
+                    }),
+                ],
+                numbered: false,
+            },
+            CodeBlock {
+                language: Some("rust".to_string()),
+                code: "let s = \"x\";\n".to_string(),
+            },
+            Image(ImageData::new(
+                Bytes::from_static(b"png"),
+                "chart".to_string(),
+                "A chart".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+            table,
+        ];
+        let mut document = Document::new(elements.clone());
+        document.metadata.title = Some("Round trip".to_string());
+
+        let (source, img_map) = generate_document(&document)?;
+        let loader = |path: &str| -> anyhow::Result<Bytes> {
+            let bytes = img_map
+                .get(path)
+                .ok_or_else(|| anyhow::anyhow!("No image: {path}"))?;
+            Ok(Bytes::from(bytes.to_vec()))
+        };
+        let parsed = Transformer::parse_with_loader(&Bytes::from(source), loader)?;
+        assert_eq!(parsed.metadata.title.as_deref(), Some("Round trip"));
+        let parsed: Vec<Element> = parsed.get_detail().into_iter().cloned().collect();
+        assert_eq!(parsed, elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_setup() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![]);
+        document.set_page_format(PageFormat::Custom(PageDimensions {
+            page_width: 150.0,
+            page_height: 200.0,
+            page_margin_top: 20.0,
+            page_margin_bottom: 15.0,
+            page_margin_left: 12.5,
+            page_margin_right: 12.5,
+        }));
+        document.set_orientation(PageOrientation::Landscape);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("width: 150mm,"));
+        assert!(source.contains("flipped: true,"));
+        assert!(source.contains("margin: (top: 20mm, bottom: 15mm, left: 12.5mm, right: 12.5mm),"));
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(parsed.page_format, document.page_format);
+        assert_eq!(parsed.orientation, PageOrientation::Landscape);
+
+        let source = "#set page(paper: \"us-legal\", margin: (x: 2cm, rest: 1in))\nText";
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        let dimensions = parsed.page_dimensions();
+        assert_eq!(
+            (dimensions.page_width, dimensions.page_height),
+            (216.0, 356.0)
+        );
+        assert_eq!(dimensions.page_margin_left, 20.0);
+        assert_eq!(dimensions.page_margin_top, 25.4);
+        assert_eq!(parsed.orientation, PageOrientation::Portrait);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_document_type() -> anyhow::Result<()> {
+        let document = Document::parse(
+            &Bytes::from("= Title\n\nBody"),
+            crate::core::DocumentType::Typst,
+        )?;
+        let generated = document.generate(crate::core::DocumentType::Typst)?;
+        assert!(std::str::from_utf8(&generated)?.contains("= Title"));
+        assert_eq!(
+            crate::core::DocumentType::from_extension("typ"),
+            Some(crate::core::DocumentType::Typst)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![spanned_table()]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("columns:3,"));
+        assert!(source.contains("table.cell(colspan: 2, rowspan: 1)[#strong[Name];],"));
+        assert!(source.contains("table.cell(colspan: 1, rowspan: 2)[Group],"));
+        assert!(source.contains("table.cell(colspan: 3, rowspan: 1)[Sum\n\nof all],"));
+        crate::pdf::Transformer::generate(&document)?;
+        Ok(())
+    }
 }
@@ -2,7 +2,9 @@ use crate::core::Element::{Table, Text};
 use crate::core::*;
 use bytes::Bytes;
 use calamine::{open_workbook_from_rs, Reader, Xls};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use log::error;
+use std::collections::HashMap;
 use std::io::Cursor;
 
 pub struct Transformer;
@@ -22,39 +24,52 @@ impl TransformerTrait for Transformer {
         for sheet_name in workbook.sheet_names().clone() {
             match workbook.worksheet_range(&sheet_name) {
                 Ok(range) => {
+                    let formulas = workbook.worksheet_formula(&sheet_name).ok();
+                    let (first_row, first_col) = range.start().unwrap_or((0, 0));
                     let mut table_rows: Vec<TableRow> = Vec::new();
                     let mut table_headers: Vec<TableHeader> = Vec::new();
                     let mut is_first_row = true;
 
-                    for row in range.rows() {
+                    for (row_index, row) in (first_row..).zip(range.rows()) {
                         if is_first_row {
                             table_headers = row
                                 .iter()
-                                .map(|header| TableHeader {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
-                                    width: 10.0,
+                                .map(|header| {
+                                    TableHeader::new(
+                                        Text {
+                                            text: header.to_string(),
+                                            size: 8,
+                                            style: TextStyle::default(),
+                                        },
+                                        10.0,
+                                    )
                                 })
                                 .collect();
                             is_first_row = false;
                         } else {
-                            let cells = row
-                                .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
+                            let cells = (first_col..)
+                                .zip(row.iter())
+                                .map(|(col_index, data)| {
+                                    let formula = formulas.as_ref().and_then(|formulas| {
+                                        formulas.get_value((row_index, col_index))
+                                    });
+                                    match CellValue::from_calamine(data, formula) {
+                                        Some(value) => TableCell::with_value(value, 8),
+                                        None => TableCell::new(Text {
+                                            text: String::new(),
+                                            size: 8,
+                                            style: TextStyle::default(),
+                                        }),
+                                    }
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
                         }
                     }
                     data.push(Table {
-                        headers: table_headers.clone(),
-                        rows: table_rows.clone(),
+                        name: Some(sheet_name.clone()),
+                        headers: table_headers,
+                        rows: table_rows,
                     });
                 }
                 Err(err) => {
@@ -66,18 +81,552 @@ impl TransformerTrait for Transformer {
         Ok(Document::new(data))
     }
 
-    fn generate(_document: &Document) -> anyhow::Result<Bytes>
+    fn generate(document: &Document) -> anyhow::Result<Bytes>
     where
         Self: Sized,
     {
-        todo!()
+        let elements = document.get_all_elements();
+        let mut tables: Vec<(&[TableHeader], &[TableRow])> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Table { headers, rows, .. } => Some((headers.as_slice(), rows.as_slice())),
+                _ => None,
+            })
+            .collect();
+        let mut names = sheet_names(elements.iter().copied());
+        // a workbook has at least one sheet
+        if tables.is_empty() {
+            tables.push((&[], &[]));
+            names.push("Sheet1".to_string());
+        }
+
+        let mut strings = SharedStrings::default();
+        let mut sheets = vec![];
+        for (index, (headers, rows)) in tables.into_iter().enumerate() {
+            sheets.push(sheet_stream(headers, rows, index == 0, &mut strings)?);
+        }
+
+        let workbook = workbook_stream(&names, &sheets, &strings);
+        Ok(Bytes::from(compound_file(WORKBOOK_STREAM, &workbook)))
+    }
+}
+
+// BIFF8 record types, see [MS-XLS] 2.3
+const BOF: u16 = 0x0809;
+const EOF: u16 = 0x000A;
+const CODEPAGE: u16 = 0x0042;
+const WINDOW1: u16 = 0x003D;
+const FONT: u16 = 0x0031;
+const FORMAT: u16 = 0x041E;
+const XF: u16 = 0x00E0;
+const STYLE: u16 = 0x0293;
+const BOUNDSHEET: u16 = 0x0085;
+const SST: u16 = 0x00FC;
+const CONTINUE: u16 = 0x003C;
+const EXTSST: u16 = 0x00FF;
+const DIMENSIONS: u16 = 0x0200;
+const NUMBER: u16 = 0x0203;
+const BOOLERR: u16 = 0x0205;
+const LABELSST: u16 = 0x00FD;
+const WINDOW2: u16 = 0x023E;
+const MERGEDCELLS: u16 = 0x00E5;
+
+const MAX_RECORD_DATA: usize = 8224;
+const MAX_ROWS: u32 = 65536;
+const MAX_COLUMNS: usize = 256;
+
+// cell formats after the 15 style formats and the default cell format
+const XF_GENERAL: u16 = 15;
+const XF_DATE: u16 = 16;
+const XF_DATETIME: u16 = 17;
+const XF_DURATION: u16 = 18;
+const NUMBER_FORMATS: [(u16, &str); 3] = [
+    (164, "yyyy-mm-dd"),
+    (165, "yyyy-mm-dd hh:mm:ss"),
+    (166, "[h]:mm:ss"),
+];
+
+const WORKBOOK_STREAM: &str = "Workbook";
+
+fn record(stream: &mut Vec<u8>, record_type: u16, data: &[u8]) {
+    debug_assert!(data.len() <= MAX_RECORD_DATA);
+    stream.extend_from_slice(&record_type.to_le_bytes());
+    stream.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    stream.extend_from_slice(data);
+}
+
+fn bof(stream: &mut Vec<u8>, substream_type: u16) {
+    let mut data = vec![];
+    data.extend_from_slice(&0x0600u16.to_le_bytes()); // BIFF8
+    data.extend_from_slice(&substream_type.to_le_bytes());
+    data.extend_from_slice(&0x0DBBu16.to_le_bytes()); // build
+    data.extend_from_slice(&0x07CCu16.to_le_bytes()); // year
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&0x0006u32.to_le_bytes()); // lowest BIFF version
+    record(stream, BOF, &data);
+}
+
+// string with an 8 or 16 bit length, always stored as UTF-16
+fn unicode_string(text: &str, wide_length: bool) -> Vec<u8> {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let mut data = vec![];
+    if wide_length {
+        data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+    } else {
+        data.push(units.len() as u8);
     }
+    data.push(0x01);
+    units
+        .iter()
+        .for_each(|unit| data.extend_from_slice(&unit.to_le_bytes()));
+    data
+}
+
+/// Strings of all sheets, cells refer to them by index
+#[derive(Default)]
+struct SharedStrings {
+    strings: Vec<String>,
+    indexes: HashMap<String, u32>,
+    total: u32,
+}
+
+impl SharedStrings {
+    fn index(&mut self, text: &str) -> u32 {
+        self.total += 1;
+        if let Some(index) = self.indexes.get(text) {
+            return *index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(text.to_string());
+        self.indexes.insert(text.to_string(), index);
+        index
+    }
+
+    // SST record and its CONTINUE records, followed by the EXTSST lookup table
+    fn write(&self, stream: &mut Vec<u8>) {
+        let mut records: Vec<Vec<u8>> = vec![];
+        let mut data = vec![];
+        data.extend_from_slice(&self.total.to_le_bytes());
+        data.extend_from_slice(&(self.strings.len() as u32).to_le_bytes());
+        // record and offset of every bucket's first string
+        let bucket_size = self.strings.len().div_ceil(127).max(8);
+        let mut buckets = vec![];
+        for (index, text) in self.strings.iter().enumerate() {
+            let units: Vec<u16> = text.encode_utf16().collect();
+            // a string header is never split and is followed by at least one character
+            if data.len() + 5 > MAX_RECORD_DATA {
+                records.push(std::mem::take(&mut data));
+            }
+            if index % bucket_size == 0 {
+                buckets.push((records.len(), data.len()));
+            }
+            data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+            data.push(0x01);
+            let mut rest = units.as_slice();
+            loop {
+                let count = rest.len().min((MAX_RECORD_DATA - data.len()) / 2);
+                rest[..count]
+                    .iter()
+                    .for_each(|unit| data.extend_from_slice(&unit.to_le_bytes()));
+                rest = &rest[count..];
+                if rest.is_empty() {
+                    break;
+                }
+                // a continued string starts with its flags again
+                records.push(std::mem::take(&mut data));
+                data.push(0x01);
+            }
+        }
+        records.push(data);
+
+        let mut record_positions = vec![];
+        for (index, data) in records.iter().enumerate() {
+            record_positions.push(stream.len());
+            record(stream, if index == 0 { SST } else { CONTINUE }, data);
+        }
+
+        let mut data = vec![];
+        data.extend_from_slice(&(bucket_size as u16).to_le_bytes());
+        for (record_index, offset) in buckets {
+            let record_offset = offset + 4;
+            let position = record_positions[record_index] + record_offset;
+            data.extend_from_slice(&(position as u32).to_le_bytes());
+            data.extend_from_slice(&(record_offset as u16).to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+        }
+        record(stream, EXTSST, &data);
+    }
+}
+
+// workbook globals followed by the sheets, each sheet's position is stored in its BOUNDSHEET record
+fn workbook_stream(names: &[String], sheets: &[Vec<u8>], strings: &SharedStrings) -> Vec<u8> {
+    let mut stream = vec![];
+    bof(&mut stream, 0x0005);
+    record(&mut stream, CODEPAGE, &1200u16.to_le_bytes()); // UTF-16
+
+    let mut window = vec![];
+    for value in [0u16, 0, 0x3000, 0x2000, 0x0038, 0, 0, 1, 0x0258] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    record(&mut stream, WINDOW1, &window);
+
+    // font 4 does not exist, the first four fonts are the defaults
+    let mut font = vec![];
+    for value in [200u16, 0, 0x7FFF, 400, 0] {
+        font.extend_from_slice(&value.to_le_bytes());
+    }
+    font.extend_from_slice(&[0, 0, 0, 0]);
+    font.extend_from_slice(&unicode_string("Arial", false));
+    for _ in 0..4 {
+        record(&mut stream, FONT, &font);
+    }
+
+    for (index, format) in NUMBER_FORMATS {
+        let mut data = index.to_le_bytes().to_vec();
+        data.extend_from_slice(&unicode_string(format, true));
+        record(&mut stream, FORMAT, &data);
+    }
+
+    let xf = |number_format: u16, flags: u16, used: u8| {
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_le_bytes()); // font
+        data.extend_from_slice(&number_format.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(&[0x20, 0, 0, used]); // bottom aligned
+        data.extend_from_slice(&[0; 8]); // no borders
+        data.extend_from_slice(&0x20C0u16.to_le_bytes()); // default colors
+        data
+    };
+    for _ in 0..15 {
+        record(&mut stream, XF, &xf(0, 0xFFF5, 0));
+    }
+    record(&mut stream, XF, &xf(0, 0x0001, 0));
+    for (index, _) in NUMBER_FORMATS {
+        record(&mut stream, XF, &xf(index, 0x0001, 0x04));
+    }
+    record(&mut stream, STYLE, &[0x00, 0x80, 0x00, 0xFF]); // Normal
+
+    let mut sheet_positions = vec![];
+    for name in names {
+        sheet_positions.push(stream.len() + 4);
+        let mut data = vec![0; 6];
+        data.extend_from_slice(&unicode_string(name, false));
+        record(&mut stream, BOUNDSHEET, &data);
+    }
+
+    strings.write(&mut stream);
+    record(&mut stream, EOF, &[]);
+
+    for (sheet, position) in sheets.iter().zip(sheet_positions) {
+        let offset = (stream.len() as u32).to_le_bytes();
+        stream[position..position + 4].copy_from_slice(&offset);
+        stream.extend_from_slice(sheet);
+    }
+    stream
+}
+
+fn sheet_stream(
+    headers: &[TableHeader],
+    rows: &[TableRow],
+    selected: bool,
+    strings: &mut SharedStrings,
+) -> anyhow::Result<Vec<u8>> {
+    let (header_columns, row_columns) = table_columns(headers, rows);
+    let header_cells = headers.iter().zip(header_columns).map(|(header, column)| {
+        (
+            0,
+            column,
+            header.colspan,
+            header.rowspan,
+            &header.elements,
+            None,
+        )
+    });
+    let row_cells =
+        (1..)
+            .zip(rows.iter().zip(row_columns))
+            .flat_map(|(row_index, (row, columns))| {
+                row.cells.iter().zip(columns).map(move |(cell, column)| {
+                    let value = cell.value.as_ref();
+                    (
+                        row_index,
+                        column,
+                        cell.colspan,
+                        cell.rowspan,
+                        &cell.elements,
+                        value,
+                    )
+                })
+            });
+
+    let mut cells = vec![];
+    let mut merged = vec![];
+    let (mut last_row, mut last_column) = (0, 0);
+    for (row, column, colspan, rowspan, elements, value) in header_cells.chain(row_cells) {
+        let (end_row, end_column) = (row + rowspan as u32 - 1, column + colspan - 1);
+        if end_row >= MAX_ROWS || end_column >= MAX_COLUMNS {
+            return Err(anyhow::anyhow!(
+                "XLS sheets are limited to {MAX_ROWS} rows and {MAX_COLUMNS} columns"
+            ));
+        }
+        last_row = last_row.max(end_row);
+        last_column = last_column.max(end_column);
+        if colspan > 1 || rowspan > 1 {
+            merged.push([row as u16, end_row as u16, column as u16, end_column as u16]);
+        }
+        write_cell(
+            &mut cells,
+            (row as u16, column as u16),
+            elements,
+            value,
+            strings,
+        );
+    }
+
+    let mut stream = vec![];
+    bof(&mut stream, 0x0010);
+    let mut dimensions = vec![];
+    dimensions.extend_from_slice(&0u32.to_le_bytes());
+    dimensions.extend_from_slice(&(last_row + 1).to_le_bytes());
+    dimensions.extend_from_slice(&0u16.to_le_bytes());
+    dimensions.extend_from_slice(&(last_column as u16 + 1).to_le_bytes());
+    dimensions.extend_from_slice(&0u16.to_le_bytes());
+    record(&mut stream, DIMENSIONS, &dimensions);
+    stream.extend_from_slice(&cells);
+
+    // grid lines, headings and zeros shown, the first sheet is the active one
+    let flags: u16 = if selected { 0x06B6 } else { 0x00B6 };
+    let mut window = flags.to_le_bytes().to_vec();
+    window.extend_from_slice(&[0; 4]);
+    window.extend_from_slice(&64u32.to_le_bytes());
+    window.extend_from_slice(&[0; 8]);
+    record(&mut stream, WINDOW2, &window);
+
+    for ranges in merged.chunks(1026) {
+        let mut data = (ranges.len() as u16).to_le_bytes().to_vec();
+        ranges
+            .iter()
+            .flatten()
+            .for_each(|value| data.extend_from_slice(&value.to_le_bytes()));
+        record(&mut stream, MERGEDCELLS, &data);
+    }
+    record(&mut stream, EOF, &[]);
+    Ok(stream)
+}
+
+// cell record of a typed value or of the text of the cell, formulas are written as their cached value
+fn write_cell(
+    stream: &mut Vec<u8>,
+    (row, column): (u16, u16),
+    elements: &[Element],
+    value: Option<&CellValue>,
+    strings: &mut SharedStrings,
+) {
+    let mut data = vec![];
+    data.extend_from_slice(&row.to_le_bytes());
+    data.extend_from_slice(&column.to_le_bytes());
+    let mut number = |data: &mut Vec<u8>, number: f64, xf: u16| {
+        data.extend_from_slice(&xf.to_le_bytes());
+        data.extend_from_slice(&number.to_le_bytes());
+        record(stream, NUMBER, data);
+    };
+    let value = match value {
+        Some(CellValue::Formula { cached, .. }) => cached.as_deref(),
+        value => value,
+    };
+    match value {
+        Some(CellValue::Int(value)) => number(&mut data, *value as f64, XF_GENERAL),
+        Some(CellValue::Float(value)) => number(&mut data, *value, XF_GENERAL),
+        Some(CellValue::DateTime(datetime)) if excel_serial(datetime).is_some() => {
+            let xf = if datetime.contains('T') {
+                XF_DATETIME
+            } else {
+                XF_DATE
+            };
+            number(&mut data, excel_serial(datetime).unwrap_or_default(), xf)
+        }
+        Some(CellValue::Duration(seconds)) => number(&mut data, seconds / 86400.0, XF_DURATION),
+        Some(CellValue::Bool(value)) => {
+            data.extend_from_slice(&XF_GENERAL.to_le_bytes());
+            data.extend_from_slice(&[*value as u8, 0]);
+            record(stream, BOOLERR, &data);
+        }
+        Some(CellValue::Error(error)) if error_code(error).is_some() => {
+            data.extend_from_slice(&XF_GENERAL.to_le_bytes());
+            data.extend_from_slice(&[error_code(error).unwrap_or_default(), 1]);
+            record(stream, BOOLERR, &data);
+        }
+        _ => {
+            let text = match value {
+                Some(value) => value.to_string(),
+                None => plain_text(elements),
+            };
+            if text.is_empty() {
+                return;
+            }
+            data.extend_from_slice(&XF_GENERAL.to_le_bytes());
+            data.extend_from_slice(&strings.index(&text).to_le_bytes());
+            record(stream, LABELSST, &data);
+        }
+    }
+}
+
+// days since 1899-12-30 of an ISO 8601 date or date and time
+fn excel_serial(datetime: &str) -> Option<f64> {
+    let datetime = datetime
+        .parse::<NaiveDateTime>()
+        .or_else(|_| {
+            datetime
+                .parse::<NaiveDate>()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .ok()?;
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_time(NaiveTime::MIN);
+    Some((datetime - epoch).num_milliseconds() as f64 / 86_400_000.0)
+}
+
+fn error_code(error: &str) -> Option<u8> {
+    match error {
+        "#NULL!" => Some(0x00),
+        "#DIV/0!" => Some(0x07),
+        "#VALUE!" => Some(0x0F),
+        "#REF!" => Some(0x17),
+        "#NAME?" => Some(0x1D),
+        "#NUM!" => Some(0x24),
+        "#N/A" => Some(0x2A),
+        _ => None,
+    }
+}
+
+// Compound File Binary container ([MS-CFB] version 3) holding a single stream
+fn compound_file(name: &str, stream: &[u8]) -> Vec<u8> {
+    const SECTOR_SIZE: usize = 512;
+    const IDS_PER_SECTOR: usize = SECTOR_SIZE / 4;
+    const HEADER_FAT_IDS: usize = 109;
+    const FREE: u32 = 0xFFFF_FFFF;
+    const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+    const FAT_SECTOR: u32 = 0xFFFF_FFFD;
+    const DIFAT_SECTOR: u32 = 0xFFFF_FFFC;
+    // smaller streams would belong to the mini stream
+    const MINI_STREAM_CUTOFF: usize = 4096;
+
+    let mut stream = stream.to_vec();
+    if stream.len() < MINI_STREAM_CUTOFF {
+        stream.resize(MINI_STREAM_CUTOFF, 0);
+    }
+    let stream_sectors = stream.len().div_ceil(SECTOR_SIZE);
+    let directory_sector = stream_sectors;
+    // the FAT also lists its own sectors and the DIFAT sectors
+    let difat_size = |fat_sectors: usize| {
+        fat_sectors
+            .saturating_sub(HEADER_FAT_IDS)
+            .div_ceil(IDS_PER_SECTOR - 1)
+    };
+    let mut fat_sectors: usize = 1;
+    while fat_sectors * IDS_PER_SECTOR < stream_sectors + 1 + fat_sectors + difat_size(fat_sectors)
+    {
+        fat_sectors += 1;
+    }
+    let difat_sectors = difat_size(fat_sectors);
+    let first_fat = directory_sector + 1;
+    let first_difat = first_fat + fat_sectors;
+    let fat_ids: Vec<u32> = (first_fat..first_difat).map(|id| id as u32).collect();
+
+    let mut fat = vec![FREE; fat_sectors * IDS_PER_SECTOR];
+    for (id, next) in fat.iter_mut().enumerate().take(stream_sectors) {
+        *next = (id + 1) as u32;
+    }
+    fat[stream_sectors - 1] = END_OF_CHAIN;
+    fat[directory_sector] = END_OF_CHAIN;
+    fat[first_fat..first_difat].fill(FAT_SECTOR);
+    fat[first_difat..first_difat + difat_sectors].fill(DIFAT_SECTOR);
+
+    let mut file = Vec::with_capacity((1 + first_difat + difat_sectors) * SECTOR_SIZE);
+    file.extend_from_slice(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+    file.extend_from_slice(&[0; 16]);
+    for value in [0x003Eu16, 0x0003, 0xFFFE, 9, 6] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    file.extend_from_slice(&[0; 6]);
+    let difat_start = if difat_sectors > 0 {
+        first_difat as u32
+    } else {
+        END_OF_CHAIN
+    };
+    for value in [
+        0,
+        fat_sectors as u32,
+        directory_sector as u32,
+        0,
+        MINI_STREAM_CUTOFF as u32,
+        END_OF_CHAIN,
+        0,
+        difat_start,
+        difat_sectors as u32,
+    ] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    for index in 0..HEADER_FAT_IDS {
+        let id = fat_ids.get(index).copied().unwrap_or(FREE);
+        file.extend_from_slice(&id.to_le_bytes());
+    }
+
+    file.extend_from_slice(&stream);
+    file.resize(SECTOR_SIZE * (1 + stream_sectors), 0);
+
+    let entry = |name: &str, entry_type: u8, child: u32, start: u32, size: usize| {
+        let mut entry = vec![0; 128];
+        let units: Vec<u16> = name.encode_utf16().collect();
+        for (index, unit) in units.iter().enumerate() {
+            entry[index * 2..index * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        let name_size = if name.is_empty() {
+            0
+        } else {
+            (units.len() as u16 + 1) * 2
+        };
+        entry[64..66].copy_from_slice(&name_size.to_le_bytes());
+        entry[66] = entry_type;
+        entry[67] = if entry_type == 0 { 0 } else { 1 }; // black
+        entry[68..72].copy_from_slice(&FREE.to_le_bytes());
+        entry[72..76].copy_from_slice(&FREE.to_le_bytes());
+        entry[76..80].copy_from_slice(&child.to_le_bytes());
+        entry[116..120].copy_from_slice(&start.to_le_bytes());
+        entry[120..124].copy_from_slice(&(size as u32).to_le_bytes());
+        entry
+    };
+    file.extend_from_slice(&entry("Root Entry", 5, 1, END_OF_CHAIN, 0));
+    file.extend_from_slice(&entry(name, 2, FREE, 0, stream.len()));
+    file.extend_from_slice(&entry("", 0, FREE, 0, 0));
+    file.extend_from_slice(&entry("", 0, FREE, 0, 0));
+
+    fat.iter()
+        .for_each(|id| file.extend_from_slice(&id.to_le_bytes()));
+
+    // FAT sectors beyond the header are listed in a chain of DIFAT sectors
+    for (index, ids) in fat_ids
+        .get(HEADER_FAT_IDS..)
+        .unwrap_or_default()
+        .chunks(IDS_PER_SECTOR - 1)
+        .enumerate()
+    {
+        let mut sector = vec![FREE; IDS_PER_SECTOR];
+        sector[..ids.len()].copy_from_slice(ids);
+        sector[IDS_PER_SECTOR - 1] = if index + 1 < difat_sectors {
+            (first_difat + index + 1) as u32
+        } else {
+            END_OF_CHAIN
+        };
+        sector
+            .iter()
+            .for_each(|id| file.extend_from_slice(&id.to_le_bytes()));
+    }
+    file
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
-    use crate::text;
+    use crate::core::tests::{init_logger, text};
     use crate::xls::*;
     use anyhow::Ok;
     use bytes::Bytes;
@@ -100,11 +649,172 @@ mod tests {
 
         debug!("Parsed document: {:#?}", parsed);
 
-        let generated_result = text::Transformer::generate(&parsed);
+        let generated_result = crate::text::Transformer::generate(&parsed);
         let generated_bytes = generated_result?;
         let generated_text = std::str::from_utf8(&generated_bytes)?;
         info!("{}", generated_text);
 
         Ok(())
     }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let document = Transformer::parse(&Bytes::from(std::fs::read("test/data/document.xls")?))?;
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let texts = |document: &Document| {
+            crate::text::Transformer::generate(document).map(|text| text.to_vec())
+        };
+        assert_eq!(texts(&parsed)?, texts(&document)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_cells() -> anyhow::Result<()> {
+        let values = vec![
+            CellValue::String("Apples".to_string()),
+            CellValue::Int(42),
+            CellValue::Float(2.5),
+            CellValue::Bool(true),
+            CellValue::DateTime("2024-05-01".to_string()),
+            CellValue::DateTime("2024-05-01T10:30:00".to_string()),
+            CellValue::Duration(45015.0),
+            CellValue::Formula {
+                formula: "B2*2".to_string(),
+                cached: Some(Box::new(CellValue::Float(84.0))),
+            },
+        ];
+        // the formula doubles the number in the second column
+        let headers = (1..=values.len())
+            .map(|column| TableHeader::new(text(&format!("Column {column}"), 8), 10.0))
+            .collect();
+        let cells = values
+            .into_iter()
+            .map(|value| TableCell::with_value(value, 8))
+            .collect();
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers,
+            rows: vec![TableRow { cells }],
+        }]);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let Table { rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        let values: Vec<Option<CellValue>> = rows[0]
+            .cells
+            .iter()
+            .map(|cell| cell.value.clone())
+            .collect();
+        // numbers are stored as floats and formulas as their cached value
+        assert_eq!(
+            values,
+            vec![
+                Some(CellValue::String("Apples".to_string())),
+                Some(CellValue::Float(42.0)),
+                Some(CellValue::Float(2.5)),
+                Some(CellValue::Bool(true)),
+                Some(CellValue::DateTime("2024-05-01".to_string())),
+                Some(CellValue::DateTime("2024-05-01T10:30:00".to_string())),
+                Some(CellValue::Duration(45015.0)),
+                Some(CellValue::Float(84.0)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sheet_names() -> anyhow::Result<()> {
+        let mut elements: Vec<Element> = ["Q1 Sales", "Q2 Sales"]
+            .into_iter()
+            .map(|name| Element::Table {
+                name: Some(name.to_string()),
+                headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text(&name[..2], 8))],
+                }],
+            })
+            .collect();
+        elements.push(Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        });
+        let parsed = Transformer::parse(&Transformer::generate(&Document::new(elements))?)?;
+        let names: Vec<Option<String>> = parsed
+            .get_all_elements()
+            .into_iter()
+            .map(|element| match element {
+                Table { name, .. } => name.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                Some("Q1 Sales".to_string()),
+                Some("Q2 Sales".to_string()),
+                Some("Sheet3".to_string())
+            ]
+        );
+        let Table { rows, .. } = parsed.get_all_elements()[2] else {
+            panic!("Expected table");
+        };
+        assert_eq!(rows[2].cells[0].text(), "Sum\nof all");
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_workbook() -> anyhow::Result<()> {
+        // strings span several CONTINUE records and the FAT needs DIFAT sectors
+        let text = |text: String| Text {
+            text,
+            size: 8,
+            style: TextStyle::default(),
+        };
+        let rows: Vec<TableRow> = (0..60000)
+            .map(|index| TableRow {
+                cells: vec![TableCell::new(text(format!("{index:0>60}")))],
+            })
+            .collect();
+        let document = Document::new(vec![Table {
+            name: None,
+            headers: vec![TableHeader::new(text("x".repeat(5000)), 10.0)],
+            rows,
+        }]);
+        let generated = Transformer::generate(&document)?;
+        assert!(generated.len() > 109 * 128 * 512);
+        let parsed = Transformer::parse(&generated)?;
+        let Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        assert_eq!(headers[0].text(), "x".repeat(5000));
+        assert_eq!(rows.len(), 60000);
+        assert_eq!(rows[59999].cells[0].text(), format!("{:0>60}", 59999));
+        Ok(())
+    }
 }
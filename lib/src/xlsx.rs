@@ -22,39 +22,52 @@ impl TransformerTrait for Transformer {
         for sheet_name in workbook.sheet_names().clone() {
             match workbook.worksheet_range(&sheet_name) {
                 Ok(range) => {
+                    let formulas = workbook.worksheet_formula(&sheet_name).ok();
+                    let (first_row, first_col) = range.start().unwrap_or((0, 0));
                     let mut table_rows: Vec<TableRow> = Vec::new();
                     let mut table_headers: Vec<TableHeader> = Vec::new();
                     let mut is_first_row = true;
 
-                    for row in range.rows() {
+                    for (row_index, row) in (first_row..).zip(range.rows()) {
                         if is_first_row {
                             table_headers = row
                                 .iter()
-                                .map(|header| TableHeader {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
-                                    width: 10.0,
+                                .map(|header| {
+                                    TableHeader::new(
+                                        Text {
+                                            text: header.to_string(),
+                                            size: 8,
+                                            style: TextStyle::default(),
+                                        },
+                                        10.0,
+                                    )
                                 })
                                 .collect();
                             is_first_row = false;
                         } else {
-                            let cells = row
-                                .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
+                            let cells = (first_col..)
+                                .zip(row.iter())
+                                .map(|(col_index, data)| {
+                                    let formula = formulas.as_ref().and_then(|formulas| {
+                                        formulas.get_value((row_index, col_index))
+                                    });
+                                    match CellValue::from_calamine(data, formula) {
+                                        Some(value) => TableCell::with_value(value, 8),
+                                        None => TableCell::new(Text {
+                                            text: String::new(),
+                                            size: 8,
+                                            style: TextStyle::default(),
+                                        }),
+                                    }
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
                         }
                     }
                     data.push(Table {
-                        headers: table_headers.clone(),
-                        rows: table_rows.clone(),
+                        name: Some(sheet_name.clone()),
+                        headers: table_headers,
+                        rows: table_rows,
                     });
                 }
                 Err(err) => {
@@ -71,42 +84,158 @@ impl TransformerTrait for Transformer {
         Self: Sized,
     {
         let mut workbook = Workbook::new();
-        fn generate_element(element: &Element, workbook: &mut Workbook) -> anyhow::Result<()> {
-            if let Table { headers, rows } = element {
-                let worksheet = workbook.add_worksheet();
-                let mut row_index = 1;
-                let mut col_index = 0;
-                for header in headers {
-                    if let Text { text, .. } = header.element.clone() {
-                        worksheet.write_string(0, col_index, text)?;
-                        col_index += 1;
-                    }
+        fn generate_element(
+            element: &Element,
+            workbook: &mut Workbook,
+            sheet_name: &str,
+        ) -> anyhow::Result<()> {
+            if let Table { headers, rows, .. } = element {
+                let worksheet = workbook.add_worksheet().set_name(sheet_name)?;
+                let (header_columns, row_columns) = table_columns(headers, rows);
+                for (header, col_index) in headers.iter().zip(header_columns) {
+                    write_cell(
+                        worksheet,
+                        (0, col_index),
+                        (header.colspan, header.rowspan),
+                        &header.elements,
+                        None,
+                    )?;
                 }
 
-                for row in rows {
-                    let mut col_index = 0;
-                    for cell in row.cells.iter() {
-                        if let Text { text, .. } = cell.element.clone() {
-                            worksheet.write_string(row_index, col_index, text)?;
-                            col_index += 1;
-                        }
+                for (row_index, (row, columns)) in (1..).zip(rows.iter().zip(row_columns)) {
+                    for (cell, col_index) in row.cells.iter().zip(columns) {
+                        write_cell(
+                            worksheet,
+                            (row_index, col_index),
+                            (cell.colspan, cell.rowspan),
+                            &cell.elements,
+                            cell.value.as_ref(),
+                        )?;
                     }
-                    row_index += 1;
                 }
             }
             Ok(())
         }
-        for element in &document.get_detail() {
-            generate_element(element, &mut workbook)?;
+        let elements = document.get_detail();
+        let tables = elements
+            .iter()
+            .filter(|element| matches!(element, Table { .. }));
+        for (element, sheet_name) in tables.zip(sheet_names(elements.iter().copied())) {
+            generate_element(element, &mut workbook, &sheet_name)?;
         }
         let xlsx_data = workbook.save_to_buffer()?;
         Ok(Bytes::from(xlsx_data))
     }
 }
 
+// write a cell, merging the range it spans
+fn write_cell(
+    worksheet: &mut Worksheet,
+    (row, col): (u32, usize),
+    (colspan, rowspan): (usize, usize),
+    elements: &[Element],
+    value: Option<&CellValue>,
+) -> anyhow::Result<()> {
+    let format = match elements {
+        [Text { style, .. }] => text_format(style),
+        _ => Format::new(),
+    };
+    let text = plain_text(elements);
+    let col = col as u16;
+    if colspan > 1 || rowspan > 1 {
+        worksheet.merge_range(
+            row,
+            col,
+            row + rowspan as u32 - 1,
+            col + colspan as u16 - 1,
+            &text,
+            &format,
+        )?;
+    } else if value.is_none() {
+        worksheet.write_string_with_format(row, col, &text, &format)?;
+    }
+    // a merged range starts with a string, its first cell is overwritten with the typed value
+    if let Some(value) = value {
+        write_value(worksheet, (row, col), value, format)?;
+    }
+    Ok(())
+}
+
+// write a typed value as a native cell, errors are kept as their text
+fn write_value(
+    worksheet: &mut Worksheet,
+    (row, col): (u32, u16),
+    value: &CellValue,
+    format: Format,
+) -> anyhow::Result<()> {
+    match value {
+        CellValue::String(text) | CellValue::Error(text) => {
+            worksheet.write_string_with_format(row, col, text, &format)?;
+        }
+        CellValue::Int(number) => {
+            worksheet.write_number_with_format(row, col, *number as f64, &format)?;
+        }
+        CellValue::Float(number) => {
+            worksheet.write_number_with_format(row, col, *number, &format)?;
+        }
+        CellValue::Bool(boolean) => {
+            worksheet.write_boolean_with_format(row, col, *boolean, &format)?;
+        }
+        CellValue::DateTime(datetime) => {
+            let number_format = if datetime.contains('T') {
+                "yyyy-mm-dd hh:mm:ss"
+            } else {
+                "yyyy-mm-dd"
+            };
+            match ExcelDateTime::parse_from_str(datetime) {
+                Ok(datetime) => {
+                    let format = format.set_num_format(number_format);
+                    worksheet.write_datetime_with_format(row, col, &datetime, &format)?;
+                }
+                Err(_) => {
+                    worksheet.write_string_with_format(row, col, datetime, &format)?;
+                }
+            }
+        }
+        CellValue::Duration(seconds) => {
+            let format = format.set_num_format("[h]:mm:ss");
+            worksheet.write_number_with_format(row, col, seconds / 86400.0, &format)?;
+        }
+        CellValue::Formula { formula, cached } => {
+            let mut formula = Formula::new(formula);
+            if let Some(cached) = cached {
+                formula = formula.set_result(cached.to_string());
+            }
+            worksheet.write_formula_with_format(row, col, formula, &format)?;
+        }
+    }
+    Ok(())
+}
+
+// cell format for the inline formatting of a text cell
+fn text_format(style: &TextStyle) -> Format {
+    let mut format = Format::new();
+    if style.bold {
+        format = format.set_bold();
+    }
+    if style.italic {
+        format = format.set_italic();
+    }
+    if style.underline {
+        format = format.set_underline(FormatUnderline::Single);
+    }
+    if style.strikethrough {
+        format = format.set_font_strikethrough();
+    }
+    if style.code {
+        format = format.set_font_name("Courier New");
+    }
+    format
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, text};
     use crate::xlsx::*;
     use anyhow::Ok;
     use bytes::Bytes;
@@ -150,4 +279,145 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_generate_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        // merged ranges keep their text in the top left cell
+        assert_eq!(headers[0].text(), "Name");
+        assert_eq!(headers[2].text(), "Total");
+        assert_eq!(rows[1].cells[1].text(), "Second");
+        assert_eq!(rows[2].cells[0].text(), "Sum\nof all");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sheet_names() -> anyhow::Result<()> {
+        let document = Document::new(
+            ["Q1 Sales", "Q2 Sales"]
+                .into_iter()
+                .map(|name| Element::Table {
+                    name: Some(name.to_string()),
+                    headers: vec![TableHeader::new(text("Region", 8), 10.0)],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text(&name[..2], 8))],
+                    }],
+                })
+                .collect(),
+        );
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let expected = vec![Some("Q1 Sales".to_string()), Some("Q2 Sales".to_string())];
+        assert_eq!(table_names(&parsed), expected);
+
+        // XLSX -> ODS -> XLSX keeps the sheet names and order
+        let ods = crate::ods::Transformer::generate(&parsed)?;
+        let parsed = crate::ods::Transformer::parse(&ods)?;
+        let parsed = Transformer::parse(&Transformer::generate(&parsed)?)?;
+        assert_eq!(table_names(&parsed), expected);
+        Ok(())
+    }
+
+    fn table_names(document: &Document) -> Vec<Option<String>> {
+        document
+            .get_all_elements()
+            .into_iter()
+            .map(|element| match element {
+                Table { name, .. } => name.clone(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    const FORMULA: &str = "B2*2";
+
+    #[test]
+    fn test_typed_cells() -> anyhow::Result<()> {
+        let values = vec![
+            CellValue::String("Apples".to_string()),
+            CellValue::Int(42),
+            CellValue::Float(2.5),
+            CellValue::Bool(true),
+            CellValue::DateTime("2024-05-01".to_string()),
+            CellValue::DateTime("2024-05-01T10:30:00".to_string()),
+            CellValue::Duration(45015.0),
+            CellValue::Formula {
+                formula: FORMULA.to_string(),
+                cached: Some(Box::new(CellValue::Float(84.0))),
+            },
+        ];
+        // the formula doubles the number in the second column
+        let headers = (1..=values.len())
+            .map(|column| TableHeader::new(text(&format!("Column {column}"), 8), 10.0))
+            .collect();
+        let cells = values
+            .into_iter()
+            .map(|value| TableCell::with_value(value, 8))
+            .collect();
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers,
+            rows: vec![TableRow { cells }],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let Table { rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("Expected table");
+        };
+        let values: Vec<Option<CellValue>> = rows[0]
+            .cells
+            .iter()
+            .map(|cell| cell.value.clone())
+            .collect();
+        // spreadsheets store every number as a float
+        assert_eq!(
+            values,
+            vec![
+                Some(CellValue::String("Apples".to_string())),
+                Some(CellValue::Float(42.0)),
+                Some(CellValue::Float(2.5)),
+                Some(CellValue::Bool(true)),
+                Some(CellValue::DateTime("2024-05-01".to_string())),
+                Some(CellValue::DateTime("2024-05-01T10:30:00".to_string())),
+                Some(CellValue::Duration(45015.0)),
+                Some(CellValue::Formula {
+                    formula: FORMULA.to_string(),
+                    cached: Some(Box::new(CellValue::Float(84.0))),
+                }),
+            ]
+        );
+        assert_eq!(rows[0].cells[6].text(), "12:30:15");
+        Ok(())
+    }
 }
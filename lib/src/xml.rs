@@ -2,14 +2,15 @@ use anyhow::Result;
 use bytes::Bytes;
 use log::error;
 use quick_xml::{
-    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event},
     Reader, Writer,
 };
 use std::str::from_utf8;
 
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    Document, DocumentMetadata, Element, ImageAlignment, ImageData, ImageDimension, ImageType,
+    ListItem, PageDimensions, PageFormat, RevisionKind, TableCell, TableHeader, TableRow,
+    TextStyle, TransformerTrait,
 };
 
 use serde::{Deserialize, Serialize};
@@ -44,7 +45,7 @@ impl Node {
                         let attr = attr?;
                         attributes.push(Attribute {
                             key: from_utf8(attr.key.as_ref())?.to_string(),
-                            value: attr.decode_and_unescape_value(&reader)?.into_owned(),
+                            value: attr.decode_and_unescape_value(reader)?.into_owned(),
                         });
                     }
 
@@ -60,8 +61,20 @@ impl Node {
                     }
                     current_node = Some(new_node);
                 }
-                Event::End(ref e) => {
-                    if String::from_utf8_lossy(e.as_ref()).to_string() == "end".to_string() {}
+                // self-closing elements have no children or text
+                Event::Empty(ref e) => {
+                    let node = Node {
+                        name: String::from_utf8_lossy(e.name().as_ref()).to_string(),
+                        attributes: Vec::new(),
+                        children: Vec::new(),
+                        text: None,
+                    };
+                    match &mut current_node {
+                        Some(parent) => parent.children.push(node),
+                        None => nodes.push(node),
+                    }
+                }
+                Event::End(_) => {
                     if let Some(node) = current_node.take() {
                         if let Some(mut parent) = stack.pop() {
                             parent.children.push(node);
@@ -72,13 +85,19 @@ impl Node {
                     }
                 }
                 Event::Text(e) => {
-                    if String::from_utf8_lossy(e.as_ref()).to_string() == "end".to_string() {}
                     if let Some(node) = &mut current_node {
                         if let Ok(text) = e.unescape() {
                             node.text = Some(text.into_owned());
                         }
                     }
                 }
+                // CDATA is not trimmed, it keeps the whitespace of code blocks
+                Event::CData(e) => {
+                    if let Some(node) = &mut current_node {
+                        let text = from_utf8(&e)?;
+                        node.text.get_or_insert_with(String::new).push_str(text);
+                    }
+                }
                 Event::Eof => {
                     break;
                 }
@@ -91,19 +110,141 @@ impl Node {
     }
 }
 
+// Reads the optional <style> node of a text element, only enabled flags are written
+fn parse_text_style(node: &Node) -> TextStyle {
+    let mut style = TextStyle::default();
+    for flag in node.children.iter() {
+        let enabled = flag.text.as_deref() == Some("true");
+        match flag.name.as_str() {
+            "bold" => style.bold = enabled,
+            "italic" => style.italic = enabled,
+            "underline" => style.underline = enabled,
+            "strikethrough" => style.strikethrough = enabled,
+            "code" => style.code = enabled,
+            _ => {}
+        }
+    }
+    style
+}
+
+// Reads the text of a node holding a number
+fn parse_number<T: std::str::FromStr>(node: &Node) -> anyhow::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match &node.text {
+        Some(value) => Ok(value.trim().parse()?),
+        None => Err(anyhow::anyhow!("Error: No value in <{}>", node.name)),
+    }
+}
+
+fn serialize_text_style(style: &TextStyle, writer: &mut Writer<&mut Vec<u8>>) -> Result<()> {
+    let flags = [
+        ("bold", style.bold),
+        ("italic", style.italic),
+        ("underline", style.underline),
+        ("strikethrough", style.strikethrough),
+        ("code", style.code),
+    ];
+    writer.write_event(Event::Start(BytesStart::new("style")))?;
+    for (name, enabled) in flags.into_iter().filter(|(_, enabled)| *enabled) {
+        writer.write_event(Event::Start(BytesStart::new(name)))?;
+        writer.write_event(Event::Text(BytesText::new(&enabled.to_string())))?;
+        writer.write_event(Event::End(BytesEnd::new(name)))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("style")))?;
+    Ok(())
+}
+
+// Reads the optional <metadata> node of the document root
+// author and date of a comment or revision, both are optional
+fn serialize_review(
+    author: &Option<String>,
+    date: &Option<String>,
+    writer: &mut Writer<&mut Vec<u8>>,
+) -> Result<()> {
+    for (name, value) in [("author", author), ("date", date)] {
+        if let Some(value) = value {
+            writer.write_event(Event::Start(BytesStart::new(name)))?;
+            writer.write_event(Event::Text(BytesText::new(value)))?;
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_metadata(node: &Node) -> DocumentMetadata {
+    let mut metadata = DocumentMetadata::default();
+    for field in node.children.iter() {
+        let value = field.text.clone();
+        match field.name.as_str() {
+            "title" => metadata.title = value,
+            "author" => metadata.author = value,
+            "subject" => metadata.subject = value,
+            "description" => metadata.description = value,
+            "language" => metadata.language = value,
+            "created" => metadata.created = value,
+            "modified" => metadata.modified = value,
+            "keywords" => {
+                metadata.keywords = field
+                    .children
+                    .iter()
+                    .filter_map(|keyword| keyword.text.clone())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+fn serialize_metadata(
+    metadata: &DocumentMetadata,
+    writer: &mut Writer<&mut Vec<u8>>,
+) -> Result<()> {
+    let fields = [
+        ("title", &metadata.title),
+        ("author", &metadata.author),
+        ("subject", &metadata.subject),
+        ("description", &metadata.description),
+        ("language", &metadata.language),
+        ("created", &metadata.created),
+        ("modified", &metadata.modified),
+    ];
+    writer.write_event(Event::Start(BytesStart::new("metadata")))?;
+    for (name, value) in fields {
+        if let Some(value) = value {
+            writer.write_event(Event::Start(BytesStart::new(name)))?;
+            writer.write_event(Event::Text(BytesText::new(value)))?;
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+    }
+    if !metadata.keywords.is_empty() {
+        writer.write_event(Event::Start(BytesStart::new("keywords")))?;
+        for keyword in metadata.keywords.iter() {
+            writer.write_event(Event::Start(BytesStart::new("keyword")))?;
+            writer.write_event(Event::Text(BytesText::new(keyword)))?;
+            writer.write_event(Event::End(BytesEnd::new("keyword")))?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("keywords")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("metadata")))?;
+    Ok(())
+}
+
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> Result<Document> {
-        let xml_data = from_utf8(&document)?;
+        let xml_data = from_utf8(document)?;
         let mut reader = Reader::from_str(xml_data);
         reader.trim_text(true);
 
         let mut element_data: Option<&Node> = None;
 
         let tree = Node::from_xml(&mut reader);
-        for node in &tree {
-            for child in node {
+        if let Ok(nodes) = &tree {
+            for child in nodes {
                 element_data = Some(child);
             }
         }
@@ -111,11 +252,8 @@ impl TransformerTrait for Transformer {
         let mut elements = Vec::new();
 
         for child in element_data.unwrap().children.iter() {
-            match child.name.as_str() {
-                "elements" => {
-                    elements = parse_element(child)?;
-                }
-                _ => {}
+            if child.name.as_str() == "elements" {
+                elements = parse_element(child)?;
             }
         }
 
@@ -123,6 +261,77 @@ impl TransformerTrait for Transformer {
             let mut elements = Vec::new();
             for element in element_data.children.iter() {
                 match element.name.as_str() {
+                    "Blockquote" => {
+                        let sub_elements = parse_element(element)?;
+                        elements.push(Element::Blockquote {
+                            elements: sub_elements,
+                        });
+                    }
+                    "ThematicBreak" => elements.push(Element::ThematicBreak),
+                    "PageBreak" => elements.push(Element::PageBreak),
+                    "Comment" => {
+                        let (mut author, mut date) = (None, None);
+                        let (mut text, mut anchor) = (String::new(), String::new());
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "author" => author = child.text.clone(),
+                                "date" => date = child.text.clone(),
+                                "text" => text = child.text.clone().unwrap_or_default(),
+                                "anchor" => anchor = child.text.clone().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Comment {
+                            author,
+                            date,
+                            text,
+                            anchor,
+                        });
+                    }
+                    "Revision" => {
+                        let (mut author, mut date) = (None, None);
+                        let mut kind = RevisionKind::Insertion;
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "author" => author = child.text.clone(),
+                                "date" => date = child.text.clone(),
+                                "kind" if child.text.as_deref() == Some("Deletion") => {
+                                    kind = RevisionKind::Deletion
+                                }
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Revision {
+                            kind,
+                            author,
+                            date,
+                            elements: parse_element(element)?,
+                        });
+                    }
+                    "Footnote" => {
+                        let label = element
+                            .children
+                            .iter()
+                            .find(|child| child.name == "label")
+                            .and_then(|child| child.text.clone())
+                            .unwrap_or_default();
+                        elements.push(Element::Footnote {
+                            label,
+                            elements: parse_element(element)?,
+                        });
+                    }
+                    "CodeBlock" => {
+                        let mut language = None;
+                        let mut code = String::new();
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "language" => language = child.text.clone(),
+                                "code" => code = child.text.clone().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::CodeBlock { language, code });
+                    }
                     "Paragraph" => {
                         let sub_elements = parse_element(element)?;
                         elements.push(Element::Paragraph {
@@ -149,7 +358,7 @@ impl TransformerTrait for Transformer {
                         }
                         elements.push(Element::List {
                             elements: sub_elements,
-                            numbered: numbered,
+                            numbered,
                         });
                     }
                     "elements" => {
@@ -158,8 +367,10 @@ impl TransformerTrait for Transformer {
                     "Text" => {
                         let mut text = "_";
                         let mut size = 10;
+                        let mut style = TextStyle::default();
                         for child in element.children.iter() {
                             match child.name.as_str() {
+                                "style" => style = parse_text_style(child),
                                 "size" => {
                                     if let Some(value) = &child.text {
                                         size = value.parse()?;
@@ -179,7 +390,8 @@ impl TransformerTrait for Transformer {
                         }
                         elements.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            size,
+                            style,
                         });
                     }
                     "Image" => {
@@ -295,7 +507,7 @@ impl TransformerTrait for Transformer {
                             title: title.to_string(),
                             url: url.to_string(),
                             alt: alt.to_string(),
-                            size: size,
+                            size,
                         });
                     }
                     "Header" => {
@@ -322,192 +534,89 @@ impl TransformerTrait for Transformer {
                         }
                         elements.push(Element::Header {
                             text: text.to_string(),
-                            level: level,
+                            level,
                         });
                     }
                     "Table" => {
                         let mut headers: Vec<TableHeader> = vec![];
                         let mut rows: Vec<TableRow> = vec![];
+                        let mut name = None;
                         for table_element in element.children.iter() {
                             match table_element.name.as_str() {
+                                "name" => name = table_element.text.clone(),
                                 "headers" => {
                                     for header in table_element.children.iter() {
-                                        let mut header_content: TableHeader = {
-                                            TableHeader {
-                                                element: Element::Text {
+                                        if header.name.as_str() == "TableHeader" {
+                                            let mut header_content = TableHeader::new(
+                                                Element::Text {
                                                     text: "_".to_string(),
                                                     size: 10,
+                                                    style: TextStyle::default(),
                                                 },
-                                                width: 8.0,
-                                            }
-                                        };
-                                        match header.name.as_str() {
-                                            "TableHeader" => {
-                                                let mut text = "_";
-                                                let mut size = 10;
-                                                let mut width = 8.0;
-                                                for table_header_element in header.children.iter() {
-                                                    for table_header_element_group in
-                                                        table_header_element.children.iter()
-                                                    {
-                                                        match table_header_element_group
-                                                            .name
-                                                            .as_str()
+                                                8.0,
+                                            );
+                                            for header_element in header.children.iter() {
+                                                match header_element.name.as_str() {
+                                                    "element" => {
+                                                        header_content.elements =
+                                                            parse_element(header_element)?;
+                                                        // older files keep the width next to the text
+                                                        if let Some(width) = header_element
+                                                            .children
+                                                            .iter()
+                                                            .find(|node| node.name == "width")
                                                         {
-                                                            "Text" => {
-                                                                for table_header_element_sub in
-                                                                    table_header_element_group
-                                                                        .children
-                                                                        .iter()
-                                                                {
-                                                                    match
-                                                                        table_header_element_sub.name.as_str()
-                                                                    {
-                                                                        "size" => {
-                                                                            if
-                                                                                let Some(value) =
-                                                                                    &table_header_element_sub.text
-                                                                            {
-                                                                                size =
-                                                                                    value.parse()?;
-                                                                            } else {
-                                                                                error!(
-                                                                                    "Error: No value"
-                                                                                );
-                                                                            }
-                                                                        }
-                                                                        "text" => {
-                                                                            if
-                                                                                let Some(value) =
-                                                                                    &table_header_element_sub.text
-                                                                            {
-                                                                                text = value;
-                                                                            } else {
-                                                                                error!(
-                                                                                    "Error: No value"
-                                                                                );
-                                                                            }
-                                                                        }
-                                                                        _ => {}
-                                                                    }
-                                                                }
-                                                            }
-                                                            "width" => {
-                                                                if let Some(value) =
-                                                                    &table_header_element_group.text
-                                                                {
-                                                                    width = value.parse()?;
-                                                                } else {
-                                                                    error!("Error: No value");
-                                                                }
-                                                            }
-                                                            _ => {}
+                                                            header_content.width =
+                                                                parse_number(width)?;
                                                         }
                                                     }
+                                                    "width" => {
+                                                        header_content.width =
+                                                            parse_number(header_element)?
+                                                    }
+                                                    "colspan" => {
+                                                        header_content.colspan =
+                                                            parse_number(header_element)?
+                                                    }
+                                                    "rowspan" => {
+                                                        header_content.rowspan =
+                                                            parse_number(header_element)?
+                                                    }
+                                                    _ => {}
                                                 }
-                                                header_content = TableHeader {
-                                                    element: {
-                                                        Element::Text {
-                                                            text: text.to_string(),
-                                                            size: size,
-                                                        }
-                                                    },
-                                                    width: width,
-                                                };
                                             }
-                                            _ => {}
+                                            headers.push(header_content);
                                         }
-                                        headers.push(header_content);
                                     }
                                 }
                                 "rows" => {
                                     for table_row in table_element.children.iter() {
-                                        let mut row_content: TableRow =
-                                            { TableRow { cells: vec![] } };
+                                        let mut row_content = TableRow { cells: vec![] };
                                         for cells in table_row.children.iter() {
-                                            let mut cells_content = vec![];
                                             for table_cell in cells.children.iter() {
-                                                match table_cell.name.as_str() {
-                                                    "TableCell" => {
-                                                        let mut cell_content: TableCell =
-                                                            TableCell {
-                                                                element: Element::Text {
-                                                                    text: "_".to_string(),
-                                                                    size: 10,
-                                                                },
-                                                            };
-                                                        for cell in table_cell.children.iter() {
-                                                            for cell_element_sub in
-                                                                cell.children.iter()
-                                                            {
-                                                                match cell_element_sub.name.as_str()
-                                                                {
-                                                                    "Text" => {
-                                                                        let mut text = "_";
-                                                                        let mut size = 10;
-                                                                        for cell_element_item in
-                                                                            cell_element_sub
-                                                                                .children
-                                                                                .iter()
-                                                                        {
-                                                                            match
-                                                                                cell_element_item.name.as_str()
-                                                                            {
-                                                                                "size" => {
-                                                                                    if
-                                                                                        let Some(
-                                                                                            value,
-                                                                                        ) =
-                                                                                            &cell_element_item.text
-                                                                                    {
-                                                                                        size =
-                                                                                            value.parse()?;
-                                                                                    } else {
-                                                                                        error!(
-                                                                                            "Error: No value"
-                                                                                        );
-                                                                                    }
-                                                                                }
-                                                                                "text" => {
-                                                                                    if
-                                                                                        let Some(
-                                                                                            value,
-                                                                                        ) =
-                                                                                            &cell_element_item.text
-                                                                                    {
-                                                                                        text =
-                                                                                            value;
-                                                                                    } else {
-                                                                                        error!(
-                                                                                            "Error: No value"
-                                                                                        );
-                                                                                    }
-                                                                                }
-                                                                                _ => {}
-                                                                            }
-                                                                        }
-                                                                        cell_content = TableCell {
-                                                                            element:
-                                                                                Element::Text {
-                                                                                    text: text
-                                                                                        .to_string(
-                                                                                        ),
-                                                                                    size: size,
-                                                                                },
-                                                                        };
-                                                                    }
-                                                                    _ => {}
-                                                                }
+                                                if table_cell.name.as_str() == "TableCell" {
+                                                    let mut cell_content =
+                                                        TableCell::with_elements(vec![]);
+                                                    for cell_element in table_cell.children.iter() {
+                                                        match cell_element.name.as_str() {
+                                                            "element" => {
+                                                                cell_content.elements =
+                                                                    parse_element(cell_element)?
                                                             }
+                                                            "colspan" => {
+                                                                cell_content.colspan =
+                                                                    parse_number(cell_element)?
+                                                            }
+                                                            "rowspan" => {
+                                                                cell_content.rowspan =
+                                                                    parse_number(cell_element)?
+                                                            }
+                                                            _ => {}
                                                         }
-                                                        cells_content.push(cell_content);
                                                     }
-                                                    _ => {}
+                                                    row_content.cells.push(cell_content);
                                                 }
                                             }
-                                            row_content = TableRow {
-                                                cells: cells_content,
-                                            };
                                         }
                                         rows.push(row_content);
                                     }
@@ -516,8 +625,9 @@ impl TransformerTrait for Transformer {
                             }
                         }
                         elements.push(Element::Table {
-                            headers: headers,
-                            rows: rows,
+                            name,
+                            headers,
+                            rows,
                         });
                     }
                     "element" => {
@@ -532,77 +642,70 @@ impl TransformerTrait for Transformer {
         fn list_parse_element(element_data: &Node) -> anyhow::Result<Vec<ListItem>> {
             let mut elements: Vec<ListItem> = vec![];
             for element in element_data.children.iter() {
-                match element.name.as_str() {
-                    "ListItem" => {
-                        for child in element.children.iter() {
-                            match child.name.as_str() {
-                                "elements" => {
-                                    for sub_child in child.children.iter() {
-                                        match sub_child.name.as_str() {
-                                            "Text" => {
-                                                let mut text = "_";
-                                                let mut size = 10;
-                                                for child in sub_child.children.iter() {
-                                                    match child.name.as_str() {
-                                                        "size" => {
-                                                            if let Some(value) = &child.text {
-                                                                size = value.parse()?;
-                                                            } else {
-                                                                error!("Error: No value");
-                                                            }
-                                                        }
-                                                        "text" => {
-                                                            if let Some(value) = &child.text {
-                                                                text = value;
-                                                            } else {
-                                                                error!("Error: No value");
-                                                            }
-                                                        }
-                                                        _ => {}
+                if element.name.as_str() == "ListItem" {
+                    for child in element.children.iter() {
+                        match child.name.as_str() {
+                            "elements" => {
+                                for sub_child in child.children.iter() {
+                                    if sub_child.name.as_str() == "Text" {
+                                        let mut text = "_";
+                                        let mut size = 10;
+                                        let mut style = TextStyle::default();
+                                        for child in sub_child.children.iter() {
+                                            match child.name.as_str() {
+                                                "style" => style = parse_text_style(child),
+                                                "size" => {
+                                                    if let Some(value) = &child.text {
+                                                        size = value.parse()?;
+                                                    } else {
+                                                        error!("Error: No value");
                                                     }
                                                 }
-                                                let sub_element = Element::Text {
-                                                    text: text.to_string(),
-                                                    size: size,
-                                                };
-                                                elements.push(ListItem {
-                                                    element: sub_element,
-                                                });
+                                                "text" => {
+                                                    if let Some(value) = &child.text {
+                                                        text = value;
+                                                    } else {
+                                                        error!("Error: No value");
+                                                    }
+                                                }
+                                                _ => {}
                                             }
-                                            _ => {}
                                         }
+                                        let sub_element = Element::Text {
+                                            text: text.to_string(),
+                                            size,
+                                            style,
+                                        };
+                                        elements.push(ListItem::new(sub_element));
                                     }
                                 }
-                                "List" => {
-                                    let mut numbered = false;
-                                    let mut sub_elements: Vec<ListItem> = vec![];
-                                    for sub_child in child.children.iter() {
-                                        match sub_child.name.as_str() {
-                                            "elements" => {
-                                                sub_elements = list_parse_element(sub_child)?;
-                                            }
-                                            "numbered" => {
-                                                if let Some(value) = &sub_child.text {
-                                                    numbered = value == "true";
-                                                } else {
-                                                    error!("Error: No value");
-                                                }
+                            }
+                            "List" => {
+                                let mut numbered = false;
+                                let mut sub_elements: Vec<ListItem> = vec![];
+                                for sub_child in child.children.iter() {
+                                    match sub_child.name.as_str() {
+                                        "elements" => {
+                                            sub_elements = list_parse_element(sub_child)?;
+                                        }
+                                        "numbered" => {
+                                            if let Some(value) = &sub_child.text {
+                                                numbered = value == "true";
+                                            } else {
+                                                error!("Error: No value");
                                             }
-                                            _ => {}
                                         }
+                                        _ => {}
                                     }
-                                    elements.push(ListItem {
-                                        element: Element::List {
-                                            elements: sub_elements,
-                                            numbered: numbered,
-                                        },
-                                    });
                                 }
-                                _ => {}
+                                elements.push(ListItem::new(Element::List {
+                                    elements: sub_elements,
+                                    numbered,
+                                }));
                             }
+                            _ => {}
                         }
                     }
-                    _ => {}
                 }
             }
             Ok(elements)
@@ -619,6 +722,7 @@ impl TransformerTrait for Transformer {
         } = PageFormat::default().dimensions();
         let mut page_header: Vec<Element> = vec![];
         let mut page_footer: Vec<Element> = vec![];
+        let mut metadata = DocumentMetadata::default();
 
         for child in element_data.unwrap().children.iter() {
             match child.name.as_str() {
@@ -656,8 +760,10 @@ impl TransformerTrait for Transformer {
                     for child_element in child.children.iter() {
                         let mut text = "_";
                         let mut size = 10;
+                        let mut style = TextStyle::default();
                         for sub_child in child_element.children.iter() {
                             match sub_child.name.as_str() {
+                                "style" => style = parse_text_style(sub_child),
                                 "size" => {
                                     if let Some(value) = &sub_child.text {
                                         size = value.parse()?;
@@ -677,16 +783,20 @@ impl TransformerTrait for Transformer {
                         }
                         page_header.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            size,
+                            style,
                         });
                     }
                 }
+                "metadata" => metadata = parse_metadata(child),
                 "page_footer" => {
                     for child_element in child.children.iter() {
                         let mut text = "_";
                         let mut size = 10;
+                        let mut style = TextStyle::default();
                         for sub_child in child_element.children.iter() {
                             match sub_child.name.as_str() {
+                                "style" => style = parse_text_style(sub_child),
                                 "size" => {
                                     if let Some(value) = &sub_child.text {
                                         size = value.parse()?;
@@ -706,7 +816,8 @@ impl TransformerTrait for Transformer {
                         }
                         page_footer.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            size,
+                            style,
                         });
                     }
                 }
@@ -722,8 +833,9 @@ impl TransformerTrait for Transformer {
             page_margin_right,
         });
 
-        let document =
+        let mut document =
             Document::new_with_dimensions(page_header, elements, page_footer, page_custom_format);
+        document.set_metadata(metadata);
         Ok(document)
     }
 
@@ -748,6 +860,80 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("level")))?;
                     writer.write_event(Event::End(BytesEnd::new("Header")))?;
                 }
+                Element::Blockquote { elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Blockquote")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Blockquote")))?;
+                }
+                Element::ThematicBreak => {
+                    writer.write_event(Event::Empty(BytesStart::new("ThematicBreak")))?;
+                }
+                Element::PageBreak => {
+                    writer.write_event(Event::Empty(BytesStart::new("PageBreak")))?;
+                }
+                Element::Comment {
+                    author,
+                    date,
+                    text,
+                    anchor,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Comment")))?;
+                    serialize_review(author, date, writer)?;
+                    for (name, value) in [("text", text), ("anchor", anchor)] {
+                        writer.write_event(Event::Start(BytesStart::new(name)))?;
+                        writer.write_event(Event::Text(BytesText::new(value)))?;
+                        writer.write_event(Event::End(BytesEnd::new(name)))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("Comment")))?;
+                }
+                Element::Revision {
+                    kind,
+                    author,
+                    date,
+                    elements,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Revision")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&format!("{kind:?}"))))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    serialize_review(author, date, writer)?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Revision")))?;
+                }
+                Element::Footnote { label, elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Footnote")))?;
+                    writer.write_event(Event::Start(BytesStart::new("label")))?;
+                    writer.write_event(Event::Text(BytesText::new(label)))?;
+                    writer.write_event(Event::End(BytesEnd::new("label")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Footnote")))?;
+                }
+                Element::CodeBlock { language, code } => {
+                    writer.write_event(Event::Start(BytesStart::new("CodeBlock")))?;
+                    if let Some(language) = language {
+                        writer.write_event(Event::Start(BytesStart::new("language")))?;
+                        writer.write_event(Event::Text(BytesText::new(language)))?;
+                        writer.write_event(Event::End(BytesEnd::new("language")))?;
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("code")))?;
+                    // "]]>" cannot appear inside CDATA, so it is split across two sections
+                    let cdata = code.replace("]]>", "]]]]><![CDATA[>");
+                    writer.write_event(Event::CData(BytesCData::new(cdata)))?;
+                    writer.write_event(Event::End(BytesEnd::new("code")))?;
+                    writer.write_event(Event::End(BytesEnd::new("CodeBlock")))?;
+                }
                 Element::Paragraph { elements } => {
                     writer.write_event(Event::Start(BytesStart::new("Paragraph")))?;
                     writer.write_event(Event::Start(BytesStart::new("elements")))?;
@@ -757,7 +943,7 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("elements")))?;
                     writer.write_event(Event::End(BytesEnd::new("Paragraph")))?;
                 }
-                Element::Text { text, size } => {
+                Element::Text { text, size, style } => {
                     writer.write_event(Event::Start(BytesStart::new("Text")))?;
                     writer.write_event(Event::Start(BytesStart::new("text")))?;
                     writer.write_event(Event::Text(BytesText::new(text)))?;
@@ -765,6 +951,9 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::Start(BytesStart::new("size")))?;
                     writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
+                    if !style.is_plain() {
+                        serialize_text_style(style, writer)?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("Text")))?;
                 }
                 Element::Image(image) => {
@@ -822,29 +1011,30 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("numbered")))?;
                     writer.write_event(Event::End(BytesEnd::new("List")))?;
                 }
-                Element::Table { headers, rows } => {
+                Element::Table {
+                    name,
+                    headers,
+                    rows,
+                } => {
                     writer.write_event(Event::Start(BytesStart::new("Table")))?;
+                    if let Some(name) = name {
+                        writer.write_event(Event::Start(BytesStart::new("name")))?;
+                        writer.write_event(Event::Text(BytesText::new(name)))?;
+                        writer.write_event(Event::End(BytesEnd::new("name")))?;
+                    }
                     writer.write_event(Event::Start(BytesStart::new("headers")))?;
                     for header in headers {
                         writer.write_event(Event::Start(BytesStart::new("TableHeader")))?;
                         writer.write_event(Event::Start(BytesStart::new("element")))?;
-                        match &header.element {
-                            Element::Text { text, size } => {
-                                writer.write_event(Event::Start(BytesStart::new("Text")))?;
-                                writer.write_event(Event::Text(BytesText::new(&text)))?;
-                                writer.write_event(Event::End(BytesEnd::new("Text")))?;
-                                writer.write_event(Event::Start(BytesStart::new("Text")))?;
-                                writer
-                                    .write_event(Event::Text(BytesText::new(&size.to_string())))?;
-                                writer.write_event(Event::End(BytesEnd::new("Text")))?;
-                            }
-                            _ => {}
+                        for element in &header.elements {
+                            serialize_element(element, writer)?;
                         }
                         writer.write_event(Event::End(BytesEnd::new("element")))?;
                         writer.write_event(Event::Start(BytesStart::new("width")))?;
                         writer
                             .write_event(Event::Text(BytesText::new(&header.width.to_string())))?;
                         writer.write_event(Event::End(BytesEnd::new("width")))?;
+                        serialize_spans(header.colspan, header.rowspan, writer)?;
                         writer.write_event(Event::End(BytesEnd::new("TableHeader")))?;
                     }
                     writer.write_event(Event::End(BytesEnd::new("headers")))?;
@@ -853,35 +1043,14 @@ impl TransformerTrait for Transformer {
                         writer.write_event(Event::Start(BytesStart::new("TableRow")))?;
                         writer.write_event(Event::Start(BytesStart::new("cells")))?;
                         for cell in &row.cells {
-                            match cell {
-                                TableCell { element } => {
-                                    writer
-                                        .write_event(Event::Start(BytesStart::new("TableCell")))?;
-                                    writer.write_event(Event::Start(BytesStart::new("element")))?;
-                                    match &element {
-                                        Element::Text { text, size } => {
-                                            writer.write_event(Event::Start(BytesStart::new(
-                                                "Text",
-                                            )))?;
-                                            writer
-                                                .write_event(Event::Text(BytesText::new(&text)))?;
-                                            writer
-                                                .write_event(Event::End(BytesEnd::new("Text")))?;
-                                            writer.write_event(Event::Start(BytesStart::new(
-                                                "Text",
-                                            )))?;
-                                            writer.write_event(Event::Text(BytesText::new(
-                                                &size.to_string(),
-                                            )))?;
-                                            writer
-                                                .write_event(Event::End(BytesEnd::new("Text")))?;
-                                        }
-                                        _ => {}
-                                    }
-                                    writer.write_event(Event::End(BytesEnd::new("element")))?;
-                                    writer.write_event(Event::End(BytesEnd::new("TableCell")))?;
-                                }
+                            writer.write_event(Event::Start(BytesStart::new("TableCell")))?;
+                            writer.write_event(Event::Start(BytesStart::new("element")))?;
+                            for element in &cell.elements {
+                                serialize_element(element, writer)?;
                             }
+                            writer.write_event(Event::End(BytesEnd::new("element")))?;
+                            serialize_spans(cell.colspan, cell.rowspan, writer)?;
+                            writer.write_event(Event::End(BytesEnd::new("TableCell")))?;
                         }
                         writer.write_event(Event::End(BytesEnd::new("cells")))?;
                         writer.write_event(Event::End(BytesEnd::new("TableRow")))?;
@@ -893,22 +1062,35 @@ impl TransformerTrait for Transformer {
             Ok(())
         }
 
-        fn list_serialize_element(
-            element: &ListItem,
+        // spans are only written for merged cells
+        fn serialize_spans(
+            colspan: usize,
+            rowspan: usize,
             writer: &mut Writer<&mut Vec<u8>>,
         ) -> Result<()> {
-            match element {
-                ListItem { element } => {
-                    writer.write_event(Event::Start(BytesStart::new("ListItem")))?;
-                    writer.write_event(Event::Start(BytesStart::new("element")))?;
-                    serialize_element(element, writer)?;
-                    writer.write_event(Event::End(BytesEnd::new("element")))?;
-                    writer.write_event(Event::End(BytesEnd::new("ListItem")))?;
+            for (name, span) in [("colspan", colspan), ("rowspan", rowspan)] {
+                if span > 1 {
+                    writer.write_event(Event::Start(BytesStart::new(name)))?;
+                    writer.write_event(Event::Text(BytesText::new(&span.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new(name)))?;
                 }
             }
             Ok(())
         }
 
+        fn list_serialize_element(
+            element: &ListItem,
+            writer: &mut Writer<&mut Vec<u8>>,
+        ) -> Result<()> {
+            let ListItem { element, .. } = element;
+            writer.write_event(Event::Start(BytesStart::new("ListItem")))?;
+            writer.write_event(Event::Start(BytesStart::new("element")))?;
+            serialize_element(element, writer)?;
+            writer.write_event(Event::End(BytesEnd::new("element")))?;
+            writer.write_event(Event::End(BytesEnd::new("ListItem")))?;
+            Ok(())
+        }
+
         for element in &document.get_detail() {
             serialize_element(element, &mut writer)?;
         }
@@ -963,39 +1145,42 @@ impl TransformerTrait for Transformer {
 
         writer.write_event(Event::Start(BytesStart::new("page_header")))?;
         for page_header_element in document.get_page_header().iter() {
-            match page_header_element {
-                Element::Text { text, size } => {
-                    writer.write_event(Event::Start(BytesStart::new("Text")))?;
-                    writer.write_event(Event::Start(BytesStart::new("text")))?;
-                    writer.write_event(Event::Text(BytesText::new(text)))?;
-                    writer.write_event(Event::End(BytesEnd::new("text")))?;
-                    writer.write_event(Event::Start(BytesStart::new("size")))?;
-                    writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
-                    writer.write_event(Event::End(BytesEnd::new("size")))?;
-                    writer.write_event(Event::End(BytesEnd::new("Text")))?;
+            if let Element::Text { text, size, style } = page_header_element {
+                writer.write_event(Event::Start(BytesStart::new("Text")))?;
+                writer.write_event(Event::Start(BytesStart::new("text")))?;
+                writer.write_event(Event::Text(BytesText::new(text)))?;
+                writer.write_event(Event::End(BytesEnd::new("text")))?;
+                writer.write_event(Event::Start(BytesStart::new("size")))?;
+                writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                writer.write_event(Event::End(BytesEnd::new("size")))?;
+                if !style.is_plain() {
+                    serialize_text_style(style, &mut writer)?;
                 }
-                _ => {}
+                writer.write_event(Event::End(BytesEnd::new("Text")))?;
             }
         }
         writer.write_event(Event::End(BytesEnd::new("page_header")))?;
 
         writer.write_event(Event::Start(BytesStart::new("page_footer")))?;
         for page_footer_element in document.get_page_footer().iter() {
-            match page_footer_element {
-                Element::Text { text, size } => {
-                    writer.write_event(Event::Start(BytesStart::new("Text")))?;
-                    writer.write_event(Event::Start(BytesStart::new("text")))?;
-                    writer.write_event(Event::Text(BytesText::new(text)))?;
-                    writer.write_event(Event::End(BytesEnd::new("text")))?;
-                    writer.write_event(Event::Start(BytesStart::new("size")))?;
-                    writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
-                    writer.write_event(Event::End(BytesEnd::new("size")))?;
-                    writer.write_event(Event::End(BytesEnd::new("Text")))?;
+            if let Element::Text { text, size, style } = page_footer_element {
+                writer.write_event(Event::Start(BytesStart::new("Text")))?;
+                writer.write_event(Event::Start(BytesStart::new("text")))?;
+                writer.write_event(Event::Text(BytesText::new(text)))?;
+                writer.write_event(Event::End(BytesEnd::new("text")))?;
+                writer.write_event(Event::Start(BytesStart::new("size")))?;
+                writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                writer.write_event(Event::End(BytesEnd::new("size")))?;
+                if !style.is_plain() {
+                    serialize_text_style(style, &mut writer)?;
                 }
-                _ => {}
+                writer.write_event(Event::End(BytesEnd::new("Text")))?;
             }
         }
         writer.write_event(Event::End(BytesEnd::new("page_footer")))?;
+        if !document.metadata.is_empty() {
+            serialize_metadata(&document.metadata, &mut writer)?;
+        }
         writer.write_event(Event::End(BytesEnd::new("Document")))?;
 
         Ok(Bytes::from(buffer))
@@ -1004,7 +1189,7 @@ impl TransformerTrait for Transformer {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, text};
     use crate::markdown;
     use crate::xml::*;
     use bytes::Bytes;
@@ -1047,4 +1232,149 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_text_style() -> anyhow::Result<()> {
+        let parsed = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                Element::Text {
+                    text: "Plain".to_string(),
+                    size: 14,
+                    style: TextStyle::default(),
+                },
+                Element::Text {
+                    text: "bold".to_string(),
+                    size: 14,
+                    style: TextStyle::bold(),
+                },
+                Element::Text {
+                    text: "code".to_string(),
+                    size: 14,
+                    style: TextStyle::code(),
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&parsed)?;
+        let generated_text = std::str::from_utf8(&generated)?;
+        assert!(generated_text.contains("<style><bold>true</bold></style>"));
+        let parsed2 = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), parsed2.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![]);
+        document.set_metadata(DocumentMetadata {
+            title: Some("Report".to_string()),
+            author: Some("John Doe".to_string()),
+            keywords: vec!["rust".to_string(), "shiva".to_string()],
+            language: Some("en".to_string()),
+            ..Default::default()
+        });
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.metadata, parsed.metadata);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::CodeBlock {
+            language: Some("rust".to_string()),
+            code: "    let a = b[c[0]]>1 && x < y;\n".to_string(),
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote_and_breaks() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Blockquote {
+                elements: vec![Element::Header {
+                    level: 2,
+                    text: "Quoted".to_string(),
+                }],
+            },
+            Element::ThematicBreak,
+            Element::PageBreak,
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_review() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Payment within", 8),
+                    Element::Revision {
+                        kind: RevisionKind::Deletion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("30", 8)],
+                    },
+                    Element::Revision {
+                        kind: RevisionKind::Insertion,
+                        author: Some("Jane Roe".to_string()),
+                        date: Some("2024-05-01T10:30:00Z".to_string()),
+                        elements: vec![text("60", 8)],
+                    },
+                    text("days", 8),
+                ],
+            },
+            Element::Comment {
+                author: Some("John Doe".to_string()),
+                date: None,
+                text: "Is this agreed?".to_string(),
+                anchor: "60 days".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_spans() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Table {
+            name: None,
+            headers: vec![
+                TableHeader::new(text("Name", 8), 10.0).span(2, 1),
+                TableHeader::new(text("Total", 8), 10.0),
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Group", 8)).span(1, 2),
+                        TableCell::new(text("First", 8)),
+                        TableCell::new(text("1", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Second", 8)),
+                        TableCell::new(text("2", 8)),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::with_elements(vec![text("Sum", 8), text("of all", 8)])
+                            .span(3, 1),
+                    ],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(document.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
 }
@@ -0,0 +1,170 @@
+#!/usr/bin/env python3
+"""Writes encrypted PDF fixtures following ISO 32000-2 7.6.4, independent of shiva's code.
+
+Needs the cryptography package, run from the repository root:
+python3 lib/test/data/make_encrypted_pdfs.py lib/test/data
+"""
+import hashlib
+import os
+import struct
+import sys
+
+from cryptography.hazmat.decrepit.ciphers.algorithms import ARC4
+from cryptography.hazmat.primitives import padding
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+
+PAD = bytes.fromhex("28BF4E5E4E758A4164004E56FFFA01082E2E00B6D0683E802F0CA9FE6453697A")
+FILE_ID = bytes.fromhex("5a6f5c2e13f04b0c9a8e7d6c5b4a3928")
+PERMISSIONS = -3904  # print, copy and extract allowed
+
+
+def rc4(key, data):
+    return Cipher(ARC4(key), mode=None).encryptor().update(data)
+
+
+def aes_cbc(key, iv, data):
+    encryptor = Cipher(algorithms.AES(key), modes.CBC(iv)).encryptor()
+    return encryptor.update(data) + encryptor.finalize()
+
+
+def aes_object(key, data):
+    padder = padding.PKCS7(128).padder()
+    padded = padder.update(data) + padder.finalize()
+    iv = os.urandom(16)
+    return iv + aes_cbc(key, iv, padded)
+
+
+def padded(password):
+    return (password + PAD)[:32]
+
+
+class Rc4Handler:
+    """Revision 3 (RC4) and revision 4 (AESV2), algorithms 2, 3, 5 and 1"""
+
+    def __init__(self, revision, user, owner, aes):
+        self.revision, self.aes, self.length = revision, aes, 16
+        digest = hashlib.md5(padded(owner)).digest()
+        for _ in range(50):
+            digest = hashlib.md5(digest).digest()
+        o = rc4(digest[: self.length], padded(user))
+        for i in range(1, 20):
+            o = rc4(bytes(b ^ i for b in digest[: self.length]), o)
+        self.o = o
+        key = hashlib.md5(
+            padded(user) + o + struct.pack("<i", PERMISSIONS) + FILE_ID
+        ).digest()
+        for _ in range(50):
+            key = hashlib.md5(key[: self.length]).digest()
+        self.key = key[: self.length]
+        u = rc4(self.key, hashlib.md5(PAD + FILE_ID).digest())
+        for i in range(1, 20):
+            u = rc4(bytes(b ^ i for b in self.key), u)
+        self.u = u + os.urandom(16)
+
+    def encrypt(self, number, data):
+        material = self.key + struct.pack("<I", number)[:3] + b"\0\0"
+        if self.aes:
+            material += b"sAlT"
+        key = hashlib.md5(material).digest()[: min(self.length + 5, 16)]
+        return aes_object(key, data) if self.aes else rc4(key, data)
+
+    def dictionary(self):
+        if self.aes:
+            filters = "/V 4 /CF << /StdCF << /CFM /AESV2 /AuthEvent /DocOpen /Length 16 >> >> /StmF /StdCF /StrF /StdCF"
+        else:
+            filters = "/V 2"
+        return (
+            f"<< /Filter /Standard {filters} /R {self.revision} /Length 128 "
+            f"/O <{self.o.hex()}> /U <{self.u.hex()}> /P {PERMISSIONS} >>"
+        )
+
+
+def hash_2b(revision, password, salt, user):
+    k = hashlib.sha256(password + salt + user).digest()
+    if revision == 5:
+        return k
+    i = 0
+    while True:
+        e = aes_cbc(k[:16], k[16:32], (password + k + user) * 64)
+        k = [hashlib.sha256, hashlib.sha384, hashlib.sha512][sum(e[:16]) % 3](e).digest()
+        i += 1
+        if i >= 64 and e[-1] <= i - 32:
+            return k[:32]
+
+
+class AesV3Handler:
+    """Revisions 5 and 6 (AESV3), algorithms 8, 9 and 10, passwords are SASLprep-ed UTF-8"""
+
+    def __init__(self, revision, user, owner):
+        self.revision = revision
+        self.key = os.urandom(32)
+        validation, key_salt = os.urandom(8), os.urandom(8)
+        self.u = hash_2b(revision, user, validation, b"") + validation + key_salt
+        self.ue = aes_cbc(hash_2b(revision, user, key_salt, b""), bytes(16), self.key)
+        validation, key_salt = os.urandom(8), os.urandom(8)
+        self.o = hash_2b(revision, owner, validation, self.u) + validation + key_salt
+        self.oe = aes_cbc(hash_2b(revision, owner, key_salt, self.u), bytes(16), self.key)
+        perms = struct.pack("<i", PERMISSIONS) + b"\xff\xff\xff\xffTadb" + os.urandom(4)
+        encryptor = Cipher(algorithms.AES(self.key), modes.ECB()).encryptor()
+        self.perms = encryptor.update(perms) + encryptor.finalize()
+
+    def encrypt(self, number, data):
+        return aes_object(self.key, data)
+
+    def dictionary(self):
+        return (
+            f"<< /Filter /Standard /V 5 /R {self.revision} /Length 256 "
+            "/CF << /StdCF << /CFM /AESV3 /AuthEvent /DocOpen /Length 32 >> >> /StmF /StdCF /StrF /StdCF "
+            f"/O <{self.o.hex()}> /U <{self.u.hex()}> /OE <{self.oe.hex()}> /UE <{self.ue.hex()}> "
+            f"/Perms <{self.perms.hex()}> /P {PERMISSIONS} >>"
+        )
+
+
+def write_pdf(path, handler):
+    content = b"BT /F1 12 Tf 72 720 Td (Confidential terms) Tj ET"
+    objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>",
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+        b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] "
+        b"/Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>",
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+        None,
+        b"<< /Title <" + handler.encrypt(6, b"Contract").hex().encode() + b"> >>",
+        handler.dictionary().encode(),
+    ]
+    encrypted_content = handler.encrypt(5, content)
+    objects[4] = (
+        b"<< /Length %d >>\nstream\n" % len(encrypted_content)
+        + encrypted_content
+        + b"\nendstream"
+    )
+    out = bytearray(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n")
+    offsets = []
+    for number, body in enumerate(objects, start=1):
+        offsets.append(len(out))
+        out += b"%d 0 obj\n" % number + body + b"\nendobj\n"
+    xref = len(out)
+    out += b"xref\n0 %d\n0000000000 65535 f \n" % (len(objects) + 1)
+    for offset in offsets:
+        out += b"%010d 00000 n \n" % offset
+    out += (
+        b"trailer\n<< /Size %d /Root 1 0 R /Info 6 0 R /Encrypt 7 0 R /ID [<%s> <%s>] >>\n"
+        % (len(objects) + 1, FILE_ID.hex().encode(), FILE_ID.hex().encode())
+    )
+    out += b"startxref\n%d\n%%%%EOF\n" % xref
+    with open(path, "wb") as file:
+        file.write(out)
+
+
+directory = sys.argv[1]
+# "€uro•" in PDFDocEncoding, the euro sign and the bullet are not at their Latin-1 positions
+write_pdf(f"{directory}/encrypted_rc4.pdf", Rc4Handler(3, b"\xa0uro\x80", b"owner", aes=False))
+write_pdf(f"{directory}/encrypted_aes128.pdf", Rc4Handler(4, b"user", b"owner", aes=True))
+write_pdf(f"{directory}/encrypted_aes256_r5.pdf", AesV3Handler(5, b"user", b"owner"))
+# the SASLprep form of "pass wörd" is "pass wörd"
+write_pdf(
+    f"{directory}/encrypted_aes256_r6.pdf",
+    AesV3Handler(6, "pass wörd".encode(), b"owner"),
+)
+# restricted only by permissions, opens without a password
+write_pdf(f"{directory}/encrypted_permissions.pdf", Rc4Handler(4, b"", b"owner", aes=True))
@@ -7,8 +7,9 @@ use futures_util::StreamExt;
 use log::{debug, error, info};
 use serde::Serialize;
 use shiva::core::{Document, TransformerTrait, TransformerWithImageLoaderSaverTrait};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 
 #[derive(Debug, Clone, Serialize)]
 struct UploadFileInfo {
@@ -110,20 +111,21 @@ async fn convert_file_zip(
      */
 
     let document = match file_extension.as_str() {
-        "md" => Document::from(
-            shiva::markdown::Transformer::parse_with_loader(
-                &input_file_data_bytes,
-                memory_image_loader(images),
-            )
-            .unwrap(),
-        ),
-        "html" | "htm" => Document::from(
-            shiva::html::Transformer::parse_with_loader(
-                &input_file_data_bytes,
-                memory_image_loader(images),
-            )
-            .unwrap(),
-        ),
+        "md" => shiva::markdown::Transformer::parse_with_loader(
+            &input_file_data_bytes,
+            memory_image_loader(images),
+        )
+        .unwrap(),
+        "html" | "htm" => shiva::html::Transformer::parse_with_loader(
+            &input_file_data_bytes,
+            memory_image_loader(images),
+        )
+        .unwrap(),
+        "typ" => shiva::typst::Transformer::parse_with_loader(
+            &input_file_data_bytes,
+            memory_image_loader(images),
+        )
+        .map_err(|_| Error::FailParseDocument)?,
         _ => return Err(Error::FailParseDocument),
     };
 
@@ -135,6 +137,7 @@ async fn convert_file_zip(
         "json" => shiva::json::Transformer::generate(&document).unwrap(),
         "rtf" => shiva::rtf::Transformer::generate(&document).unwrap(),
         "docx" => shiva::docx::Transformer::generate(&document).unwrap(),
+        "typ" => generate_typst(&document, &file_name)?,
         _ => return Err(Error::FailConvertFile),
     };
 
@@ -144,24 +147,47 @@ async fn convert_file_zip(
     })
 }
 
+// typst source refers to its images by file name, they are sent along with it in a zip archive
+// instead of being written to the working directory of the server
+fn generate_typst(document: &Document, file_name: &str) -> Result<Bytes> {
+    let images = RefCell::new(vec![]);
+    let source = shiva::typst::Transformer::generate_with_saver(document, |bytes, name| {
+        images.borrow_mut().push((name.to_string(), bytes.clone()));
+        Ok(())
+    })
+    .map_err(|_| Error::FailConvertFile)?;
+    let images = images.into_inner();
+    if images.is_empty() {
+        return Ok(source);
+    }
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    let source_name = format!("{file_name}.typ");
+    for (name, data) in std::iter::once((source_name, source)).chain(images) {
+        archive
+            .start_file(name, options)
+            .and_then(|_| archive.write_all(&data).map_err(Into::into))
+            .map_err(|_| Error::FailConvertFile)?;
+    }
+    let archive = archive.finish().map_err(|_| Error::FailConvertFile)?;
+    Ok(Bytes::from(archive.into_inner()))
+}
+
 fn memory_image_loader(images: HashMap<String, Bytes>) -> impl Fn(&str) -> anyhow::Result<Bytes> {
     let image_loader = move |image: &str| -> anyhow::Result<Bytes> {
         let bytes = images
             .get(image)
             .cloned()
             .ok_or_else(|| anyhow::anyhow!("No image: {}", image))?;
-        Ok(Bytes::from(bytes))
+        Ok(bytes)
     };
     image_loader
 }
 
 //checking the supported formats in the archive
 fn supported_extensions_in_archive(file_extension: &str) -> bool {
-    match file_extension {
-        "md" | "html" | "htm" | "png" => true,
-
-        _ => false,
-    }
+    matches!(file_extension, "md" | "html" | "htm" | "typ" | "png")
 }
 
 //unpacking the archive
@@ -201,8 +227,7 @@ async fn unpacking(mut field: Field<'_>) -> Result<StructUploadFile> {
             .split(".")
             .next()
             .map(|name_file_in_archive| name_file_in_archive.to_lowercase())
-            .filter(|name_file_in_archive| !name_file_in_archive.trim().is_empty())
-            .map(String::from);
+            .filter(|name_file_in_archive| !name_file_in_archive.trim().is_empty());
 
         //we define the extension of each file
         let file_extension_in_archive = file
@@ -210,8 +235,7 @@ async fn unpacking(mut field: Field<'_>) -> Result<StructUploadFile> {
             .split(".")
             .last()
             .map(|ext| ext.to_lowercase())
-            .filter(|ext| !ext.trim().is_empty())
-            .map(String::from);
+            .filter(|ext| !ext.trim().is_empty());
 
         debug!(
             "in ZIP {}.{}",
@@ -221,12 +245,12 @@ async fn unpacking(mut field: Field<'_>) -> Result<StructUploadFile> {
 
         //checking the supported format
         if let Some(ref ext) = file_extension_in_archive {
-            if supported_extensions_in_archive(&ext) {
+            if supported_extensions_in_archive(ext) {
                 found_supported_file = true;
                 let mut file_data_buf = Vec::new();
                 file.read_to_end(&mut file_data_buf).unwrap();
                 match ext.as_str() {
-                    "html" | "htm" | "md" => {
+                    "html" | "htm" | "md" | "typ" => {
                         file_name = file_name_in_archive;
                         file_data = Some(Bytes::from(file_data_buf));
                         file_extension = file_extension_in_archive.clone();
@@ -252,8 +276,8 @@ async fn unpacking(mut field: Field<'_>) -> Result<StructUploadFile> {
     // building the Upload File Zip structure
     let upload_file_zip = UploadFileZip {
         file_name: file_name.unwrap_or("Shiva_convert".to_string()),
-        file_data: file_data.ok_or_else(|| Error::FailBytes)?,
-        file_extension: file_extension.ok_or_else(|| Error::ExtensionMissing)?,
+        file_data: file_data.ok_or(Error::FailBytes)?,
+        file_extension: file_extension.ok_or(Error::ExtensionMissing)?,
         images,
     };
 
@@ -278,16 +302,14 @@ async fn upload_file(mut multipart: Multipart) -> Result<StructUploadFile> {
                 .split(".")
                 .next()
                 .map(|upload_name| upload_name.to_lowercase())
-                .filter(|upload_name| !upload_name.trim().is_empty())
-                .map(String::from);
+                .filter(|upload_name| !upload_name.trim().is_empty());
 
             //defining the file extension
             file_extension = filename
                 .split(".")
                 .last()
                 .map(|ext| ext.to_lowercase())
-                .filter(|ext| !ext.trim().is_empty())
-                .map(String::from);
+                .filter(|ext| !ext.trim().is_empty());
 
             //matching the file extension
             if let Some(ref ext) = file_extension {
@@ -335,15 +357,13 @@ async fn convert_file(
     debug!("download file format: {}", output_format);
 
     let document = match file_extension.as_str() {
-        "md" => {
-            Document::from(shiva::markdown::Transformer::parse(&input_file_data_bytes).unwrap())
-        }
-        "html" | "htm" => {
-            Document::from(shiva::html::Transformer::parse(&input_file_data_bytes).unwrap())
-        }
-        "txt" => Document::from(shiva::text::Transformer::parse(&input_file_data_bytes).unwrap()),
-        "pdf" => Document::from(shiva::pdf::Transformer::parse(&input_file_data_bytes).unwrap()),
-        "json" => Document::from(shiva::json::Transformer::parse(&input_file_data_bytes).unwrap()),
+        "md" => shiva::markdown::Transformer::parse(&input_file_data_bytes).unwrap(),
+        "html" | "htm" => shiva::html::Transformer::parse(&input_file_data_bytes).unwrap(),
+        "txt" => shiva::text::Transformer::parse(&input_file_data_bytes).unwrap(),
+        "pdf" => shiva::pdf::Transformer::parse(&input_file_data_bytes).unwrap(),
+        "json" => shiva::json::Transformer::parse(&input_file_data_bytes).unwrap(),
+        "typ" => shiva::typst::Transformer::parse(&input_file_data_bytes)
+            .map_err(|_| Error::FailParseDocument)?,
         _ => return Err(Error::FailParseDocument),
     };
 
@@ -355,6 +375,7 @@ async fn convert_file(
         "json" => shiva::json::Transformer::generate(&document).unwrap(),
         "rtf" => shiva::rtf::Transformer::generate(&document).unwrap(),
         "docx" => shiva::docx::Transformer::generate(&document).unwrap(),
+        "typ" => generate_typst(&document, &file_name)?,
         _ => return Err(Error::FailConvertFile),
     };
 
@@ -365,9 +386,8 @@ async fn convert_file(
 }
 
 async fn supported_format(file_extension: &str) -> bool {
-    match file_extension {
-        "md" | "html" | "htm" | "txt" | "pdf" | "json" => true,
-
-        _ => false,
-    }
+    matches!(
+        file_extension,
+        "md" | "html" | "htm" | "txt" | "pdf" | "json" | "typ"
+    )
 }
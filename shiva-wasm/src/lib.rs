@@ -35,5 +35,5 @@ pub fn convert(
             return Err(err.to_string().into());
         }
     };
-    return Ok(generated.to_vec());
+    Ok(generated.to_vec())
 }
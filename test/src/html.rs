@@ -4,7 +4,7 @@ mod tests {
     use shiva::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
     use shiva::core::{
         Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-        TableCell, TableHeader, TableRow, TransformerTrait,
+        TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
     };
     use shiva::html::Transformer;
 
@@ -108,7 +108,7 @@ mod tests {
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
             Paragraph { elements } => match &elements[0] {
-                Text { text, size: _ } => {
+                Text { text, .. } => {
                     assert_eq!(text, "First Paragraph");
                 }
                 _ => panic!("Expected Paragraph"),
@@ -136,6 +136,7 @@ mod tests {
                 elements: [Text {
                     text: "First Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -143,6 +144,7 @@ mod tests {
                 elements: [Text {
                     text: "Second Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -150,6 +152,7 @@ mod tests {
                 elements: [Text {
                     text: "Third Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -157,6 +160,7 @@ mod tests {
                 elements: [Text {
                     text: "Fourth Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -164,6 +168,7 @@ mod tests {
                 elements: [Text {
                     text: "Fifth Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -171,6 +176,7 @@ mod tests {
                 elements: [Text {
                     text: "Sixth Paragraph".to_string(),
                     size: 8,
+                    style: TextStyle::default(),
                 }]
                 .to_vec(),
             },
@@ -212,13 +218,11 @@ mod tests {
             List {
                 elements,
                 numbered: _,
-            } => match &elements[0] {
-                ListItem { element } => match element {
-                    Text { text, size: _ } => {
-                        assert_eq!(text, "List item 1");
-                    }
-                    _ => panic!("Expected Paragraph"),
-                },
+            } => match &elements[0].element {
+                Text { text, .. } => {
+                    assert_eq!(text, "List item 1");
+                }
+                _ => panic!("Expected Paragraph"),
             },
             _ => panic!("Expected Paragraph"),
         }
@@ -241,29 +245,19 @@ mod tests {
 </html>"#;
         let elements = [
             List {
-                elements: vec![{
-                    ListItem {
-                        element: {
-                            Text {
-                                size: 8,
-                                text: "List item 1".to_string(),
-                            }
-                        },
-                    }
-                }],
+                elements: vec![ListItem::new(Text {
+                    size: 8,
+                    style: TextStyle::default(),
+                    text: "List item 1".to_string(),
+                })],
                 numbered: true,
             },
             List {
-                elements: vec![{
-                    ListItem {
-                        element: {
-                            Text {
-                                size: 8,
-                                text: "List item one".to_string(),
-                            }
-                        },
-                    }
-                }],
+                elements: vec![ListItem::new(Text {
+                    size: 8,
+                    style: TextStyle::default(),
+                    text: "List item one".to_string(),
+                })],
                 numbered: false,
             },
         ]
@@ -303,24 +297,18 @@ mod tests {
         let parsed: Document = Transformer::parse(&html_document.as_bytes().into())?;
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            Table { headers, rows } => {
-                match &headers[0] {
-                    TableHeader { element, width: _ } => match element {
-                        Text { text, size: _ } => {
-                            assert_eq!(text, "Syntax");
-                        }
-                        _ => panic!("Expected Paragraph"),
-                    },
+            Table { headers, rows, .. } => {
+                match &headers[0].elements[0] {
+                    Text { text, .. } => {
+                        assert_eq!(text, "Syntax");
+                    }
+                    _ => panic!("Expected Paragraph"),
                 }
-                match &rows[0] {
-                    TableRow { cells } => match &cells[0] {
-                        TableCell { element } => match element {
-                            Text { text, size: _ } => {
-                                assert_eq!(text, "Header");
-                            }
-                            _ => panic!("Expected Paragraph"),
-                        },
-                    },
+                match &rows[0].cells[0].elements[0] {
+                    Text { text, .. } => {
+                        assert_eq!(text, "Header");
+                    }
+                    _ => panic!("Expected Paragraph"),
                 }
             }
             _ => panic!("Expected Paragraph"),
@@ -350,77 +338,52 @@ mod tests {
 </body>
 </html>"#;
         let elements = [Table {
+            name: None,
             headers: vec![
-                {
-                    TableHeader {
-                        element: {
-                            Text {
-                                size: 8,
-                                text: "Syntax".to_string(),
-                            }
-                        },
-                        width: 10.0,
-                    }
-                },
-                {
-                    TableHeader {
-                        element: {
-                            Text {
-                                size: 8,
-                                text: "Description".to_string(),
-                            }
-                        },
-                        width: 10.0,
-                    }
-                },
+                TableHeader::new(
+                    Text {
+                        size: 8,
+                        style: TextStyle::default(),
+                        text: "Syntax".to_string(),
+                    },
+                    10.0,
+                ),
+                TableHeader::new(
+                    Text {
+                        size: 8,
+                        style: TextStyle::default(),
+                        text: "Description".to_string(),
+                    },
+                    10.0,
+                ),
             ],
             rows: vec![
                 TableRow {
                     cells: vec![
-                        {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Header".to_string(),
-                                    }
-                                },
-                            }
-                        },
-                        {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Title".to_string(),
-                                    }
-                                },
-                            }
-                        },
+                        TableCell::new(Text {
+                            size: 8,
+                            style: TextStyle::default(),
+                            text: "Header".to_string(),
+                        }),
+                        TableCell::new(Text {
+                            size: 8,
+                            style: TextStyle::default(),
+                            text: "Title".to_string(),
+                        }),
                     ],
                 },
                 TableRow {
                     cells: vec![
-                        {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Paragraph".to_string(),
-                                    }
-                                },
-                            }
-                        },
-                        {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Text".to_string(),
-                                    }
-                                },
-                            }
-                        },
+                        TableCell::new(Text {
+                            size: 8,
+                            style: TextStyle::default(),
+                            text: "Paragraph".to_string(),
+                        }),
+                        TableCell::new(Text {
+                            size: 8,
+                            style: TextStyle::default(),
+                            text: "Text".to_string(),
+                        }),
                     ],
                 },
             ],
@@ -481,6 +444,7 @@ mod tests {
                 {
                     Text {
                         size: 8,
+                        style: TextStyle::default(),
                         text: "bla".to_string(),
                     }
                 },
@@ -497,6 +461,7 @@ mod tests {
                 {
                     Text {
                         size: 8,
+                        style: TextStyle::default(),
                         text: "bla bla".to_string(),
                     }
                 },
@@ -564,6 +529,7 @@ mod tests {
                 },
                 Text {
                     size: 8,
+                    style: TextStyle::default(),
                     text: "  ".to_string(),
                 },
                 Hyperlink {
@@ -609,6 +575,7 @@ mod tests {
         let mut header_elements: Vec<Element> = Vec::new();
         let header: Element = Text {
             size: 10,
+            style: TextStyle::default(),
             text: std::string::String::from("This is page header text"),
         };
 
@@ -642,6 +609,7 @@ mod tests {
         let mut header_elements: Vec<Element> = Vec::new();
         let header: Element = Text {
             size: 10,
+            style: TextStyle::default(),
             text: std::string::String::from("This is page header text"),
         };
 
@@ -674,6 +642,7 @@ mod tests {
         let mut footer_elements: Vec<Element> = Vec::new();
         let footer: Element = Text {
             size: 10,
+            style: TextStyle::default(),
             text: std::string::String::from("This is page footer text"),
         };
 
@@ -707,6 +676,7 @@ mod tests {
         let mut footer_elements: Vec<Element> = Vec::new();
         let footer: Element = Text {
             size: 10,
+            style: TextStyle::default(),
             text: std::string::String::from("This is page footer text"),
         };
 